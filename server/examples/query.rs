@@ -7,18 +7,36 @@ use server::net;
 use server::net::types::DataSet;
 use server::storage::{ResultSet, SqlType};
 use std::cmp::{max, min};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 
 
 fn main() {
 
+    let users = match auth::Catalog::open(Path::new("data"), None, Default::default()) {
+        Ok(catalog) => Arc::new(Mutex::new(catalog)),
+        Err(error) => {
+            println!("{:?}", error);
+            return
+        }
+    };
     print!("Username: ");
     let username = read_query();
-    let mut user = auth::User { _name: username.into(), _currentDatabase: None };
+    print!("Password: ");
+    let password = read_query();
+    let mut user = match auth::find_user(&users, &username, &password) {
+        Ok(user) => user,
+        Err(error) => {
+            println!("{:?}", error);
+            return
+        }
+    };
+    let mut session = query::Session::new(query::Settings::default());
     println!("to exit program type 'exit'");
     print!("Sql Query: ");
     let mut query = read_query();
     while query != "exit" {
-        execute(&query, & mut user);
+        execute(&query, & mut user, &mut session);
         print!("Sql Query: ");
         query = read_query();
     }
@@ -26,13 +44,13 @@ fn main() {
 }
 
 
-fn execute(query: &str, user: & mut auth::User) {
+fn execute(query: &str, user: & mut auth::User, session: &mut query::Session) {
         let ast = parse::parse(query);
 
         match ast {
         Ok(tree) => {
                 println!("{:?}", tree);
-                match query::execute_from_ast(tree, user) {
+                match query::execute_from_ast(tree, user, session) {
                     Ok(s) => display(&mut net::types::preprocess(&s)),
                     Err(error) => println!("{:?}", error),
                 };
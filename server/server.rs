@@ -5,29 +5,36 @@ extern crate rustc_serialize;
 extern crate server;
 
 use rustc_serialize::json;
+use std::cmp::max;
 use std::fs::File;
 use std::io::Read;
 use docopt::Docopt;
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, SocketAddr};
 use std::str::FromStr;
+use std::time::Duration;
 
 /// For console input, manages flags and arguments
 const USAGE: &'static str = "
-Usage: uosql-server [--cfg=<file>] [--bind=<address>] [--port=<port>]
-[--dir=<directory>]
+Usage: uosql-server [--cfg=<file>] [--bind=<address>...] [--port=<port>]
+[--socket=<path>] [--dir=<directory>]
 
 Options:
     --cfg=<file>        Enter a configuration file.
-    --bind=<address>    Change the bind address.
-    --port=<port>       Change the port.
+    --bind=<address>    Change the bind address, IPv4 or IPv6, with or
+                        without port. Can be given several times.
+    --port=<port>       Change the port of all bind addresses.
+    --socket=<path>     Listen on a Unix socket as well.
     --dir=<directory>   Change the path of the database.
 ";
 
+const DEFAULT_PORT: u16 = 4242;
+
 #[derive(Debug, RustcDecodable)]
 struct Args {
    flag_cfg: Option<String>,
-   flag_bind: Option<String>,
+   flag_bind: Vec<String>,
    flag_port: Option<u16>,
+   flag_socket: Option<String>,
    flag_dir: Option<String>
 }
 
@@ -48,20 +55,38 @@ fn main() {
     let mut config = read_conf_from_json(args.flag_cfg
                                 .unwrap_or("config.json".into()));
 
-    // Change the bind address if flag is set
-    config.address = args.flag_bind.and_then(|b| Ipv4Addr::from_str(&b)
-                                   .ok()).unwrap_or(config.address);
+    // Change the bind addresses if flags are set
+    if !args.flag_bind.is_empty() {
+        config.addresses = parse_addresses(&args.flag_bind, DEFAULT_PORT);
+    }
+
+    // Change port of all addresses if flag is set
+    if let Some(port) = args.flag_port {
+        for addr in config.addresses.iter_mut() {
+            addr.set_port(port);
+        }
+    }
 
-    // Change port if flag is set
-    config.port = args.flag_port.unwrap_or(config.port);
+    // Change the Unix socket if flag is set
+    config.socket = args.flag_socket.or(config.socket);
 
     // Change directory is flag is set
     config.dir = args.flag_dir.unwrap_or(config.dir);
 
-    info!("Bind: {}  Port: {}  Directory: {}",
-                        config.address, config.port, config.dir);
+    let addresses: Vec<_> = config.addresses.iter().map(|a| a.to_string()).collect();
+    info!("Bind: {}  Socket: {}  Directory: {}  Memory per query: {} bytes  \
+           Connections: {}  TLS: {}",
+                        addresses.join(", "), config.socket.as_ref().map_or("-", |s| &s[..]),
+                        config.dir, config.memory_limit, config.limits.max_connections,
+                        if config.require_tls { "required" }
+                        else if config.tls_cert.is_some() { "offered" }
+                        else { "off" });
 
-    // Start listening for incoming Tcp connections
+    // SIGTERM and SIGINT stop the server cleanly
+    server::shutdown::catch_signals();
+
+    // Start listening for incoming Tcp connections, until the server is
+    // asked to stop
     server::listen(config);
 }
 
@@ -74,8 +99,30 @@ fn read_conf_from_json(name: String) -> server::Config {
     #[derive(Debug, RustcDecodable, Default)]
     struct CfgFile {
         address: Option<String>,
+        addresses: Option<Vec<String>>,
         port: Option<u16>,
-        dir: Option<String>
+        socket: Option<String>,
+        socket_mode: Option<String>,
+        dir: Option<String>,
+        memory_limit: Option<u64>,
+        admin_password: Option<String>,
+        tls_cert: Option<String>,
+        tls_key: Option<String>,
+        require_tls: Option<bool>,
+        audit_file: Option<String>,
+        audit_users: Option<Vec<String>>,
+        audit_classes: Option<Vec<String>>,
+        audit_max_size: Option<u64>,
+        audit_keep: Option<u32>,
+        login_delay_ms: Option<u64>,
+        login_max_delay_ms: Option<u64>,
+        login_max_failures: Option<u32>,
+        login_lockout_secs: Option<u64>,
+        max_connections: Option<usize>,
+        max_user_connections: Option<u32>,
+        idle_timeout_secs: Option<u64>,
+        login_timeout_secs: Option<u64>,
+        shutdown_grace_secs: Option<u64>
     }
 
     // Read from JSON file and decode to CfgFile
@@ -89,19 +136,112 @@ fn read_conf_from_json(name: String) -> server::Config {
         }
     }
 
-    let s = config.address.unwrap_or("127.0.0.1".into());
-    let bind = match Ipv4Addr::from_str(&s) {
-        Ok(n) => n,
-        Err(_) => {
-            warn!("Invalid bind address, set to default");
-            Ipv4Addr::new(127,0,0,1)
-        }
+    // A single address and a list of them may be given, both are taken
+    let mut names: Vec<String> = config.address.into_iter().collect();
+    names.extend(config.addresses.unwrap_or(Vec::new()).into_iter());
+    if names.is_empty() {
+        names.push("127.0.0.1".into());
+    }
+    let port = config.port.unwrap_or(DEFAULT_PORT);
+    let addresses = parse_addresses(&names, port);
+
+    // The permissions of the socket are written in octal like for chmod,
+    // by default the owner and its group may connect
+    let socket_mode = match config.socket_mode {
+        Some(mode) => u32::from_str_radix(&mode, 8).unwrap_or_else(|_| {
+            warn!("Invalid permissions of the Unix socket '{}', set to default", mode);
+            0o660
+        }),
+        None => 0o660
+    };
+
+    // Only the known classes of audit records are taken
+    let audit_classes = config.audit_classes.map(|names| names.iter()
+        .filter_map(|name| {
+            let class = server::audit::Class::from_name(name);
+            if class.is_none() {
+                warn!("Unknown class of audit records '{}', ignored", name);
+            }
+            class
+        })
+        .collect());
+    let audit = match config.audit_file {
+        Some(file) => Some(server::audit::Settings {
+            path: file.into(),
+            users: config.audit_users,
+            classes: audit_classes,
+            max_size: config.audit_max_size.unwrap_or(16 * 1024 * 1024),
+            keep: config.audit_keep.unwrap_or(8)
+        }),
+        None => None
+    };
+
+    let throttle = server::auth::throttle::Settings::default();
+    let login_throttle = server::auth::throttle::Settings {
+        delay: config.login_delay_ms.map_or(throttle.delay, Duration::from_millis),
+        max_delay: config.login_max_delay_ms.map_or(throttle.max_delay, Duration::from_millis),
+        max_failures: config.login_max_failures.unwrap_or(throttle.max_failures),
+        lockout: config.login_lockout_secs.map_or(throttle.lockout, Duration::from_secs)
+    };
+
+    // Without an idle timeout quiet connections are kept open, the login
+    // always has to be done within the login timeout (0 counts as 1)
+    let limits = server::conn::Limits {
+        max_connections: config.max_connections.unwrap_or(100),
+        max_user_connections: config.max_user_connections.unwrap_or(0),
+        idle_timeout: match config.idle_timeout_secs {
+            Some(0) | None => None,
+            Some(secs) => Some(Duration::from_secs(secs))
+        },
+        login_timeout: Duration::from_secs(max(config.login_timeout_secs.unwrap_or(10), 1))
     };
 
     // Return configuration, all None datafields set to default
     server::Config {
-        address: bind,
-        port: config.port.unwrap_or(4242),
-        dir: config.dir.unwrap_or("data".into())
+        addresses: addresses,
+        socket: config.socket,
+        socket_mode: socket_mode,
+        dir: config.dir.unwrap_or("data".into()),
+        memory_limit: config.memory_limit.unwrap_or(64 * 1024 * 1024),
+        admin_password: config.admin_password,
+        tls_cert: config.tls_cert,
+        tls_key: config.tls_key,
+        require_tls: config.require_tls.unwrap_or(false),
+        audit: audit,
+        login_throttle: login_throttle,
+        limits: limits,
+        shutdown_grace: Duration::from_secs(config.shutdown_grace_secs.unwrap_or(30))
+    }
+}
+
+/// Parses the addresses to listen on, an address without a port gets the
+/// given one. Invalid addresses are left out, if none is left the default
+/// address is taken.
+fn parse_addresses(names: &[String], port: u16) -> Vec<SocketAddr> {
+    let mut addresses: Vec<SocketAddr> = names.iter().filter_map(|name| {
+        let addr = SocketAddr::from_str(name).ok()
+            .or_else(|| IpAddr::from_str(name).ok().map(|ip| SocketAddr::new(ip, port)));
+        if addr.is_none() {
+            warn!("Invalid bind address '{}', ignored", name);
+        }
+        addr
+    }).collect();
+    if addresses.is_empty() {
+        warn!("No valid bind address, set to default");
+        addresses.push(SocketAddr::new(IpAddr::from_str("127.0.0.1").unwrap(), port));
     }
+    addresses
+}
+
+#[test]
+fn test_parse_addresses() {
+    let names: Vec<String> = vec!["[::1]:4000".into(), "::1".into(), "10.0.0.1".into(),
+        "0.0.0.0:5000".into(), "no-address".into()];
+    let addresses: Vec<String> = parse_addresses(&names, 4242).iter()
+        .map(|a| a.to_string()).collect();
+    assert_eq!(addresses, vec!["[::1]:4000", "[::1]:4242", "10.0.0.1:4242", "0.0.0.0:5000"]);
+
+    let names: Vec<String> = vec!["no-address".into()];
+    assert_eq!(parse_addresses(&names, 4242),
+        vec![SocketAddr::from_str("127.0.0.1:4242").unwrap()]);
 }
@@ -0,0 +1,283 @@
+//! Audit log of logins and executed statements
+//!
+//! Unlike the debug log of `logger`, the audit log tells who ran what. The
+//! file is only ever appended to, one record per line with tab separated
+//! fields:
+//!
+//! ```text
+//! time  peer  user  database  class  result  rows  statement
+//! ```
+//!
+//! The time is in milliseconds since the Unix epoch and the result is `ok`
+//! or the code of the error (see `net::errors`). `rows` is the number of
+//! rows of the result, or 1 for an insert. The storage engines do not
+//! report how many rows an update or a delete changed yet, so these records
+//! have `-` instead.
+//! Backslashes, tabs and line breaks in the fields are escaped. Passwords
+//! in statements are never written.
+//!
+//! A file growing beyond the maximum size is renamed to `<file>.1`, older
+//! ones to `<file>.2` and so on, and a new file is started.
+//!
+
+use parse::ast::*;
+use std::borrow::Cow;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Kinds of records, the audit log can be restricted to some of them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Class {
+    // logins, successful or not
+    Login,
+    // SELECT, EXPLAIN and DESCRIBE
+    Read,
+    // INSERT, UPDATE and DELETE
+    Write,
+    // CREATE, ALTER and DROP of databases, tables and views
+    Define,
+    // users, roles and privileges
+    Access,
+    // USE, SET and ANALYZE
+    Session,
+    // statements with a syntax error
+    Invalid,
+}
+
+impl Class {
+    /// Returns the class of the statement
+    pub fn of(query: &Query) -> Class {
+        match query {
+            &Query::ManipulationStmt(ref stmt) => match stmt {
+                &ManipulationStmt::Select(_) | &ManipulationStmt::Explain(_) |
+                &ManipulationStmt::Describe(_) => Class::Read,
+                &ManipulationStmt::Insert(_) | &ManipulationStmt::Update(_) |
+                &ManipulationStmt::Delete(_) => Class::Write,
+                &ManipulationStmt::SetRole(_) => Class::Access,
+                &ManipulationStmt::Use(_) | &ManipulationStmt::Analyze(_) |
+                &ManipulationStmt::Set(_) => Class::Session,
+            },
+            &Query::DefStmt(ref stmt) => match stmt {
+                &DefStmt::Create(CreateStmt::User(_)) | &DefStmt::Create(CreateStmt::Role(_)) |
+                &DefStmt::Alter(AltStmt::User(_)) | &DefStmt::Drop(DropStmt::User(_)) |
+                &DefStmt::Drop(DropStmt::Role(_)) | &DefStmt::Grant(_) | &DefStmt::Revoke(_) |
+                &DefStmt::GrantRole(_) | &DefStmt::RevokeRole(_) => Class::Access,
+                _ => Class::Define,
+            },
+            &Query::Dummy => Class::Invalid,
+        }
+    }
+
+    /// Returns the class with the name used in the configuration
+    pub fn from_name(name: &str) -> Option<Class> {
+        match name {
+            "login" => Some(Class::Login),
+            "read" => Some(Class::Read),
+            "write" => Some(Class::Write),
+            "define" => Some(Class::Define),
+            "access" => Some(Class::Access),
+            "session" => Some(Class::Session),
+            "invalid" => Some(Class::Invalid),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            &Class::Login => "login",
+            &Class::Read => "read",
+            &Class::Write => "write",
+            &Class::Define => "define",
+            &Class::Access => "access",
+            &Class::Session => "session",
+            &Class::Invalid => "invalid",
+        }
+    }
+}
+
+/// Returns the text of the statement as it is written to the audit log,
+/// statements setting a password are replaced by one without it
+pub fn redact<'a>(query: &Query, text: &'a str) -> Cow<'a, str> {
+    match query {
+        &Query::DefStmt(DefStmt::Create(CreateStmt::User(ref stmt))) =>
+            format!("CREATE USER {} PASSWORD '***'", stmt.name).into(),
+        &Query::DefStmt(DefStmt::Alter(AltStmt::User(ref stmt))) =>
+            format!("ALTER USER {} PASSWORD '***'", stmt.name).into(),
+        _ => text.into(),
+    }
+}
+
+/// Returns the text of a statement with a syntax error as it is written to
+/// the audit log. It can not be told where a password is, so statements
+/// mentioning one are left out.
+pub fn redact_invalid(text: &str) -> Cow<str> {
+    if text.to_lowercase().contains("password") {
+        "<statement with a password>".into()
+    } else {
+        text.into()
+    }
+}
+
+/// Settings of the audit log, taken from the server configuration
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub path: PathBuf,
+    // only statements of these users are recorded, all if None
+    pub users: Option<Vec<String>>,
+    // only records of these classes are written, all if None
+    pub classes: Option<Vec<Class>>,
+    // bytes the file may grow to before it is rotated
+    pub max_size: u64,
+    // rotated files which are kept
+    pub keep: u32,
+}
+
+/// The audit log shared by all connections
+pub struct AuditLog {
+    settings: Settings,
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    /// Opens the file of the audit log, records are appended
+    pub fn open(settings: Settings) -> io::Result<AuditLog> {
+        let file = try!(open_append(&settings.path));
+        Ok(AuditLog { settings: settings, file: Mutex::new(file) })
+    }
+
+    /// Returns true if records of the user and the class are written
+    fn wants(&self, user: &str, class: Class) -> bool {
+        self.settings.users.as_ref().map_or(true, |users| users.iter().any(|u| u == user))
+            && self.settings.classes.as_ref().map_or(true, |classes| classes.contains(&class))
+    }
+
+    /// Appends the record to the file. It is rotated first if the record
+    /// would not fit anymore.
+    fn write(&self, line: &str) -> io::Result<()> {
+        // a thread which panicked while writing left at most half a line
+        let mut file = match self.file.lock() {
+            Ok(file) => file,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let size = try!(file.metadata()).len();
+        if size > 0 && size + line.len() as u64 > self.settings.max_size {
+            try!(self.rotate());
+            *file = try!(open_append(&self.settings.path));
+        }
+        file.write_all(line.as_bytes())
+    }
+
+    /// Renames the file to `<file>.1`, `<file>.1` to `<file>.2` and so on.
+    /// The oldest file is removed.
+    fn rotate(&self) -> io::Result<()> {
+        let rotated = |n: u32| {
+            let mut name = self.settings.path.clone().into_os_string();
+            name.push(format!(".{}", n));
+            PathBuf::from(name)
+        };
+        if self.settings.keep == 0 {
+            return fs::remove_file(&self.settings.path)
+        }
+        for n in (1..self.settings.keep).rev() {
+            if rotated(n).exists() {
+                try!(fs::rename(rotated(n), rotated(n + 1)));
+            }
+        }
+        fs::rename(&self.settings.path, rotated(1))
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().write(true).append(true).create(true).open(path)
+}
+
+/// The audit log as seen by one connection
+pub struct Auditor {
+    log: Option<Arc<AuditLog>>,
+    // address of the client
+    peer: String,
+}
+
+impl Auditor {
+    pub fn new(log: Option<Arc<AuditLog>>, peer: String) -> Auditor {
+        Auditor { log: log, peer: peer }
+    }
+
+    /// Records a login, the result is the code of the error if it failed
+    pub fn login(&self, user: &str, result: Result<(), u16>) {
+        self.statement(user, None, Class::Login, "", result.map(|_| None))
+    }
+
+    /// Records a statement. The result is the number of rows, None if the
+    /// statement does not report it, or the code of the error.
+    pub fn statement(&self, user: &str, database: Option<&str>, class: Class,
+        statement: &str, result: Result<Option<u64>, u16>)
+    {
+        let log = match self.log {
+            Some(ref log) if log.wants(user, class) => log,
+            _ => return,
+        };
+        let time = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|t| t.as_secs() * 1000 + t.subsec_nanos() as u64 / 1_000_000)
+            .unwrap_or(0);
+        let (result, rows) = match result {
+            Ok(rows) => ("ok".to_string(), rows.map_or("-".to_string(), |n| n.to_string())),
+            Err(code) => (code.to_string(), "-".to_string()),
+        };
+        let line = format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n", time, escape(&self.peer),
+            escape(user), escape(database.unwrap_or("-")), class.name(), result, rows,
+            escape(statement));
+        if let Err(e) = log.write(&line) {
+            error!("Could not write to the audit log: {}", e);
+        }
+    }
+}
+
+/// Escapes the characters which would break the record into several fields
+/// or lines
+fn escape(field: &str) -> Cow<str> {
+    if !field.contains(|c: char| c == '\\' || c == '\t' || c == '\n' || c == '\r') {
+        return field.into()
+    }
+    field.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+        .replace('\r', "\\r").into()
+}
+
+#[test]
+fn test_rotation() {
+    use std::env;
+    use std::io::Read;
+
+    let dir = env::temp_dir().join("uosql-audit-test");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("audit.log");
+    let log = AuditLog::open(Settings {
+        path: path.clone(),
+        users: Some(vec!["elena".into()]),
+        classes: None,
+        max_size: 150,
+        keep: 2,
+    }).unwrap();
+    let auditor = Auditor::new(Some(Arc::new(log)), "127.0.0.1:4000".into());
+
+    auditor.login("elena", Ok(()));
+    auditor.login("peter", Err(141));
+    auditor.statement("elena", Some("test"), Class::Read, "select *\n from foo", Ok(Some(3)));
+    auditor.statement("elena", None, Class::Write, "delete from foo", Err(105));
+
+    // the third record did not fit anymore
+    let mut old = String::new();
+    File::open(dir.join("audit.log.1")).unwrap().read_to_string(&mut old).unwrap();
+    let lines: Vec<Vec<_>> = old.lines().map(|l| l.split('\t').skip(1).collect()).collect();
+    assert_eq!(lines, vec![
+        vec!["127.0.0.1:4000", "elena", "-", "login", "ok", "-", ""],
+        vec!["127.0.0.1:4000", "elena", "test", "read", "ok", "3", "select *\\n from foo"],
+    ]);
+    let mut new = String::new();
+    File::open(&path).unwrap().read_to_string(&mut new).unwrap();
+    assert!(new.ends_with("\telena\t-\twrite\t105\t-\tdelete from foo\n"));
+}
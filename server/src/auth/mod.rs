@@ -0,0 +1,588 @@
+//! Module for authenticating users and check privileges
+//!
+//! Contains the `User` type and functions to:
+//!
+//! - check if a username-password pair is valid
+//! - load the corresponding user
+//! - check user permissions for every query
+//!
+//! The accounts are kept in the user catalog, a file in the data directory.
+//! Passwords are not stored, only the keys derived from a salted PBKDF2 hash
+//! of them that the login needs (see `scram`). On the first start the
+//! catalog is created with the `admin` account.
+//!
+//! Failed logins are counted, guessing passwords is slowed down and
+//! finally locked out (see `throttle`).
+//!
+//! Privileges are granted to users or to roles. A user or role that is a
+//! member of a role has the privileges of the role, roles may be members of
+//! other roles.
+//!
+
+pub mod scram;
+pub mod throttle;
+
+use super::parse::ast::Privilege;
+use super::storage;
+use bincode::SizeLimit;
+use bincode::rustc_serialize::{encode_into, decode_from, EncodingError, DecodingError};
+use rand::{OsRng, Rng};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Name of the account created on the first start
+pub const ADMIN: &'static str = "admin";
+
+/// Name of the catalog file in the data directory
+const CATALOG_FILE: &'static str = "users.bin";
+
+/// Name of the file in the data directory with the generated admin password
+const PASSWORD_FILE: &'static str = "admin-password";
+
+/// Name of the file in the data directory with the secret for made up salts
+const SECRET_FILE: &'static str = "login-secret";
+
+/// Rounds of PBKDF2 for new passwords, makes guessing them slow
+const ITERATIONS: u32 = 10000;
+const SALT_LEN: usize = 16;
+
+/// Contains information about the user that opened the connection. Is used
+/// for every type of access control.
+pub struct User {
+    pub name: String,
+    pub _currentDatabase: Option<storage::Database>,
+    // the accounts of the server, shared by all connections
+    pub users: Users,
+    // set with `SET ROLE`, only the privileges of the role are used then
+    pub role: Option<String>,
+}
+
+impl User {
+    /// Returns the user or role whose privileges are checked
+    pub fn principal(&self) -> &str {
+        self.role.as_ref().unwrap_or(&self.name)
+    }
+
+    /// Returns true if the user may manage the accounts of the server. An
+    /// admin gives that up with `SET ROLE`.
+    pub fn is_admin(&self) -> bool {
+        lock(&self.users).is_admin(self.principal())
+    }
+}
+
+/// Errors that may occur during user authentication
+#[derive(Debug)]
+pub enum AuthError {
+    UserNotFound,
+    WrongPassword,
+    UserExists,
+    RoleNotFound,
+    RoleExists,
+    // `SET ROLE` to a role the user is not a member of
+    NotAMember,
+    // the role would become a member of itself
+    CircularMembership,
+    // the seconds to wait before the next login
+    TooManyAttempts(u64),
+    // the catalog could not be read or written
+    Storage(storage::Error),
+}
+
+impl From<storage::Error> for AuthError {
+    fn from(error: storage::Error) -> AuthError {
+        AuthError::Storage(error)
+    }
+}
+
+impl From<io::Error> for AuthError {
+    fn from(error: io::Error) -> AuthError {
+        AuthError::Storage(error.into())
+    }
+}
+
+impl From<EncodingError> for AuthError {
+    fn from(error: EncodingError) -> AuthError {
+        AuthError::Storage(error.into())
+    }
+}
+
+impl From<DecodingError> for AuthError {
+    fn from(error: DecodingError) -> AuthError {
+        AuthError::Storage(error.into())
+    }
+}
+
+/// The user catalog shared by all connections
+pub type Users = Arc<Mutex<Catalog>>;
+
+/// Locks the catalog. A connection panicking while holding the lock never
+/// leaves the catalog half changed, so a poisoned lock is still used.
+pub fn lock(users: &Users) -> MutexGuard<Catalog> {
+    users.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A stored account
+#[derive(Debug, RustcEncodable, RustcDecodable)]
+struct Account {
+    name: String,
+    salt: Vec<u8>,
+    // rounds the keys were computed with, older accounts may have fewer
+    iterations: u32,
+    stored_key: Vec<u8>,
+    server_key: Vec<u8>,
+    // admins have every privilege
+    admin: bool,
+    grants: Grants,
+}
+
+/// A role, it holds privileges for its members
+#[derive(Debug, RustcEncodable, RustcDecodable)]
+struct Role {
+    name: String,
+    grants: Grants,
+}
+
+/// The privileges and roles of an account or a role
+#[derive(Debug, Clone, Default, RustcEncodable, RustcDecodable)]
+struct Grants {
+    privileges: Vec<Grant>,
+    // names of the roles this is a member of
+    roles: Vec<String>,
+}
+
+/// A privilege for a database or, with a table name, for a single table
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
+struct Grant {
+    database: String,
+    table: Option<String>,
+    privilege: Privilege,
+}
+
+impl Grant {
+    fn allows(&self, database: &str, table: Option<&str>, privilege: Privilege) -> bool {
+        self.privilege == privilege && self.database == database
+            && (self.table.is_none() || self.table.as_ref().map(|t| &t[..]) == table)
+    }
+}
+
+impl Account {
+    fn new(name: &str, password: &str, admin: bool) -> Result<Account, AuthError> {
+        let salt = try!(scram::random_bytes(SALT_LEN));
+        let salted = scram::salted_password(password, &salt, ITERATIONS);
+        Ok(Account {
+            name: name.into(),
+            salt: salt,
+            iterations: ITERATIONS,
+            stored_key: scram::stored_key(&scram::client_key(&salted)),
+            server_key: scram::server_key(&salted),
+            admin: admin,
+            grants: Grants::default(),
+        })
+    }
+
+    fn check_password(&self, password: &str) -> bool {
+        let salted = scram::salted_password(password, &self.salt, self.iterations);
+        let key = scram::stored_key(&scram::client_key(&salted));
+        scram::signatures_match(&key, &self.stored_key)
+    }
+}
+
+/// The accounts of the server, saved after every change
+#[derive(Debug)]
+pub struct Catalog {
+    path: PathBuf,
+    accounts: HashMap<String, Account>,
+    roles: HashMap<String, Role>,
+    // makes up salts for unknown users, kept in a file next to the catalog
+    // so the salts stay the same after a restart
+    secret: Vec<u8>,
+    // failed logins, they are not stored
+    throttle: throttle::Throttle,
+}
+
+impl Catalog {
+    /// Loads the catalog of the data directory. If there is none yet, it is
+    /// created with the admin account. Without a given admin password a
+    /// random one is chosen and written to a file only the owner can read.
+    pub fn open(dir: &Path, admin_password: Option<&str>, throttle: throttle::Settings)
+        -> Result<Catalog, AuthError>
+    {
+        let path = dir.join(CATALOG_FILE);
+        if path.exists() {
+            let secret = try!(load_secret(&dir.join(SECRET_FILE)));
+            let mut file = try!(File::open(&path));
+            let (accounts, roles): (Vec<Account>, Vec<Role>) =
+                try!(decode_from(&mut file, SizeLimit::Infinite));
+            return Ok(Catalog {
+                path: path,
+                accounts: accounts.into_iter().map(|a| (a.name.clone(), a)).collect(),
+                roles: roles.into_iter().map(|r| (r.name.clone(), r)).collect(),
+                secret: secret,
+                throttle: throttle::Throttle::new(throttle),
+            })
+        }
+
+        try!(fs::create_dir_all(dir));
+        let password = match admin_password {
+            Some(password) => password.to_string(),
+            None => {
+                let password: String = try!(OsRng::new()).gen_ascii_chars().take(16).collect();
+                let file = dir.join(PASSWORD_FILE);
+                try!(write_private_file(&file, format!("{}\n", password).as_bytes()));
+                warn!("Created the account '{}', its password is in {:?}", ADMIN, file);
+                password
+            },
+        };
+        let mut catalog = Catalog {
+            path: path,
+            accounts: HashMap::new(),
+            roles: HashMap::new(),
+            secret: try!(load_secret(&dir.join(SECRET_FILE))),
+            throttle: throttle::Throttle::new(throttle),
+        };
+        catalog.accounts.insert(ADMIN.into(), try!(Account::new(ADMIN, &password, true)));
+        try!(catalog.save());
+        info!("Created the user catalog {:?}", catalog.path);
+        Ok(catalog)
+    }
+
+    /// Returns the salt and the rounds of the password hash of the user. An
+    /// unknown user gets a made up salt which stays the same, so the answer
+    /// does not tell whether the user exists.
+    pub fn challenge(&self, name: &str) -> (Vec<u8>, u32) {
+        match self.accounts.get(name) {
+            Some(account) => (account.salt.clone(), account.iterations),
+            None => (scram::hmac(&self.secret, name.as_bytes())[..SALT_LEN].to_vec(), ITERATIONS),
+        }
+    }
+
+    /// Checks the proof of a challenge-response login and returns the
+    /// signature of the server
+    pub fn verify(&self, name: &str, auth_message: &[u8], proof: &[u8])
+        -> Result<Vec<u8>, AuthError>
+    {
+        match self.accounts.get(name) {
+            Some(account) if scram::verify_proof(&account.stored_key, auth_message, proof) =>
+                Ok(scram::server_signature(&account.server_key, auth_message)),
+            Some(_) => Err(AuthError::WrongPassword),
+            None => Err(AuthError::UserNotFound),
+        }
+    }
+
+    /// Returns the failed logins
+    pub fn throttle(&self) -> &throttle::Throttle {
+        &self.throttle
+    }
+
+    /// Checks the password of the account
+    pub fn check(&self, name: &str, password: &str) -> Result<(), AuthError> {
+        match self.accounts.get(name) {
+            Some(account) if account.check_password(password) => Ok(()),
+            Some(_) => Err(AuthError::WrongPassword),
+            None => Err(AuthError::UserNotFound),
+        }
+    }
+
+    pub fn is_admin(&self, name: &str) -> bool {
+        self.accounts.get(name).map(|a| a.admin).unwrap_or(false)
+    }
+
+    /// Returns true if the user or role may run statements of the kind on
+    /// the table or, without a table, on the whole database. The privileges
+    /// of all roles it is a member of count, too.
+    pub fn has_privilege(&self, name: &str, database: &str, table: Option<&str>,
+        privilege: Privilege) -> bool
+    {
+        if self.is_admin(name) {
+            return true
+        }
+        let mut names = self.member_roles(name);
+        names.push(name.into());
+        names.iter().filter_map(|n| self.grants(n)).any(|grants| {
+            grants.privileges.iter().any(|g| g.allows(database, table, privilege))
+        })
+    }
+
+    /// Returns the roles the user or role is a member of, directly or through
+    /// other roles
+    pub fn member_roles(&self, name: &str) -> Vec<String> {
+        let mut found = HashSet::new();
+        let mut todo = vec![name.to_string()];
+        while let Some(name) = todo.pop() {
+            if let Some(grants) = self.grants(&name) {
+                for role in &grants.roles {
+                    if found.insert(role.clone()) {
+                        todo.push(role.clone());
+                    }
+                }
+            }
+        }
+        found.into_iter().collect()
+    }
+
+    pub fn role_exists(&self, name: &str) -> bool {
+        self.roles.contains_key(name)
+    }
+
+    pub fn is_member(&self, name: &str, role: &str) -> bool {
+        self.member_roles(name).iter().any(|r| r == role)
+    }
+
+    fn grants(&self, name: &str) -> Option<&Grants> {
+        match self.accounts.get(name) {
+            Some(account) => Some(&account.grants),
+            None => self.roles.get(name).map(|r| &r.grants),
+        }
+    }
+
+    fn grants_mut(&mut self, name: &str) -> Result<&mut Grants, AuthError> {
+        match self.accounts.get_mut(name) {
+            Some(account) => return Ok(&mut account.grants),
+            None => {},
+        }
+        match self.roles.get_mut(name) {
+            Some(role) => Ok(&mut role.grants),
+            None => Err(AuthError::UserNotFound),
+        }
+    }
+
+    /// Gives the privileges to the user or role, privileges it already has
+    /// are left alone
+    pub fn grant(&mut self, name: &str, database: &str, table: Option<&str>,
+        privileges: &[Privilege]) -> Result<(), AuthError>
+    {
+        {
+            let grants = try!(self.grants_mut(name));
+            for &privilege in privileges {
+                let grant = Grant {
+                    database: database.into(),
+                    table: table.map(|t| t.into()),
+                    privilege: privilege,
+                };
+                if !grants.privileges.contains(&grant) {
+                    grants.privileges.push(grant);
+                }
+            }
+        }
+        self.save()
+    }
+
+    /// Takes the privileges from the user or role. Only the privileges given
+    /// for exactly this database or table are taken.
+    pub fn revoke(&mut self, name: &str, database: &str, table: Option<&str>,
+        privileges: &[Privilege]) -> Result<(), AuthError>
+    {
+        try!(self.grants_mut(name)).privileges.retain(|g| g.database != database
+            || g.table.as_ref().map(|t| &t[..]) != table
+            || !privileges.contains(&g.privilege));
+        self.save()
+    }
+
+    /// Makes the user or role a member of the role
+    pub fn grant_role(&mut self, role: &str, member: &str) -> Result<(), AuthError> {
+        if !self.roles.contains_key(role) {
+            return Err(AuthError::RoleNotFound)
+        }
+        if role == member || self.is_member(role, member) {
+            return Err(AuthError::CircularMembership)
+        }
+        {
+            let grants = try!(self.grants_mut(member));
+            if !grants.roles.iter().any(|r| r == role) {
+                grants.roles.push(role.into());
+            }
+        }
+        self.save()
+    }
+
+    pub fn revoke_role(&mut self, role: &str, member: &str) -> Result<(), AuthError> {
+        if !self.roles.contains_key(role) {
+            return Err(AuthError::RoleNotFound)
+        }
+        try!(self.grants_mut(member)).roles.retain(|r| r != role);
+        self.save()
+    }
+
+    pub fn create_role(&mut self, name: &str) -> Result<(), AuthError> {
+        if self.roles.contains_key(name) {
+            return Err(AuthError::RoleExists)
+        }
+        if self.accounts.contains_key(name) {
+            return Err(AuthError::UserExists)
+        }
+        self.roles.insert(name.into(), Role { name: name.into(), grants: Grants::default() });
+        self.save()
+    }
+
+    /// Removes the role, its members lose its privileges
+    pub fn drop_role(&mut self, name: &str) -> Result<(), AuthError> {
+        if self.roles.remove(name).is_none() {
+            return Err(AuthError::RoleNotFound)
+        }
+        for account in self.accounts.values_mut() {
+            account.grants.roles.retain(|r| r != name);
+        }
+        for role in self.roles.values_mut() {
+            role.grants.roles.retain(|r| r != name);
+        }
+        self.save()
+    }
+
+    pub fn create_user(&mut self, name: &str, password: &str) -> Result<(), AuthError> {
+        if self.accounts.contains_key(name) {
+            return Err(AuthError::UserExists)
+        }
+        if self.roles.contains_key(name) {
+            return Err(AuthError::RoleExists)
+        }
+        self.accounts.insert(name.into(), try!(Account::new(name, password, false)));
+        self.save()
+    }
+
+    pub fn set_password(&mut self, name: &str, password: &str) -> Result<(), AuthError> {
+        let (admin, grants) = match self.accounts.get(name) {
+            Some(account) => (account.admin, account.grants.clone()),
+            None => return Err(AuthError::UserNotFound),
+        };
+        let mut account = try!(Account::new(name, password, admin));
+        account.grants = grants;
+        self.accounts.insert(name.into(), account);
+        self.save()
+    }
+
+    pub fn drop_user(&mut self, name: &str) -> Result<(), AuthError> {
+        if self.accounts.remove(name).is_none() {
+            return Err(AuthError::UserNotFound)
+        }
+        self.save()
+    }
+
+    /// Writes the catalog file and its directory entry to the disk
+    pub fn sync(&self) -> io::Result<()> {
+        storage::sync_file(&self.path)
+    }
+
+    /// Writes the catalog to a new file first, so a crash never leaves a
+    /// broken catalog behind
+    fn save(&self) -> Result<(), AuthError> {
+        let tmp = self.path.with_extension("tmp");
+        {
+            let mut file = try!(File::create(&tmp));
+            let accounts: Vec<_> = self.accounts.values().collect();
+            let roles: Vec<_> = self.roles.values().collect();
+            try!(encode_into(&(accounts, roles), &mut file, SizeLimit::Infinite));
+            try!(file.sync_all());
+        }
+        try!(fs::rename(&tmp, &self.path));
+        Ok(())
+    }
+}
+
+/// Validates username and password and returns the matched user. Used by
+/// tools running on the server itself, clients log in with `login`.
+///
+/// # Failures
+/// If the user was not found or the password does not match, an `Err` value
+/// is returned. See `AuthError` for more information.
+pub fn find_user(users: &Users, name: &str, passwd: &str) -> Result<User, AuthError> {
+    try!(lock(users).check(name, passwd));
+    debug!("User '{}' was succesfully authenticated", name);
+    Ok(new_user(users, name))
+}
+
+/// Validates the proof of a challenge-response login from the address and
+/// returns the matched user and the signature of the server. Failed logins
+/// are counted, a user who has to wait is refused without a check.
+pub fn login(users: &Users, name: &str, address: Option<IpAddr>, auth_message: &[u8],
+    proof: &[u8]) -> Result<(User, Vec<u8>), AuthError>
+{
+    let mut catalog = lock(users);
+    try!(check_throttle(&catalog, name, address));
+    let verified = catalog.verify(name, auth_message, proof);
+    let signature = match verified {
+        Ok(signature) => signature,
+        Err(e) => {
+            catalog.throttle.failed(name, address);
+            return Err(e)
+        }
+    };
+    catalog.throttle.succeeded(name);
+    debug!("User '{}' was succesfully authenticated", name);
+    Ok((new_user(users, name), signature))
+}
+
+/// Returns an error if the user has to wait before logging in from the
+/// address
+pub fn check_throttle(catalog: &Catalog, name: &str, address: Option<IpAddr>)
+    -> Result<(), AuthError>
+{
+    match catalog.throttle.wait(name, address) {
+        // rounded up, so waiting the seconds is enough
+        Some(wait) => Err(AuthError::TooManyAttempts(wait.as_secs()
+            + if wait.subsec_nanos() > 0 { 1 } else { 0 })),
+        None => Ok(()),
+    }
+}
+
+fn new_user(users: &Users, name: &str) -> User {
+    User {
+        name: name.into(),
+        _currentDatabase: None,
+        users: users.clone(),
+        role: None,
+    }
+}
+
+/// Reads the secret for made up salts. If there is none yet, or it is
+/// broken, a new one is chosen and written.
+fn load_secret(path: &Path) -> Result<Vec<u8>, AuthError> {
+    use std::io::Read;
+
+    match File::open(path) {
+        Ok(mut file) => {
+            let mut secret = Vec::new();
+            try!(file.read_to_end(&mut secret));
+            if secret.len() == SALT_LEN {
+                return Ok(secret)
+            }
+            warn!("The secret in {:?} is broken, a new one is chosen", path);
+        },
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {},
+        Err(e) => return Err(e.into()),
+    }
+    let secret = try!(scram::random_bytes(SALT_LEN));
+    try!(write_private_file(path, &secret));
+    Ok(secret)
+}
+
+/// Writes the data to a file only the owner may read
+fn write_private_file(path: &Path, data: &[u8]) -> io::Result<()> {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let mut file = try!(OpenOptions::new().write(true).create(true).truncate(true)
+        .mode(0o600).open(path));
+    // the mode only applies to a new file
+    try!(fs::set_permissions(path, fs::Permissions::from_mode(0o600)));
+    try!(file.write_all(data));
+    file.sync_all()
+}
+
+#[test]
+fn test_secret_survives_restart() {
+    use std::env;
+
+    let dir = env::temp_dir().join(format!("uosql-secret-{}", OsRng::new().unwrap().next_u32()));
+    let (salt, _) = Catalog::open(&dir, Some("secret"), throttle::Settings::default())
+        .unwrap().challenge("nobody");
+    // the admin account exists now, the catalog is loaded from the file
+    let (again, _) = Catalog::open(&dir, None, throttle::Settings::default())
+        .unwrap().challenge("nobody");
+    assert_eq!(salt, again);
+    let _ = fs::remove_dir_all(&dir);
+}
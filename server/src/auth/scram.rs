@@ -0,0 +1,133 @@
+//! SCRAM-style challenge-response login
+//!
+//! The password never crosses the wire. The greeting of the server carries
+//! a nonce, the client answers with its name and a nonce of its own and
+//! gets the salt and the rounds of its password hash back. It then proves
+//! that it knows the password:
+//!
+//! - SaltedPassword = PBKDF2-HMAC-SHA256(password, salt, rounds)
+//! - ClientKey = HMAC(SaltedPassword, "Client Key")
+//! - StoredKey = SHA256(ClientKey)
+//! - ClientProof = ClientKey XOR HMAC(StoredKey, AuthMessage)
+//!
+//! The server only keeps StoredKey and ServerKey = HMAC(SaltedPassword,
+//! "Server Key"). It recovers ClientKey from the proof and compares its
+//! hash with StoredKey. It answers with HMAC(ServerKey, AuthMessage), so the
+//! client knows that the server has the keys, too. The AuthMessage is made
+//! of the name and both nonces, so a proof is only good for one login.
+//!
+
+use byteorder::{BigEndian, WriteBytesExt};
+use crypto::digest::Digest;
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::pbkdf2::pbkdf2;
+use crypto::sha2::Sha256;
+use crypto::util::fixed_time_eq;
+use rand::{OsRng, Rng};
+use std::io;
+
+/// Length of the nonces of server and client in bytes
+pub const NONCE_LEN: usize = 24;
+
+const KEY_LEN: usize = 32;
+
+/// Returns random bytes for a nonce or a salt
+pub fn random_bytes(len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![0; len];
+    try!(OsRng::new()).fill_bytes(&mut bytes);
+    Ok(bytes)
+}
+
+pub fn salted_password(password: &str, salt: &[u8], iterations: u32) -> Vec<u8> {
+    let mut mac = Hmac::new(Sha256::new(), password.as_bytes());
+    let mut salted = vec![0; KEY_LEN];
+    pbkdf2(&mut mac, salt, iterations, &mut salted);
+    salted
+}
+
+pub fn client_key(salted_password: &[u8]) -> Vec<u8> {
+    hmac(salted_password, b"Client Key")
+}
+
+pub fn server_key(salted_password: &[u8]) -> Vec<u8> {
+    hmac(salted_password, b"Server Key")
+}
+
+pub fn stored_key(client_key: &[u8]) -> Vec<u8> {
+    let mut sha = Sha256::new();
+    sha.input(client_key);
+    let mut key = vec![0; KEY_LEN];
+    sha.result(&mut key);
+    key
+}
+
+/// Returns the message both sides sign: the name and the nonces, each with
+/// its length in front
+pub fn auth_message(name: &str, server_nonce: &[u8], client_nonce: &[u8]) -> Vec<u8> {
+    let mut msg = Vec::new();
+    for part in &[name.as_bytes(), server_nonce, client_nonce] {
+        // writing to a vector does not fail
+        let _ = msg.write_u32::<BigEndian>(part.len() as u32);
+        msg.extend(part.iter().cloned());
+    }
+    msg
+}
+
+/// Returns the proof the client sends and the signature it expects from
+/// the server in return
+pub fn client_final(password: &str, salt: &[u8], iterations: u32, auth_message: &[u8])
+    -> (Vec<u8>, Vec<u8>)
+{
+    let salted = salted_password(password, salt, iterations);
+    let client_key = client_key(&salted);
+    let signature = hmac(&stored_key(&client_key), auth_message);
+    let proof = xor(&client_key, &signature);
+    (proof, server_signature(&server_key(&salted), auth_message))
+}
+
+/// Checks the proof of the client against the stored key
+pub fn verify_proof(stored: &[u8], auth_message: &[u8], proof: &[u8]) -> bool {
+    if proof.len() != KEY_LEN {
+        return false
+    }
+    let client_key = xor(proof, &hmac(stored, auth_message));
+    fixed_time_eq(&stored_key(&client_key), stored)
+}
+
+pub fn server_signature(server_key: &[u8], auth_message: &[u8]) -> Vec<u8> {
+    hmac(server_key, auth_message)
+}
+
+/// Compares two signatures in constant time
+pub fn signatures_match(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && fixed_time_eq(a, b)
+}
+
+pub fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::new(Sha256::new(), key);
+    mac.input(data);
+    mac.result().code().to_vec()
+}
+
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().zip(b.iter()).map(|(x, y)| x ^ y).collect()
+}
+
+#[test]
+fn test_proof() {
+    let salt = b"salt";
+    let msg = auth_message("bob", b"server", b"client");
+    let (proof, signature) = client_final("secret", salt, 16, &msg);
+
+    let salted = salted_password("secret", salt, 16);
+    let stored = stored_key(&client_key(&salted));
+    assert!(verify_proof(&stored, &msg, &proof));
+    assert!(signatures_match(&signature, &server_signature(&server_key(&salted), &msg)));
+
+    // a wrong password or another login does not pass
+    let (wrong, _) = client_final("guess", salt, 16, &msg);
+    assert!(!verify_proof(&stored, &msg, &wrong));
+    let other = auth_message("bob", b"server2", b"client");
+    assert!(!verify_proof(&stored, &other, &proof));
+}
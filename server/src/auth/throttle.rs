@@ -0,0 +1,225 @@
+//! Slows down the guessing of passwords
+//!
+//! Failed logins are counted per user name and per client address. After a
+//! failure the next login has to wait, twice as long after every further
+//! failure up to a maximum. After too many failures in a row the name or
+//! address is locked out for a while. The count of a name starts again
+//! after a successful login, the count of both after the lockout time
+//! without failures.
+//!
+
+use std::cmp::{max, min};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// Names and addresses whose failures are kept at most. If there are more,
+/// the ones without a failure for the longest time are forgotten first.
+const MAX_ENTRIES: usize = 100000;
+
+/// How failed logins are slowed down, taken from the server configuration
+#[derive(Debug, Clone)]
+pub struct Settings {
+    // wait after the first failure, doubled with every further one
+    pub delay: Duration,
+    pub max_delay: Duration,
+    // failures in a row after which logins are refused for `lockout`, 0
+    // never locks out
+    pub max_failures: u32,
+    pub lockout: Duration,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            max_failures: 10,
+            lockout: Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+/// Whose failed logins are counted
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    User(String),
+    Address(IpAddr),
+}
+
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    last: Instant,
+}
+
+/// A user name or address which has to wait before the next login
+#[derive(Debug)]
+pub struct Lockout {
+    pub key: Key,
+    pub failures: u32,
+    // locked out after too many failures, otherwise only slowed down
+    pub locked: bool,
+    pub wait: Duration,
+}
+
+/// The failed logins of all connections
+#[derive(Debug)]
+pub struct Throttle {
+    settings: Settings,
+    failures: HashMap<Key, Failures>,
+    capacity: usize,
+}
+
+impl Throttle {
+    pub fn new(settings: Settings) -> Throttle {
+        Throttle { settings: settings, failures: HashMap::new(), capacity: MAX_ENTRIES }
+    }
+
+    /// Returns how long the user has to wait before logging in from the
+    /// address, None if the login may go on
+    pub fn wait(&self, name: &str, address: Option<IpAddr>) -> Option<Duration> {
+        let now = Instant::now();
+        let user = self.wait_for(&Key::User(name.into()), now);
+        let address = address.and_then(|address| self.wait_for(&Key::Address(address), now));
+        match (user, address) {
+            (Some(user), Some(address)) => Some(max(user, address)),
+            (user, address) => user.or(address),
+        }
+    }
+
+    /// Counts a failed login of the user from the address
+    pub fn failed(&mut self, name: &str, address: Option<IpAddr>) {
+        let now = Instant::now();
+        self.forget_expired(now);
+        self.count(Key::User(name.into()), now);
+        if let Some(address) = address {
+            self.count(Key::Address(address), now);
+        }
+    }
+
+    /// Starts the count of the user again after a successful login
+    pub fn succeeded(&mut self, name: &str) {
+        self.failures.remove(&Key::User(name.into()));
+    }
+
+    /// Returns the names and addresses which have to wait before the next
+    /// login
+    pub fn lockouts(&self) -> Vec<Lockout> {
+        let now = Instant::now();
+        let mut lockouts: Vec<_> = self.failures.iter().filter_map(|(key, failures)| {
+            self.wait_for(key, now).map(|wait| Lockout {
+                key: key.clone(),
+                failures: failures.count,
+                locked: self.locks_out(failures),
+                wait: wait,
+            })
+        }).collect();
+        lockouts.sort_by(|a, b| b.wait.cmp(&a.wait));
+        lockouts
+    }
+
+    fn count(&mut self, key: Key, now: Instant) {
+        if !self.failures.contains_key(&key) && self.failures.len() >= self.capacity {
+            let oldest = self.failures.iter().min_by_key(|&(_, failures)| failures.last)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.failures.remove(&oldest);
+            }
+        }
+        let failures = self.failures.entry(key).or_insert(Failures { count: 0, last: now });
+        failures.count += 1;
+        failures.last = now;
+    }
+
+    fn locks_out(&self, failures: &Failures) -> bool {
+        self.settings.max_failures > 0 && failures.count >= self.settings.max_failures
+    }
+
+    // the end of the wait after the last failure
+    fn until(&self, failures: &Failures) -> Instant {
+        if self.locks_out(failures) {
+            return failures.last + self.settings.lockout
+        }
+        let factor = 1u32 << min(failures.count.saturating_sub(1), 16);
+        let delay = self.settings.delay.checked_mul(factor).unwrap_or(self.settings.max_delay);
+        failures.last + min(delay, self.settings.max_delay)
+    }
+
+    fn wait_for(&self, key: &Key, now: Instant) -> Option<Duration> {
+        match self.failures.get(key).map(|failures| self.until(failures)) {
+            Some(until) if until > now => Some(until.duration_since(now)),
+            _ => None,
+        }
+    }
+
+    // forgets the failures which are older than the lockout time
+    fn forget_expired(&mut self, now: Instant) {
+        let lockout = self.settings.lockout;
+        let expired: Vec<Key> = self.failures.iter()
+            .filter(|&(_, failures)| now.duration_since(failures.last) >= lockout)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            self.failures.remove(&key);
+        }
+    }
+}
+
+#[test]
+fn test_throttle() {
+    let mut throttle = Throttle::new(Settings {
+        delay: Duration::from_secs(10),
+        max_delay: Duration::from_secs(30),
+        max_failures: 3,
+        lockout: Duration::from_secs(600),
+    });
+    let address = "10.0.0.1".parse().ok();
+    assert!(throttle.wait("elena", address).is_none());
+
+    // the wait doubles with every failure
+    throttle.failed("elena", address);
+    assert!(throttle.wait("elena", None).unwrap() <= Duration::from_secs(10));
+    throttle.failed("elena", address);
+    assert!(throttle.wait("elena", None).unwrap() > Duration::from_secs(10));
+
+    // the address has to wait for every name
+    assert!(throttle.wait("peter", address).is_some());
+    assert!(throttle.wait("peter", None).is_none());
+
+    throttle.failed("elena", address);
+    let lockouts = throttle.lockouts();
+    assert_eq!(lockouts.len(), 2);
+    assert!(lockouts.iter().all(|l| l.locked && l.failures == 3));
+    assert!(lockouts[0].wait > Duration::from_secs(30));
+
+    // a login starts the count of the name again, not of the address
+    throttle.succeeded("elena");
+    assert!(throttle.wait("elena", None).is_none());
+    assert!(throttle.wait("elena", address).is_some());
+}
+
+#[test]
+fn test_throttle_bounds() {
+    // doubling a long delay does not overflow
+    let mut throttle = Throttle::new(Settings {
+        delay: Duration::from_secs(u64::max_value() / 4),
+        max_delay: Duration::from_secs(30),
+        max_failures: 0,
+        lockout: Duration::from_secs(600),
+    });
+    for _ in 0..4 {
+        throttle.failed("elena", None);
+    }
+    assert!(throttle.wait("elena", None).unwrap() <= Duration::from_secs(30));
+
+    // the name without a failure for the longest time is forgotten
+    throttle.capacity = 2;
+    throttle.failed("peter", None);
+    throttle.failed("elena", None);
+    throttle.failed("maria", None);
+    assert_eq!(throttle.failures.len(), 2);
+    assert!(throttle.wait("peter", None).is_none());
+    assert!(throttle.wait("elena", None).is_some());
+    assert!(throttle.wait("maria", None).is_some());
+}
@@ -1,52 +1,473 @@
 //! Contains the entry point code for handling an incoming connection.
 //!
-use std::net::TcpStream;
 use net;
+use net::tls::{self, Socket, Stream};
+use audit;
 use auth;
 use parse;
-use super::query;
+use shutdown;
+use super::query::{self, ResultSink, ExecutionError};
+use parse::ast::{ManipulationStmt, Query};
 use net::types::*;
-use storage::{ResultSet};
-use storage::types::{SqlType, Column};
+use storage::types::Column;
+use bincode::rustc_serialize::DecodingError;
 use std::error::Error;
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::atomic::{AtomicBool, AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
+use std::thread;
+use std::time::Duration;
 
-pub fn handle(mut stream: TcpStream) {
-    // Logging about the new connection
-    let addr = stream.peer_addr()
-        .map(|a| a.to_string())
-        .unwrap_or("???".into());
-    info!("Handling connection from {}", addr);
+/// Prepared statements a connection may keep at once
+const MAX_STATEMENTS: usize = 1024;
 
-    // Perform handshake, check user login.
-    let res = net::do_handshake(&mut stream);
-
-    let mut user;
-    match res {
-        Ok((name, pw)) => {
-            info!("Connection established. Handshake sent");
-            user = match auth::find_user(&name, &pw) {
-                Ok(u) => {
-                    match net::send_info_package(&mut stream,
-                        PkgType::AccGranted)
-                    {
-                        Ok(_) => u,
-                        Err(e) => { error!("{}", e.description()); return }
-                    }
+/// Numbers the sessions of all connections
+static NEXT_SESSION: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// The open sessions: id -> (cancel key, cancel flag). Another connection
+/// knowing the id and the key can cancel the running query of a session.
+pub type Sessions = Arc<Mutex<HashMap<u64, (u64, Arc<AtomicBool>)>>>;
+
+/// The sockets of the open sessions by session id, to close them when the
+/// server stops
+pub type Sockets = Arc<Mutex<HashMap<u64, Socket>>>;
+
+/// Removes the session from the open sessions when the connection ends
+struct Registration {
+    sessions: Sessions,
+    sockets: Sockets,
+    id: u64,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.remove(&self.id);
+        }
+        if let Ok(mut sockets) = self.sockets.lock() {
+            sockets.remove(&self.id);
+        }
+    }
+}
+
+/// The open connections: user name -> number of connections
+pub type UserConnections = Arc<Mutex<HashMap<String, u32>>>;
+
+/// Removes a connection of the user when it ends
+struct UserRegistration {
+    connections: UserConnections,
+    name: String,
+}
+
+impl Drop for UserRegistration {
+    fn drop(&mut self) {
+        let mut connections = self.connections.lock().unwrap_or_else(|e| e.into_inner());
+        let last = match connections.get_mut(&self.name) {
+            Some(count) => {
+                *count -= 1;
+                *count == 0
+            },
+            None => false,
+        };
+        if last {
+            connections.remove(&self.name);
+        }
+    }
+}
+
+/// Counts a connection of the user, None if the user already has as many
+/// connections as allowed (0 allows any number)
+fn register_user(connections: &UserConnections, name: &str, max: u32)
+    -> Option<UserRegistration>
+{
+    let mut connections_of = connections.lock().unwrap_or_else(|e| e.into_inner());
+    let count = connections_of.entry(name.into()).or_insert(0);
+    if max > 0 && *count >= max {
+        return None
+    }
+    *count += 1;
+    Some(UserRegistration { connections: connections.clone(), name: name.into() })
+}
+
+/// Limits of the connections, taken from the server configuration
+#[derive(Debug, Clone)]
+pub struct Limits {
+    // connections handled at once, one worker thread each
+    pub max_connections: usize,
+    // connections a single user may have open, 0 for no limit
+    pub max_user_connections: u32,
+    // connections without a command for this long are closed
+    pub idle_timeout: Option<Duration>,
+    // the handshake and the login have to be done within this time, so
+    // clients that never log in do not keep the workers busy
+    pub login_timeout: Duration,
+}
+
+/// What all connections share
+#[derive(Clone)]
+pub struct Shared {
+    pub settings: query::Settings,
+    // running queries can be cancelled from other connections
+    pub sessions: Sessions,
+    pub users: auth::Users,
+    pub tls: Option<Arc<tls::Acceptor>>,
+    pub audit: Option<Arc<audit::AuditLog>>,
+    pub user_connections: UserConnections,
+    pub limits: Limits,
+    pub sockets: Sockets,
+}
+
+/// Ends reading on all connections. Idle connections are closed at once,
+/// running statements may finish and send their result.
+pub fn close_connections(shared: &Shared) {
+    let sockets = shared.sockets.lock().unwrap_or_else(|e| e.into_inner());
+    for socket in sockets.values() {
+        if let Err(e) = socket.shutdown_read() {
+            debug!("Could not close a connection: {}", e);
+        }
+    }
+}
+
+/// Cancels the running statements of all connections
+pub fn cancel_statements(shared: &Shared) {
+    let sessions = shared.sessions.lock().unwrap_or_else(|e| e.into_inner());
+    for &(_, ref flag) in sessions.values() {
+        flag.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Ends reading from the socket unless the login is done within the
+/// timeout, so a client sending single bytes can not stretch it either.
+/// Dropping the returned sender stops the watch.
+fn login_deadline(socket: Socket, timeout: Duration) -> mpsc::Sender<()> {
+    let (done, wait) = mpsc::channel();
+    thread::spawn(move || {
+        if let Err(RecvTimeoutError::Timeout) = wait.recv_timeout(timeout) {
+            info!("Login was not done in time. Connection closed.");
+            let _ = socket.shutdown_read();
+        }
+    });
+    done
+}
+
+/// Returns a random key for a session, it can not be guessed from the id
+fn cancel_key() -> io::Result<u64> {
+    let bytes = try!(auth::scram::random_bytes(8));
+    Ok(bytes.iter().fold(0, |key, &byte| key << 8 | byte as u64))
+}
+
+/// Sets the cancel flag of the session if the key is right
+fn cancel_session(sessions: &Sessions, id: u64, key: u64) -> bool {
+    let sessions = match sessions.lock() {
+        Ok(sessions) => sessions,
+        Err(_) => return false,
+    };
+    match sessions.get(&id) {
+        Some(&(ref k, ref flag)) if *k == key => {
+            flag.store(true, Ordering::SeqCst);
+            true
+        },
+        _ => false,
+    }
+}
+
+/// Sends the result of a query to the client while it is produced
+struct ResponseStream<'s> {
+    stream: &'s mut Stream,
+    // bytes of a row and rows sent, for the audit log
+    row_size: usize,
+    rows: Option<u64>,
+}
+
+impl<'s> ResultSink for ResponseStream<'s> {
+    fn columns(&mut self, columns: &[Column]) -> Result<(), ExecutionError> {
+        self.row_size = columns.iter().fold(0, |size, c| size + c.get_size() as usize);
+        if self.row_size > 0 {
+            self.rows = Some(0);
+        }
+        net::send_response_columns(&mut *self.stream, columns).map_err(|e| {
+            warn!("Failed to send packet: {}", e);
+            ExecutionError::SendFailed
+        })
+    }
+
+    fn batch(&mut self, data: &[u8]) -> Result<(), ExecutionError> {
+        if let Some(ref mut rows) = self.rows {
+            *rows += (data.len() / self.row_size) as u64;
+        }
+        net::send_response_batch(&mut *self.stream, data).map_err(|e| {
+            warn!("Failed to send packet: {}", e);
+            ExecutionError::SendFailed
+        })
+    }
+}
+
+/// Executes the query and sends the result, the rows are sent while they
+/// are produced. The statement is recorded in the audit log with its text.
+/// Returns false if the query failed.
+fn execute_query(stream: &mut Stream, tree: Query, text: &str, user: &mut auth::User,
+    session: &mut query::Session, auditor: &audit::Auditor) -> bool
+{
+    let class = audit::Class::of(&tree);
+    let text = audit::redact(&tree, text);
+    // the tree itself may hold a password
+    debug!("{}", text);
+    let database = user._currentDatabase.as_ref().map(|base| base.name.clone());
+    // an insert always adds one row
+    let inserted = match tree {
+        Query::ManipulationStmt(ManipulationStmt::Insert(_)) => Some(1),
+        _ => None,
+    };
+
+    let (res, rows) = {
+        let mut sink = ResponseStream { stream: &mut *stream, row_size: 0, rows: None };
+        let res = query::execute_streamed(tree, user, session, &mut sink);
+        (res, sink.rows.or(inserted))
+    };
+
+    debug!("{:?}", res);
+
+    // the client is gone
+    let send_failed = match res {
+        Err(ExecutionError::SendFailed) => true,
+        _ => false,
+    };
+    let res = res.map(|_| rows).map_err(ClientErrMsg::from);
+    auditor.statement(&user.name, database.as_ref().map(|base| &base[..]), class, &text,
+        res.as_ref().map(|&rows| rows).map_err(|e| e.code));
+    if send_failed {
+        return false
+    }
+
+    let (sent, ok) = match res {
+        Ok(_) => (net::send_response_end(stream), true),
+        // the client reads the error instead of the result or, if it
+        // already reads rows, instead of the next batch
+        Err(error) => (net::send_error_package(stream, error), false),
+    };
+    match sent {
+        Ok(_) => ok,
+        Err(_) => {
+            warn!("Failed to send packet.");
+            false
+        }
+    }
+}
+
+/// Sends the syntax error and records the statement in the audit log
+fn send_parse_error(stream: &mut Stream, error: parse::parser::ParseError, text: &str,
+    user: &auth::User, auditor: &audit::Auditor)
+{
+    error!("{:?}", error);
+    let msg = ClientErrMsg::from(net::Error::UnEoq(error));
+    auditor.statement(&user.name, user._currentDatabase.as_ref().map(|base| &base.name[..]),
+        audit::Class::Invalid, &audit::redact_invalid(text), Err(msg.code));
+    match net::send_error_package(stream, msg) {
+        Ok(_) => {},
+        Err(_) => warn!("Failed to send error.")
+    }
+}
+
+fn send_unknown_statement(stream: &mut Stream) {
+    match net::send_error_package(stream, net::Error::UnknownStatement.into()) {
+        Ok(_) => {},
+        Err(_) => warn!("Failed to send error.")
+    }
+}
+
+/// Performs the handshake, TLS if the client asks for it and the
+/// challenge-response login, the password never crosses the wire. The
+/// session id and the cancel key are only sent once the login succeeded.
+/// Returns None if the login failed. Logins of known names are recorded in
+/// the audit log. The connection counts for the user as long as the
+/// returned registration lives.
+fn login(mut stream: Stream, shared: &Shared, session_id: u64, key: u64,
+    auditor: &audit::Auditor) -> Option<(Stream, auth::User, UserRegistration)>
+{
+    let users = &shared.users;
+    let nonce = match auth::scram::random_bytes(auth::scram::NONCE_LEN) {
+        Ok(nonce) => nonce,
+        Err(e) => {
+            error!("Could not create a nonce: {}", e);
+            return None
+        }
+    };
+    let address = stream.peer_addr().map(|addr| addr.ip());
+    // TLS is neither offered nor required over a Unix socket
+    let tls = if stream.is_unix() { None } else { shared.tls.as_ref().map(|tls| &**tls) };
+    let start = net::do_handshake(&mut stream, nonce.clone(), tls.is_some());
+    let (mut stream, start) = match (start, tls, stream) {
+        // the rest of the login is encrypted
+        (Ok(net::Start::Tls), Some(acceptor), Stream::Plain(stream)) =>
+            match acceptor.accept(stream) {
+                Ok(mut stream) => {
+                    let start = net::read_start(&mut stream);
+                    (stream, start)
                 },
-                Err(_) => {
-                    let _ =
-                        net::send_info_package(&mut stream, PkgType::AccDenied);
-                    error!("Authentication failed. Connection closed.");
-                    return
+                Err(e) => {
+                    error!("TLS handshake failed: {}. Connection closed.", e);
+                    return None
                 }
-            }
+            },
+        (start, _, stream) => (stream, start),
+    };
+    let start = match start {
+        Ok(net::Start::Auth(start)) => start,
+        Err(net::Error::ProtocolVersion) => {
+            let _ = net::send_error_package(&mut stream, net::Error::ProtocolVersion.into());
+            error!("Client uses a protocol older than version {}. Connection closed.",
+                net::PROTOCOL_VERSION);
+            return None
         },
-        _ => {
+        // TLS is not offered or already running
+        Ok(net::Start::Tls) | Err(_) => {
             let _ = net::send_info_package(&mut stream, PkgType::AccDenied);
             error!("Authentication failed. Connection closed.");
-            return
+            return None
         }
     };
+    if !stream.is_tls() && tls.map_or(false, |tls| tls.required) {
+        let _ = net::send_error_package(&mut stream, net::Error::TlsRequired.into());
+        error!("Client did not start TLS, which is required. Connection closed.");
+        return None
+    }
+    info!("Connection established. Handshake sent");
+
+    // a name or address with too many failed logins is refused right away
+    let throttled = auth::check_throttle(&auth::lock(users), &start.username, address);
+    if let Err(e) = throttled {
+        let msg = ClientErrMsg::from(e);
+        auditor.login(&start.username, Err(msg.code));
+        let _ = net::send_error_package(&mut stream, msg);
+        error!("Too many failed logins of '{}'. Connection closed.", start.username);
+        return None
+    }
+
+    let (salt, iterations) = auth::lock(users).challenge(&start.username);
+    let challenge = AuthChallenge { salt: salt, iterations: iterations };
+    let proof = match net::send_challenge(&mut stream, challenge)
+        .and_then(|_| net::read_proof(&mut stream))
+    {
+        Ok(proof) => proof,
+        Err(e) => {
+            error!("{}", e.description());
+            return None
+        }
+    };
+
+    let message = auth::scram::auth_message(&start.username, &nonce, &start.nonce);
+    match auth::login(users, &start.username, address, &message, &proof.proof) {
+        Ok((user, signature)) => {
+            let limit = shared.limits.max_user_connections;
+            let registration = match register_user(&shared.user_connections, &user.name, limit) {
+                Some(registration) => registration,
+                None => {
+                    let msg = ClientErrMsg::from(net::Error::TooManyUserConnections);
+                    auditor.login(&user.name, Err(msg.code));
+                    let _ = net::send_error_package(&mut stream, msg);
+                    error!("'{}' has {} connections open already. Connection closed.",
+                        user.name, limit);
+                    return None
+                }
+            };
+            auditor.login(&user.name, Ok(()));
+            let granted = AccessGranted {
+                signature: signature,
+                session_id: session_id,
+                cancel_key: key,
+            };
+            match net::send_acc_granted(&mut stream, granted) {
+                Ok(_) => Some((stream, user, registration)),
+                Err(e) => {
+                    error!("{}", e.description());
+                    None
+                }
+            }
+        },
+        Err(e) => {
+            let _ = net::send_info_package(&mut stream, PkgType::AccDenied);
+            error!("Authentication of '{}' failed: {:?}. Connection closed.",
+                start.username, e);
+            auditor.login(&start.username, Err(ClientErrMsg::from(e).code));
+            None
+        }
+    }
+}
+
+pub fn handle(stream: Stream, shared: Shared) {
+    // Logging about the new connection
+    let addr = match stream.peer_addr() {
+        Some(a) => a.to_string(),
+        None if stream.is_unix() => "unix socket".into(),
+        None => "???".into(),
+    };
+    info!("Handling connection from {}", addr);
+    let auditor = audit::Auditor::new(shared.audit.clone(), addr);
+
+    // a client that does not log in does not keep the worker
+    if let Err(e) = stream.set_read_timeout(Some(shared.limits.login_timeout)) {
+        warn!("Could not set the login timeout: {}", e);
+    }
+    let login_watch = match stream.socket() {
+        Ok(socket) => Some(login_deadline(socket, shared.limits.login_timeout)),
+        Err(e) => {
+            warn!("Could not watch the login: {}", e);
+            None
+        },
+    };
+
+    let sessions = &shared.sessions;
+    let mut session = query::Session::new(shared.settings.clone());
+    let session_id = NEXT_SESSION.fetch_add(1, Ordering::SeqCst) as u64;
+    let key = match cancel_key() {
+        Ok(key) => key,
+        Err(e) => {
+            error!("Could not create a cancel key: {}", e);
+            return
+        },
+    };
+    if let Ok(mut sessions) = sessions.lock() {
+        sessions.insert(session_id, (key, session.cancel_flag()));
+    }
+    match stream.socket() {
+        Ok(socket) => {
+            let mut sockets = shared.sockets.lock().unwrap_or_else(|e| e.into_inner());
+            sockets.insert(session_id, socket);
+        },
+        Err(e) => warn!("Could not keep the socket of the connection: {}", e),
+    }
+    let _registration = Registration {
+        sessions: sessions.clone(),
+        sockets: shared.sockets.clone(),
+        id: session_id,
+    };
+
+    // the server began to stop after the socket was accepted, it is closed
+    // here as it may have been missed by `close_connections`
+    let mut stream = stream;
+    if shutdown::is_requested() {
+        let _ = net::send_error_package(&mut stream, net::Error::ShuttingDown.into());
+        return
+    }
+
+    // Perform handshake, check user login.
+    let (mut stream, mut user, _user_registration) =
+        match login(stream, &shared, session_id, key, &auditor) {
+            Some(login) => login,
+            None => return,
+        };
+    drop(login_watch);
+
+    // a quiet client does not keep the worker forever
+    if let Err(e) = stream.set_read_timeout(shared.limits.idle_timeout) {
+        warn!("Could not set the idle timeout: {}", e);
+    }
+
+    // prepared statements of this connection and their text by their handle
+    let mut statements = HashMap::<u32, (String, Query)>::new();
+    let mut next_statement = 0;
 
     // Read commands from the client (with help of `net`)
     loop {
@@ -76,49 +497,161 @@ pub fn handle(mut stream: TcpStream) {
                         Err(_) => warn!("Failed to send packet.")
                     }
                 },
+                // cancel the running query of another session
+                Command::Cancel(id, key) => {
+                    let sent = if cancel_session(sessions, id, key) {
+                        info!("Cancel request for session {}", id);
+                        net::send_info_package(&mut stream, PkgType::Ok)
+                    } else {
+                        net::send_error_package(&mut stream,
+                            net::Error::UnknownSession.into())
+                    };
+                    match sent {
+                        Ok(_) => { },
+                        Err(_) => warn!("Failed to send packet.")
+                    }
+                },
                 // send the query string for parsing
                 Command::Query(q) => {
 
                     debug!("Query received, dispatch query to parser.");
 
                     // Call parser to obtain AST
-                    let ast = parse::parse(&q);
-
-                    match ast {
+                    match parse::parse(&q) {
                         Ok(tree) => {
-                            debug!("{:?}", tree);
-
-                            // Pass AST to query executer
-                            let mut r2 = query::execute_from_ast(tree, &mut user);
-
-                            debug!("{:?}", r2);
-
-                            let r = r2.unwrap_or(
-                                    ResultSet { data: vec![], columns: vec![
-                                        Column::new("error", SqlType::Int, false,
-                                        "error mind the error, not an error again, I hate errors",
-                                        false)]
-                                    }
-                                );
-                            // Send response package
-                            match net::send_response_package(&mut stream, r) {
-                                Ok(_) => { },
-                                Err(_) => warn!("Failed to send packet.")
-                            }
+                            execute_query(&mut stream, tree, &q, &mut user, &mut session,
+                                &auditor);
                         },
-
-                        Err(error) => {
-                            error!("{:?}", error);
-                            match net::send_error_package(&mut stream,
-                                net::Error::UnEoq(error).into())
-                            {
-                                Ok(_) => {},
-                                Err(_) => warn!("Failed to send error.")
+                        Err(error) => send_parse_error(&mut stream, error, &q, &user, &auditor),
+                    }
+                    continue
+                },
+                // execute the statements in order, every one gets its own
+                // result
+                Command::Script(script, keep_going) => {
+                    for (text, tree) in parse::parse_script_statements(&script) {
+                        let ok = match tree {
+                            Ok(tree) => execute_query(&mut stream, tree, text, &mut user,
+                                &mut session, &auditor),
+                            Err(error) => {
+                                send_parse_error(&mut stream, error, text, &user, &auditor);
+                                false
                             }
+                        };
+                        if !ok && !keep_going {
+                            break
                         }
                     }
-                    continue
-                }
+                    match net::send_info_package(&mut stream, PkgType::ScriptEnd) {
+                        Ok(_) => { },
+                        Err(_) => warn!("Failed to send packet.")
+                    }
+                },
+                // parse the query once and keep it for later executions
+                Command::Prepare(q) => {
+                    if statements.len() >= MAX_STATEMENTS {
+                        let error = net::Error::TooManyStatements.into();
+                        match net::send_error_package(&mut stream, error) {
+                            Ok(_) => {},
+                            Err(_) => warn!("Failed to send error.")
+                        }
+                        continue
+                    }
+                    let tree = match parse::parse(&q) {
+                        Ok(tree) => tree,
+                        Err(error) => {
+                            send_parse_error(&mut stream, error, &q, &user, &auditor);
+                            continue
+                        }
+                    };
+                    let sent = match query::parameter_types(&tree, &mut user, &mut session) {
+                        Ok(params) => {
+                            next_statement += 1;
+                            statements.insert(next_statement, (q, tree));
+                            net::send_prepared_package(&mut stream, PreparedStmt {
+                                handle: next_statement,
+                                params: params,
+                            })
+                        },
+                        Err(error) => net::send_error_package(&mut stream, error.into()),
+                    };
+                    match sent {
+                        Ok(_) => { },
+                        Err(_) => warn!("Failed to send packet.")
+                    }
+                },
+                // bind the values to a prepared statement and execute it
+                Command::Execute(handle, values) => {
+                    let (text, mut tree) = match statements.get(&handle) {
+                        Some(&(ref text, ref tree)) => (text.clone(), tree.clone()),
+                        None => {
+                            send_unknown_statement(&mut stream);
+                            continue
+                        }
+                    };
+                    match query::bind(&mut tree, &values) {
+                        Ok(_) => {
+                            // the audit log shows the values the statement ran with
+                            let text = format!("{} -- {:?}", text, values);
+                            execute_query(&mut stream, tree, &text, &mut user, &mut session,
+                                &auditor);
+                        },
+                        Err(error) => match net::send_error_package(&mut stream, error.into()) {
+                            Ok(_) => { },
+                            Err(_) => warn!("Failed to send packet.")
+                        },
+                    }
+                },
+                // stop the server, the connections end as for a signal
+                Command::Shutdown => {
+                    let database = user._currentDatabase.as_ref().map(|base| base.name.clone());
+                    let sent = if user.is_admin() {
+                        info!("Shutdown requested by '{}'", user.name);
+                        auditor.statement(&user.name, database.as_ref().map(|d| &d[..]),
+                            audit::Class::Session, "SHUTDOWN", Ok(None));
+                        shutdown::request();
+                        net::send_info_package(&mut stream, PkgType::Ok)
+                    } else {
+                        let msg = ClientErrMsg::from(ExecutionError::PermissionDenied);
+                        auditor.statement(&user.name, database.as_ref().map(|d| &d[..]),
+                            audit::Class::Session, "SHUTDOWN", Err(msg.code));
+                        net::send_error_package(&mut stream, msg)
+                    };
+                    match sent {
+                        Ok(_) => { },
+                        Err(_) => warn!("Failed to send packet.")
+                    }
+                },
+                Command::Deallocate(handle) => {
+                    if statements.remove(&handle).is_none() {
+                        send_unknown_statement(&mut stream);
+                        continue
+                    }
+                    match net::send_info_package(&mut stream, PkgType::Ok) {
+                        Ok(_) => { },
+                        Err(_) => warn!("Failed to send packet.")
+                    }
+                },
+            },
+            // the client sent nothing for too long
+            Err(net::Error::Decode(DecodingError::IoError(ref e)))
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                info!("Connection was idle for too long. Connection closed.");
+                let _ = net::send_error_package(&mut stream, net::Error::IdleTimeout.into());
+                return
+            },
+            // the server is stopping and ended reading
+            Err(net::Error::Decode(_)) if shutdown::is_requested() => {
+                info!("Server is shutting down. Connection closed.");
+                let _ = net::send_error_package(&mut stream, net::Error::ShuttingDown.into());
+                return
+            },
+            // the connection is gone or the rest of the stream can not be
+            // told apart anymore
+            Err(net::Error::Decode(e)) => {
+                debug!("Client disconnected: {}", e);
+                return
             },
             Err(_) => continue // TODO: error handling
         }
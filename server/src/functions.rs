@@ -0,0 +1,388 @@
+//! Built-in scalar functions
+//!
+//! Every function has a type rule, which computes the type of the result
+//! from the types of the arguments, and an implementation working on single
+//! values. A value of `None` stands for NULL.
+//!
+
+use super::parse::token::Lit;
+use super::storage::types::SqlType;
+use std::cmp::{max, min};
+
+/// A single value, `None` is NULL
+pub type Value = Option<Lit>;
+
+/// A built-in function
+pub struct Function {
+    pub name: &'static str,
+    pub min_args: usize,
+    // None: any number of arguments
+    pub max_args: Option<usize>,
+    result_type: fn(&[SqlType]) -> Result<SqlType, FunctionError>,
+    call: fn(&[Value]) -> Result<Value, FunctionError>,
+}
+
+impl Function {
+    /// Checks the arguments and returns the type of the result
+    pub fn result_type(&self, args: &[SqlType]) -> Result<SqlType, FunctionError> {
+        if args.len() < self.min_args || self.max_args.map_or(false, |m| args.len() > m) {
+            return Err(FunctionError::WrongArgumentCount(self.name.to_string()))
+        }
+        (self.result_type)(args).map_err(|e| match e {
+            FunctionError::WrongArgumentType(_) =>
+                FunctionError::WrongArgumentType(self.name.to_string()),
+            e => e,
+        })
+    }
+
+    /// Calls the function, the arguments have to match `result_type`
+    pub fn call(&self, args: &[Value]) -> Result<Value, FunctionError> {
+        (self.call)(args)
+    }
+}
+
+static FUNCTIONS: &'static [Function] = &[
+    Function { name: "upper", min_args: 1, max_args: Some(1),
+        result_type: case_type, call: upper },
+    Function { name: "lower", min_args: 1, max_args: Some(1),
+        result_type: case_type, call: lower },
+    Function { name: "trim", min_args: 1, max_args: Some(1),
+        result_type: string_type, call: trim },
+    Function { name: "length", min_args: 1, max_args: Some(1),
+        result_type: length_type, call: length },
+    Function { name: "substr", min_args: 2, max_args: Some(3),
+        result_type: substr_type, call: substr },
+    Function { name: "concat", min_args: 1, max_args: None,
+        result_type: concat_type, call: concat },
+    Function { name: "abs", min_args: 1, max_args: Some(1),
+        result_type: int_type, call: abs },
+    Function { name: "round", min_args: 1, max_args: Some(2),
+        result_type: int_type, call: round },
+    Function { name: "mod", min_args: 2, max_args: Some(2),
+        result_type: int_type, call: modulo },
+    Function { name: "coalesce", min_args: 1, max_args: None,
+        result_type: common_type, call: coalesce },
+    Function { name: "nullif", min_args: 2, max_args: Some(2),
+        result_type: nullif_type, call: nullif },
+];
+
+/// Returns the function with the given name, ignoring the case
+pub fn lookup(name: &str) -> Result<&'static Function, FunctionError> {
+    let name = name.to_lowercase();
+    match FUNCTIONS.iter().find(|f| f.name == name) {
+        Some(function) => Ok(function),
+        None => Err(FunctionError::UnknownFunction(name)),
+    }
+}
+
+/// Returns the type all values can be converted to, char types get the
+/// largest length
+pub fn common_type(types: &[SqlType]) -> Result<SqlType, FunctionError> {
+    let mut result = types[0].clone();
+    for t in &types[1..] {
+        result = match (&result, t) {
+            (&SqlType::Char(a), &SqlType::Char(b)) => SqlType::Char(max(a, b)),
+            (a, b) if a == b => a.clone(),
+            _ => return Err(FunctionError::WrongArgumentType("".into())),
+        };
+    }
+    Ok(result)
+}
+
+/// Returns the char type able to hold a string of the given length,
+/// char columns keep one byte for the terminating NUL
+pub fn char_type(len: usize) -> SqlType {
+    SqlType::Char(min(len + 1, 255) as u8)
+}
+
+// --------------------------------------------------------------------------
+// Type rules
+// --------------------------------------------------------------------------
+
+fn string_type(args: &[SqlType]) -> Result<SqlType, FunctionError> {
+    match args[0] {
+        SqlType::Char(_) => Ok(args[0].clone()),
+        _ => Err(FunctionError::WrongArgumentType("".into())),
+    }
+}
+
+/// Changing the case can make a string longer, a character takes up to
+/// three times its bytes afterwards ('\u{390}' becomes three characters)
+fn case_type(args: &[SqlType]) -> Result<SqlType, FunctionError> {
+    match args[0] {
+        SqlType::Char(n) => Ok(char_type((max(n, 1) as usize - 1) * 3)),
+        _ => Err(FunctionError::WrongArgumentType("".into())),
+    }
+}
+
+fn length_type(args: &[SqlType]) -> Result<SqlType, FunctionError> {
+    try!(string_type(args));
+    Ok(SqlType::Int)
+}
+
+fn substr_type(args: &[SqlType]) -> Result<SqlType, FunctionError> {
+    if args[1..].iter().any(|t| *t != SqlType::Int) {
+        return Err(FunctionError::WrongArgumentType("".into()))
+    }
+    string_type(args)
+}
+
+fn concat_type(args: &[SqlType]) -> Result<SqlType, FunctionError> {
+    // length of the text form of every argument
+    let len = args.iter().fold(0, |acc, t| acc + match t {
+        &SqlType::Char(n) => max(n, 1) as usize - 1,
+        &SqlType::Int => 11,
+        &SqlType::Bool => 5,
+    });
+    Ok(char_type(len))
+}
+
+fn int_type(args: &[SqlType]) -> Result<SqlType, FunctionError> {
+    if args.iter().any(|t| *t != SqlType::Int) {
+        return Err(FunctionError::WrongArgumentType("".into()))
+    }
+    Ok(SqlType::Int)
+}
+
+fn nullif_type(args: &[SqlType]) -> Result<SqlType, FunctionError> {
+    try!(common_type(args));
+    Ok(args[0].clone())
+}
+
+// --------------------------------------------------------------------------
+// Implementations
+// --------------------------------------------------------------------------
+
+fn upper(args: &[Value]) -> Result<Value, FunctionError> {
+    map_string(&args[0], |s| s.to_uppercase())
+}
+
+fn lower(args: &[Value]) -> Result<Value, FunctionError> {
+    map_string(&args[0], |s| s.to_lowercase())
+}
+
+fn trim(args: &[Value]) -> Result<Value, FunctionError> {
+    map_string(&args[0], |s| s.trim().to_string())
+}
+
+fn length(args: &[Value]) -> Result<Value, FunctionError> {
+    match args[0] {
+        Some(Lit::String(ref s)) => Ok(Some(Lit::Int(s.chars().count() as i64))),
+        None => Ok(None),
+        _ => Err(FunctionError::WrongArgumentType("length".into())),
+    }
+}
+
+/// SUBSTR(s, start [, count]), start counts from 1
+fn substr(args: &[Value]) -> Result<Value, FunctionError> {
+    if args.iter().any(|a| a.is_none()) {
+        return Ok(None)
+    }
+    let start = max(try!(int_arg(&args[1], "substr")), 1) as usize - 1;
+    let count = match args.get(2) {
+        Some(count) => max(try!(int_arg(count, "substr")), 0) as usize,
+        None => usize::max_value(),
+    };
+    map_string(&args[0], |s| s.chars().skip(start).take(count).collect())
+}
+
+/// CONCAT is NULL if any argument is NULL
+fn concat(args: &[Value]) -> Result<Value, FunctionError> {
+    let mut result = String::new();
+    for arg in args {
+        match arg {
+            &Some(Lit::String(ref s)) => result.push_str(s),
+            &Some(Lit::Int(i)) => result.push_str(&i.to_string()),
+            &Some(Lit::Bool(b)) => result.push_str(if b != 0 { "true" } else { "false" }),
+            &Some(Lit::Float(f)) => result.push_str(&f.to_string()),
+            &Some(Lit::Param(n)) => result.push_str(&format!("${}", n)),
+            &None => return Ok(None),
+        }
+    }
+    Ok(Some(Lit::String(result)))
+}
+
+fn abs(args: &[Value]) -> Result<Value, FunctionError> {
+    match args[0] {
+        Some(_) => match try!(int_arg(&args[0], "abs")).checked_abs() {
+            Some(x) => Ok(Some(Lit::Int(x))),
+            None => Err(FunctionError::OutOfRange("abs".into())),
+        },
+        None => Ok(None),
+    }
+}
+
+/// ROUND(x [, digits]), only negative digits change integers:
+/// ROUND(1250, -2) = 1300
+fn round(args: &[Value]) -> Result<Value, FunctionError> {
+    if args.iter().any(|a| a.is_none()) {
+        return Ok(None)
+    }
+    let x = try!(int_arg(&args[0], "round"));
+    let digits = match args.get(1) {
+        Some(digits) => try!(int_arg(digits, "round")),
+        None => 0,
+    };
+    if digits >= 0 {
+        return Ok(Some(Lit::Int(x)))
+    }
+    // beyond 18 digits everything rounds to 0
+    if digits < -18 {
+        return Ok(Some(Lit::Int(0)))
+    }
+    let factor = 10i64.pow((-digits) as u32);
+    let rounded = match x.checked_abs().and_then(|x| x.checked_add(factor / 2)) {
+        Some(x) => x / factor * factor,
+        None => return Err(FunctionError::OutOfRange("round".into())),
+    };
+    Ok(Some(Lit::Int(if x < 0 { -rounded } else { rounded })))
+}
+
+fn modulo(args: &[Value]) -> Result<Value, FunctionError> {
+    if args.iter().any(|a| a.is_none()) {
+        return Ok(None)
+    }
+    let divisor = try!(int_arg(&args[1], "mod"));
+    if divisor == 0 {
+        return Err(FunctionError::DivisionByZero)
+    }
+    match try!(int_arg(&args[0], "mod")).checked_rem(divisor) {
+        Some(x) => Ok(Some(Lit::Int(x))),
+        None => Err(FunctionError::OutOfRange("mod".into())),
+    }
+}
+
+fn coalesce(args: &[Value]) -> Result<Value, FunctionError> {
+    Ok(args.iter().filter_map(|a| a.clone()).next())
+}
+
+fn nullif(args: &[Value]) -> Result<Value, FunctionError> {
+    if args[0] == args[1] {
+        Ok(None)
+    } else {
+        Ok(args[0].clone())
+    }
+}
+
+fn map_string<F>(value: &Value, f: F) -> Result<Value, FunctionError>
+    where F: Fn(&str) -> String
+{
+    match value {
+        &Some(Lit::String(ref s)) => Ok(Some(Lit::String(f(s)))),
+        &None => Ok(None),
+        _ => Err(FunctionError::WrongArgumentType("".into())),
+    }
+}
+
+fn int_arg(value: &Value, name: &str) -> Result<i64, FunctionError> {
+    match value {
+        &Some(Lit::Int(i)) => Ok(i),
+        _ => Err(FunctionError::WrongArgumentType(name.to_string())),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FunctionError {
+    UnknownFunction(String),
+    // the name of the function
+    WrongArgumentCount(String),
+    WrongArgumentType(String),
+    DivisionByZero,
+    // the result does not fit into an integer
+    OutOfRange(String),
+}
+
+#[cfg(test)]
+fn call(name: &str, args: Vec<Value>) -> Result<Value, FunctionError> {
+    lookup(name).unwrap().call(&args)
+}
+
+#[cfg(test)]
+fn string(s: &str) -> Value {
+    Some(Lit::String(s.into()))
+}
+
+#[cfg(test)]
+fn int(i: i64) -> Value {
+    Some(Lit::Int(i))
+}
+
+#[test]
+fn test_result_types() {
+    let char_5 = SqlType::Char(5);
+    assert_eq!(lookup("UPPER").unwrap().result_type(&[SqlType::Char(11)]), Ok(SqlType::Char(31)));
+    assert_eq!(lookup("trim").unwrap().result_type(&[char_5.clone()]), Ok(char_5.clone()));
+    assert_eq!(lookup("length").unwrap().result_type(&[char_5.clone()]), Ok(SqlType::Int));
+    assert_eq!(lookup("substr").unwrap().result_type(&[char_5.clone(), SqlType::Int]),
+        Ok(char_5.clone()));
+    assert_eq!(lookup("substr").unwrap().result_type(&[char_5.clone(), SqlType::Bool]),
+        Err(FunctionError::WrongArgumentType("substr".into())));
+    // 4 characters, 11 for an integer and 5 for a bool
+    assert_eq!(lookup("concat").unwrap()
+        .result_type(&[char_5.clone(), SqlType::Int, SqlType::Bool]), Ok(SqlType::Char(21)));
+    assert_eq!(lookup("abs").unwrap().result_type(&[char_5.clone()]),
+        Err(FunctionError::WrongArgumentType("abs".into())));
+    assert_eq!(lookup("mod").unwrap().result_type(&[SqlType::Int]),
+        Err(FunctionError::WrongArgumentCount("mod".into())));
+    assert_eq!(lookup("coalesce").unwrap().result_type(&[SqlType::Char(3), char_5.clone()]),
+        Ok(char_5.clone()));
+    assert!(lookup("coalesce").unwrap().result_type(&[SqlType::Int, SqlType::Bool]).is_err());
+    assert_eq!(lookup("nullif").unwrap().result_type(&[SqlType::Char(3), char_5.clone()]),
+        Ok(SqlType::Char(3)));
+    assert_eq!(lookup("nope").err(), Some(FunctionError::UnknownFunction("nope".into())));
+}
+
+#[test]
+fn test_calls() {
+    assert_eq!(call("upper", vec![string("abc")]), Ok(string("ABC")));
+    assert_eq!(call("lower", vec![None]), Ok(None));
+    assert_eq!(call("trim", vec![string("  a b ")]), Ok(string("a b")));
+    assert_eq!(call("length", vec![string("\u{e4}b")]), Ok(int(2)));
+    assert_eq!(call("substr", vec![string("hello"), int(2), int(3)]), Ok(string("ell")));
+    assert_eq!(call("substr", vec![string("hello"), int(0)]), Ok(string("hello")));
+    assert_eq!(call("substr", vec![string("hello"), int(2), int(-1)]), Ok(string("")));
+    assert_eq!(call("concat", vec![string("a"), int(1), Some(Lit::Bool(1))]),
+        Ok(string("a1true")));
+    assert_eq!(call("concat", vec![string("a"), None]), Ok(None));
+    assert_eq!(call("abs", vec![int(-5)]), Ok(int(5)));
+    assert_eq!(call("round", vec![int(1250), int(-2)]), Ok(int(1300)));
+    assert_eq!(call("round", vec![int(-1250), int(-2)]), Ok(int(-1300)));
+    assert_eq!(call("round", vec![int(1249), int(-2)]), Ok(int(1200)));
+    assert_eq!(call("round", vec![int(7), int(2)]), Ok(int(7)));
+    assert_eq!(call("round", vec![int(5), int(-19)]), Ok(int(0)));
+    assert_eq!(call("mod", vec![int(-7), int(3)]), Ok(int(-1)));
+    assert_eq!(call("mod", vec![int(1), int(0)]), Err(FunctionError::DivisionByZero));
+    assert_eq!(call("coalesce", vec![None, int(2), int(3)]), Ok(int(2)));
+    assert_eq!(call("nullif", vec![int(1), int(1)]), Ok(None));
+    assert_eq!(call("nullif", vec![int(1), int(2)]), Ok(int(1)));
+}
+
+#[test]
+fn test_integer_overflow() {
+    let min = i64::min_value();
+    assert_eq!(call("abs", vec![int(min)]), Err(FunctionError::OutOfRange("abs".into())));
+    assert_eq!(call("round", vec![int(min), int(-2)]),
+        Err(FunctionError::OutOfRange("round".into())));
+    assert_eq!(call("round", vec![int(i64::max_value()), int(-1)]),
+        Err(FunctionError::OutOfRange("round".into())));
+    assert_eq!(call("mod", vec![int(min), int(-1)]),
+        Err(FunctionError::OutOfRange("mod".into())));
+}
+
+#[test]
+fn test_case_change_fits_type() {
+    // these grow or shrink when their case changes
+    for c in &["\u{390}", "\u{df}", "\u{23f}", "\u{130}", "a"] {
+        let s = c.repeat(40 / c.len());
+        let n = match lookup("upper").unwrap().result_type(&[char_type(s.len())]).unwrap() {
+            SqlType::Char(n) => n as usize - 1,
+            _ => unreachable!(),
+        };
+        for name in &["upper", "lower"] {
+            match call(name, vec![string(&s)]) {
+                Ok(Some(Lit::String(ref r))) => assert!(r.len() <= n, "{} {}", name, c),
+                other => panic!("{:?}", other),
+            }
+        }
+    }
+}
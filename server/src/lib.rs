@@ -1,46 +1,309 @@
 extern crate bincode;
 extern crate byteorder;
+extern crate crypto;
+#[macro_use]
+extern crate lazy_static;
+extern crate libc;
 #[macro_use]
 extern crate log;
+extern crate openssl;
+extern crate rand;
 extern crate rustc_serialize;
 extern crate term_painter as term;
 
+pub mod audit;
 pub mod auth;
 pub mod conn;
+pub mod functions;
 pub mod logger;
 pub mod net;
 pub mod parse;
+pub mod pool;
 pub mod query;
+pub mod shutdown;
 pub mod storage;
 
-use std::net::{Ipv4Addr, SocketAddrV4};
+use net::tls::Stream;
+use std::io;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
 /// A struct for managing configurations
 #[derive(Debug)]
 pub struct Config {
-    pub address: Ipv4Addr,
-    pub port: u16,
-    pub dir: String
+    // TCP addresses to listen on, IPv4 or IPv6
+    pub addresses: Vec<SocketAddr>,
+    // path of a Unix socket to listen on as well
+    pub socket: Option<String>,
+    // permissions of the socket file, only users who may write to it can
+    // connect
+    pub socket_mode: u32,
+    pub dir: String,
+    // bytes a single query may keep in memory before it uses temporary files
+    pub memory_limit: u64,
+    // password of the admin account created on the first start
+    pub admin_password: Option<String>,
+    // PEM files of the certificate and its key, clients may use TLS if
+    // both are given
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    // logins without TLS are refused
+    pub require_tls: bool,
+    // logins and statements are recorded if this is given
+    pub audit: Option<audit::Settings>,
+    // how failed logins are slowed down and locked out
+    pub login_throttle: auth::throttle::Settings,
+    // how many connections are handled at once and for how long
+    pub limits: conn::Limits,
+    // time running statements get to finish when the server stops
+    pub shutdown_grace: Duration,
+}
+
+/// A connection waiting for a worker of the pool
+struct Connection {
+    stream: Stream,
+    shared: conn::Shared,
 }
 
-/// Listens for incoming TCP streams
+impl pool::Job for Connection {
+    fn run(self: Box<Self>) {
+        let connection = *self;
+        conn::handle(connection.stream, connection.shared)
+    }
+}
+
+/// Listens for incoming TCP streams on all addresses and for streams of the
+/// Unix socket, until the server is asked to stop (see `shutdown`)
 pub fn listen(config: Config) {
     use std::net::TcpListener;
-    use std::thread;
 
-    // Converting configurations to a valid socket address
-    let sock_addr = SocketAddrV4::new(config.address, config.port);
-    let listener = TcpListener::bind(sock_addr).unwrap();
+    // the accounts are kept in the data directory
+    let users = match auth::Catalog::open(Path::new(&config.dir),
+        config.admin_password.as_ref().map(|p| &p[..]), config.login_throttle.clone())
+    {
+        Ok(catalog) => Arc::new(Mutex::new(catalog)),
+        Err(e) => {
+            error!("Could not open the user catalog: {:?}", e);
+            return
+        }
+    };
+
+    // temporary files of queries are kept in the data directory
+    let settings = query::Settings {
+        memory_limit: config.memory_limit,
+        temp_dir: Path::new(&config.dir).join("tmp"),
+    };
+
+    // the certificate is loaded once for all connections
+    let tls = match (&config.tls_cert, &config.tls_key) {
+        (&Some(ref cert), &Some(ref key)) =>
+            match net::tls::Acceptor::new(Path::new(cert), Path::new(key), config.require_tls) {
+                Ok(acceptor) => Some(Arc::new(acceptor)),
+                Err(e) => {
+                    error!("Could not load the TLS certificate: {}", e);
+                    return
+                }
+            },
+        (&None, &None) if !config.require_tls => None,
+        _ => {
+            error!("TLS needs both a certificate and a key file");
+            return
+        }
+    };
+
+    // who ran what is recorded for all connections in one file
+    let audit = match config.audit {
+        Some(ref settings) => match audit::AuditLog::open(settings.clone()) {
+            Ok(log) => Some(Arc::new(log)),
+            Err(e) => {
+                error!("Could not open the audit log: {}", e);
+                return
+            }
+        },
+        None => None,
+    };
+
+    let shared = conn::Shared {
+        settings: settings,
+        sessions: conn::Sessions::default(),
+        users: users,
+        tls: tls,
+        audit: audit,
+        user_connections: conn::UserConnections::default(),
+        limits: config.limits.clone(),
+        sockets: conn::Sockets::default(),
+    };
+    // every connection is handled by a worker of the pool
+    let pool = Arc::new(pool::Pool::new(config.limits.max_connections));
+
+    // all addresses are bound before any connection is accepted
+    let mut tcp_listeners = Vec::new();
+    for addr in &config.addresses {
+        match TcpListener::bind(addr) {
+            Ok(listener) => tcp_listeners.push(listener),
+            Err(e) => {
+                error!("Could not listen on {}: {}", addr, e);
+                return
+            }
+        }
+    }
+    #[cfg(unix)]
+    let unix_listener = match config.socket {
+        Some(ref path) => match bind_socket(Path::new(path), config.socket_mode) {
+            Ok(listener) => Some(listener),
+            Err(e) => {
+                error!("Could not listen on the Unix socket {}: {}", path, e);
+                return
+            }
+        },
+        None => None,
+    };
+    #[cfg(not(unix))]
+    {
+        if config.socket.is_some() {
+            error!("Unix sockets are not supported on this system");
+            return
+        }
+    }
+
+    // every listener accepts its connections in its own thread. They do
+    // not block, so the listeners notice when the server is asked to stop.
+    let mut threads = Vec::new();
+    for listener in tcp_listeners {
+        if let Err(e) = listener.set_nonblocking(true) {
+            error!("Could not set up the listener: {}", e);
+            return
+        }
+        let shared = shared.clone();
+        let pool = pool.clone();
+        threads.push(thread::spawn(move || serve(listener.incoming(), shared, &pool)));
+    }
+    #[cfg(unix)]
+    if let Some(listener) = unix_listener {
+        if let Err(e) = listener.set_nonblocking(true) {
+            error!("Could not set up the listener: {}", e);
+            return
+        }
+        let shared = shared.clone();
+        let pool = pool.clone();
+        threads.push(thread::spawn(move || serve(listener.incoming(), shared, &pool)));
+    }
+    for thread in threads {
+        let _ = thread.join();
+    }
+
+    // no connection is accepted anymore
+    info!("Shutting down...");
+    conn::close_connections(&shared);
+    if !wait_for_connections(&pool, config.shutdown_grace) {
+        warn!("Cancelling the statements still running after {} seconds",
+            config.shutdown_grace.as_secs());
+        conn::cancel_statements(&shared);
+        if !wait_for_connections(&pool, Duration::from_secs(CANCEL_WAIT_SECS)) {
+            warn!("{} connections did not end, they are dropped", pool.busy());
+        }
+    }
+    if let Some(ref path) = config.socket {
+        let _ = std::fs::remove_file(path);
+    }
+    shutdown::sync(&shared.users);
+    info!("Server stopped");
+}
+
+/// Seconds cancelled statements get to end
+const CANCEL_WAIT_SECS: u64 = 5;
+
+/// Waits until all connections ended, false if some are still open after
+/// the timeout
+fn wait_for_connections(pool: &pool::Pool, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    while pool.busy() > 0 {
+        if Instant::now() >= deadline {
+            return false
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    true
+}
+
+/// Creates the Unix socket with the permissions. It is bound in a folder
+/// only the server may enter and moved into place once it has its mode, so
+/// nobody connects before. A socket left behind by an earlier run is
+/// replaced, one another server still listens on is not.
+#[cfg(unix)]
+fn bind_socket(path: &Path, mode: u32) -> io::Result<UnixListener> {
+    use std::fs::{self, DirBuilder};
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+    use std::os::unix::net::UnixStream;
+    use std::process;
+
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                "the file exists and is no socket"))
+        }
+        match UnixStream::connect(path) {
+            Ok(_) => return Err(io::Error::new(io::ErrorKind::AddrInUse,
+                "another server listens on the socket")),
+            Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused => {},
+            Err(e) => return Err(e),
+        }
+    }
 
-    // Accept connections and process them
-    for stream in listener.incoming() {
+    let name = match path.file_name() {
+        Some(name) => name.to_os_string(),
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+            "the socket path has no file name")),
+    };
+    let dir = path.with_file_name(format!(".{}.{}", name.to_string_lossy(), process::id()));
+    try!(DirBuilder::new().mode(0o700).create(&dir));
+    let tmp = dir.join(&name);
+    let bound = UnixListener::bind(&tmp).and_then(|listener| {
+        try!(fs::set_permissions(&tmp, fs::Permissions::from_mode(mode)));
+        // replaces a socket left behind at once
+        try!(fs::rename(&tmp, path));
+        Ok(listener)
+    });
+    let _ = fs::remove_file(&tmp);
+    let _ = fs::remove_dir(&dir);
+    bound
+}
+
+/// Accepts connections and hands them to the pool until the server is asked
+/// to stop. A connection is refused if all workers are busy.
+fn serve<S, I>(incoming: I, shared: conn::Shared, pool: &pool::Pool)
+    where S: Into<Stream>, I: Iterator<Item = io::Result<S>>
+{
+    for stream in incoming {
+        if shutdown::is_requested() {
+            return
+        }
         match stream {
             Ok(stream) => {
-                // Connection succeeded: Spawn thread and handle
-                thread::spawn(move|| {
-                    conn::handle(stream)
-                });
+                // the connection does not inherit the mode of the listener
+                // everywhere
+                let stream: Stream = stream.into();
+                if let Err(e) = stream.set_nonblocking(false) {
+                    warn!("Failed to set up incoming connection: {}", e);
+                    continue
+                }
+                // Connection succeeded: hand it to a worker
+                let connection = Connection { stream: stream, shared: shared.clone() };
+                if let Err(mut connection) = pool.execute(connection) {
+                    warn!("All {} workers are busy. Connection refused.",
+                        shared.limits.max_connections);
+                    let _ = net::send_error_package(&mut connection.stream,
+                        net::Error::TooManyConnections.into());
+                }
             },
+            // nothing to accept, look again soon
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock =>
+                thread::sleep(Duration::from_millis(100)),
             Err(e) => {
                 // Something went wrong...
                 warn!("Failed to accept incoming connection: {:?}", e);
@@ -48,3 +311,40 @@ pub fn listen(config: Config) {
         }
     }
 }
+
+#[cfg(unix)]
+#[test]
+fn test_bind_socket() {
+    use std::env;
+    use std::fs::{self, File};
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = env::temp_dir().join(format!("uosql-socket-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("uosql.sock");
+
+    let listener = bind_socket(&path, 0o660).unwrap();
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o660);
+    // nothing is left of the folder the socket was bound in
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    // a server listens on it, the socket is kept
+    match bind_socket(&path, 0o660) {
+        Err(ref e) if e.kind() == io::ErrorKind::AddrInUse => {},
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+    // a socket left behind is replaced
+    drop(listener);
+    let listener = bind_socket(&path, 0o600).unwrap();
+    assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    drop(listener);
+
+    // other files are never removed
+    fs::remove_file(&path).unwrap();
+    File::create(&path).unwrap();
+    assert!(bind_socket(&path, 0o660).is_err());
+    assert!(fs::metadata(&path).unwrap().is_file());
+    let _ = fs::remove_dir_all(&dir);
+}
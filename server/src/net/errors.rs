@@ -0,0 +1,223 @@
+//! Stable codes of the errors sent to the client
+//!
+//! Every error the client can see gets a number which never changes and a
+//! class in the style of SQLSTATE, so clients can react to errors without
+//! parsing messages. Syntax errors also carry the position of the failing
+//! token in the query.
+//!
+//! The codes are grouped: 0-99 protocol errors (see `types`), 100-199
+//! query execution and accounts, 200-299 storage and 300-399 syntax errors.
+//!
+
+use super::types::ClientErrMsg;
+use auth::AuthError;
+use functions::FunctionError;
+use parse::lex::{LexError, MAX_PARAM};
+use parse::parser::ParseError;
+use query::ExecutionError;
+use storage;
+
+const SYNTAX: &'static str = "42601";
+const UNDEFINED_TABLE: &'static str = "42P01";
+const UNDEFINED_COLUMN: &'static str = "42703";
+const UNDEFINED_FUNCTION: &'static str = "42883";
+const UNDEFINED_OBJECT: &'static str = "42704";
+const DUPLICATE_TABLE: &'static str = "42P07";
+const DUPLICATE_OBJECT: &'static str = "42710";
+const INSUFFICIENT_PRIVILEGE: &'static str = "42501";
+const INVALID_PASSWORD: &'static str = "28P01";
+const INVALID_AUTHORIZATION: &'static str = "28000";
+const INVALID_GRANT: &'static str = "0LP01";
+const INVALID_DEFINITION: &'static str = "42P17";
+const DATATYPE_MISMATCH: &'static str = "42804";
+const NO_DATABASE: &'static str = "3D000";
+const DATA: &'static str = "22000";
+const NULL_VALUE: &'static str = "22004";
+const DIVISION_BY_ZERO: &'static str = "22012";
+const OUT_OF_RANGE: &'static str = "22003";
+const INVALID_VALUE: &'static str = "22023";
+const CARDINALITY: &'static str = "21000";
+const UNIQUE_VIOLATION: &'static str = "23505";
+const PREREQUISITE: &'static str = "55000";
+const PARAMETERS: &'static str = "07001";
+const CONNECTION_FAILURE: &'static str = "08006";
+const IO: &'static str = "58030";
+const CORRUPTED: &'static str = "XX001";
+const INTERNAL: &'static str = "XX000";
+
+impl From<ExecutionError> for ClientErrMsg {
+    fn from(error: ExecutionError) -> ClientErrMsg {
+        let (code, class, msg) = match error {
+            ExecutionError::ParseError(e) => return e.into(),
+            ExecutionError::StorageError(e) => return e.into(),
+            ExecutionError::FunctionError(e) => return e.into(),
+            ExecutionError::AuthError(e) => return e.into(),
+            ExecutionError::Cancelled => return super::Error::Cancelled.into(),
+            ExecutionError::StatementTimeout => return super::Error::Timeout.into(),
+            ExecutionError::UnknownError => (100, INTERNAL, "unknown error".into()),
+            ExecutionError::NoDatabaseSelected =>
+                (101, NO_DATABASE, "no database selected".into()),
+            ExecutionError::InsertMissmatch =>
+                (102, DATA, "values do not match the columns of the table".into()),
+            ExecutionError::DebugError(msg) => (103, INTERNAL, msg),
+            ExecutionError::UnknownAlias => (104, UNDEFINED_TABLE, "unknown table alias".into()),
+            ExecutionError::UnknownColumn => (105, UNDEFINED_COLUMN, "unknown column".into()),
+            ExecutionError::CompareDatatypeMissmatch =>
+                (106, DATATYPE_MISMATCH, "compared values have different types".into()),
+            ExecutionError::TableNotEmpty => (107, PREREQUISITE, "table is not empty".into()),
+            ExecutionError::TableExists => (108, DUPLICATE_TABLE, "table already exists".into()),
+            ExecutionError::ViewExists => (109, DUPLICATE_TABLE, "view already exists".into()),
+            ExecutionError::RecursiveView =>
+                (110, INVALID_DEFINITION, "view refers to itself".into()),
+            ExecutionError::SubqueryTooManyRows =>
+                (111, CARDINALITY, "subquery returned more than one row".into()),
+            ExecutionError::EmptySubquery => (112, CARDINALITY, "subquery returned no row".into()),
+            ExecutionError::SetOperationColumnCount =>
+                (113, SYNTAX, "selects of a set operation differ in their columns".into()),
+            ExecutionError::SetOperationTypeMissmatch =>
+                (114, DATATYPE_MISMATCH, "selects of a set operation differ in types".into()),
+            ExecutionError::CaseTypeMissmatch =>
+                (115, DATATYPE_MISMATCH, "results of CASE have different types".into()),
+            ExecutionError::NullValue => (116, NULL_VALUE, "NULL can not be stored".into()),
+            ExecutionError::SendFailed =>
+                (117, CONNECTION_FAILURE, "result could not be sent".into()),
+            ExecutionError::UnknownSetting => (118, UNDEFINED_OBJECT, "unknown setting".into()),
+            ExecutionError::InvalidSettingValue =>
+                (119, INVALID_VALUE, "invalid value for the setting".into()),
+            ExecutionError::WrongParameterCount =>
+                (120, PARAMETERS, "wrong number of parameter values".into()),
+            ExecutionError::InvalidParameter =>
+                (121, PARAMETERS, "a parameter value can not be a placeholder".into()),
+            ExecutionError::PermissionDenied =>
+                (122, INSUFFICIENT_PRIVILEGE, "permission denied".into()),
+            ExecutionError::MissingPrivilege(privilege, object) =>
+                (123, INSUFFICIENT_PRIVILEGE, format!("permission denied: {} on {}",
+                    format!("{:?}", privilege).to_uppercase(), object)),
+            ExecutionError::UnboundParameter =>
+                (124, PARAMETERS, "parameters are only allowed in prepared statements".into()),
+            ExecutionError::MissingParameter(n) =>
+                (125, PARAMETERS, format!("parameter ${} does not occur in the statement", n)),
+        };
+        ClientErrMsg::new(code, class, msg)
+    }
+}
+
+impl From<AuthError> for ClientErrMsg {
+    fn from(error: AuthError) -> ClientErrMsg {
+        let (code, class, msg) = match error {
+            AuthError::Storage(e) => return e.into(),
+            AuthError::UserNotFound => (140, UNDEFINED_OBJECT, "unknown user".into()),
+            AuthError::WrongPassword => (141, INVALID_PASSWORD, "wrong password".into()),
+            AuthError::UserExists => (142, DUPLICATE_OBJECT, "user already exists".into()),
+            AuthError::RoleNotFound => (143, UNDEFINED_OBJECT, "unknown role".into()),
+            AuthError::RoleExists => (144, DUPLICATE_OBJECT, "role already exists".into()),
+            AuthError::NotAMember =>
+                (145, INSUFFICIENT_PRIVILEGE, "user is not a member of the role".into()),
+            AuthError::CircularMembership =>
+                (146, INVALID_GRANT, "role would become a member of itself".into()),
+            AuthError::TooManyAttempts(seconds) => (147, INVALID_AUTHORIZATION,
+                format!("too many failed logins, try again in {} seconds", seconds)),
+        };
+        ClientErrMsg::new(code, class, msg)
+    }
+}
+
+impl From<FunctionError> for ClientErrMsg {
+    fn from(error: FunctionError) -> ClientErrMsg {
+        let (code, class, msg) = match error {
+            FunctionError::UnknownFunction(name) =>
+                (130, UNDEFINED_FUNCTION, format!("unknown function {}", name)),
+            FunctionError::WrongArgumentCount(name) =>
+                (131, UNDEFINED_FUNCTION, format!("wrong number of arguments for {}", name)),
+            FunctionError::WrongArgumentType(name) =>
+                (132, DATATYPE_MISMATCH, format!("wrong type of argument for {}", name)),
+            FunctionError::DivisionByZero => (133, DIVISION_BY_ZERO, "division by zero".into()),
+            FunctionError::OutOfRange(name) =>
+                (134, OUT_OF_RANGE, format!("result of {} is out of range", name)),
+        };
+        ClientErrMsg::new(code, class, msg)
+    }
+}
+
+impl From<storage::Error> for ClientErrMsg {
+    fn from(error: storage::Error) -> ClientErrMsg {
+        let (code, class, msg) = match error {
+            storage::Error::Io(e) => (200, IO, format!("IO error: {}", e)),
+            storage::Error::BinEn(e) => (201, IO, format!("could not encode: {:?}", e)),
+            storage::Error::BinDe(e) => (202, CORRUPTED, format!("could not decode: {:?}", e)),
+            storage::Error::Byteorder(e) => (203, CORRUPTED, format!("could not read: {:?}", e)),
+            storage::Error::Utf8Error(_) | storage::Error::Utf8StrError(_) =>
+                (204, CORRUPTED, "invalid UTF-8 in stored data".into()),
+            storage::Error::NulError(_) => (205, DATA, "string contains a NUL byte".into()),
+            storage::Error::WrongMagicNmbr => (206, CORRUPTED, "file has a wrong format".into()),
+            storage::Error::Engine => (207, INTERNAL, "storage engine error".into()),
+            storage::Error::LoadDataBase =>
+                (208, NO_DATABASE, "database could not be loaded".into()),
+            storage::Error::RemoveColumn => (209, UNDEFINED_COLUMN, "column not found".into()),
+            storage::Error::AddColumn => (210, DUPLICATE_TABLE, "column already exists".into()),
+            storage::Error::InvalidType =>
+                (211, DATATYPE_MISMATCH, "value does not match the column type".into()),
+            storage::Error::InterruptedRead => (212, IO, "read was interrupted".into()),
+            storage::Error::OutOfBounds => (213, INTERNAL, "position out of bounds".into()),
+            storage::Error::MissingPrimaryKey =>
+                (214, INVALID_DEFINITION, "table has no primary key".into()),
+            storage::Error::InvalidColumn => (215, UNDEFINED_COLUMN, "invalid column".into()),
+            storage::Error::NotAPrimaryKey =>
+                (216, INVALID_DEFINITION, "column is not a primary key".into()),
+            storage::Error::NoImplementation =>
+                (217, INTERNAL, "not implemented by the storage engine".into()),
+            storage::Error::WrongLength => (218, DATA, "value has a wrong length".into()),
+            storage::Error::NoOperationPossible =>
+                (219, PREREQUISITE, "operation not possible".into()),
+            storage::Error::InvalidState => (220, INTERNAL, "invalid state".into()),
+            storage::Error::EndOfFile => (221, IO, "unexpected end of file".into()),
+            storage::Error::BeginningOfFile => (222, IO, "unexpected beginning of file".into()),
+            storage::Error::PrimaryKeyValueExists =>
+                (223, UNIQUE_VIOLATION, "primary key value already exists".into()),
+            storage::Error::FoundNoPrimaryKey =>
+                (224, INVALID_DEFINITION, "found no primary key".into()),
+            storage::Error::PrimaryKeyNotAllowed =>
+                (225, INVALID_DEFINITION, "primary key not allowed".into()),
+        };
+        ClientErrMsg::new(code, class, msg)
+    }
+}
+
+impl From<ParseError> for ClientErrMsg {
+    fn from(error: ParseError) -> ClientErrMsg {
+        let (code, msg, span) = match error {
+            ParseError::UnknownError => (300, "syntax error".into(), None),
+            ParseError::LexError(LexError::UnclosedQuotationmark) =>
+                (301, "unclosed quotation mark".into(), None),
+            ParseError::LexError(LexError::ParameterOutOfRange) =>
+                (322, format!("parameter numbers go up to {}", MAX_PARAM), None),
+            ParseError::EmptyQueryError => (302, "empty query".into(), None),
+            ParseError::UnexpectedEoq => (303, "unexpected end of query".into(), None),
+            ParseError::InvalidEoq => (304, "statement goes on after its end".into(), None),
+            ParseError::WrongKeyword(span) => (305, "unexpected keyword".into(), Some(span)),
+            ParseError::WrongToken(span) => (306, "unexpected token".into(), Some(span)),
+            ParseError::DatatypeMissmatch(span) =>
+                (307, "datatype does not match".into(), Some(span)),
+            ParseError::NotAKeyword(span) => (308, "expected a keyword".into(), Some(span)),
+            ParseError::NotAToken(span) => (309, "expected a token".into(), Some(span)),
+            ParseError::NotAWord(span) => (310, "expected a name".into(), Some(span)),
+            ParseError::NotADatatype(span) => (311, "expected a datatype".into(), Some(span)),
+            ParseError::NotANumber(span) => (312, "expected a number".into(), Some(span)),
+            ParseError::NotALiteral(span) => (313, "expected a literal".into(), Some(span)),
+            ParseError::ColumnCountMissmatch =>
+                (314, "numbers of columns and values differ".into(), None),
+            ParseError::MissingParenthesis(span) =>
+                (315, "missing parenthesis".into(), Some(span)),
+            ParseError::LimitError => (316, "invalid LIMIT".into(), None),
+            ParseError::ReservedKeyword(span) =>
+                (317, "reserved keyword used as a name".into(), Some(span)),
+            ParseError::CommentIsNoString => (318, "comment has to be a string".into(), None),
+            ParseError::LikePatternNoString =>
+                (319, "LIKE pattern has to be a string".into(), None),
+            ParseError::DebugError(msg) => (320, msg, None),
+            ParseError::PasswordIsNoString =>
+                (321, "password has to be a string".into(), None),
+        };
+        ClientErrMsg { code: code, class: SYNTAX.into(), msg: msg, span: span }
+    }
+}
@@ -15,6 +15,8 @@
 //! ...
 //!
 pub mod types;
+pub mod tls;
+mod errors;
 
 use std;
 use std::fmt;
@@ -23,11 +25,22 @@ use std::io::{self, Write, Read};
 use bincode::rustc_serialize::{EncodingError, DecodingError, decode_from, encode_into};
 use bincode::SizeLimit;
 use self::types::*;
-use storage::ResultSet;
+use storage::{ResultSet, Column};
 use parse::parser::ParseError;
 
-const PROTOCOL_VERSION: u8 = 1;
+/// Version 2 replaced the login with the password in clear by a
+/// challenge-response login, version 3 offers TLS in the greeting.
+pub const PROTOCOL_VERSION: u8 = 4;
 const WELCOME_MSG: &'static str = "Welcome to the fabulous uoSQL database.";
+/// Clients of protocol version 1 only read the version and the message of
+/// the greeting and do not understand errors, so they are told here
+const VERSION_NOTICE: &'static str =
+    "Clients older than protocol version 2 send the password in clear and are refused, \
+    please update the client.";
+/// Maximum size of a command in bytes, scripts can be large
+pub const MAX_COMMAND_SIZE: u64 = 1024 * 1024;
+/// Longest message of an error package in bytes, longer ones are cut
+const MAX_ERROR_MSG: usize = 4096;
 
 /// Collection of possible errors while communicating with the client.
 #[derive(Debug)]
@@ -38,6 +51,25 @@ pub enum Error {
     Encode(EncodingError),
     Decode(DecodingError),
     UnEoq(ParseError),
+    Aborted,
+    Cancelled,
+    Timeout,
+    UnknownSession,
+    UnknownStatement,
+    // the connection has as many prepared statements as allowed
+    TooManyStatements,
+    // the client logs in like an older protocol version
+    ProtocolVersion,
+    // the server only accepts logins over TLS
+    TlsRequired,
+    // all workers are busy with other connections
+    TooManyConnections,
+    // the user has as many connections open as allowed
+    TooManyUserConnections,
+    // the client sent no command for longer than the idle timeout
+    IdleTimeout,
+    // the server is stopping and closes the connection
+    ShuttingDown,
 }
 
 /// Implement display for description of Error
@@ -56,7 +88,19 @@ impl std::error::Error for Error {
             &Error::UnknownCmd => "cannot interpret command: unknown",
             &Error::Encode(_) => "could not encode/ send package",
             &Error::Decode(_) => "could not decode/ receive package",
-            &Error::UnEoq(_) => "parsing error"
+            &Error::UnEoq(_) => "parsing error",
+            &Error::Aborted => "query failed while sending the result",
+            &Error::Cancelled => "query was cancelled",
+            &Error::Timeout => "query exceeded the statement timeout",
+            &Error::UnknownSession => "no session with this id and cancel key",
+            &Error::UnknownStatement => "no prepared statement with this handle",
+            &Error::TooManyStatements => "too many prepared statements, deallocate some",
+            &Error::ProtocolVersion => "client protocol version is not supported",
+            &Error::TlsRequired => "server requires TLS",
+            &Error::TooManyConnections => "too many connections",
+            &Error::TooManyUserConnections => "too many connections of this user",
+            &Error::IdleTimeout => "connection was idle for too long",
+            &Error::ShuttingDown => "server is shutting down",
         }
     }
 }
@@ -89,25 +133,77 @@ impl From<ParseError> for Error {
     }
 }
 
-/// Write a welcome-message to the given server-client-stream.
-pub fn do_handshake<W: Write + Read>(stream: &mut W)
-    -> Result<(String, String), Error>
+/// What the client sends after the greeting
+#[derive(Debug)]
+pub enum Start {
+    // the TLS handshake follows, then the start of the login
+    Tls,
+    Auth(AuthStart),
+}
+
+/// Write a welcome-message to the given server-client-stream and read the
+/// answer of the client. The nonce makes the proof of the client good for
+/// this login only. `tls` offers TLS to the client.
+pub fn do_handshake<W: Write + Read>(stream: &mut W, nonce: Vec<u8>, tls: bool)
+    -> Result<Start, Error>
 {
-    let greet = Greeting::make_greeting(PROTOCOL_VERSION, WELCOME_MSG.into());
+    let message = format!("{} {}", WELCOME_MSG, VERSION_NOTICE);
+    let greet = Greeting::make_greeting(PROTOCOL_VERSION, message, nonce, tls);
 
     // send handshake packet to client
     try!(encode_into(&PkgType::Greet, stream, SizeLimit::Bounded(1024)));
     try!(encode_into(&greet, stream, SizeLimit::Bounded(1024)));
 
-    // receive login data from client
-    let login = read_login(stream);
-    match login {
-        Ok(sth) => Ok((sth.username, sth.password)),
-        Err(msg) => Err(msg)
+    read_start(stream)
+}
+
+/// Read the start of the login, the name and the nonce of the client, or
+/// the request to start TLS first.
+pub fn read_start<R: Read>(stream: &mut R) -> Result<Start, Error> {
+    let status: PkgType = try!(decode_from(stream, SizeLimit::Bounded(1024)));
+    match status {
+        PkgType::AuthStart =>
+            Ok(Start::Auth(try!(decode_from(stream, SizeLimit::Bounded(1024))))),
+        PkgType::StartTls => Ok(Start::Tls),
+        // clients up to protocol version 1 send the password
+        PkgType::Login => {
+            let _ = decode_from::<R, Login>(stream, SizeLimit::Bounded(1024));
+            Err(Error::ProtocolVersion)
+        },
+        _ => Err(Error::UnexpectedPkg)
+    }
+}
+
+/// Send the salt and the rounds of the password hash to the client.
+pub fn send_challenge<W: Write>(mut stream: &mut W, challenge: AuthChallenge)
+    -> Result<(), Error>
+{
+    try!(encode_into(&PkgType::AuthChallenge, stream, SizeLimit::Bounded(1024)));
+    try!(encode_into(&challenge, stream, SizeLimit::Bounded(1024)));
+    Ok(())
+}
+
+/// Read the proof of the client that it knows the password.
+pub fn read_proof<R: Read>(stream: &mut R) -> Result<AuthProof, Error> {
+    let status: PkgType = try!(decode_from(stream, SizeLimit::Bounded(1024)));
+    match status {
+        PkgType::AuthProof =>
+            decode_from(stream, SizeLimit::Bounded(1024)).map_err(|e| e.into()),
+        _ => Err(Error::UnexpectedPkg)
     }
 }
 
-/// Read the data from the response to the handshake,
+/// Grant access, with the signature of the server and the keys to cancel
+/// the queries of the session (see `AccessGranted`).
+pub fn send_acc_granted<W: Write>(mut stream: &mut W, granted: AccessGranted)
+    -> Result<(), Error>
+{
+    try!(encode_into(&PkgType::AccGranted, stream, SizeLimit::Bounded(1024)));
+    try!(encode_into(&granted, stream, SizeLimit::Bounded(1024)));
+    Ok(())
+}
+
+/// Read the data from the response to the handshake of protocol version 1,
 /// username and password extracted and returned.
 pub fn read_login<R: Read + Write>(stream: &mut R)
     -> Result<Login, Error>
@@ -120,7 +216,7 @@ pub fn read_login<R: Read + Write>(stream: &mut R)
             // read the login data
             decode_from(stream, SizeLimit::Bounded(1024)).map_err(|e| e.into()),
         PkgType::Command => { // free the stream
-            let _ = decode_from::<R, Command>(stream, SizeLimit::Bounded(4096));
+            let _ = decode_from::<R, Command>(stream, SizeLimit::Bounded(MAX_COMMAND_SIZE));
             Err(Error::UnexpectedPkg)
         },
         _ =>
@@ -141,18 +237,28 @@ pub fn read_commands<R: Read + Write>(stream: &mut R)
             Err(Error::UnexpectedPkg)
         },
         PkgType::Command =>
-            decode_from(stream, SizeLimit::Bounded(4096)).map_err(|e| e.into()),
+            decode_from(stream, SizeLimit::Bounded(MAX_COMMAND_SIZE)).map_err(|e| e.into()),
         _ =>
             Err(Error::UnexpectedPkg)
     }
 }
 
-/// Send error package with given error code status.
-pub fn send_error_package<W: Write>(mut stream: &mut W, err: ClientErrMsg)
+/// Send error package with given error code status. Long messages are cut.
+pub fn send_error_package<W: Write>(mut stream: &mut W, mut err: ClientErrMsg)
     -> Result<(), Error>
 {
+    if err.msg.len() > MAX_ERROR_MSG {
+        let mut end = MAX_ERROR_MSG;
+        while !err.msg.is_char_boundary(end) {
+            end -= 1;
+        }
+        err.msg.truncate(end);
+    }
+    // encoded first, the header is not sent alone if encoding fails
+    let mut body = Vec::new();
+    try!(encode_into(&err, &mut body, SizeLimit::Bounded(MAX_ERROR_MSG as u64 + 1024)));
     try!(encode_into(&PkgType::Error, stream, SizeLimit::Bounded(1024)));
-    try!(encode_into(&err, &mut stream, SizeLimit::Bounded(1024)));
+    try!(stream.write_all(&body));
     Ok(())
 }
 
@@ -174,6 +280,37 @@ pub fn send_response_package<W: Write>(mut stream: &mut W, data: ResultSet)
 }
 
 
+/// Send the handle and the parameter types of a prepared statement.
+pub fn send_prepared_package<W: Write>(mut stream: &mut W, stmt: PreparedStmt)
+    -> Result<(), Error>
+{
+    try!(encode_into(&PkgType::Prepared, stream, SizeLimit::Bounded(1024)));
+    try!(encode_into(&stmt, stream, SizeLimit::Infinite));
+    Ok(())
+}
+
+/// Send the columns of a streamed response.
+pub fn send_response_columns<W: Write>(mut stream: &mut W, columns: &[Column])
+    -> Result<(), Error>
+{
+    try!(encode_into(&PkgType::ResponseColumns, stream, SizeLimit::Bounded(1024)));
+    try!(encode_into(&columns.to_vec(), stream, SizeLimit::Infinite));
+    Ok(())
+}
+
+/// Send the data of some rows of a streamed response.
+pub fn send_response_batch<W: Write>(mut stream: &mut W, data: &[u8])
+    -> Result<(), Error>
+{
+    try!(encode_into(&PkgType::ResponseBatch, stream, SizeLimit::Bounded(1024)));
+    try!(encode_into(&data.to_vec(), stream, SizeLimit::Infinite));
+    Ok(())
+}
+
+/// Send the end of a streamed response.
+pub fn send_response_end<W: Write>(stream: &mut W) -> Result<(), Error> {
+    send_info_package(stream, PkgType::ResponseEnd)
+}
 
 // # Some information for the `net` working group:
 //
@@ -206,9 +343,12 @@ pub fn test_send_error_packet() {
     // could not encode/ send package
     let vec2 = vec![0, 0, 0, 3, // for error packet
         0, 2, // for kind of error
+        0, 0, 0, 0, 0, 0, 0, 5, // for the size of the class string
+        48, 56, 80, 48, 49, // class itself
         0, 0, 0, 0, 0, 0, 0, 27, // for the size of the message string
         114, 101, 99, 101, 105, 118, 101, 100, 32, 117, 110, 101, 120, 112, 101,
-        99, 116, 101, 100, 32, 112, 97, 99, 107, 97, 103, 101]; // string itself
+        99, 116, 101, 100, 32, 112, 97, 99, 107, 97, 103, 101, // string itself
+        0]; // no span
     let err = Error::UnexpectedPkg;
 
     // test if the message is sent
@@ -217,6 +357,66 @@ pub fn test_send_error_packet() {
     assert_eq!(vec, vec2);
 }
 
+#[test]
+pub fn test_send_long_error() {
+    use std::io::Cursor;
+    let mut vec = Vec::new();
+    // the cut falls into the middle of a two byte character
+    let msg = format!("x{}", "\u{e4}".repeat(MAX_ERROR_MSG));
+    assert!(send_error_package(&mut vec, ClientErrMsg::new(1, "XX000", msg)).is_ok());
+
+    let mut stream = Cursor::new(vec);
+    let pkg: PkgType = decode_from(&mut stream, SizeLimit::Infinite).unwrap();
+    assert!(pkg == PkgType::Error);
+    let msg: ClientErrMsg = decode_from(&mut stream, SizeLimit::Infinite).unwrap();
+    assert_eq!(msg.msg.len(), MAX_ERROR_MSG - 1);
+    assert!(msg.msg.ends_with('\u{e4}'));
+}
+
+#[test]
+pub fn test_send_parse_error() {
+    use std::io::Cursor;
+    use parse::Span;
+    let mut vec = Vec::new();
+    let err = Error::UnEoq(ParseError::NotAWord(Span { lo: 9, hi: 10 }));
+
+    assert!(send_error_package(&mut vec, err.into()).is_ok());
+
+    // the client gets the position of the failing token
+    let mut stream = Cursor::new(vec);
+    let pkg: PkgType = decode_from(&mut stream, SizeLimit::Infinite).unwrap();
+    assert!(pkg == PkgType::Error);
+    let msg: ClientErrMsg = decode_from(&mut stream, SizeLimit::Infinite).unwrap();
+    assert_eq!(msg.code, 310);
+    assert_eq!(msg.class, "42601");
+    assert_eq!(msg.span, Some(Span { lo: 9, hi: 10 }));
+}
+
+#[test]
+pub fn test_send_response_stream() {
+    use std::io::Cursor;
+    use storage::SqlType;
+    let mut vec = Vec::new();
+    let columns = vec![Column::new("id", SqlType::Int, false, "", true)];
+
+    assert!(send_response_columns(&mut vec, &columns).is_ok());
+    assert!(send_response_batch(&mut vec, &[0, 0, 0, 1, 0, 0, 0, 2]).is_ok());
+    assert!(send_response_end(&mut vec).is_ok());
+
+    // read the packages back in the order they were sent
+    let mut stream = Cursor::new(vec);
+    let pkg: PkgType = decode_from(&mut stream, SizeLimit::Infinite).unwrap();
+    assert!(pkg == PkgType::ResponseColumns);
+    let cols: Vec<Column> = decode_from(&mut stream, SizeLimit::Infinite).unwrap();
+    assert_eq!(cols[0].name, "id");
+    let pkg: PkgType = decode_from(&mut stream, SizeLimit::Infinite).unwrap();
+    assert!(pkg == PkgType::ResponseBatch);
+    let data: Vec<u8> = decode_from(&mut stream, SizeLimit::Infinite).unwrap();
+    assert_eq!(data, vec![0, 0, 0, 1, 0, 0, 0, 2]);
+    let pkg: PkgType = decode_from(&mut stream, SizeLimit::Infinite).unwrap();
+    assert!(pkg == PkgType::ResponseEnd);
+}
+
 #[test]
 pub fn test_read_commands(){
     // test if the commands are correctly decoded
@@ -246,6 +446,144 @@ pub fn test_read_commands(){
     assert_eq!(command_res.unwrap(), Command::Query("select".into()));
 }
 
+#[test]
+pub fn test_read_cancel_command() {
+    use std::io::Cursor;
+    let mut vec = Vec::new();
+
+    let _ = encode_into(&PkgType::Command, &mut vec, SizeLimit::Bounded(1024));
+    let _ = encode_into(&Command::Cancel(3, 42), &mut vec, SizeLimit::Bounded(1024));
+
+    let command_res = read_commands(&mut Cursor::new(vec));
+    assert_eq!(command_res.unwrap(), Command::Cancel(3, 42));
+}
+
+#[test]
+pub fn test_read_execute_command() {
+    use std::io::Cursor;
+    use parse::token::Lit;
+    let mut vec = Vec::new();
+
+    let cmd = Command::Execute(1, vec![Lit::Int(5), Lit::String("a".into())]);
+    let _ = encode_into(&PkgType::Command, &mut vec, SizeLimit::Bounded(1024));
+    let _ = encode_into(&cmd, &mut vec, SizeLimit::Bounded(1024));
+
+    let command_res = read_commands(&mut Cursor::new(vec));
+    assert_eq!(command_res.unwrap(), cmd);
+}
+
+/// Reads what the client sent and keeps what the server writes, like the
+/// two directions of a socket
+#[cfg(test)]
+struct Pipe {
+    input: io::Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+#[cfg(test)]
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+#[cfg(test)]
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+pub fn test_handshake() {
+    use std::io::Cursor;
+    let mut vec = Vec::new();
+
+    let start = AuthStart { username: "elena".into(), nonce: vec![1, 2, 3] };
+    let _ = encode_into(&PkgType::AuthStart, &mut vec, SizeLimit::Bounded(1024));
+    let _ = encode_into(&start, &mut vec, SizeLimit::Bounded(1024));
+
+    let mut pipe = Pipe { input: Cursor::new(vec), output: Vec::new() };
+    match do_handshake(&mut pipe, vec![4, 5, 6], false).unwrap() {
+        Start::Auth(start) => {
+            assert_eq!(start.username, "elena");
+            assert_eq!(start.nonce, vec![1, 2, 3]);
+        },
+        Start::Tls => panic!("client did not ask for TLS"),
+    }
+
+    // the greeting carries the nonce of the server
+    let mut stream = Cursor::new(pipe.output);
+    let pkg: PkgType = decode_from(&mut stream, SizeLimit::Infinite).unwrap();
+    assert!(pkg == PkgType::Greet);
+    let greet: Greeting = decode_from(&mut stream, SizeLimit::Infinite).unwrap();
+    assert_eq!(greet.protocol_version, PROTOCOL_VERSION);
+    assert_eq!(greet.nonce, vec![4, 5, 6]);
+    assert!(!greet.tls);
+    // nothing else goes out before the login, the cancel key comes later
+    assert_eq!(stream.position() as usize, stream.get_ref().len());
+}
+
+#[test]
+pub fn test_acc_granted() {
+    use std::io::Cursor;
+    let granted = AccessGranted { signature: vec![7, 8], session_id: 3, cancel_key: 42 };
+    let mut vec = Vec::new();
+    send_acc_granted(&mut vec, granted).unwrap();
+
+    let mut stream = Cursor::new(vec);
+    let pkg: PkgType = decode_from(&mut stream, SizeLimit::Infinite).unwrap();
+    assert!(pkg == PkgType::AccGranted);
+    let granted: AccessGranted = decode_from(&mut stream, SizeLimit::Infinite).unwrap();
+    assert_eq!((granted.signature, granted.session_id, granted.cancel_key), (vec![7, 8], 3, 42));
+}
+
+#[test]
+pub fn test_handshake_start_tls() {
+    use std::io::Cursor;
+    let mut vec = Vec::new();
+    let _ = encode_into(&PkgType::StartTls, &mut vec, SizeLimit::Bounded(1024));
+
+    let mut pipe = Pipe { input: Cursor::new(vec), output: Vec::new() };
+    match do_handshake(&mut pipe, vec![4, 5, 6], true) {
+        Ok(Start::Tls) => {},
+        _ => panic!("request to start TLS was not read"),
+    }
+
+    // the server offers TLS in the greeting
+    let mut stream = Cursor::new(pipe.output);
+    let _: PkgType = decode_from(&mut stream, SizeLimit::Infinite).unwrap();
+    let greet: Greeting = decode_from(&mut stream, SizeLimit::Infinite).unwrap();
+    assert!(greet.tls);
+}
+
+#[test]
+pub fn test_handshake_old_client() {
+    use std::io::Cursor;
+    let mut vec = Vec::new();
+
+    let login = Login { username: "elena".into(), password: "prakt".into() };
+    let _ = encode_into(&PkgType::Login, &mut vec, SizeLimit::Bounded(1024));
+    let _ = encode_into(&login, &mut vec, SizeLimit::Bounded(1024));
+
+    let mut pipe = Pipe { input: Cursor::new(vec), output: Vec::new() };
+    match do_handshake(&mut pipe, vec![4, 5, 6], false) {
+        Err(Error::ProtocolVersion) => {},
+        _ => panic!("password in clear was accepted"),
+    }
+
+    // the client reads the version and the message only
+    let mut stream = Cursor::new(pipe.output);
+    let _: PkgType = decode_from(&mut stream, SizeLimit::Infinite).unwrap();
+    let _: u8 = decode_from(&mut stream, SizeLimit::Infinite).unwrap();
+    let message: String = decode_from(&mut stream, SizeLimit::Infinite).unwrap();
+    assert!(message.contains(VERSION_NOTICE));
+}
+
 #[test]
 pub fn testlogin() {
     use std::io::Cursor;        // stream to read from
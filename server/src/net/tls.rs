@@ -0,0 +1,399 @@
+//! Optional TLS for the connections
+//!
+//! A server with a certificate offers TLS in its greeting. The client
+//! answers with `PkgType::StartTls` and both sides run the TLS handshake on
+//! the same TCP stream, before the login. The proof of the password, the
+//! queries and their results are encrypted from then on.
+//!
+//! The login has no channel binding, so encryption alone does not keep out
+//! a man in the middle: the client has to check the certificate of the
+//! server, against a CA and the address or against a pinned certificate
+//! (see `Trust`).
+//!
+//! Connections over a Unix socket never leave the machine, TLS is not
+//! offered for them.
+//!
+
+use openssl::error::ErrorStack;
+use openssl::ssl::{HandshakeError, SslAcceptor, SslConnector, SslFiletype, SslMethod,
+    SslStream, SslVerifyMode};
+use openssl::x509::X509;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::time::Duration;
+
+/// Errors of loading certificates and of the TLS handshake
+#[derive(Debug)]
+pub enum Error {
+    Ssl(ErrorStack),
+    Io(io::Error),
+    // the handshake failed, with the reason
+    Handshake(String),
+    // the server did not send the pinned certificate
+    CertificateMismatch,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Error::Ssl(ref e) => e.fmt(f),
+            &Error::Io(ref e) => e.fmt(f),
+            &Error::Handshake(ref reason) => reason.fmt(f),
+            &Error::CertificateMismatch =>
+                "the certificate of the server is not the pinned one".fmt(f),
+        }
+    }
+}
+
+impl From<ErrorStack> for Error {
+    fn from(err: ErrorStack) -> Error {
+        Error::Ssl(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<HandshakeError<TcpStream>> for Error {
+    fn from(err: HandshakeError<TcpStream>) -> Error {
+        Error::Handshake(err.to_string())
+    }
+}
+
+/// A connection, TCP encrypted or not, or a Unix socket
+pub enum Stream {
+    Plain(TcpStream),
+    Tls(SslStream<TcpStream>),
+    Unix(UnixStream),
+}
+
+impl Stream {
+    /// Returns the underlying TCP stream, None for a Unix socket
+    pub fn get_ref(&self) -> Option<&TcpStream> {
+        match self {
+            &Stream::Plain(ref stream) => Some(stream),
+            &Stream::Tls(ref stream) => Some(stream.get_ref()),
+            &Stream::Unix(_) => None,
+        }
+    }
+
+    /// Lets reads fail after the time without data, never with None
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            &Stream::Plain(ref stream) => stream.set_read_timeout(timeout),
+            &Stream::Tls(ref stream) => stream.get_ref().set_read_timeout(timeout),
+            &Stream::Unix(ref stream) => stream.set_read_timeout(timeout),
+        }
+    }
+
+    /// Makes reads and writes block or not
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            &Stream::Plain(ref stream) => stream.set_nonblocking(nonblocking),
+            &Stream::Tls(ref stream) => stream.get_ref().set_nonblocking(nonblocking),
+            &Stream::Unix(ref stream) => stream.set_nonblocking(nonblocking),
+        }
+    }
+
+    /// Returns a second handle of the socket
+    pub fn socket(&self) -> io::Result<Socket> {
+        match self {
+            &Stream::Plain(ref stream) => stream.try_clone().map(Socket::Tcp),
+            &Stream::Tls(ref stream) => stream.get_ref().try_clone().map(Socket::Tcp),
+            &Stream::Unix(ref stream) => stream.try_clone().map(Socket::Unix),
+        }
+    }
+
+    /// Returns the address of the other side, None for a Unix socket
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.get_ref().and_then(|stream| stream.peer_addr().ok())
+    }
+
+    pub fn is_tls(&self) -> bool {
+        match self {
+            &Stream::Tls(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_unix(&self) -> bool {
+        match self {
+            &Stream::Unix(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A second handle of the socket of a stream, which can end the stream from
+/// another thread
+pub enum Socket {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Socket {
+    /// Ends reading, a blocked read of the stream returns at once. Writing
+    /// still works.
+    pub fn shutdown_read(&self) -> io::Result<()> {
+        match self {
+            &Socket::Tcp(ref socket) => socket.shutdown(Shutdown::Read),
+            &Socket::Unix(ref socket) => socket.shutdown(Shutdown::Read),
+        }
+    }
+}
+
+impl From<TcpStream> for Stream {
+    fn from(stream: TcpStream) -> Stream {
+        Stream::Plain(stream)
+    }
+}
+
+impl From<UnixStream> for Stream {
+    fn from(stream: UnixStream) -> Stream {
+        Stream::Unix(stream)
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            &mut Stream::Plain(ref mut stream) => stream.read(buf),
+            &mut Stream::Tls(ref mut stream) => stream.read(buf),
+            &mut Stream::Unix(ref mut stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            &mut Stream::Plain(ref mut stream) => stream.write(buf),
+            &mut Stream::Tls(ref mut stream) => stream.write(buf),
+            &mut Stream::Unix(ref mut stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            &mut Stream::Plain(ref mut stream) => stream.flush(),
+            &mut Stream::Tls(ref mut stream) => stream.flush(),
+            &mut Stream::Unix(ref mut stream) => stream.flush(),
+        }
+    }
+}
+
+/// The server side of TLS: the certificate and its key
+pub struct Acceptor {
+    acceptor: SslAcceptor,
+    // logins without TLS are refused
+    pub required: bool,
+}
+
+impl Acceptor {
+    /// Loads the certificate, followed by its chain, and the private key,
+    /// both PEM files
+    pub fn new(cert: &Path, key: &Path, required: bool) -> Result<Acceptor, Error> {
+        let mut builder = try!(SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()));
+        try!(builder.set_certificate_chain_file(cert));
+        try!(builder.set_private_key_file(key, SslFiletype::PEM));
+        try!(builder.check_private_key());
+        Ok(Acceptor { acceptor: builder.build(), required: required })
+    }
+
+    /// Runs the server side of the TLS handshake
+    pub fn accept(&self, stream: TcpStream) -> Result<Stream, Error> {
+        Ok(Stream::Tls(try!(self.acceptor.accept(stream))))
+    }
+}
+
+/// How the client checks that it talks to the right server
+pub enum Trust<'a> {
+    // the certificate has to be signed by a CA of the PEM file and issued
+    // for the address or name of the server
+    Ca(&'a Path),
+    // the certificate has to be the one in the PEM file
+    Pinned(&'a Path),
+    // the certificate has to be signed by a CA the system trusts and issued
+    // for the address or name of the server
+    System,
+}
+
+/// Runs the client side of the TLS handshake with the server `host`, an IP
+/// address or a name. The handshake fails unless the certificate of the
+/// server passes the check of `trust`.
+pub fn connect(stream: TcpStream, host: &str, trust: Trust) -> Result<Stream, Error> {
+    let mut builder = try!(SslConnector::builder(SslMethod::tls()));
+    let pinned = match trust {
+        Trust::Ca(file) => {
+            try!(builder.set_ca_file(file));
+            None
+        },
+        Trust::System => {
+            try!(builder.set_default_verify_paths());
+            None
+        },
+        // a pinned certificate is compared as a whole after the handshake,
+        // it is usually self-signed
+        Trust::Pinned(file) => {
+            let mut pem = Vec::new();
+            try!(try!(File::open(file)).read_to_end(&mut pem));
+            builder.set_verify(SslVerifyMode::NONE);
+            Some(try!(try!(X509::from_pem(&pem)).to_der()))
+        },
+    };
+    let config = try!(builder.build().configure()).verify_hostname(pinned.is_none());
+    let stream = try!(config.connect(host, stream));
+    if let Some(pinned) = pinned {
+        let sent = match stream.ssl().peer_certificate() {
+            Some(cert) => try!(cert.to_der()),
+            None => return Err(Error::CertificateMismatch),
+        };
+        if sent != pinned {
+            return Err(Error::CertificateMismatch)
+        }
+    }
+    Ok(Stream::Tls(stream))
+}
+
+/// Creates a self-signed certificate for the names and addresses, with its
+/// key, as PEM files
+#[cfg(test)]
+fn self_signed(names: &[&str], cert_file: &Path, key_file: &Path) {
+    use openssl::asn1::Asn1Time;
+    use openssl::bn::{BigNum, MsbOption};
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::X509NameBuilder;
+    use openssl::x509::extension::SubjectAlternativeName;
+
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", names[0]).unwrap();
+    let name = name.build();
+    let mut serial = BigNum::new().unwrap();
+    serial.rand(64, MsbOption::MAYBE_ZERO, false).unwrap();
+
+    let mut cert = X509::builder().unwrap();
+    cert.set_version(2).unwrap();
+    cert.set_serial_number(&serial.to_asn1_integer().unwrap()).unwrap();
+    cert.set_subject_name(&name).unwrap();
+    cert.set_issuer_name(&name).unwrap();
+    cert.set_pubkey(&key).unwrap();
+    cert.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+    cert.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+    let mut alt_names = SubjectAlternativeName::new();
+    for name in names {
+        if name.parse::<::std::net::IpAddr>().is_ok() {
+            alt_names.ip(name);
+        } else {
+            alt_names.dns(name);
+        }
+    }
+    let alt_names = alt_names.build(&cert.x509v3_context(None, None)).unwrap();
+    cert.append_extension(alt_names).unwrap();
+    cert.sign(&key, MessageDigest::sha256()).unwrap();
+
+    File::create(cert_file).unwrap().write_all(&cert.build().to_pem().unwrap()).unwrap();
+    File::create(key_file).unwrap().write_all(&key.private_key_to_pem_pkcs8().unwrap())
+        .unwrap();
+}
+
+/// Runs a server with the certificate for one connection and sends five
+/// bytes over TLS, which the server sends back
+#[cfg(test)]
+fn echo_once(cert_file: &Path, key_file: &Path, host: &str, trust: Trust)
+    -> Result<Stream, Error>
+{
+    use std::net::TcpListener;
+    use std::thread;
+
+    let acceptor = Acceptor::new(cert_file, key_file, true).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        // the handshake fails if the client refuses the certificate
+        if let Ok(mut stream) = acceptor.accept(stream) {
+            let mut buf = [0; 5];
+            if stream.read_exact(&mut buf).is_ok() {
+                let _ = stream.write_all(&buf);
+            }
+        }
+    });
+
+    let stream = connect(TcpStream::connect(addr).unwrap(), host, trust);
+    let stream = stream.and_then(|mut stream| {
+        try!(stream.write_all(b"hello"));
+        let mut buf = [0; 5];
+        try!(stream.read_exact(&mut buf));
+        assert_eq!(&buf, b"hello");
+        Ok(stream)
+    });
+    server.join().unwrap();
+    stream
+}
+
+#[cfg(test)]
+fn test_files(name: &str) -> (::std::path::PathBuf, ::std::path::PathBuf) {
+    use std::env;
+    use std::fs;
+
+    let dir = env::temp_dir().join(format!("uosql-tls-test-{}", name));
+    fs::create_dir_all(&dir).unwrap();
+    (dir.join("cert.pem"), dir.join("key.pem"))
+}
+
+#[test]
+fn test_tls_stream() {
+    // a self-signed certificate is its own CA
+    let (cert_file, key_file) = test_files("ca");
+    self_signed(&["localhost", "127.0.0.1"], &cert_file, &key_file);
+
+    let stream = echo_once(&cert_file, &key_file, "127.0.0.1", Trust::Ca(&cert_file));
+    assert!(stream.unwrap().is_tls());
+    assert!(echo_once(&cert_file, &key_file, "localhost", Trust::Ca(&cert_file)).is_ok());
+}
+
+#[test]
+fn test_tls_wrong_host() {
+    // the certificate is valid, but issued for another server
+    let (cert_file, key_file) = test_files("host");
+    self_signed(&["db.example.com", "10.1.2.3"], &cert_file, &key_file);
+
+    assert!(echo_once(&cert_file, &key_file, "127.0.0.1", Trust::Ca(&cert_file)).is_err());
+    assert!(echo_once(&cert_file, &key_file, "localhost", Trust::Ca(&cert_file)).is_err());
+}
+
+#[test]
+fn test_tls_untrusted() {
+    let (cert_file, key_file) = test_files("untrusted");
+    self_signed(&["127.0.0.1"], &cert_file, &key_file);
+    let (other_file, other_key) = test_files("untrusted-other");
+    self_signed(&["127.0.0.1"], &other_file, &other_key);
+
+    assert!(echo_once(&cert_file, &key_file, "127.0.0.1", Trust::Ca(&other_file)).is_err());
+    assert!(echo_once(&cert_file, &key_file, "127.0.0.1", Trust::System).is_err());
+}
+
+#[test]
+fn test_tls_pinned() {
+    let (cert_file, key_file) = test_files("pinned");
+    self_signed(&["127.0.0.1"], &cert_file, &key_file);
+    let (other_file, other_key) = test_files("pinned-other");
+    self_signed(&["127.0.0.1"], &other_file, &other_key);
+
+    // the address does not matter, the certificate is known
+    assert!(echo_once(&cert_file, &key_file, "db", Trust::Pinned(&cert_file)).is_ok());
+    match echo_once(&cert_file, &key_file, "127.0.0.1", Trust::Pinned(&other_file)) {
+        Err(Error::CertificateMismatch) => (),
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}
@@ -6,6 +6,8 @@ use std::error::Error;
 use storage::ResultSet;
 use storage::{Column, SqlType};
 use storage::types::FromSql;
+use parse::token::Lit;
+use parse::Span;
 use std::cmp::{max};
 
 /// Representation of a ResultSet with its useful functions to get data.
@@ -272,69 +274,142 @@ pub enum PkgType {
     Response,
     AccDenied,
     AccGranted,
+    // a streamed response: the columns are followed by any number of
+    // batches of rows and the end package
+    ResponseColumns,
+    ResponseBatch,
+    ResponseEnd,
+    // answer to Command::Prepare, followed by a `PreparedStmt`
+    Prepared,
+    // follows the results of the statements of a script
+    ScriptEnd,
+    // the challenge-response login, see `auth::scram`
+    AuthStart,
+    AuthChallenge,
+    AuthProof,
+    // the client starts TLS, see `net::tls`
+    StartTls,
 }
 
 /// Struct to send the kind of error and error message to the client
 #[derive(RustcEncodable, RustcDecodable, Debug)]
 pub struct ClientErrMsg {
-    code: u16,
-    pub msg: String
+    // stable number of the error, see `net::errors`
+    pub code: u16,
+    // five characters in the style of SQLSTATE, e.g. "42601" for syntax
+    // errors
+    pub class: String,
+    pub msg: String,
+    // the failing part of the query
+    pub span: Option<Span>,
+}
+
+impl ClientErrMsg {
+    pub fn new(code: u16, class: &str, msg: String) -> ClientErrMsg {
+        ClientErrMsg { code: code, class: class.into(), msg: msg, span: None }
+    }
 }
 
 /// Convert the possible Error to a serializable ClientErrMsg struct
 impl From<super::Error> for ClientErrMsg {
     fn from(error: super::Error) -> ClientErrMsg {
-        match error {
-            super::Error::Io(_) => ClientErrMsg {
-                code: 0,
-                msg: error.description().into()
-            },
-            super::Error::UnexpectedPkg => ClientErrMsg {
-                code: 2,
-                msg: error.description().into()
-            },
-            super::Error::UnknownCmd => ClientErrMsg {
-                code: 3,
-                msg: error.description().into()
-            },
-            super::Error::Encode(_) => ClientErrMsg {
-                code: 4,
-                msg: error.description().into()
-            },
-            super::Error::Decode(_) => ClientErrMsg {
-                code: 5,
-                msg: error.description().into()
-            },
-            super::Error::UnEoq(_) => ClientErrMsg {
-                code: 6,
-                msg: error.description().into()
-            }
-        }
+        let (code, class) = match error {
+            super::Error::Io(_) => (0, "08000"),
+            super::Error::UnexpectedPkg => (2, "08P01"),
+            super::Error::UnknownCmd => (3, "08P01"),
+            super::Error::Encode(_) => (4, "08P01"),
+            super::Error::Decode(_) => (5, "08P01"),
+            super::Error::UnEoq(e) => return e.into(),
+            super::Error::Aborted => (7, "08006"),
+            super::Error::Cancelled => (8, "57014"),
+            super::Error::Timeout => (9, "57014"),
+            super::Error::UnknownSession => (10, "08003"),
+            super::Error::UnknownStatement => (11, "26000"),
+            super::Error::ProtocolVersion => (12, "08P01"),
+            super::Error::TlsRequired => (13, "28000"),
+            super::Error::TooManyConnections => (14, "53300"),
+            super::Error::TooManyUserConnections => (15, "53300"),
+            super::Error::IdleTimeout => (16, "57P05"),
+            super::Error::ShuttingDown => (17, "57P01"),
+            super::Error::TooManyStatements => (18, "54000"),
+        };
+        ClientErrMsg::new(code, class, error.description().into())
     }
 }
 
 /// This is the first packet being sent by the server after the TCP connection
-/// is established.
+/// is established. Up to protocol version 3 the session id and the cancel
+/// key followed the message, they come with `AccessGranted` now.
 #[derive(RustcEncodable, RustcDecodable)]
 pub struct Greeting {
     pub protocol_version: u8,   // 1 byte
     pub message: String,        // n bytes
+    pub nonce: Vec<u8>,         // 8 + n bytes, since protocol version 2
+    pub tls: bool,              // 1 byte, since protocol version 3
 }
 
 impl Greeting {
-    pub fn make_greeting(version: u8, msg: String) -> Greeting {
-        Greeting { protocol_version: version, message: msg }
+    pub fn make_greeting(version: u8, msg: String, nonce: Vec<u8>, tls: bool) -> Greeting {
+        Greeting {
+            protocol_version: version,
+            message: msg,
+            nonce: nonce,
+            tls: tls,
+        }
     }
 }
 
-/// The client responds with this packet to a `Greeting` packet, finishing the
-/// authentication handshake.
+/// A statement prepared by the server. The handle is used to execute it,
+/// the type of a parameter is None if it does not follow from the query.
+#[derive(RustcEncodable, RustcDecodable, Debug)]
+pub struct PreparedStmt {
+    pub handle: u32,
+    pub params: Vec<Option<SqlType>>,
+}
+
+/// The client responded with this packet to a `Greeting` packet up to
+/// protocol version 1. It carries the password in clear and is rejected now.
 #[derive(Default, RustcEncodable, RustcDecodable)]
 pub struct Login {
     pub username: String,
     pub password: String
 }
 
+/// The client responds with this packet to a `Greeting` packet, starting
+/// the challenge-response login.
+#[derive(Default, RustcEncodable, RustcDecodable, Debug)]
+pub struct AuthStart {
+    pub username: String,
+    pub nonce: Vec<u8>,
+}
+
+/// The server answers `AuthStart` with the salt and the rounds of the
+/// password hash of the user.
+#[derive(Default, RustcEncodable, RustcDecodable, Debug)]
+pub struct AuthChallenge {
+    pub salt: Vec<u8>,
+    pub iterations: u32,
+}
+
+/// The client proves that it knows the password, which finishes the
+/// authentication handshake. The server answers with `AccGranted` and its
+/// own signature, or with `AccDenied`.
+#[derive(Default, RustcEncodable, RustcDecodable, Debug)]
+pub struct AuthProof {
+    pub proof: Vec<u8>,
+}
+
+/// Sent with `AccGranted`. The signature proves that the server knows the
+/// keys of the password. The session id and the cancel key allow the client
+/// to cancel its queries from another connection, they are only sent after
+/// the login and encrypted if TLS runs.
+#[derive(Default, RustcEncodable, RustcDecodable, Debug)]
+pub struct AccessGranted {
+    pub signature: Vec<u8>,
+    pub session_id: u64,
+    pub cancel_key: u64,
+}
+
 /// Sent by the client to the server.
 ///
 /// Many commands are executed via query, but there are some "special"
@@ -345,6 +420,19 @@ pub enum Command {
     Quit,
     Ping,
     Query(String),
-    // Shutdown,
+    // cancels the running query of the session with the id, if the
+    // cancel key matches
+    Cancel(u64, u64),
+    // parses the query with ? or $n placeholders and keeps it
+    Prepare(String),
+    // executes a prepared statement with the values for its parameters
+    Execute(u32, Vec<Lit>),
+    // forgets a prepared statement
+    Deallocate(u32),
+    // several statements separated by semicolons, executed in order. The
+    // flag tells whether to go on after a statement failed.
+    Script(String, bool),
+    // stops the server, only for admins
+    Shutdown,
     // Statistics,
 }
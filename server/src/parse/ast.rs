@@ -14,7 +14,11 @@ pub enum Query {
 pub enum DefStmt {
     Create(CreateStmt),
     Alter(AltStmt),
-    Drop(DropStmt)
+    Drop(DropStmt),
+    Grant(GrantStmt),
+    Revoke(GrantStmt),
+    GrantRole(RoleGrantStmt),
+    RevokeRole(RoleGrantStmt),
 }
 
 /// All Data Manipulation Statements
@@ -26,6 +30,12 @@ pub enum ManipulationStmt {
     Delete(DeleteStmt),
     Use(UseStmt),
     Describe(String),
+    Explain(ExplainStmt),
+    // collects the statistics of a table or, without a name, of all tables
+    Analyze(Option<String>),
+    Set(SetStmt),
+    // uses the privileges of the role, without a role the own ones again
+    SetRole(Option<String>),
 }
 
 /// Split between creatable content (only Tables yet)
@@ -34,12 +44,16 @@ pub enum CreateStmt {
     Table(CreateTableStmt),
     View(CreateViewStmt),
     Database(String),
+    User(UserStmt),
+    Role(String),
 }
 
 /// Split between alterable content (only Tables yet)
 #[derive(Debug, Clone, PartialEq)]
 pub enum AltStmt {
-    Table(AlterTableStmt)
+    Table(AlterTableStmt),
+    // sets the password
+    User(UserStmt),
     //Column(String)
     //View(String)
 }
@@ -49,7 +63,9 @@ pub enum AltStmt {
 pub enum DropStmt {
     Table(String),
     View(String),
-    Database(String)
+    Database(String),
+    User(String),
+    Role(String)
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -57,6 +73,59 @@ pub enum UseStmt {
     Database(String)
 }
 
+/// Changes a setting of the session, e.g. `SET statement_timeout = 500`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetStmt {
+    pub name: String,
+    pub value: token::Lit,
+}
+
+/// An account and its password, e.g. `CREATE USER bob PASSWORD 'secret'`
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserStmt {
+    pub name: String,
+    pub password: String,
+}
+
+/// Privileges given to or taken from a user with GRANT and REVOKE, e.g.
+/// `GRANT SELECT, INSERT ON TABLE foo TO bob`
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrantStmt {
+    pub privileges: Vec<Privilege>,
+    pub on: PrivilegeLevel,
+    // a user or a role
+    pub user: String,
+}
+
+/// Membership in a role given with GRANT or taken with REVOKE, e.g.
+/// `GRANT engineers TO bob`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoleGrantStmt {
+    pub role: String,
+    // a user or a role
+    pub member: String,
+}
+
+/// The kinds of statements a user may be allowed to run
+#[derive(Debug, Clone, Copy, PartialEq, RustcEncodable, RustcDecodable)]
+pub enum Privilege {
+    Select,
+    Insert,
+    Update,
+    Delete,
+    Create,
+    Drop,
+    Alter,
+}
+
+/// Privileges hold for a whole database or a single table. A table without
+/// database belongs to the database in use.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrivilegeLevel {
+    Database(String),
+    Table(Option<String>, String),
+}
+
 /// Information for table creation
 #[derive(Debug, Clone, PartialEq)]
 pub struct CreateTableStmt {
@@ -107,11 +176,17 @@ pub struct UpdateStmt {
 }
 
 /// Information for data selection
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
 pub struct SelectStmt {
     pub target: Vec<Target>,
     pub tid: Vec<String>,
     pub alias: HashMap<String, String>,
+    // derived tables: selects in the from list, by their name in `tid`
+    pub derived: HashMap<String, SelectStmt>,
+    // SELECT DISTINCT
+    pub distinct: bool,
+    // set operations with further selects, applied from left to right
+    pub compound: Vec<Compound>,
     pub cond: Option<Conditions>,
     //pub groupby: Option<GroupBy>,
     //pub orderby: Option<OrderBy>,
@@ -120,8 +195,33 @@ pub struct SelectStmt {
     pub limit: Option<Limit>,
 }
 
-/// Information for data selection
+/// EXPLAIN [ANALYZE] of a select
 #[derive(Debug, Clone, PartialEq)]
+pub struct ExplainStmt {
+    // also run the query and measure every operator
+    pub analyze: bool,
+    pub select: SelectStmt,
+}
+
+/// A set operation combining the result so far with another select
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct Compound {
+    pub op: SetOp,
+    // UNION ALL etc. keep duplicates
+    pub all: bool,
+    pub select: Box<SelectStmt>,
+}
+
+/// Possible set operations between selects
+#[derive(Debug, Clone, PartialEq, Copy, RustcEncodable, RustcDecodable)]
+pub enum SetOp {
+    Union,
+    Intersect,
+    Except,
+}
+
+/// Information for data selection
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
 pub struct Target {
     pub alias: Option<String>,
     pub col: Col,
@@ -129,17 +229,32 @@ pub struct Target {
 }
 
 /// Information for data selection in select
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
 pub enum Col {
     // select a specified column
     Specified(String),
     // for example: table.* => select every column in table
-    Every
+    Every,
+    // scalar subquery, must return at most one row
+    Subquery(Box<SelectStmt>),
+    // function call, CASE or literal
+    Expr(Expr),
+}
 
+/// Expressions computed for every row
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
+pub enum Expr {
+    // optional table alias and column name
+    Column(Option<String>, String),
+    Literal(token::Lit),
+    // function name in lower case and the arguments
+    Function(String, Vec<Expr>),
+    // CASE WHEN cond THEN expr ... [ELSE expr] END
+    Case(Vec<(Conditions, Expr)>, Option<Box<Expr>>),
 }
 
 /// Information for data output limiting
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
 pub struct Limit {
     //limit the count of the output
     pub count: Option<i64>,
@@ -164,7 +279,7 @@ pub struct DeleteStmt {
 }
 
 /// Additional operations for ordering and limiting
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
 pub enum SpecOps {
     OrderByAsc(String),
     OrderByDesc(String),
@@ -173,15 +288,23 @@ pub enum SpecOps {
 }
 
 /// Conditions for managing AND/OR where-clauses
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
 pub enum Conditions {
     Leaf(Condition),
     And(Box<Conditions>, Box<Conditions>),
-    Or(Box<Conditions>, Box<Conditions>)
+    Or(Box<Conditions>, Box<Conditions>),
+    Not(Box<Conditions>),
+    In(InCondition),
+    Between(BetweenCondition),
+    Like(LikeCondition),
+    Exists(Box<SelectStmt>),
+    // a condition already decided, left when the columns of an outer query
+    // are replaced by their values in a subquery
+    Const(bool),
 }
 
 /// Information for the where-clause
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
 pub struct Condition {
     pub aliascol: Option<String>,
     pub col: String,
@@ -194,7 +317,43 @@ pub struct Condition {
     pub rhs: CondType
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// Information for the IN predicate: col IN (...)
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct InCondition {
+    pub aliascol: Option<String>,
+    pub col: String,
+    pub set: InSet,
+}
+
+/// Possible right hand sides of the IN predicate
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
+pub enum InSet {
+    Select(Box<SelectStmt>),
+    List(Vec<token::Lit>),
+}
+
+/// Information for the BETWEEN predicate: col BETWEEN low AND high
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct BetweenCondition {
+    pub aliascol: Option<String>,
+    pub col: String,
+    // literals or columns
+    pub low: Expr,
+    pub high: Expr,
+}
+
+/// Information for the LIKE and ILIKE predicates, % matches any number of
+/// characters and _ exactly one
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct LikeCondition {
+    pub aliascol: Option<String>,
+    pub col: String,
+    pub pattern: String,
+    // ILIKE ignores the case
+    pub ignore_case: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
 pub struct Sort {
     pub alias: Option<String>,
     pub col: String,
@@ -202,7 +361,7 @@ pub struct Sort {
 }
 
 /// Allowed operators for where-clause
-#[derive(Debug, Clone, PartialEq, Copy)]
+#[derive(Debug, Clone, PartialEq, Copy, RustcEncodable, RustcDecodable)]
 pub enum CompType {
     Equ,
     NEqu,
@@ -223,13 +382,27 @@ impl CompType {
             &CompType::SEThan => CompType::GThan,
         }
     }
+
+    /// returns the operator to use when both sides are swapped
+    pub fn mirror(&self) -> CompType {
+        match self {
+            &CompType::Equ => CompType::Equ,
+            &CompType::NEqu => CompType::NEqu,
+            &CompType::GThan => CompType::SThan,
+            &CompType::SThan => CompType::GThan,
+            &CompType::GEThan => CompType::SEThan,
+            &CompType::SEThan => CompType::GEThan,
+        }
+    }
 }
 
 /// Allowed data types for where-clause
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
 pub enum CondType {
     Literal(token::Lit),
-    Word(String)
+    Word(String),
+    // scalar subquery, must return at most one row
+    Subquery(Box<SelectStmt>),
 }
 
 #[derive(Debug, PartialEq)]
@@ -241,7 +414,7 @@ pub enum DataSrc {
 
 
 /// Possible values for "Order By" keyword
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
 pub enum Order {
     Asc,
     Desc
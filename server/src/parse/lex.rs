@@ -3,6 +3,9 @@ use std::str::Chars;
 use super::Span;
 use std::iter::{Iterator};
 
+/// Highest parameter number of a statement
+pub const MAX_PARAM: u32 = 65535;
+
 /// A lexer with its associated query, a char iterator, and
 /// positions (last, current, next)
 pub struct Lexer<'a> {
@@ -12,7 +15,9 @@ pub struct Lexer<'a> {
     curr: Option<char>,
     curr_pos: Option<usize>,
     next: Option<char>,
-    span_start: Option<usize>
+    span_start: Option<usize>,
+    // number of ? placeholders so far, they are numbered from left to right
+    params: u32,
 }
 
 impl<'a> Lexer<'a> {
@@ -26,6 +31,7 @@ impl<'a> Lexer<'a> {
             last_pos: None,
             next: None,
             span_start: None,
+            params: 0,
             chs: query.chars()
         };
         lex.dbump();
@@ -44,16 +50,11 @@ impl<'a> Lexer<'a> {
         // Advance last_pos to position of current char
         self.last_pos = self.curr_pos;
 
-        // Next position is current position plus the utf8 length
-        // of the next character
-        match self.next {
-            Some(c) => {
-                self.curr_pos = match self.curr_pos {
-                    Some(n) => Some(n + c.len_utf8()),
-                    None => Some(c.len_utf8()) // Start at pos 0
-                }
-            }
-            _ => {}
+        // Current position is the byte index behind the last char, at the
+        // end of the query it is the length of the query
+        self.curr_pos = match (self.last_pos, self.last) {
+            (Some(n), Some(c)) => Some(n + c.len_utf8()),
+            _ => Some(0) // Start at pos 0
         };
     }
 
@@ -231,11 +232,28 @@ impl<'a> Lexer<'a> {
             //     Token::Bang
             // },
 
-            // //question marks
-            // '?' => {
-            //     self.bump();
-            //     Token::QMark
-            // },
+            // question marks: the next parameter
+            '?' => {
+                self.bump();
+                if self.params >= MAX_PARAM {
+                    return Err(LexError::ParameterOutOfRange)
+                }
+                self.params += 1;
+                Token::Param(self.params)
+            },
+
+            // numbered parameters: $1, $2, ...
+            '$' if nexchar.is_digit(10) => {
+                self.bump();
+                let digits = self.scan_nums();
+                match digits.parse::<u32>() {
+                    Ok(0) => Token::Unknown,
+                    Ok(n) if n <= MAX_PARAM => Token::Param(n),
+                    // the digits only fail to parse if the number is too big
+                    _ if !digits.contains('.') => return Err(LexError::ParameterOutOfRange),
+                    _ => Token::Unknown,
+                }
+            },
 
             // ParenOp
             '(' => {
@@ -348,5 +366,7 @@ impl<'a> Lexer<'a> {
 
 #[derive(PartialEq, Debug)]
 pub enum LexError {
-    UnclosedQuotationmark
+    UnclosedQuotationmark,
+    // $n above MAX_PARAM or too many ? placeholders
+    ParameterOutOfRange,
 }
@@ -12,7 +12,7 @@ pub mod tests;
 pub use self::parser::Parser;
 
 /// Represents a substring in the query string in byte indices.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy, RustcEncodable, RustcDecodable)]
 pub struct Span {
     pub lo: usize,
     pub hi: usize,
@@ -25,3 +25,18 @@ pub fn parse(query: &str) -> Result<ast::Query,parser::ParseError> {
     Parser::create(query).parse()
 
 }
+
+/// Parses a string of several statements separated by semicolons. Returns
+/// the AST or the error of every statement in order.
+pub fn parse_script(query: &str) -> Vec<Result<ast::Query, parser::ParseError>> {
+    Parser::create(query).parse_script()
+}
+
+/// Like `parse_script`, but also returns the text of every statement.
+pub fn parse_script_statements(query: &str)
+    -> Vec<(&str, Result<ast::Query, parser::ParseError>)>
+{
+    Parser::create(query).parse_script_spans().into_iter()
+        .map(|(span, tree)| (&query[span.lo..span.hi], tree))
+        .collect()
+}
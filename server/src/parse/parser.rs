@@ -1,6 +1,7 @@
 ///  Program for testing and playing with the parser
 ///
 
+use std::cmp::max;
 use std::iter::Iterator;
 use super::ast::*;
 use super::token::{TokenSpan, Lit};
@@ -25,7 +26,9 @@ pub struct Parser<'a> {
     // the current token given by the lexer
     curr: Option<TokenSpan>,
     // next token
-    peek: Option<TokenSpan>
+    peek: Option<TokenSpan>,
+    // a semicolon ends the statement, another one may follow
+    script: bool,
 }
 
 impl<'a> Parser<'a> {
@@ -33,13 +36,63 @@ impl<'a> Parser<'a> {
     /// Constructs a Parser for the given query.
     pub fn create(query: &'a str) -> Parser<'a> {
         let l = Lexer::from_query(query);
-        let mut p = Parser { lexiter: l, last: None, curr: None, peek: None };
+        let mut p = Parser { lexiter: l, last: None, curr: None, peek: None, script: false };
         // Sets initial position of lexer and curr/peek
         p.bump();
         p.bump();
         p
     }
 
+    /// Parses a string of statements separated by semicolons into one AST
+    /// per statement. A statement with a syntax error is skipped up to the
+    /// next semicolon, so the following statements are parsed anyway.
+    pub fn parse_script(&mut self) -> Vec<Result<Query, ParseError>> {
+        self.parse_script_spans().into_iter().map(|(_, query)| query).collect()
+    }
+
+    /// Like `parse_script`, but also returns the span of every statement in
+    /// the script, without the semicolon.
+    pub fn parse_script_spans(&mut self) -> Vec<(Span, Result<Query, ParseError>)> {
+        self.script = true;
+        let mut queries = Vec::new();
+        loop {
+            // skips empty statements
+            while self.expect_token(&[Token::Semi]).is_ok() {
+                if let Err(e) = self.bump() {
+                    let end = self.last_end();
+                    queries.push((Span { lo: end, hi: end }, Err(e)));
+                    return queries
+                }
+            }
+            let lo = match self.curr {
+                Some(ref token) => token.span.lo,
+                None => return queries,
+            };
+            let query = self.parse();
+            let mut error = None;
+            if query.is_err() {
+                // skips the rest of the statement
+                while self.curr.is_some() && self.expect_token(&[Token::Semi]).is_err() {
+                    if let Err(e) = self.bump() {
+                        error = Some(e);
+                        break
+                    }
+                }
+            }
+            let hi = max(lo, self.last_end());
+            queries.push((Span { lo: lo, hi: hi }, query));
+            if let Some(e) = error {
+                queries.push((Span { lo: hi, hi: hi }, Err(e)));
+                return queries
+            }
+        }
+    }
+
+    // end of the last token read
+    fn last_end(&self) -> usize {
+        self.last.as_ref().map_or(0, |token| token.span.hi)
+    }
+
     /// Parses the given query into an AST
     pub fn parse(&mut self) -> Result<Query, ParseError> {
         // deletes Whitespaces in the beginning of Query
@@ -47,7 +100,8 @@ impl<'a> Parser<'a> {
         // first token is checked if it's a keyword using expect_keyword()
         let keywords = &[Keyword::Create, Keyword::Drop, Keyword::Alter,
         Keyword::Use, Keyword::Delete, Keyword::Insert, Keyword::Describe,
-        Keyword::Update, Keyword::Select];
+        Keyword::Update, Keyword::Select, Keyword::Explain, Keyword::Analyze,
+        Keyword::Set, Keyword::Grant, Keyword::Revoke];
         let querytype = self.expect_keyword(keywords).map_err(|e| match e {
             ParseError::UnexpectedEoq => ParseError::EmptyQueryError,
             _ => e,
@@ -73,6 +127,25 @@ impl<'a> Parser<'a> {
                     ));
                 Ok(try!(self.return_query_ast(query)))
             },
+            // Grant-Query
+            Keyword::Grant => {
+                // a role is named where the privileges would be
+                let stmt = if self.check_next_name() {
+                    DefStmt::GrantRole(try!(self.parse_role_grant_stmt(Keyword::To)))
+                } else {
+                    DefStmt::Grant(try!(self.parse_grant_stmt(Keyword::To)))
+                };
+                Ok(try!(self.return_query_ast(Query::DefStmt(stmt))))
+            },
+            // Revoke-Query
+            Keyword::Revoke => {
+                let stmt = if self.check_next_name() {
+                    DefStmt::RevokeRole(try!(self.parse_role_grant_stmt(Keyword::From)))
+                } else {
+                    DefStmt::Revoke(try!(self.parse_grant_stmt(Keyword::From)))
+                };
+                Ok(try!(self.return_query_ast(Query::DefStmt(stmt))))
+            },
             // Use-Query
             Keyword::Use => {
                 let query = Query::ManipulationStmt(ManipulationStmt::Use(
@@ -116,6 +189,40 @@ impl<'a> Parser<'a> {
                     ));
                 Ok(try!(self.return_query_ast(query)))
             }
+            //Explain-Query
+            Keyword::Explain => {
+                let query = Query::ManipulationStmt(ManipulationStmt::Explain(
+                    try!(self.parse_explain_stmt())
+                    ));
+                Ok(try!(self.return_query_ast(query)))
+            }
+            //Analyze-Query
+            Keyword::Analyze => {
+                let mut table = None;
+                if self.peek.is_some() && !self.check_next_token(&[Token::Semi]) {
+                    try!(self.bump());
+                    table = Some(try!(self.expect_word(false)));
+                }
+                let query = Query::ManipulationStmt(ManipulationStmt::Analyze(table));
+                Ok(try!(self.return_query_ast(query)))
+            }
+            //Set-Query
+            Keyword::Set => {
+                let query = if self.check_next_keyword(&[Keyword::Role]) {
+                    try!(self.bump());
+                    try!(self.bump());
+                    // SET ROLE NONE goes back to the own privileges
+                    let role = try!(self.expect_word(false));
+                    Query::ManipulationStmt(ManipulationStmt::SetRole(
+                        if role.to_lowercase() == "none" { None } else { Some(role) }
+                        ))
+                } else {
+                    Query::ManipulationStmt(ManipulationStmt::Set(
+                        try!(self.parse_set_stmt())
+                        ))
+                };
+                Ok(try!(self.return_query_ast(query)))
+            }
 
             // Unknown Error
             _ => Err(ParseError::UnknownError)
@@ -144,7 +251,7 @@ impl<'a> Parser<'a> {
         }
 
         match try!(self.expect_keyword(&[Keyword::Table, Keyword::Database, Keyword::View,
-            Keyword::Or])) {
+            Keyword::User, Keyword::Role, Keyword::Or])) {
             // Create the table subtree
             Keyword::Table => Ok(CreateStmt::Table(try!(self.parse_create_table_stmt()))),
             // Create Database subtree
@@ -166,6 +273,13 @@ impl<'a> Parser<'a> {
                     sel: try!(self.parse_select_stmt()),
                 }))
             },
+            // Create User subtree
+            Keyword::User if !view_check => Ok(CreateStmt::User(try!(self.parse_user_stmt()))),
+            // Create Role subtree
+            Keyword::Role if !view_check => {
+                try!(self.bump());
+                Ok(CreateStmt::Role(try!(self.expect_word(false))))
+            },
             _ => Err(ParseError::UnknownError),
         }
     }
@@ -181,11 +295,12 @@ impl<'a> Parser<'a> {
             tid: try!(self.expect_word(false)),
             cols: Vec::<ColumnInfo>::new()
         };
-        try!(self.bump());
-        // if there is a ParenOp token.....
-        if self.curr.is_none() {
+        // a table without columns ends here
+        if self.peek.is_none() || self.check_next_token(&[Token::Semi]) {
             return Ok(table_info)
         }
+        try!(self.bump());
+        // if there is a ParenOp token.....
         try!(self.expect_token(&[Token::ParenOp]));
         // ...call parse_create_column_vec to generate the column vector subtree
         table_info.cols = try!(self.parse_create_column_vec());
@@ -218,8 +333,9 @@ impl<'a> Parser<'a> {
     // Parses tokens for alter statement
     fn parse_alt_stmt(&mut self) -> Result<AltStmt, ParseError> {
         try!(self.bump());
-        match try!(self.expect_keyword(&[Keyword::Table])) {
+        match try!(self.expect_keyword(&[Keyword::Table, Keyword::User])) {
             Keyword::Table => Ok(AltStmt::Table(try!(self.parse_alter_table_stmt()))),
+            Keyword::User => Ok(AltStmt::User(try!(self.parse_user_stmt()))),
 
             // Unknown parsing error
             _ => Err(ParseError::UnknownError),
@@ -236,6 +352,19 @@ impl<'a> Parser<'a> {
         Ok(alt_table_stmt)
     }
 
+    // Parses the name and the password of an account
+    fn parse_user_stmt(&mut self) -> Result<UserStmt, ParseError> {
+        try!(self.bump());
+        let name = try!(self.expect_word(false));
+        try!(self.bump());
+        try!(self.expect_keyword(&[Keyword::Password]));
+        try!(self.bump());
+        match try!(self.expect_literal()) {
+            Lit::String(password) => Ok(UserStmt { name: name, password: password }),
+            _ => Err(ParseError::PasswordIsNoString),
+        }
+    }
+
     // Parses operations applied on selected table including tablename and
     // datatype if necessary
     fn parse_alter_op(&mut self) -> Result<AlterOp, ParseError> {
@@ -264,11 +393,20 @@ impl<'a> Parser<'a> {
     // Parses the tokens for drop statement
     fn parse_drop_stmt(&mut self) -> Result<DropStmt, ParseError> {
         try!(self.bump());
-        match try!(self.expect_keyword(&[Keyword::Table, Keyword::Database, Keyword::View])) {
+        match try!(self.expect_keyword(&[Keyword::Table, Keyword::Database, Keyword::View,
+            Keyword::User, Keyword::Role])) {
             Keyword::Table => {
                 try!(self.bump());
                 Ok(DropStmt::Table(try!(self.expect_word(false))))
             },
+            Keyword::User => {
+                try!(self.bump());
+                Ok(DropStmt::User(try!(self.expect_word(false))))
+            },
+            Keyword::Role => {
+                try!(self.bump());
+                Ok(DropStmt::Role(try!(self.expect_word(false))))
+            },
             Keyword::Database => {
                 try!(self.bump());
                 Ok(DropStmt::Database(try!(self.expect_word(false))))
@@ -281,6 +419,78 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // Parses the privileges, the database or table and the user of grant and
+    // revoke statements. The user follows `to` or `from`.
+    fn parse_grant_stmt(&mut self, user_keyword: Keyword) -> Result<GrantStmt, ParseError> {
+        try!(self.bump());
+        let mut privileges = Vec::new();
+        if self.expect_keyword(&[Keyword::All]).is_ok() {
+            privileges = vec![Privilege::Select, Privilege::Insert, Privilege::Update,
+                Privilege::Delete, Privilege::Create, Privilege::Drop, Privilege::Alter];
+            if self.check_next_keyword(&[Keyword::Privileges]) {
+                try!(self.bump());
+            }
+            try!(self.bump());
+        } else {
+            loop {
+                privileges.push(match try!(self.expect_keyword(&[Keyword::Select,
+                    Keyword::Insert, Keyword::Update, Keyword::Delete, Keyword::Create,
+                    Keyword::Drop, Keyword::Alter]))
+                {
+                    Keyword::Select => Privilege::Select,
+                    Keyword::Insert => Privilege::Insert,
+                    Keyword::Update => Privilege::Update,
+                    Keyword::Delete => Privilege::Delete,
+                    Keyword::Create => Privilege::Create,
+                    Keyword::Drop => Privilege::Drop,
+                    Keyword::Alter => Privilege::Alter,
+                    _ => return Err(ParseError::UnknownError),
+                });
+                try!(self.bump());
+                if self.expect_token(&[Token::Comma]).is_err() {
+                    break
+                }
+                try!(self.bump());
+            }
+        }
+        try!(self.expect_keyword(&[Keyword::On]));
+        try!(self.bump());
+        let on = if self.expect_keyword(&[Keyword::Database]).is_ok() {
+            try!(self.bump());
+            PrivilegeLevel::Database(try!(self.expect_word(false)))
+        } else {
+            if self.expect_keyword(&[Keyword::Table]).is_ok() {
+                try!(self.bump());
+            }
+            let name = try!(self.expect_word(false));
+            // database.table
+            if self.check_next_token(&[Token::Dot]) {
+                try!(self.bump());
+                try!(self.bump());
+                PrivilegeLevel::Table(Some(name), try!(self.expect_word(false)))
+            } else {
+                PrivilegeLevel::Table(None, name)
+            }
+        };
+        try!(self.bump());
+        try!(self.expect_keyword(&[user_keyword]));
+        try!(self.bump());
+        Ok(GrantStmt { privileges: privileges, on: on, user: try!(self.expect_word(false)) })
+    }
+
+    // Parses the role and the member of grant and revoke statements for
+    // roles. The member follows `to` or `from`.
+    fn parse_role_grant_stmt(&mut self, member_keyword: Keyword)
+        -> Result<RoleGrantStmt, ParseError>
+    {
+        try!(self.bump());
+        let role = try!(self.expect_word(false));
+        try!(self.bump());
+        try!(self.expect_keyword(&[member_keyword]));
+        try!(self.bump());
+        Ok(RoleGrantStmt { role: role, member: try!(self.expect_word(false)) })
+    }
+
     // Parses the tokens for use statement
     fn parse_use_stmt(&mut self) -> Result<UseStmt, ParseError> {
         try!(self.bump());
@@ -293,6 +503,16 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // Parses the tokens for set statement
+    fn parse_set_stmt(&mut self) -> Result<SetStmt, ParseError> {
+        try!(self.bump());
+        let name = try!(self.expect_word(false));
+        try!(self.bump());
+        try!(self.expect_token(&[Token::Equ]));
+        try!(self.bump());
+        Ok(SetStmt { name: name, value: try!(self.expect_literal()) })
+    }
+
     // Parses tokens for insert statement
     fn parse_insert_stmt(&mut self) -> Result<InsertStmt, ParseError> {
         try!(self.bump());
@@ -457,6 +677,10 @@ impl<'a> Parser<'a> {
     fn parse_select_stmt(&mut self) -> Result<SelectStmt, ParseError>{
         let mut targetvec = Vec::new();
         let mut done = false;
+        let distinct = self.check_next_keyword(&[Keyword::Distinct]);
+        if distinct {
+            try!(self.bump());
+        }
         // parsing optional targets, at least one
         while !done
         {
@@ -468,12 +692,21 @@ impl<'a> Parser<'a> {
                 try!(self.bump());
                 try!(self.bump());
             };
-            // required target column
-            let targetcol = match self.expect_token(&[Token::Star]) {
-                Err(err) => Col::Specified(try!(self.expect_word(true))),
-                Ok(Token::Star) => Col::Every,
-                _ => return Err(ParseError::UnknownError) ,
-                };
+            // required target column, scalar subquery or expression
+            let targetcol = if self.expect_token(&[Token::ParenOp]).is_ok() {
+                Col::Subquery(Box::new(try!(self.parse_subquery())))
+            } else if targetalias.is_none() && (self.check_next_token(&[Token::ParenOp])
+                || self.expect_keyword(&[Keyword::Case]).is_ok()
+                || self.expect_literal().is_ok())
+            {
+                Col::Expr(try!(self.parse_expr()))
+            } else {
+                match self.expect_token(&[Token::Star]) {
+                    Err(err) => Col::Specified(try!(self.expect_word(true))),
+                    Ok(Token::Star) => Col::Every,
+                    _ => return Err(ParseError::UnknownError) ,
+                }
+            };
             try!(self.bump());
             // optional target column rename
             let mut targetrename = None;
@@ -492,23 +725,37 @@ impl<'a> Parser<'a> {
         try!(self.expect_keyword(&[Keyword::From]));
         let mut tidvec = Vec::new();
         let mut aliasmap = HashMap::new();
+        let mut derivedmap = HashMap::new();
         done = false;
         // parsing optional tables
         while !done
         {
             try!(self.bump());
-            let tableid = try!(self.expect_word(false));
-            if !self.check_next_keyword(&[Keyword::Where, Keyword::Limit,
-                Keyword::Group, Keyword::Order])
-            && !self.check_next_token(&[Token::Comma]) {
+            // derived table: a subquery which needs a name
+            if self.expect_token(&[Token::ParenOp]).is_ok() {
+                let subquery = try!(self.parse_subquery());
                 try!(self.bump());
-                match self.expect_word(false) {
-                    Err(ParseError::UnexpectedEoq) => (),
-                    Err(err) => return Err(err),
-                    Ok(s) => { aliasmap.insert(s.clone(), tableid.clone()); () },
+                if self.expect_keyword(&[Keyword::As]).is_ok() {
+                    try!(self.bump());
                 }
+                let name = try!(self.expect_word(false));
+                derivedmap.insert(name.clone(), subquery);
+                tidvec.push(name);
+            } else {
+                let tableid = try!(self.expect_word(false));
+                if !self.check_next_keyword(&[Keyword::Where, Keyword::Limit,
+                    Keyword::Group, Keyword::Order, Keyword::Union, Keyword::Intersect,
+                    Keyword::Except])
+                && !self.check_next_token(&[Token::Comma, Token::ParenCl]) {
+                    try!(self.bump());
+                    match self.expect_word(false) {
+                        Err(ParseError::UnexpectedEoq) => (),
+                        Err(err) => return Err(err),
+                        Ok(s) => { aliasmap.insert(s.clone(), tableid.clone()); () },
+                    }
+                }
+                tidvec.push(tableid);
             }
-            tidvec.push(tableid);
             if !self.check_next_token(&[Token::Comma]) {
                 done = true;
                 try!(self.bump());
@@ -522,6 +769,42 @@ impl<'a> Parser<'a> {
         if self.expect_keyword(&[Keyword::Where]).is_ok() {
             conditions = Some(try!(self.parse_where_part()));
         }
+        // set operations, ORDER BY and LIMIT of the last select apply to the
+        // whole result
+        let setop = match self.expect_keyword(&[Keyword::Union, Keyword::Intersect,
+            Keyword::Except])
+        {
+            Ok(Keyword::Union) => Some(SetOp::Union),
+            Ok(Keyword::Intersect) => Some(SetOp::Intersect),
+            Ok(Keyword::Except) => Some(SetOp::Except),
+            _ => None,
+        };
+        if let Some(op) = setop {
+            try!(self.bump());
+            let all = self.expect_keyword(&[Keyword::All]).is_ok();
+            if all {
+                try!(self.bump());
+            }
+            try!(self.expect_keyword(&[Keyword::Select]));
+            let mut right = try!(self.parse_select_stmt());
+            swap(&mut order_vec, &mut right.order);
+            let limit = right.limit.take();
+            let mut compound = Vec::new();
+            swap(&mut compound, &mut right.compound);
+            compound.insert(0, Compound { op: op, all: all, select: Box::new(right) });
+            return Ok(SelectStmt {
+                target: targetvec,
+                tid: tidvec,
+                alias: aliasmap,
+                derived: derivedmap,
+                distinct: distinct,
+                compound: compound,
+                cond: conditions,
+                spec_op: None,
+                order: order_vec,
+                limit: limit,
+            })
+        }
         if self.expect_keyword(&[Keyword::Group]).is_ok() {
             try!(self.bump());
             try!(self.expect_keyword(&[Keyword::By]));
@@ -587,6 +870,9 @@ impl<'a> Parser<'a> {
             target: targetvec,
             tid: tidvec,
             alias: aliasmap,
+            derived: derivedmap,
+            distinct: distinct,
+            compound: Vec::new(),
             cond: conditions,
             spec_op: None,
             order : order_vec,
@@ -609,51 +895,81 @@ impl<'a> Parser<'a> {
     // checks, if query is ended correctly. if yes -> returns query as ast
     fn return_query_ast(&mut self, query: Query) -> Result<Query, ParseError> {
         try!(self.bump());
-        if self.curr.is_none() {
+        if self.curr.is_none() || (self.script && self.expect_token(&[Token::Semi]).is_ok()) {
             Ok(query)
         } else {
             Err(ParseError::InvalidEoq)
         }
     }
-    // parses the where part into Conditions type
+    // parses EXPLAIN [ANALYZE] followed by a select
+    fn parse_explain_stmt(&mut self) -> Result<ExplainStmt, ParseError> {
+        try!(self.bump());
+        let analyze = self.expect_keyword(&[Keyword::Analyze]).is_ok();
+        if analyze {
+            try!(self.bump());
+        }
+        try!(self.expect_keyword(&[Keyword::Select]));
+        Ok(ExplainStmt {
+            analyze: analyze,
+            select: try!(self.parse_select_stmt()),
+        })
+    }
+    // parses a select in parenthesis, curr has to be the opening parenthesis.
+    // Afterwards curr is the closing parenthesis.
+    fn parse_subquery(&mut self) -> Result<SelectStmt, ParseError> {
+        try!(self.bump());
+        try!(self.expect_keyword(&[Keyword::Select]));
+        let stmt = try!(self.parse_select_stmt());
+        // depending on the last clause the select stops either on its last
+        // token or on the one after it
+        if self.expect_token(&[Token::ParenCl]).is_err() {
+            try!(self.bump());
+        }
+        try!(self.expect_token(&[Token::ParenCl]).map_err(|e| match e {
+            ParseError::WrongToken(span) => ParseError::MissingParenthesis(span),
+            _ => e,
+        }));
+        Ok(stmt)
+    }
+
+    // parses the where part into Conditions type, AND binds stronger than
+    // OR. curr has to be the token before the conditions and ends up on the
+    // token after them
     fn parse_where_part(&mut self) -> Result<Conditions, ParseError> {
-        let mut cond;
+        let cond = try!(self.parse_and_part());
+        if self.expect_keyword(&[Keyword::Or]).is_ok() {
+            return Ok(Conditions::Or(Box::new(cond), Box::new(try!(self.parse_where_part()))))
+        }
+        Ok(cond)
+    }
+
+    // parses conditions connected by AND
+    fn parse_and_part(&mut self) -> Result<Conditions, ParseError> {
+        let mut cond = try!(self.parse_not_part());
+        while self.expect_keyword(&[Keyword::And]).is_ok() {
+            cond = Conditions::And(Box::new(cond), Box::new(try!(self.parse_not_part())));
+        }
+        Ok(cond)
+    }
+
+    // parses a predicate, a negation or a group in parentheses
+    fn parse_not_part(&mut self) -> Result<Conditions, ParseError> {
+        if self.check_next_keyword(&[Keyword::Not]) {
+            try!(self.bump());
+            return Ok(Conditions::Not(Box::new(try!(self.parse_not_part()))))
+        }
         if self.check_next_token(&[Token::ParenOp]) {
             try!(self.bump());
-            cond = try!(self.parse_where_part());
+            let cond = try!(self.parse_where_part());
             try!(self.expect_token(&[Token::ParenCl]).map_err(|e| match e {
                 ParseError::WrongToken(span) => ParseError::MissingParenthesis(span),
                 _ => e,
             }));
-            if self.check_next_keyword(&[Keyword::Or,Keyword::And]) {
-                try!(self.bump());
-                if self.expect_keyword(&[Keyword::Or]).is_ok() {
-                    cond = Conditions::Or(
-                        Box::new(cond),Box::new(try!(self.parse_where_part())));
-                } else if self.expect_keyword(&[Keyword::And]).is_ok(){
-                    cond = Conditions::And(
-                        Box::new(cond),Box::new(try!(self.parse_where_part())));
-                };
-            }
-        } else {
-            cond = Conditions::Leaf(try!(self.parse_condition()));
             try!(self.bump());
-            while self.expect_keyword(&[Keyword::And, Keyword::Or]).is_ok() {
-                if self.expect_keyword(&[Keyword::Or]).is_ok() {
-                    cond = Conditions::Or(Box::new(cond),Box::new(try!(self.parse_where_part())));
-                } else {
-                    if self.check_next_token(&[Token::ParenOp]) {
-                        cond = Conditions::And(
-                            Box::new(cond),
-                            Box::new(try!(self.parse_where_part())));
-                    } else {
-                        cond = Conditions::And(Box::new(cond),
-                            Box::new(Conditions::Leaf(try!(self.parse_condition()))));
-                        try!(self.bump());
-                    };
-                };
-            };
+            return Ok(cond)
         }
+        let cond = try!(self.parse_predicate());
+        try!(self.bump());
         Ok(cond)
     }
 
@@ -664,6 +980,17 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // checks if the next token is a word which is no keyword
+    fn check_next_name(&self) -> bool {
+        match self.peek {
+            Some(ref token) => match token.tok {
+                Token::Word(ref s) => keyword_from_string(s).is_none(),
+                _ => false,
+            },
+            None => false,
+        }
+    }
+
     fn check_next_keyword(&self, checkkeyword: &[Keyword]) -> bool {
         let tokenspan = match self.peek {
             Some(ref s) => s.clone(),
@@ -678,9 +1005,14 @@ impl<'a> Parser<'a> {
             None => false
         }
     }
-    // aprses a single condition
-    fn parse_condition(&mut self) -> Result<Condition, ParseError> {
+    // parses a single predicate: a comparison, IN or EXISTS
+    fn parse_predicate(&mut self) -> Result<Conditions, ParseError> {
         try!(self.bump());
+        if self.expect_keyword(&[Keyword::Exists]).is_ok() {
+            try!(self.bump());
+            try!(self.expect_token(&[Token::ParenOp]));
+            return Ok(Conditions::Exists(Box::new(try!(self.parse_subquery()))))
+        }
         let mut alias = None;
         if self.check_next_token(&[Token::Dot]) {
             alias = Some(try!(self.expect_word(false)));
@@ -689,6 +1021,160 @@ impl<'a> Parser<'a> {
         };
         let columnname = try!(self.expect_word(true));
         try!(self.bump());
+        // col NOT IN/BETWEEN/LIKE
+        let negated = self.expect_keyword(&[Keyword::Not]).is_ok();
+        if negated {
+            try!(self.bump());
+            try!(self.expect_keyword(&[Keyword::In, Keyword::Between, Keyword::Like,
+                Keyword::ILike]));
+        }
+        let cond = if self.expect_keyword(&[Keyword::In]).is_ok() {
+            try!(self.bump());
+            try!(self.expect_token(&[Token::ParenOp]));
+            Conditions::In(InCondition {
+                aliascol: alias,
+                col: columnname,
+                set: try!(self.parse_in_set()),
+            })
+        } else if self.expect_keyword(&[Keyword::Between]).is_ok() {
+            try!(self.bump());
+            let low = try!(self.parse_bound());
+            try!(self.bump());
+            try!(self.expect_keyword(&[Keyword::And]));
+            try!(self.bump());
+            let high = try!(self.parse_bound());
+            Conditions::Between(BetweenCondition {
+                aliascol: alias,
+                col: columnname,
+                low: low,
+                high: high,
+            })
+        } else if self.expect_keyword(&[Keyword::Like, Keyword::ILike]).is_ok() {
+            let ignore_case = self.expect_keyword(&[Keyword::ILike]).is_ok();
+            try!(self.bump());
+            let pattern = match try!(self.expect_literal()) {
+                Lit::String(s) => s,
+                _ => return Err(ParseError::LikePatternNoString),
+            };
+            Conditions::Like(LikeCondition {
+                aliascol: alias,
+                col: columnname,
+                pattern: pattern,
+                ignore_case: ignore_case,
+            })
+        } else {
+            Conditions::Leaf(try!(self.parse_condition(alias, columnname)))
+        };
+        if negated {
+            Ok(Conditions::Not(Box::new(cond)))
+        } else {
+            Ok(cond)
+        }
+    }
+
+    // parses a bound of BETWEEN, a literal or a column. curr has to be the
+    // first token and ends up on the last one
+    fn parse_bound(&mut self) -> Result<Expr, ParseError> {
+        if let Ok(lit) = self.expect_literal() {
+            return Ok(Expr::Literal(lit))
+        }
+        let word = try!(self.expect_word(true));
+        if self.check_next_token(&[Token::Dot]) {
+            try!(self.bump());
+            try!(self.bump());
+            return Ok(Expr::Column(Some(word), try!(self.expect_word(true))))
+        }
+        Ok(Expr::Column(None, word))
+    }
+
+    // parses a column, literal, function call or CASE expression, curr has
+    // to be the first token and ends up on the last one
+    fn parse_expr(&mut self) -> Result<Expr, ParseError> {
+        if self.expect_keyword(&[Keyword::Case]).is_ok() {
+            return self.parse_case()
+        }
+        if let Ok(lit) = self.expect_literal() {
+            return Ok(Expr::Literal(lit))
+        }
+        let word = try!(self.expect_word(true));
+        if self.check_next_token(&[Token::Dot]) {
+            try!(self.bump());
+            try!(self.bump());
+            return Ok(Expr::Column(Some(word), try!(self.expect_word(true))))
+        }
+        if !self.check_next_token(&[Token::ParenOp]) {
+            return Ok(Expr::Column(None, word))
+        }
+        try!(self.bump());
+        let mut args = Vec::new();
+        if self.check_next_token(&[Token::ParenCl]) {
+            try!(self.bump());
+        } else {
+            loop {
+                try!(self.bump());
+                args.push(try!(self.parse_expr()));
+                try!(self.bump());
+                if self.expect_token(&[Token::ParenCl]).is_ok() {
+                    break;
+                }
+                try!(self.expect_token(&[Token::Comma]).map_err(|e| match e {
+                    ParseError::WrongToken(span) => ParseError::MissingParenthesis(span),
+                    _ => e,
+                }));
+            }
+        }
+        Ok(Expr::Function(word.to_lowercase(), args))
+    }
+
+    // parses CASE WHEN cond THEN expr ... [ELSE expr] END, curr has to be
+    // CASE and ends up on END
+    fn parse_case(&mut self) -> Result<Expr, ParseError> {
+        let mut whens = Vec::new();
+        try!(self.bump());
+        try!(self.expect_keyword(&[Keyword::When]));
+        while self.expect_keyword(&[Keyword::When]).is_ok() {
+            let cond = try!(self.parse_where_part());
+            try!(self.expect_keyword(&[Keyword::Then]));
+            try!(self.bump());
+            whens.push((cond, try!(self.parse_expr())));
+            try!(self.bump());
+        }
+        let mut default = None;
+        if self.expect_keyword(&[Keyword::Else]).is_ok() {
+            try!(self.bump());
+            default = Some(Box::new(try!(self.parse_expr())));
+            try!(self.bump());
+        }
+        try!(self.expect_keyword(&[Keyword::End]));
+        Ok(Expr::Case(whens, default))
+    }
+
+    // parses a subquery or a list of literals, curr has to be the opening
+    // parenthesis and ends up on the closing one
+    fn parse_in_set(&mut self) -> Result<InSet, ParseError> {
+        if self.check_next_keyword(&[Keyword::Select]) {
+            return Ok(InSet::Select(Box::new(try!(self.parse_subquery()))))
+        }
+        let mut values = Vec::new();
+        loop {
+            try!(self.bump());
+            values.push(try!(self.expect_literal()));
+            try!(self.bump());
+            if self.expect_token(&[Token::ParenCl]).is_ok() {
+                break;
+            }
+            try!(self.expect_token(&[Token::Comma]).map_err(|e| match e {
+                ParseError::WrongToken(span) => ParseError::MissingParenthesis(span),
+                _ => e,
+            }));
+        }
+        Ok(InSet::List(values))
+    }
+
+    // parses a single comparison, curr has to be the operator
+    fn parse_condition(&mut self, alias: Option<String>, columnname: String)
+        -> Result<Condition, ParseError>
+    {
         let operation = match try!(self.expect_token(&[Token::Equ, Token::GThan,
         Token::SThan, Token::GEThan,
         Token::NEqu, Token::SEThan])) {
@@ -703,16 +1189,20 @@ impl<'a> Parser<'a> {
         };
         try!(self.bump());
         let mut rhsalias = None;
-        let rhs = match self.expect_word(false) {
-            Ok(s) => {
-                if self.check_next_token(&[Token::Dot]) {
-                    rhsalias = Some(s);
-                    try!(self.bump());
-                    try!(self.bump());
-                }
-                CondType::Word(try!(self.expect_word(true)))
-            },
-            _ => CondType::Literal(try!(self.expect_literal())),
+        let rhs = if self.expect_token(&[Token::ParenOp]).is_ok() {
+            CondType::Subquery(Box::new(try!(self.parse_subquery())))
+        } else {
+            match self.expect_word(false) {
+                Ok(s) => {
+                    if self.check_next_token(&[Token::Dot]) {
+                        rhsalias = Some(s);
+                        try!(self.bump());
+                        try!(self.bump());
+                    }
+                    CondType::Word(try!(self.expect_word(true)))
+                },
+                _ => CondType::Literal(try!(self.expect_literal())),
+            }
         };
         Ok(Condition {
             aliascol: alias,
@@ -889,6 +1379,7 @@ impl<'a> Parser<'a> {
                     }
                 }
                 Token::Literal(ref s) => s.clone(),
+                Token::Param(n) => Lit::Param(n),
                 _ => return Err(ParseError::NotALiteral(
                  Span { lo: span_lo , hi: span_hi }
                  ))
@@ -1012,6 +1503,31 @@ fn keyword_from_string(string: &str) -> Option<Keyword> {
                 "not" => Some(Keyword::Not),
                 "null" => Some(Keyword::Null),
                 "comment" => Some(Keyword::Comment),
+                "in" => Some(Keyword::In),
+                "exists" => Some(Keyword::Exists),
+                "between" => Some(Keyword::Between),
+                "distinct" => Some(Keyword::Distinct),
+                "union" => Some(Keyword::Union),
+                "intersect" => Some(Keyword::Intersect),
+                "except" => Some(Keyword::Except),
+                "all" => Some(Keyword::All),
+                "case" => Some(Keyword::Case),
+                "when" => Some(Keyword::When),
+                "then" => Some(Keyword::Then),
+                "else" => Some(Keyword::Else),
+                "end" => Some(Keyword::End),
+                "like" => Some(Keyword::Like),
+                "ilike" => Some(Keyword::ILike),
+                "explain" => Some(Keyword::Explain),
+                "analyze" => Some(Keyword::Analyze),
+                "user" => Some(Keyword::User),
+                "password" => Some(Keyword::Password),
+                "grant" => Some(Keyword::Grant),
+                "revoke" => Some(Keyword::Revoke),
+                "on" => Some(Keyword::On),
+                "to" => Some(Keyword::To),
+                "privileges" => Some(Keyword::Privileges),
+                "role" => Some(Keyword::Role),
                 _ => None,
             }
 }
@@ -1031,17 +1547,24 @@ pub enum Keyword {
     Alter,
     Use,
     Describe,
+    Explain,
     // data manipulation keywords
     Select,
     Update,
     Insert,
     Delete,
     Set,
+    // data control keywords
+    Grant,
+    Revoke,
     // 2nd level keywords
     Table,
     Database,
+    Analyze,
     View,
     Column,
+    User,
+    Role,
     // 3rd level keywords
     From,
     Where,
@@ -1066,6 +1589,25 @@ pub enum Keyword {
     Not,
     Null,
     Comment,
+    In,
+    Exists,
+    Between,
+    Like,
+    ILike,
+    Distinct,
+    Union,
+    Intersect,
+    Except,
+    All,
+    Case,
+    When,
+    Then,
+    Else,
+    End,
+    Password,
+    On,
+    To,
+    Privileges,
 }
 
 #[derive(Debug, PartialEq)]
@@ -1093,6 +1635,8 @@ pub enum ParseError {
     LimitError,
     ReservedKeyword(Span),
     CommentIsNoString,
+    LikePatternNoString,
+    PasswordIsNoString,
     //Used for debugging
     DebugError(String)
 // TODO: introduce good errors and think more about it
@@ -4,7 +4,7 @@
 use std::iter::Iterator;
 use super::ast::*;
 use super::token:: {TokenSpan, Lit};
-use super::lex::Lexer;
+use super::lex::{Lexer, LexError};
 use std::mem::swap;
 use super::token::Token;
 use super::Span;
@@ -110,6 +110,95 @@ fn test_create_database() {
         CreateStmt::Database("foo".to_string()))));
 }
 
+#[test]
+fn test_create_user() {
+    let mut p = parser::Parser::create("create user bob password 'secret'");
+
+    assert_eq!(p.parse().unwrap(), Query::DefStmt(DefStmt::Create(
+        CreateStmt::User(UserStmt {
+            name: "bob".to_string(),
+            password: "secret".to_string(),
+        }))));
+}
+
+#[test]
+fn test_alter_user() {
+    let mut p = parser::Parser::create("alter user bob password 'new'");
+
+    assert_eq!(p.parse().unwrap(), Query::DefStmt(DefStmt::Alter(
+        AltStmt::User(UserStmt {
+            name: "bob".to_string(),
+            password: "new".to_string(),
+        }))));
+
+    let mut p = parser::Parser::create("alter user bob password 42");
+    assert_eq!(p.parse(), Err(parser::ParseError::PasswordIsNoString));
+}
+
+#[test]
+fn test_grant() {
+    let mut p = parser::Parser::create("grant select, insert on table foo to bob");
+
+    assert_eq!(p.parse().unwrap(), Query::DefStmt(DefStmt::Grant(GrantStmt {
+        privileges: vec![Privilege::Select, Privilege::Insert],
+        on: PrivilegeLevel::Table(None, "foo".to_string()),
+        user: "bob".to_string(),
+    })));
+
+    let mut p = parser::Parser::create("grant select on shop.foo to bob");
+
+    assert_eq!(p.parse().unwrap(), Query::DefStmt(DefStmt::Grant(GrantStmt {
+        privileges: vec![Privilege::Select],
+        on: PrivilegeLevel::Table(Some("shop".to_string()), "foo".to_string()),
+        user: "bob".to_string(),
+    })));
+}
+
+#[test]
+fn test_revoke() {
+    let mut p = parser::Parser::create("revoke all privileges on database shop from bob");
+
+    assert_eq!(p.parse().unwrap(), Query::DefStmt(DefStmt::Revoke(GrantStmt {
+        privileges: vec![Privilege::Select, Privilege::Insert, Privilege::Update,
+            Privilege::Delete, Privilege::Create, Privilege::Drop, Privilege::Alter],
+        on: PrivilegeLevel::Database("shop".to_string()),
+        user: "bob".to_string(),
+    })));
+}
+
+#[test]
+fn test_roles() {
+    let mut p = parser::Parser::create("create role engineers");
+    assert_eq!(p.parse().unwrap(), Query::DefStmt(DefStmt::Create(
+        CreateStmt::Role("engineers".to_string()))));
+
+    let mut p = parser::Parser::create("grant engineers to bob");
+    assert_eq!(p.parse().unwrap(), Query::DefStmt(DefStmt::GrantRole(RoleGrantStmt {
+        role: "engineers".to_string(),
+        member: "bob".to_string(),
+    })));
+
+    let mut p = parser::Parser::create("revoke engineers from staff");
+    assert_eq!(p.parse().unwrap(), Query::DefStmt(DefStmt::RevokeRole(RoleGrantStmt {
+        role: "engineers".to_string(),
+        member: "staff".to_string(),
+    })));
+
+    let mut p = parser::Parser::create("drop role engineers");
+    assert_eq!(p.parse().unwrap(), Query::DefStmt(DefStmt::Drop(
+        DropStmt::Role("engineers".to_string()))));
+}
+
+#[test]
+fn test_set_role() {
+    let mut p = parser::Parser::create("set role engineers");
+    assert_eq!(p.parse().unwrap(), Query::ManipulationStmt(
+        ManipulationStmt::SetRole(Some("engineers".to_string()))));
+
+    let mut p = parser::Parser::create("set role none");
+    assert_eq!(p.parse().unwrap(), Query::ManipulationStmt(ManipulationStmt::SetRole(None)));
+}
+
 #[test]
 fn test_alter_table_add_column() {
     let mut p = parser::Parser::create("alter table foo add bar int");
@@ -197,6 +286,14 @@ fn test_drop_view() {
         DropStmt::View("foo".to_string()))));
 }
 
+#[test]
+fn test_drop_user() {
+    let mut p = parser::Parser::create("drop user bob");
+
+    assert_eq!(p.parse().unwrap(), Query::DefStmt(DefStmt::Drop(
+        DropStmt::User("bob".to_string()))));
+}
+
 #[test]
 fn test_use_database() {
     let mut p = parser::Parser::create("use database foo");
@@ -213,6 +310,30 @@ fn test_describe_column() {
         ManipulationStmt::Describe("foo".to_string())));
 }
 
+#[test]
+fn test_analyze() {
+    let mut p = parser::Parser::create("analyze foo");
+
+    assert_eq!(p.parse().unwrap(), Query::ManipulationStmt(
+        ManipulationStmt::Analyze(Some("foo".to_string()))));
+
+    let mut p = parser::Parser::create("analyze");
+
+    assert_eq!(p.parse().unwrap(), Query::ManipulationStmt(
+        ManipulationStmt::Analyze(None)));
+}
+
+#[test]
+fn test_set() {
+    let mut p = parser::Parser::create("set statement_timeout = 500");
+
+    assert_eq!(p.parse().unwrap(), Query::ManipulationStmt(
+        ManipulationStmt::Set(SetStmt {
+            name: "statement_timeout".to_string(),
+            value: Lit::Int(500),
+        })));
+}
+
 #[test]
 fn test_insert_1() {
     let mut p = parser::Parser::create("insert into foo values
@@ -228,6 +349,61 @@ fn test_insert_1() {
     })));
 }
 
+#[test]
+fn test_parse_script() {
+    let mut p = parser::Parser::create(
+        "describe foo; insert into foo values ('a;b');; describe; analyze");
+
+    let queries = p.parse_script();
+    assert_eq!(queries.len(), 4);
+    assert_eq!(queries[0], Ok(Query::ManipulationStmt(
+        ManipulationStmt::Describe("foo".to_string()))));
+    assert_eq!(queries[1], Ok(Query::ManipulationStmt(
+        ManipulationStmt::Insert(InsertStmt {
+            tid: "foo".to_string(),
+            col: Vec::<String>::new(),
+            val: vec![Lit::String("a;b".to_string())],
+    }))));
+    // the error does not stop the following statements
+    assert!(queries[2].is_err());
+    assert_eq!(queries[3], Ok(Query::ManipulationStmt(
+        ManipulationStmt::Analyze(None))));
+}
+
+#[test]
+fn test_parse_script_statements() {
+    let statements = super::parse_script_statements(
+        "describe foo; insert into foo values ('a;b');; describe;\nanalyze");
+
+    let texts: Vec<_> = statements.iter().map(|&(text, _)| text).collect();
+    assert_eq!(texts, vec!["describe foo", "insert into foo values ('a;b')", "describe",
+        "analyze"]);
+    assert!(statements[2].1.is_err());
+}
+
+#[test]
+fn test_insert_params() {
+    let mut p = parser::Parser::create("insert into foo values (?, $3, ?)");
+
+    assert_eq!(p.parse().unwrap(), Query::ManipulationStmt(
+        ManipulationStmt::Insert(InsertStmt {
+            tid: "foo".to_string(),
+            col: Vec::<String>::new(),
+            val: vec![Lit::Param(1), Lit::Param(3), Lit::Param(2)],
+    })));
+}
+
+#[test]
+fn test_param_out_of_range() {
+    for query in &["select * from foo where id = $65536",
+                   "select * from foo where id = $4294967295",
+                   "select * from foo where id = $99999999999999999999"] {
+        assert_eq!(parser::Parser::create(query).parse(),
+            Err(parser::ParseError::LexError(LexError::ParameterOutOfRange)));
+    }
+    assert!(parser::Parser::create("select * from foo where id = $65535").parse().is_ok());
+}
+
 #[test]
 fn test_insert_2() {
     let mut p = parser::Parser::create("insert into foo () values
@@ -306,6 +482,9 @@ fn test_select_full_with_table_alias() {
             }],
             tid: selected_tables,
             alias: aliashm,
+            derived: HashMap::new(),
+            distinct: false,
+            compound: Vec::new(),
             cond: None,
             spec_op: None,
             order: Vec::new(),
@@ -329,6 +508,9 @@ fn test_select_specific_column() {
             }],
             tid: selected_tables,
             alias: aliashm,
+            derived: HashMap::new(),
+            distinct: false,
+            compound: Vec::new(),
             cond: None,
             spec_op: None,
             order: Vec::new(),
@@ -356,6 +538,9 @@ fn test_select_specific_columns() {
             }],
             tid: selected_tables,
             alias: aliashm,
+            derived: HashMap::new(),
+            distinct: false,
+            compound: Vec::new(),
             cond: None,
             spec_op: None,
             order: Vec::new(),
@@ -383,6 +568,9 @@ fn test_select_specific_columns_alias() {
             }],
             tid: selected_tables,
             alias: aliashm,
+            derived: HashMap::new(),
+            distinct: false,
+            compound: Vec::new(),
             cond: None,
             spec_op: None,
             order: Vec::new(),
@@ -410,6 +598,9 @@ fn test_select_specific_columns_alias_dot() {
             }],
             tid: selected_tables,
             alias: aliashm,
+            derived: HashMap::new(),
+            distinct: false,
+            compound: Vec::new(),
             cond: None,
             spec_op: None,
             order: Vec::new(),
@@ -435,6 +626,9 @@ fn test_select_full_where_clause() {
             }],
             tid: selected_tables,
             alias: aliashm,
+            derived: HashMap::new(),
+            distinct: false,
+            compound: Vec::new(),
             cond: Some(Conditions::Or(
                 Box::new(Conditions::And(
                     Box::new(Conditions::Leaf(Condition {
@@ -487,6 +681,9 @@ fn test_select_full_no_where_limit() {
             }],
             tid: vec!["foo".to_string()],
             alias: HashMap::new(),
+            derived: HashMap::new(),
+            distinct: false,
+            compound: Vec::new(),
             cond: None,
             spec_op: None,
             order: Vec::new(),
@@ -515,6 +712,9 @@ fn test_select_full_where_clause_limit() {
             }],
             tid: selected_tables,
             alias: aliashm,
+            derived: HashMap::new(),
+            distinct: false,
+            compound: Vec::new(),
             cond: Some(Conditions::Or(
                 Box::new(Conditions::And(
                     Box::new(Conditions::Leaf(Condition {
@@ -581,6 +781,9 @@ fn test_select_complete_1() {
             }],
             tid: selected_tables,
             alias: aliashm,
+            derived: HashMap::new(),
+            distinct: false,
+            compound: Vec::new(),
             cond: Some(Conditions::Or(
                 Box::new(Conditions::And(
                     Box::new(Conditions::Leaf(Condition {
@@ -648,6 +851,9 @@ fn test_select_complete_2_with_order_by() {
             }],
             tid: selected_tables,
             alias: aliashm,
+            derived: HashMap::new(),
+            distinct: false,
+            compound: Vec::new(),
             cond: Some(Conditions::Or(
                 Box::new(Conditions::And(
                     Box::new(Conditions::Leaf(Condition {
@@ -710,6 +916,9 @@ fn test_create_view_1() {
                     }],
                     tid: vec!["bar".to_string()],
                     alias: HashMap::new(),
+                    derived: HashMap::new(),
+                    distinct: false,
+                    compound: Vec::new(),
                     cond: None,
                     spec_op: None,
                     order: Vec::new(),
@@ -736,6 +945,9 @@ fn test_create_view_2() {
                     }],
                     tid: vec!["bar".to_string()],
                     alias: HashMap::new(),
+                    derived: HashMap::new(),
+                    distinct: false,
+                    compound: Vec::new(),
                     cond: None,
                     spec_op: None,
                     order: Vec::new(),
@@ -862,6 +1074,502 @@ fn test_mult_where_blocks_priority_4_param() {
     );
 }
 
+#[test]
+fn test_select_where_in_subquery() {
+    let mut p = parser::Parser::create(
+        "select * from foo where id in (select id from bar)");
+
+    assert_eq!(p.parse().unwrap(), Query::ManipulationStmt(
+        ManipulationStmt::Select(SelectStmt {
+            target: vec![Target {
+                alias: None,
+                col: Col::Every,
+                rename: None,
+            }],
+            tid: vec!["foo".to_string()],
+            alias: HashMap::new(),
+            derived: HashMap::new(),
+            distinct: false,
+            compound: Vec::new(),
+            cond: Some(Conditions::In(InCondition {
+                aliascol: None,
+                col: "id".to_string(),
+                set: InSet::Select(Box::new(SelectStmt {
+                    target: vec![Target {
+                        alias: None,
+                        col: Col::Specified("id".to_string()),
+                        rename: None,
+                    }],
+                    tid: vec!["bar".to_string()],
+                    alias: HashMap::new(),
+                    derived: HashMap::new(),
+                    distinct: false,
+                    compound: Vec::new(),
+                    cond: None,
+                    spec_op: None,
+                    order: Vec::new(),
+                    limit: None,
+                })),
+            })),
+            spec_op: None,
+            order: Vec::new(),
+            limit: None,
+    })));
+}
+
+#[test]
+fn test_select_where_exists_correlated() {
+    let mut p = parser::Parser::create(
+        "select * from foo f where exists (select * from bar b where b.id = f.id)");
+    let mut aliashm = HashMap::new();
+    aliashm.insert("f".to_string(), "foo".to_string());
+    let mut subaliashm = HashMap::new();
+    subaliashm.insert("b".to_string(), "bar".to_string());
+
+    assert_eq!(p.parse().unwrap(), Query::ManipulationStmt(
+        ManipulationStmt::Select(SelectStmt {
+            target: vec![Target {
+                alias: None,
+                col: Col::Every,
+                rename: None,
+            }],
+            tid: vec!["foo".to_string()],
+            alias: aliashm,
+            derived: HashMap::new(),
+            distinct: false,
+            compound: Vec::new(),
+            cond: Some(Conditions::Exists(Box::new(SelectStmt {
+                target: vec![Target {
+                    alias: None,
+                    col: Col::Every,
+                    rename: None,
+                }],
+                tid: vec!["bar".to_string()],
+                alias: subaliashm,
+                derived: HashMap::new(),
+                distinct: false,
+                compound: Vec::new(),
+                cond: Some(Conditions::Leaf(Condition {
+                    aliascol: Some("b".to_string()),
+                    col: "id".to_string(),
+                    op: CompType::Equ,
+                    aliasrhs: Some("f".to_string()),
+                    rhs: CondType::Word("id".to_string()),
+                })),
+                spec_op: None,
+                order: Vec::new(),
+                limit: None,
+            }))),
+            spec_op: None,
+            order: Vec::new(),
+            limit: None,
+    })));
+}
+
+#[test]
+fn test_select_scalar_subqueries() {
+    let mut p = parser::Parser::create(
+        "select name, (select total from stats) as c from foo
+        where age > (select age from bar limit 1)");
+
+    assert_eq!(p.parse().unwrap(), Query::ManipulationStmt(
+        ManipulationStmt::Select(SelectStmt {
+            target: vec![Target {
+                alias: None,
+                col: Col::Specified("name".to_string()),
+                rename: None,
+            }, Target {
+                alias: None,
+                col: Col::Subquery(Box::new(SelectStmt {
+                    target: vec![Target {
+                        alias: None,
+                        col: Col::Specified("total".to_string()),
+                        rename: None,
+                    }],
+                    tid: vec!["stats".to_string()],
+                    alias: HashMap::new(),
+                    derived: HashMap::new(),
+                    distinct: false,
+                    compound: Vec::new(),
+                    cond: None,
+                    spec_op: None,
+                    order: Vec::new(),
+                    limit: None,
+                })),
+                rename: Some("c".to_string()),
+            }],
+            tid: vec!["foo".to_string()],
+            alias: HashMap::new(),
+            derived: HashMap::new(),
+            distinct: false,
+            compound: Vec::new(),
+            cond: Some(Conditions::Leaf(Condition {
+                aliascol: None,
+                col: "age".to_string(),
+                op: CompType::GThan,
+                aliasrhs: None,
+                rhs: CondType::Subquery(Box::new(SelectStmt {
+                    target: vec![Target {
+                        alias: None,
+                        col: Col::Specified("age".to_string()),
+                        rename: None,
+                    }],
+                    tid: vec!["bar".to_string()],
+                    alias: HashMap::new(),
+                    derived: HashMap::new(),
+                    distinct: false,
+                    compound: Vec::new(),
+                    cond: None,
+                    spec_op: None,
+                    order: Vec::new(),
+                    limit: Some(Limit {
+                        count: Some(1),
+                        offset: None,
+                    }),
+                })),
+            })),
+            spec_op: None,
+            order: Vec::new(),
+            limit: None,
+    })));
+}
+
+#[test]
+fn test_select_derived_table() {
+    let mut p = parser::Parser::create("select x.id from (select id from bar) as x");
+    let mut derivedhm = HashMap::new();
+    derivedhm.insert("x".to_string(), SelectStmt {
+        target: vec![Target {
+            alias: None,
+            col: Col::Specified("id".to_string()),
+            rename: None,
+        }],
+        tid: vec!["bar".to_string()],
+        alias: HashMap::new(),
+        derived: HashMap::new(),
+        distinct: false,
+        compound: Vec::new(),
+        cond: None,
+        spec_op: None,
+        order: Vec::new(),
+        limit: None,
+    });
+
+    assert_eq!(p.parse().unwrap(), Query::ManipulationStmt(
+        ManipulationStmt::Select(SelectStmt {
+            target: vec![Target {
+                alias: Some("x".to_string()),
+                col: Col::Specified("id".to_string()),
+                rename: None,
+            }],
+            tid: vec!["x".to_string()],
+            alias: HashMap::new(),
+            derived: derivedhm,
+            distinct: false,
+            compound: Vec::new(),
+            cond: None,
+            spec_op: None,
+            order: Vec::new(),
+            limit: None,
+    })));
+}
+
+#[test]
+fn test_delete_where_in_list_not_between() {
+    let mut p = parser::Parser::create(
+        "delete from foo where id in (1, 2, 3) and age not between 18 and 30");
+
+    assert_eq!(p.parse().unwrap(), Query::ManipulationStmt(
+        ManipulationStmt::Delete(DeleteStmt {
+            tid: "foo".to_string(),
+            alias: HashMap::new(),
+            cond: Some(Conditions::And(
+                Box::new(Conditions::In(InCondition {
+                    aliascol: None,
+                    col: "id".to_string(),
+                    set: InSet::List(vec![Lit::Int(1), Lit::Int(2), Lit::Int(3)]),
+                })),
+                Box::new(Conditions::Not(Box::new(Conditions::Between(BetweenCondition {
+                    aliascol: None,
+                    col: "age".to_string(),
+                    low: Expr::Literal(Lit::Int(18)),
+                    high: Expr::Literal(Lit::Int(30)),
+                }))))
+            ))
+        }))
+    );
+}
+
+#[test]
+fn test_delete_where_between_columns() {
+    let mut p = parser::Parser::create("delete from foo f where age between f.low and 30");
+
+    let mut aliashm = HashMap::new();
+    aliashm.insert("f".to_string(), "foo".to_string());
+
+    assert_eq!(p.parse().unwrap(), Query::ManipulationStmt(
+        ManipulationStmt::Delete(DeleteStmt {
+            tid: "foo".to_string(),
+            alias: aliashm,
+            cond: Some(Conditions::Between(BetweenCondition {
+                aliascol: None,
+                col: "age".to_string(),
+                low: Expr::Column(Some("f".to_string()), "low".to_string()),
+                high: Expr::Literal(Lit::Int(30)),
+            }))
+        }))
+    );
+}
+
+#[test]
+fn test_delete_where_like_ilike() {
+    let mut p = parser::Parser::create(
+        "delete from foo f where f.name like 'pe_er%' or name not ilike '%PAN'");
+
+    let mut aliashm = HashMap::new();
+    aliashm.insert("f".to_string(), "foo".to_string());
+
+    assert_eq!(p.parse().unwrap(), Query::ManipulationStmt(
+        ManipulationStmt::Delete(DeleteStmt {
+            tid: "foo".to_string(),
+            alias: aliashm,
+            cond: Some(Conditions::Or(
+                Box::new(Conditions::Like(LikeCondition {
+                    aliascol: Some("f".to_string()),
+                    col: "name".to_string(),
+                    pattern: "pe_er%".to_string(),
+                    ignore_case: false,
+                })),
+                Box::new(Conditions::Not(Box::new(Conditions::Like(LikeCondition {
+                    aliascol: None,
+                    col: "name".to_string(),
+                    pattern: "%PAN".to_string(),
+                    ignore_case: true,
+                }))))
+            ))
+        }))
+    );
+}
+
+#[test]
+fn test_delete_where_not_group() {
+    let mut p = parser::Parser::create(
+        "delete from foo where not (a = 1 or b = 2) and c = 3");
+
+    let leaf = |col: &str, val| Conditions::Leaf(Condition {
+        aliascol: None,
+        col: col.to_string(),
+        op: CompType::Equ,
+        aliasrhs: None,
+        rhs: CondType::Literal(Lit::Int(val)),
+    });
+
+    assert_eq!(p.parse().unwrap(), Query::ManipulationStmt(
+        ManipulationStmt::Delete(DeleteStmt {
+            tid: "foo".to_string(),
+            alias: HashMap::new(),
+            cond: Some(Conditions::And(
+                Box::new(Conditions::Not(Box::new(Conditions::Or(
+                    Box::new(leaf("a", 1)),
+                    Box::new(leaf("b", 2)))))),
+                Box::new(leaf("c", 3))
+            ))
+        }))
+    );
+}
+
+#[test]
+fn test_select_where_group_limit() {
+    let mut p = parser::Parser::create("select * from foo where (a = 1) limit 2");
+
+    match p.parse().unwrap() {
+        Query::ManipulationStmt(ManipulationStmt::Select(stmt)) => {
+            assert_eq!(stmt.cond, Some(Conditions::Leaf(Condition {
+                aliascol: None,
+                col: "a".to_string(),
+                op: CompType::Equ,
+                aliasrhs: None,
+                rhs: CondType::Literal(Lit::Int(1)),
+            })));
+            assert_eq!(stmt.limit, Some(Limit { count: Some(2), offset: None }));
+        },
+        _ => panic!("expected a select statement"),
+    }
+}
+
+#[test]
+fn test_select_distinct() {
+    let mut p = parser::Parser::create("select distinct name from foo");
+
+    assert_eq!(p.parse().unwrap(), Query::ManipulationStmt(
+        ManipulationStmt::Select(SelectStmt {
+            target: vec![Target {
+                alias: None,
+                col: Col::Specified("name".to_string()),
+                rename: None,
+            }],
+            tid: vec!["foo".to_string()],
+            alias: HashMap::new(),
+            derived: HashMap::new(),
+            distinct: true,
+            compound: Vec::new(),
+            cond: None,
+            spec_op: None,
+            order: Vec::new(),
+            limit: None,
+    })));
+}
+
+#[test]
+fn test_select_union_all_limit() {
+    let mut p = parser::Parser::create(
+        "select name from foo union all select name from bar where id = 1 limit 5");
+
+    let select = |table: &str| SelectStmt {
+        target: vec![Target {
+            alias: None,
+            col: Col::Specified("name".to_string()),
+            rename: None,
+        }],
+        tid: vec![table.to_string()],
+        alias: HashMap::new(),
+        derived: HashMap::new(),
+        distinct: false,
+        compound: Vec::new(),
+        cond: None,
+        spec_op: None,
+        order: Vec::new(),
+        limit: None,
+    };
+    let mut right = select("bar");
+    right.cond = Some(Conditions::Leaf(Condition {
+        aliascol: None,
+        col: "id".to_string(),
+        op: CompType::Equ,
+        aliasrhs: None,
+        rhs: CondType::Literal(Lit::Int(1)),
+    }));
+    let mut sol = select("foo");
+    sol.compound = vec![Compound { op: SetOp::Union, all: true, select: Box::new(right) }];
+    sol.limit = Some(Limit { count: Some(5), offset: None });
+
+    assert_eq!(p.parse().unwrap(), Query::ManipulationStmt(ManipulationStmt::Select(sol)));
+}
+
+#[test]
+fn test_select_intersect_except_chain() {
+    let mut p = parser::Parser::create(
+        "select id from foo intersect select id from bar except select id from baz");
+
+    match p.parse().unwrap() {
+        Query::ManipulationStmt(ManipulationStmt::Select(stmt)) => {
+            let ops: Vec<(SetOp, bool, String)> = stmt.compound.iter()
+                .map(|c| (c.op, c.all, c.select.tid[0].clone()))
+                .collect();
+            assert_eq!(ops, vec![
+                (SetOp::Intersect, false, "bar".to_string()),
+                (SetOp::Except, false, "baz".to_string()),
+            ]);
+            assert!(stmt.compound.iter().all(|c| c.select.compound.is_empty()));
+        },
+        _ => panic!("expected a select statement"),
+    }
+}
+
+#[test]
+fn test_select_functions() {
+    let mut p = parser::Parser::create(
+        "select upper(f.name) as n, substr(name, 2, 3), coalesce(nullif(age, 0), 1) from foo f");
+
+    match p.parse().unwrap() {
+        Query::ManipulationStmt(ManipulationStmt::Select(stmt)) => {
+            assert_eq!(stmt.target, vec![
+                Target {
+                    alias: None,
+                    col: Col::Expr(Expr::Function("upper".to_string(), vec![
+                        Expr::Column(Some("f".to_string()), "name".to_string()),
+                    ])),
+                    rename: Some("n".to_string()),
+                },
+                Target {
+                    alias: None,
+                    col: Col::Expr(Expr::Function("substr".to_string(), vec![
+                        Expr::Column(None, "name".to_string()),
+                        Expr::Literal(Lit::Int(2)),
+                        Expr::Literal(Lit::Int(3)),
+                    ])),
+                    rename: None,
+                },
+                Target {
+                    alias: None,
+                    col: Col::Expr(Expr::Function("coalesce".to_string(), vec![
+                        Expr::Function("nullif".to_string(), vec![
+                            Expr::Column(None, "age".to_string()),
+                            Expr::Literal(Lit::Int(0)),
+                        ]),
+                        Expr::Literal(Lit::Int(1)),
+                    ])),
+                    rename: None,
+                },
+            ]);
+            assert_eq!(stmt.tid, vec!["foo".to_string()]);
+        },
+        _ => panic!("expected a select statement"),
+    }
+}
+
+#[test]
+fn test_select_case() {
+    let mut p = parser::Parser::create("select case when age < 18 then 'minor'
+        else 'adult' end as grp from foo where id = 1");
+
+    match p.parse().unwrap() {
+        Query::ManipulationStmt(ManipulationStmt::Select(stmt)) => {
+            assert_eq!(stmt.target, vec![Target {
+                alias: None,
+                col: Col::Expr(Expr::Case(
+                    vec![(Conditions::Leaf(Condition {
+                        aliascol: None,
+                        col: "age".to_string(),
+                        op: CompType::SThan,
+                        aliasrhs: None,
+                        rhs: CondType::Literal(Lit::Int(18)),
+                    }), Expr::Literal(Lit::String("minor".to_string())))],
+                    Some(Box::new(Expr::Literal(Lit::String("adult".to_string())))))),
+                rename: Some("grp".to_string()),
+            }]);
+            assert!(stmt.cond.is_some());
+        },
+        _ => panic!("expected a select statement"),
+    }
+}
+
+#[test]
+fn test_explain_analyze() {
+    let mut p = parser::Parser::create("explain analyze select * from foo");
+
+    assert_eq!(p.parse().unwrap(), Query::ManipulationStmt(
+        ManipulationStmt::Explain(ExplainStmt {
+            analyze: true,
+            select: SelectStmt {
+                target: vec![Target {
+                    alias: None,
+                    col: Col::Every,
+                    rename: None,
+                }],
+                tid: vec!["foo".to_string()],
+                alias: HashMap::new(),
+                derived: HashMap::new(),
+                distinct: false,
+                compound: Vec::new(),
+                cond: None,
+                spec_op: None,
+                order: Vec::new(),
+                limit: None,
+            },
+    })));
+}
+
 /*#[test]
 fn to_do() {
     let mut p = parser::Parser::create("
@@ -876,6 +1584,7 @@ fn to_do() {
 // Result::Err unittest
 // ============================================================================
 
+
 #[test]
 fn err_create_table_error1() {
     let mut p = parser::Parser::create("cReAtE table");
@@ -887,8 +1596,8 @@ fn err_create_table_error1() {
 fn err_create_keyword1() {
     let mut p = parser::Parser::create("   table create");
     let sol = parser::ParseError::WrongKeyword(Span {
-        lo: 5,
-        hi: 10,
+        lo: 3,
+        hi: 8,
     });
 
     assert_eq!(p.parse(), Err(sol));
@@ -899,7 +1608,7 @@ fn err_create_keyword1() {
 fn err_create_wrong_token_1() {
     let mut p = parser::Parser::create("create table Studenten )");
     let sol = parser::ParseError::WrongToken(Span {
-        lo: 24,
+        lo: 23,
         hi: 24,
     });
 
@@ -910,7 +1619,7 @@ fn err_create_wrong_token_1() {
 fn err_create_wrong_token_2() {
     let mut p = parser::Parser::create("create table studenten (asd int(");
     let sol = parser::ParseError::WrongToken(Span {
-        lo: 32,
+        lo: 31,
         hi: 32,
     });
 
@@ -921,8 +1630,8 @@ fn err_create_wrong_token_2() {
 fn err_create_wrong_token_3() {
     let mut p = parser::Parser::create("create table studenten (asd asd)");
     let sol = parser::ParseError::NotADatatype(Span {
-        lo: 30,
-        hi: 32,
+        lo: 28,
+        hi: 31,
     });
 
     assert_eq!(p.parse(), Err(sol));
@@ -932,8 +1641,8 @@ fn err_create_wrong_token_3() {
 fn err_create_missing_parenthesis() {
     let mut p = parser::Parser::create("create table studenten asd int)");
     let sol = parser::ParseError::WrongToken(Span {
-        lo: 25,
-        hi: 28,
+        lo: 23,
+        hi: 26,
     });
 
     assert_eq!(p.parse(), Err(sol));
@@ -943,8 +1652,8 @@ fn err_create_missing_parenthesis() {
 fn err_create_not_a_keyword_1() {
     let mut p = parser::Parser::create("hallo table studenten");
     let sol = parser::ParseError::NotAKeyword(Span {
-        lo: 2,
-        hi: 7,
+        lo: 0,
+        hi: 5,
     });
 
     assert_eq!(p.parse(), Err(sol));
@@ -954,8 +1663,8 @@ fn err_create_not_a_keyword_1() {
 fn err_create_not_a_keyword_2() {
     let mut p = parser::Parser::create("create asd Studenten");
     let sol = parser::ParseError::NotAKeyword(Span {
-        lo: 9,
-        hi: 12,
+        lo: 7,
+        hi: 10,
     });
 
     assert_eq!(p.parse(), Err(sol));
@@ -981,7 +1690,7 @@ fn err_create_invalid_eoq_2() {
 fn err_describe() {
     let mut p = parser::Parser::create("describe ,");
     let sol = parser::ParseError::NotAWord(Span {
-        lo: 10,
+        lo: 9,
         hi: 10,
     });
 
@@ -992,7 +1701,7 @@ fn err_describe() {
 fn err_describe_2() {
     let mut p = parser::Parser::create("describe table");
     let sol = parser::ParseError::ReservedKeyword(Span {
-        lo: 11,
+        lo: 9,
         hi: 14,
     });
 
@@ -1003,8 +1712,8 @@ fn err_describe_2() {
 fn err_alter_1() {
     let mut p = parser::Parser::create("alter table table add bar int");
     let sol = parser::ParseError::ReservedKeyword(Span {
-        lo: 14,
-        hi: 19,
+        lo: 12,
+        hi: 17,
     });
 
     assert_eq!(p.parse(), Err(sol));
@@ -1014,7 +1723,7 @@ fn err_alter_1() {
 fn err_alter_2() {
     let mut p = parser::Parser::create("alter table foo add bar foo");
     let sol = parser::ParseError::NotADatatype(Span {
-        lo: 26,
+        lo: 24,
         hi: 27,
     });
 
@@ -1025,7 +1734,7 @@ fn err_alter_2() {
 fn err_alter_3() {
     let mut p = parser::Parser::create("alter table foo drop bar_1");
     let sol = parser::ParseError::NotAKeyword(Span {
-        lo: 23,
+        lo: 21,
         hi: 26,
     });
 
@@ -1036,8 +1745,8 @@ fn err_alter_3() {
 fn err_alter_5() {
     let mut p = parser::Parser::create("alter table foo add (bar int");
     let sol = parser::ParseError::NotAWord(Span {
-        lo: 22,
-        hi: 23,
+        lo: 20,
+        hi: 21,
     });
 
     assert_eq!(p.parse(), Err(sol));
@@ -1047,7 +1756,7 @@ fn err_alter_5() {
 fn err_alter_6() {
     let mut p = parser::Parser::create("alter table foo drop column (");
     let sol = parser::ParseError::NotAWord(Span {
-        lo: 29,
+        lo: 28,
         hi: 29,
     });
 
@@ -1059,7 +1768,7 @@ fn err_alter_6() {
 fn err_alter_8() {
     let mut p = parser::Parser::create("alter table foo modify asd");
     let sol = parser::ParseError::NotAKeyword(Span {
-        lo: 25,
+        lo: 23,
         hi: 26,
     });
 
@@ -1070,7 +1779,7 @@ fn err_alter_8() {
 fn err_alter_9() {
     let mut p = parser::Parser::create("alter table foo modify column bar asd");
     let sol = parser::ParseError::NotADatatype(Span {
-        lo: 36,
+        lo: 34,
         hi: 37,
     });
 
@@ -1089,8 +1798,8 @@ fn err_alter_10() {
 fn err_use_1() {
     let mut p = parser::Parser::create("use table foo");
     let sol = parser::ParseError::WrongKeyword(Span {
-        lo: 6,
-        hi: 11,
+        lo: 4,
+        hi: 9,
     });
 
     assert_eq!(p.parse(), Err(sol));
@@ -1100,7 +1809,7 @@ fn err_use_1() {
 fn err_use_2() {
     let mut p = parser::Parser::create("use database use");
     let sol = parser::ParseError::ReservedKeyword(Span {
-        lo: 15,
+        lo: 13,
         hi: 16,
     });
 
@@ -1111,7 +1820,7 @@ fn err_use_2() {
 fn err_use_3() {
     let mut p = parser::Parser::create("use database 1");
     let sol = parser::ParseError::NotAWord(Span {
-        lo: 14,
+        lo: 13,
         hi: 14,
     });
 
@@ -1122,7 +1831,7 @@ fn err_use_3() {
 fn err_drop_1() {
     let mut p = parser::Parser::create("drop foo");
     let sol = parser::ParseError::NotAKeyword(Span {
-        lo: 7,
+        lo: 5,
         hi: 8,
     });
 
@@ -1133,7 +1842,7 @@ fn err_drop_1() {
 fn err_drop_2() {
     let mut p = parser::Parser::create("drop table table");
     let sol = parser::ParseError::ReservedKeyword(Span {
-        lo: 13,
+        lo: 11,
         hi: 16,
     });
 
@@ -1144,7 +1853,7 @@ fn err_drop_2() {
 fn err_drop_3() {
     let mut p = parser::Parser::create("drop table ]");
     let sol = parser::ParseError::NotAWord(Span {
-        lo: 12,
+        lo: 11,
         hi: 12,
     });
 
@@ -1155,7 +1864,7 @@ fn err_drop_3() {
 fn err_insert_1() {
     let mut p = parser::Parser::create("insert a");
     let sol = parser::ParseError::NotAKeyword(Span {
-        lo: 8,
+        lo: 7,
         hi: 8,
     });
 
@@ -1166,7 +1875,7 @@ fn err_insert_1() {
 fn err_insert_2() {
     let mut p = parser::Parser::create("insert into into");
     let sol = parser::ParseError::ReservedKeyword(Span {
-        lo: 14,
+        lo: 12,
         hi: 16,
     });
 
@@ -1177,8 +1886,8 @@ fn err_insert_2() {
 fn err_insert_3() {
     let mut p = parser::Parser::create("insert into foo bar ('⊂(▀¯▀⊂)', 420, 'lel'");
     let sol = parser::ParseError::NotAKeyword(Span {
-        lo: 18,
-        hi: 21,
+        lo: 16,
+        hi: 19,
     });
 
     assert_eq!(p.parse(), Err(sol));
@@ -1191,3 +1900,27 @@ fn err_insert_4() {
 
     assert_eq!(p.parse(), Err(sol));
 }
+
+#[test]
+fn err_subquery_unclosed() {
+    let mut p = parser::Parser::create("select * from foo where id in (select id from bar");
+    let sol = parser::ParseError::UnexpectedEoq;
+
+    assert_eq!(p.parse(), Err(sol));
+}
+
+#[test]
+fn err_like_pattern_no_string() {
+    let mut p = parser::Parser::create("delete from foo where name like 3");
+    let sol = parser::ParseError::LikePatternNoString;
+
+    assert_eq!(p.parse(), Err(sol));
+}
+
+#[test]
+fn err_case_without_end() {
+    let mut p = parser::Parser::create("select case when a = 1 then 2 from foo");
+    let sol = parser::ParseError::WrongKeyword(Span { lo: 30, hi: 34 });
+
+    assert_eq!(p.parse(), Err(sol));
+}
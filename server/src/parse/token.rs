@@ -8,12 +8,15 @@ pub struct TokenSpan {
     pub span: Span,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
 pub enum Lit {
 	String(String),
 	Int(i64),
     Float(f64),
     Bool(u8),
+    // placeholder for the n-th parameter of a prepared statement, starting
+    // at 1. It is replaced by the bound value before the execution.
+    Param(u32),
 }
 
 impl Lit {
@@ -23,6 +26,7 @@ impl Lit {
             &Lit::Int(ref i) => DataSrc::Int(i.clone()),
             &Lit::Float(ref f) => DataSrc::String(f.to_string()),
             &Lit::Bool(ref b) => DataSrc::Bool(b.clone()),
+            &Lit::Param(n) => DataSrc::String(format!("${}", n)),
         }
     }
 
@@ -32,6 +36,7 @@ impl Lit {
             &Lit::Int(_) => SqlType::Int,
             &Lit::Float(_) => SqlType::Char(0),
             &Lit::Bool(_) => SqlType::Bool,
+            &Lit::Param(_) => SqlType::Char(0),
         }
     }
 
@@ -51,7 +56,8 @@ pub enum Token {
     Dot,
     Comma,
     // Bang,
-    // QMark,
+    // parameter placeholder: ? or $n
+    Param(u32),
 
     // delimiter (,),',"
     ParenOp,
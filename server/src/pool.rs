@@ -0,0 +1,117 @@
+//! A fixed number of worker threads for the connections
+//!
+//! Every connection keeps a worker busy until it is closed, so the number of
+//! workers is the number of connections handled at once. A job arriving
+//! while all workers are busy is handed back instead of waiting, the server
+//! refuses the connection then.
+//!
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+/// Work handed to the pool
+pub trait Job: Send {
+    fn run(self: Box<Self>);
+}
+
+/// The workers and the queue of their jobs
+pub struct Pool {
+    sender: Mutex<Sender<Box<Job>>>,
+    // jobs handed to the workers which have not finished yet
+    busy: Arc<AtomicUsize>,
+    size: usize,
+}
+
+impl Pool {
+    /// Starts the workers, at least one
+    pub fn new(size: usize) -> Pool {
+        let size = if size == 0 { 1 } else { size };
+        let (sender, receiver) = mpsc::channel::<Box<Job>>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let busy = Arc::new(AtomicUsize::new(0));
+        for _ in 0..size {
+            let receiver = receiver.clone();
+            let busy = busy.clone();
+            thread::spawn(move || work(receiver, busy));
+        }
+        Pool { sender: Mutex::new(sender), busy: busy, size: size }
+    }
+
+    /// Runs the job on an idle worker. Returns the job if all workers are
+    /// busy.
+    pub fn execute<J: Job + 'static>(&self, job: J) -> Result<(), J> {
+        if self.busy.fetch_add(1, Ordering::SeqCst) >= self.size {
+            self.busy.fetch_sub(1, Ordering::SeqCst);
+            return Err(job)
+        }
+        let sender = self.sender.lock().unwrap_or_else(|e| e.into_inner());
+        // the workers never stop, so there is always one to receive it
+        let _ = sender.send(Box::new(job));
+        Ok(())
+    }
+
+    /// Returns the number of jobs which have not finished yet
+    pub fn busy(&self) -> usize {
+        self.busy.load(Ordering::SeqCst)
+    }
+}
+
+/// Runs the jobs of the queue one after another. A panicking job does not
+/// take the worker with it.
+fn work(receiver: Arc<Mutex<Receiver<Box<Job>>>>, busy: Arc<AtomicUsize>) {
+    loop {
+        let job = {
+            let receiver = receiver.lock().unwrap_or_else(|e| e.into_inner());
+            receiver.recv()
+        };
+        let job = match job {
+            Ok(job) => job,
+            // the pool is gone
+            Err(_) => return,
+        };
+        if panic::catch_unwind(AssertUnwindSafe(|| job.run())).is_err() {
+            error!("A connection ended with a panic");
+        }
+        busy.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn test_pool() {
+    use std::sync::mpsc::channel;
+
+    struct Wait(Receiver<()>, Sender<()>);
+    impl Job for Wait {
+        fn run(self: Box<Self>) {
+            let _ = self.0.recv();
+            let _ = self.1.send(());
+        }
+    }
+
+    let pool = Pool::new(2);
+    let (done, finished) = channel();
+    let (go1, wait1) = channel();
+    let (go2, wait2) = channel();
+    assert!(pool.execute(Wait(wait1, done.clone())).is_ok());
+    assert!(pool.execute(Wait(wait2, done.clone())).is_ok());
+
+    // both workers are busy
+    let (_go3, wait3) = channel();
+    assert!(pool.execute(Wait(wait3, done.clone())).is_err());
+
+    go1.send(()).unwrap();
+    finished.recv().unwrap();
+    // the count drops right after the job, give the worker the time
+    while pool.busy() > 1 {
+        thread::yield_now();
+    }
+    let (go4, wait4) = channel();
+    assert!(pool.execute(Wait(wait4, done.clone())).is_ok());
+    go2.send(()).unwrap();
+    go4.send(()).unwrap();
+    finished.recv().unwrap();
+    finished.recv().unwrap();
+}
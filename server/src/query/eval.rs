@@ -0,0 +1,246 @@
+//! Evaluation of conditions and expressions for single rows
+//!
+
+use super::{Executor, ExecutionError, NameInfos, OuterRow, SubqueryCache};
+use super::{lookup_column, column_value, decode_value, like_match};
+use super::super::parse::ast::*;
+use super::super::parse::token::Lit;
+use super::super::storage::Column;
+use super::super::storage::types::SqlType;
+use super::super::functions::{self, Value};
+use std::collections::HashMap;
+
+/// Names of the columns of a row: alias -> table name, column name ->
+/// table name and table name -> (column name -> column index)
+#[derive(Debug, Clone, Default)]
+pub struct Names {
+    pub alias: HashMap<String, String>,
+    pub column_table: HashMap<String, String>,
+    pub table_columns: HashMap<String, HashMap<String, usize>>,
+}
+
+impl Names {
+    /// Names of the columns of a single table. An output column without
+    /// a table gets an empty table name.
+    pub fn new(columns: &[(Option<String>, String)], aliases: &HashMap<String, String>)
+        -> Names
+    {
+        let mut names = Names { alias: aliases.clone(), ..Names::default() };
+        for (index, &(ref table, ref name)) in columns.iter().enumerate() {
+            let table = table.clone().unwrap_or(String::new());
+            names.alias.insert(table.clone(), table.clone());
+            names.column_table.insert(name.clone(), table.clone());
+            names.table_columns.entry(table).or_insert(HashMap::new())
+                .insert(name.clone(), index);
+        }
+        names
+    }
+
+    /// Names of a source in the from list
+    pub fn source(table: &str, columns: &[Column], aliases: &[String]) -> Names {
+        let columns: Vec<_> = columns.iter()
+            .map(|c| (Some(table.to_string()), c.name.clone()))
+            .collect();
+        let aliases = aliases.iter().map(|a| (a.clone(), table.to_string())).collect();
+        Names::new(&columns, &aliases)
+    }
+
+    /// Names of the rows of a join, the columns of right follow the ones of
+    /// left
+    pub fn join(mut left: Names, right: Names, offset: usize) -> Names {
+        left.alias.extend(right.alias.into_iter());
+        left.column_table.extend(right.column_table.into_iter());
+        for (table, columns) in right.table_columns {
+            let columns = columns.into_iter().map(|(name, i)| (name, i + offset));
+            left.table_columns.entry(table).or_insert(HashMap::new()).extend(columns);
+        }
+        left
+    }
+
+    pub fn infos(&self) -> NameInfos {
+        (&self.alias, &self.column_table, &self.table_columns)
+    }
+
+    pub fn lookup(&self, alias: &Option<String>, column: &str)
+        -> Result<usize, ExecutionError>
+    {
+        lookup_column(self.infos(), alias, column)
+    }
+}
+
+/// The subquery results an operator keeps between rows
+#[derive(Default)]
+pub struct EvalCache {
+    subqueries: Vec<(SelectStmt, SubqueryCache)>,
+}
+
+impl EvalCache {
+    pub fn get(&mut self, stmt: &SelectStmt) -> &mut SubqueryCache {
+        let found = self.subqueries.iter().position(|&(ref s, _)| s == stmt);
+        let position = match found {
+            Some(position) => position,
+            None => {
+                self.subqueries.push((stmt.clone(), SubqueryCache::new()));
+                self.subqueries.len() - 1
+            },
+        };
+        &mut self.subqueries[position].1
+    }
+}
+
+impl<'a> Executor<'a> {
+    /// Checks if the row matches the conditions
+    pub fn eval_condition(&self, cond: &Conditions, row: &OuterRow, cache: &mut EvalCache)
+        -> Result<bool, ExecutionError>
+    {
+        match cond {
+            &Conditions::And(ref c1, ref c2) =>
+                Ok(try!(self.eval_condition(c1, row, cache))
+                    && try!(self.eval_condition(c2, row, cache))),
+            &Conditions::Or(ref c1, ref c2) =>
+                Ok(try!(self.eval_condition(c1, row, cache))
+                    || try!(self.eval_condition(c2, row, cache))),
+            &Conditions::Not(ref c) => Ok(!try!(self.eval_condition(c, row, cache))),
+            &Conditions::Leaf(ref c) => {
+                let index = try!(lookup_column(row.infos, &c.aliascol, &c.col));
+                match c.rhs {
+                    CondType::Word(ref column) => {
+                        let index2 = try!(lookup_column(row.infos, &c.aliasrhs, column));
+                        let sql_type = &row.columns[index].sql_type;
+                        if *sql_type != row.columns[index2].sql_type {
+                            return Err(ExecutionError::CompareDatatypeMissmatch)
+                        }
+                        Ok(try!(sql_type.cmp(&column_value(row.columns, row.row, index),
+                            &column_value(row.columns, row.row, index2), c.op)))
+                    },
+                    CondType::Literal(ref lit) =>
+                        self.compare_value(row.columns, row.row, index, lit, c.op),
+                    CondType::Subquery(ref sub) => {
+                        let result = try!(self.execute_subquery(sub, row, cache.get(sub)));
+                        match try!(result.scalar()) {
+                            Some(value) =>
+                                self.compare_value(row.columns, row.row, index, &value, c.op),
+                            // comparing with no value is never true
+                            None => Ok(false),
+                        }
+                    },
+                }
+            },
+            &Conditions::In(ref c) => {
+                let index = try!(lookup_column(row.infos, &c.aliascol, &c.col));
+                match c.set {
+                    InSet::List(ref values) =>
+                        self.contains_value(row.columns, row.row, index, values),
+                    InSet::Select(ref sub) => {
+                        let result = try!(self.execute_subquery(sub, row, cache.get(sub)));
+                        self.contains_value(row.columns, row.row, index, &result.values)
+                    },
+                }
+            },
+            &Conditions::Between(ref c) => self.eval_between(c, row),
+            &Conditions::Like(ref c) => {
+                let index = try!(lookup_column(row.infos, &c.aliascol, &c.col));
+                let value = match try!(decode_value(&row.columns[index].sql_type,
+                    &column_value(row.columns, row.row, index)))
+                {
+                    Lit::String(s) => s,
+                    _ => return Err(ExecutionError::CompareDatatypeMissmatch),
+                };
+                let (pattern, value) = if c.ignore_case {
+                    (c.pattern.to_lowercase(), value.to_lowercase())
+                } else {
+                    (c.pattern.clone(), value)
+                };
+                Ok(like_match(&pattern.chars().collect::<Vec<_>>(),
+                    &value.chars().collect::<Vec<_>>()))
+            },
+            &Conditions::Exists(ref sub) => {
+                let result = try!(self.execute_subquery(sub, row, cache.get(sub)));
+                Ok(!result.values.is_empty())
+            },
+            &Conditions::Const(value) => Ok(value),
+        }
+    }
+
+    /// Checks if the column lies between both bounds of the row
+    pub fn eval_between(&self, cond: &BetweenCondition, row: &OuterRow)
+        -> Result<bool, ExecutionError>
+    {
+        let index = try!(lookup_column(row.infos, &cond.aliascol, &cond.col));
+        let bounds = [(&cond.low, CompType::GEThan), (&cond.high, CompType::SEThan)];
+        for &(bound, op) in &bounds {
+            // the bounds are literals or columns, they have no subqueries
+            let value = match try!(self.eval_expr(bound, row, &mut EvalCache::default())) {
+                Some(value) => value,
+                None => return Ok(false),
+            };
+            if !try!(self.compare_value(row.columns, row.row, index, &value, op)) {
+                return Ok(false)
+            }
+        }
+        Ok(true)
+    }
+
+    /// Computes the value of an expression for the row
+    pub fn eval_expr(&self, expr: &Expr, row: &OuterRow, cache: &mut EvalCache)
+        -> Result<Value, ExecutionError>
+    {
+        match expr {
+            &Expr::Column(ref alias, ref name) => {
+                let index = try!(lookup_column(row.infos, alias, name));
+                Ok(Some(try!(decode_value(&row.columns[index].sql_type,
+                    &column_value(row.columns, row.row, index)))))
+            },
+            &Expr::Literal(ref lit) => Ok(Some(lit.clone())),
+            &Expr::Function(ref name, ref args) => {
+                let function = try!(functions::lookup(name));
+                let mut values = Vec::new();
+                for arg in args {
+                    values.push(try!(self.eval_expr(arg, row, cache)));
+                }
+                Ok(try!(function.call(&values)))
+            },
+            &Expr::Case(ref whens, ref default) => {
+                for &(ref cond, ref then) in whens {
+                    if try!(self.eval_condition(cond, row, cache)) {
+                        return self.eval_expr(then, row, cache)
+                    }
+                }
+                match default {
+                    &Some(ref default) => self.eval_expr(default, row, cache),
+                    &None => Ok(None),
+                }
+            },
+        }
+    }
+
+    /// Returns the type of an expression's values
+    pub fn expr_type(&self, expr: &Expr, columns: &[Column], names: &Names)
+        -> Result<SqlType, ExecutionError>
+    {
+        match expr {
+            &Expr::Column(ref alias, ref name) =>
+                Ok(columns[try!(names.lookup(alias, name))].sql_type.clone()),
+            &Expr::Literal(Lit::String(ref s)) => Ok(functions::char_type(s.len())),
+            &Expr::Literal(ref lit) => Ok(lit.sqltype()),
+            &Expr::Function(ref name, ref args) => {
+                let function = try!(functions::lookup(name));
+                let mut types = Vec::new();
+                for arg in args {
+                    types.push(try!(self.expr_type(arg, columns, names)));
+                }
+                Ok(try!(function.result_type(&types)))
+            },
+            &Expr::Case(ref whens, ref default) => {
+                let mut types = Vec::new();
+                for &(_, ref then) in whens {
+                    types.push(try!(self.expr_type(then, columns, names)));
+                }
+                if let &Some(ref default) = default {
+                    types.push(try!(self.expr_type(default, columns, names)));
+                }
+                functions::common_type(&types).map_err(|_| ExecutionError::CaseTypeMissmatch)
+            },
+        }
+    }
+}
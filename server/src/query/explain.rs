@@ -0,0 +1,208 @@
+//! EXPLAIN and EXPLAIN ANALYZE
+//!
+//! The result has one row per plan node. A node is followed by its inputs,
+//! which are indented one level deeper.
+//!
+
+use super::{Executor, ExecutionError};
+use super::expr_name;
+use super::plan::{LogicalPlan, Access, JoinKind};
+use super::super::parse::ast::*;
+use super::super::parse::token::Lit;
+use super::super::storage::{Column, Rows, SqlType};
+use super::super::functions;
+use std::cmp::max;
+use std::io::Cursor;
+
+/// Longest text of an operator, char columns keep one byte for the NUL
+const MAX_TEXT: usize = 254;
+
+impl<'a> Executor<'a> {
+    /// Returns the plan of the select. With ANALYZE the select is run, too,
+    /// and the rows and microseconds of every operator are added.
+    pub fn execute_explain_stmt(&self, stmt: ExplainStmt)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        let plan = try!(self.optimize(try!(self.plan_select(stmt.select))));
+        let mut lines = Vec::new();
+        try!(self.explain_plan(&plan, 0, &mut lines));
+
+        let mut stats = Vec::new();
+        if stmt.analyze {
+            let (mut operator, _) = try!(self.build_measured(plan, &mut stats));
+            while try!(operator.next()).is_some() {}
+        }
+
+        let width = lines.iter().fold(1, |acc, &(ref text, _)| max(acc, text.len()));
+        let mut columns = vec![
+            Column::new("operator", functions::char_type(width), false, "", false),
+            Column::new("estimated_rows", SqlType::Int, false, "", false),
+        ];
+        if stmt.analyze {
+            columns.push(Column::new("actual_rows", SqlType::Int, false, "", false));
+            columns.push(Column::new("time_us", SqlType::Int, false, "", false));
+        }
+
+        let mut rows = Rows::new(Cursor::new(Vec::<u8>::new()), &columns);
+        for (i, (text, estimate)) in lines.into_iter().enumerate() {
+            let mut values = vec![Lit::String(text), Lit::Int(estimate as i64)];
+            if let Some(node) = stats.get(i) {
+                let node = node.borrow();
+                let micros = node.time.as_secs() * 1_000_000
+                    + node.time.subsec_nanos() as u64 / 1_000;
+                values.push(Lit::Int(node.rows as i64));
+                values.push(Lit::Int(micros as i64));
+            }
+            let mut row = Vec::<u8>::new();
+            for (column, value) in columns.iter().zip(values.iter()) {
+                try!(column.sql_type.encode_into(&mut row, value));
+            }
+            try!(rows.add_row(&row));
+        }
+        try!(rows.reset_pos());
+        Ok(rows)
+    }
+
+    /// Adds the text and the estimated rows of the plan and its inputs
+    fn explain_plan(&self, plan: &LogicalPlan, depth: usize, lines: &mut Vec<(String, u64)>)
+        -> Result<(), ExecutionError>
+    {
+        let indent = match depth {
+            0 => String::new(),
+            _ => format!("{}-> ", (0..depth - 1).map(|_| "   ").collect::<String>()),
+        };
+        let mut text = indent + &describe(plan);
+        if text.len() > MAX_TEXT {
+            let mut end = MAX_TEXT;
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            text.truncate(end);
+        }
+        lines.push((text, try!(self.estimate_rows(plan))));
+        for input in plan.inputs() {
+            try!(self.explain_plan(input, depth + 1, lines));
+        }
+        Ok(())
+    }
+}
+
+/// Describes a single plan node
+fn describe(plan: &LogicalPlan) -> String {
+    match plan {
+        &LogicalPlan::Scan { ref table, ref access, .. } => match access {
+            &Access::Full => format!("Seq Scan on {}", table),
+            &Access::Lookup { ref column, op, ref value, index: false } =>
+                format!("Engine Lookup on {}: {} {} {}", table, column, comp_text(op),
+                    lit_text(value)),
+            &Access::Lookup { ref column, op, ref value, index: true } => {
+                let scan = if op == CompType::Equ { "Index Scan" } else { "Index Range Scan" };
+                format!("{} on {} using {}: {} {} {}", scan, table, column, column,
+                    comp_text(op), lit_text(value))
+            },
+        },
+        &LogicalPlan::SystemScan { ref table, .. } => format!("System Scan on {}", table),
+        &LogicalPlan::Subquery { ref name, .. } => format!("Subquery Scan on {}", name),
+        &LogicalPlan::Filter { ref cond, .. } => format!("Filter: {}", condition_text(cond)),
+        &LogicalPlan::Join { ref cond, kind, .. } => {
+            let join = match kind {
+                JoinKind::NestedLoop => "Nested Loop Join",
+                JoinKind::Hash => "Hash Join",
+            };
+            match cond {
+                &Some(ref cond) => format!("{}: {}", join, condition_text(cond)),
+                &None => join.into(),
+            }
+        },
+        &LogicalPlan::Project { ref targets, .. } => format!("Project: {}",
+            targets.iter().map(target_text).collect::<Vec<_>>().join(", ")),
+        &LogicalPlan::Aggregate { ref group, .. } => match group {
+            &Some(ref group) => format!("Aggregate: {} columns", group.len()),
+            &None => "Distinct".into(),
+        },
+        &LogicalPlan::Sort { ref keys, .. } => format!("Sort: {}",
+            keys.iter().map(|key| {
+                let order = if key.order == Some(Order::Desc) { " desc" } else { "" };
+                format!("{}{}", column_text(&key.alias, &key.col), order)
+            }).collect::<Vec<_>>().join(", ")),
+        &LogicalPlan::Limit { offset, count, .. } => match count {
+            Some(count) => format!("Limit: {} offset {}", count, offset),
+            None => format!("Offset: {}", offset),
+        },
+        &LogicalPlan::SetOperation { op, all, .. } => {
+            let op = match op {
+                SetOp::Union => "Union",
+                SetOp::Intersect => "Intersect",
+                SetOp::Except => "Except",
+            };
+            if all { format!("{} All", op) } else { op.into() }
+        },
+    }
+}
+
+fn target_text(target: &Target) -> String {
+    let text = match target.col {
+        Col::Every => column_text(&target.alias, "*"),
+        Col::Specified(ref column) => column_text(&target.alias, column),
+        Col::Expr(ref expr) => expr_name(expr),
+        Col::Subquery(_) => "(subquery)".into(),
+    };
+    match target.rename {
+        Some(ref rename) => format!("{} as {}", text, rename),
+        None => text,
+    }
+}
+
+fn condition_text(cond: &Conditions) -> String {
+    match cond {
+        &Conditions::And(ref c1, ref c2) =>
+            format!("({} and {})", condition_text(c1), condition_text(c2)),
+        &Conditions::Or(ref c1, ref c2) =>
+            format!("({} or {})", condition_text(c1), condition_text(c2)),
+        &Conditions::Not(ref c) => format!("not {}", condition_text(c)),
+        &Conditions::Leaf(ref c) => {
+            let rhs = match c.rhs {
+                CondType::Literal(ref lit) => lit_text(lit),
+                CondType::Word(ref column) => column_text(&c.aliasrhs, column),
+                CondType::Subquery(_) => "(subquery)".into(),
+            };
+            format!("{} {} {}", column_text(&c.aliascol, &c.col), comp_text(c.op), rhs)
+        },
+        &Conditions::In(ref c) => {
+            let set = match c.set {
+                InSet::List(ref values) =>
+                    values.iter().map(lit_text).collect::<Vec<_>>().join(", "),
+                InSet::Select(_) => "subquery".into(),
+            };
+            format!("{} in ({})", column_text(&c.aliascol, &c.col), set)
+        },
+        &Conditions::Between(ref c) => format!("{} between {} and {}",
+            column_text(&c.aliascol, &c.col), expr_name(&c.low), expr_name(&c.high)),
+        &Conditions::Like(ref c) => format!("{} {} '{}'", column_text(&c.aliascol, &c.col),
+            if c.ignore_case { "ilike" } else { "like" }, c.pattern),
+        &Conditions::Exists(_) => "exists (subquery)".into(),
+        &Conditions::Const(value) => value.to_string(),
+    }
+}
+
+fn comp_text(op: CompType) -> &'static str {
+    match op {
+        CompType::Equ => "=",
+        CompType::NEqu => "<>",
+        CompType::GThan => ">",
+        CompType::SThan => "<",
+        CompType::GEThan => ">=",
+        CompType::SEThan => "<=",
+    }
+}
+
+fn column_text(alias: &Option<String>, column: &str) -> String {
+    match alias {
+        &Some(ref alias) => format!("{}.{}", alias, column),
+        &None => column.to_string(),
+    }
+}
+
+fn lit_text(lit: &Lit) -> String {
+    expr_name(&Expr::Literal(lit.clone()))
+}
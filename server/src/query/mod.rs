@@ -0,0 +1,1672 @@
+//! Query excecution moduleT
+//!
+//! This module takes the output of the SQL parser and executed that query
+//! by calling the appropriate `storage` and `auth` methods.
+//!
+
+use super::parse::ast::*;
+use super::parse::token::Lit;
+use super::storage::{Database, Column, Table, Rows, ResultSet, Engine, EngineID, Error};
+use super::storage::types::SqlType;
+use super::storage;
+use super::auth;
+use super::functions;
+use super::parse::parser::ParseError;
+use std::io::{self, Write, Read, Seek};
+use std::fs::File;
+use std::io::Cursor;
+use std::collections::{HashMap, HashSet};
+use std::cmp::{max, Ordering};
+use std::mem;
+use byteorder::{BigEndian, WriteBytesExt, ReadBytesExt};
+
+mod eval;
+mod explain;
+mod optimize;
+mod physical;
+mod plan;
+mod prepare;
+mod privileges;
+mod session;
+mod spill;
+mod stats;
+mod system;
+mod users;
+#[cfg(test)]
+mod tests;
+
+pub use self::prepare::{bind, parameter_types};
+pub use self::session::Session;
+pub use self::spill::Settings;
+pub use self::system::is_system_table;
+use self::eval::EvalCache;
+use self::session::Interrupt;
+use self::spill::MemoryBudget;
+
+pub struct Executor<'a> {
+    pub user: &'a mut auth::User,
+    session: &'a mut Session,
+    // working memory of the query
+    budget: MemoryBudget,
+    // stops the statement when it is cancelled or runs too long
+    interrupt: Interrupt,
+}
+
+/// Number of bytes of rows collected before they are handed to the sink
+pub const BATCH_SIZE: usize = 64 * 1024;
+
+/// Receives the result of a query while it is computed
+pub trait ResultSink {
+    /// Called once with the columns of the result, before any rows
+    fn columns(&mut self, columns: &[Column]) -> Result<(), ExecutionError>;
+
+    /// Called with the data of some rows, laid out like in a `ResultSet`
+    fn batch(&mut self, data: &[u8]) -> Result<(), ExecutionError>;
+}
+
+/// Name lookup maps of a row set: alias -> table name, column name -> table
+/// name and table name -> (column name -> column index)
+pub type NameInfos<'b> = (&'b HashMap<String, String>,
+                      &'b HashMap<String, String>,
+                      &'b HashMap<String, HashMap<String, usize>>);
+
+
+
+    pub fn execute_from_ast<'a>(mut query: Query, user: &'a mut auth::User,
+        session: &'a mut Session)
+        -> Result<ResultSet, ExecutionError> {
+
+        try!(prepare::check_bound(&mut query));
+        let mut executor = Executor::new(user, session);
+        try!(executor.check_privileges(&query));
+
+        let res = match query {
+            Query::ManipulationStmt(stmt) => executor.execute_manipulation_stmt(stmt),
+            Query::DefStmt(stmt) => executor.execute_def_stmt(stmt),
+            _ => return Err(ExecutionError::ParseError(ParseError::UnknownError)),
+
+        };
+        Ok(try!(try!(res).to_result_set()))
+    }
+
+    /// Executes the query and hands the result to the sink in batches. The
+    /// rows of a select are passed on as they are produced, so the whole
+    /// result is never kept in memory.
+    pub fn execute_streamed<S: ResultSink>(mut query: Query, user: &mut auth::User,
+        session: &mut Session, sink: &mut S)
+        -> Result<(), ExecutionError>
+    {
+        try!(prepare::check_bound(&mut query));
+        match query {
+            Query::ManipulationStmt(ManipulationStmt::Select(stmt)) => {
+                let executor = Executor::new(user, session);
+                try!(executor.check_select(&stmt));
+                executor.stream_select(stmt, sink)
+            },
+            query => {
+                let result = try!(execute_from_ast(query, user, session));
+                try!(sink.columns(&result.columns));
+                if !result.data.is_empty() {
+                    try!(sink.batch(&result.data));
+                }
+                Ok(())
+            },
+        }
+    }
+
+
+
+
+impl<'a> Executor<'a> {
+
+
+    pub fn new(user: &'a mut auth::User, session: &'a mut Session) -> Executor<'a> {
+        let budget = MemoryBudget::new(&session.settings);
+        let interrupt = session.interrupt();
+        Executor { user: user, session: session, budget: budget, interrupt: interrupt }
+    }
+
+
+    fn execute_manipulation_stmt(&mut self, mut query: ManipulationStmt)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError> {
+
+
+        match query {
+            ManipulationStmt::Use(stmt) => self.execute_use_stmt(stmt),
+            ManipulationStmt::Insert(stmt) => self.execute_insert_stmt(stmt),
+            ManipulationStmt::Describe(stmt) => self.execute_describe_stmt(stmt),
+            ManipulationStmt::Select(stmt) => self.execute_select_stmt(stmt),
+            ManipulationStmt::Delete(stmt) => self.execute_delete_stmt(stmt),
+            ManipulationStmt::Explain(stmt) => self.execute_explain_stmt(stmt),
+            ManipulationStmt::Analyze(table) => self.execute_analyze_stmt(table),
+            ManipulationStmt::Set(stmt) => self.execute_set_stmt(stmt),
+            ManipulationStmt::SetRole(role) => self.execute_set_role_stmt(role),
+            _ => Err(ExecutionError::DebugError("Feature not implemented yet!".into())),
+        }
+
+    }
+
+    fn execute_def_stmt(&mut self, query: DefStmt)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+
+        match query {
+            DefStmt::Create(stmt) => self.execute_create_stmt(stmt),
+            DefStmt::Drop(stmt) =>  self.execute_drop_stmt(stmt),
+            DefStmt::Alter(stmt) => self.execute_alt_stmt(stmt),
+            DefStmt::Grant(stmt) => self.execute_grant_stmt(stmt),
+            DefStmt::Revoke(stmt) => self.execute_revoke_stmt(stmt),
+            DefStmt::GrantRole(stmt) => self.execute_grant_role_stmt(stmt),
+            DefStmt::RevokeRole(stmt) => self.execute_revoke_role_stmt(stmt),
+        }
+    }
+
+    fn execute_use_stmt(&mut self, query: UseStmt)
+    -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+
+        match query {
+            UseStmt::Database(querybase) => {
+                self.user._currentDatabase = Some(try!(Database::load(&querybase)));
+                Ok(generate_rows_dummy())
+            }
+        }
+    }
+
+    fn execute_insert_stmt(&mut self, stmt: InsertStmt)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        let table = try!(self.get_table(&stmt.tid));
+
+        if !stmt.col.is_empty() {
+            return Err(ExecutionError::DebugError("Not implemented:
+            Insert just some values into some columns.
+            Use insert into table values (_,....) instead".into()))
+        }
+
+        let mut writevec = Vec::<u8>::new();
+        {
+            let columns = table.columns();
+            let insertvalues = stmt.val;
+            if insertvalues.len() != columns.len() {
+                return Err(ExecutionError::InsertMissmatch)
+            }
+
+            let mut index = 0;
+
+            for column in table.columns() {
+                info!("inserting at {:?}", writevec.len());
+                info!("This is the insertvalue: {:?}", insertvalues[index] );
+                column.sql_type.encode_into(&mut writevec,&insertvalues[index]);
+                index += 1;
+            }
+        }
+        let mut engine = table.create_engine();
+        info!("handing data vector {:?} to storage engine",writevec);
+        try!(engine.insert_row(&writevec));
+        Ok(generate_rows_dummy())
+
+    }
+
+    /// Plans a select statement and collects the rows it produces
+    fn execute_select_stmt(&self, stmt: SelectStmt)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        let plan = try!(self.optimize(try!(self.plan_select(stmt))));
+        let (mut operator, _) = try!(self.build_operator(plan));
+        let mut rows = Rows::new(Cursor::new(Vec::<u8>::new()), operator.columns());
+        while let Some(row) = try!(operator.next()) {
+            try!(rows.add_row(&row));
+        }
+        try!(rows.reset_pos());
+        Ok(rows)
+    }
+
+    /// Changes a setting of the session
+    fn execute_set_stmt(&mut self, stmt: SetStmt)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        match (&*stmt.name.to_lowercase(), stmt.value) {
+            // milliseconds, 0 turns the timeout off
+            ("statement_timeout", Lit::Int(0)) => self.session.statement_timeout = None,
+            ("statement_timeout", Lit::Int(ms)) if ms > 0 =>
+                self.session.statement_timeout = Some(ms as u64),
+            ("statement_timeout", _) => return Err(ExecutionError::InvalidSettingValue),
+            _ => return Err(ExecutionError::UnknownSetting),
+        }
+        Ok(generate_rows_dummy())
+    }
+
+    /// Runs the select and hands its rows to the sink, at most
+    /// `BATCH_SIZE` bytes at a time
+    fn stream_select<S: ResultSink>(&self, stmt: SelectStmt, sink: &mut S)
+        -> Result<(), ExecutionError>
+    {
+        let plan = try!(self.optimize(try!(self.plan_select(stmt))));
+        let (mut operator, _) = try!(self.build_operator(plan));
+        try!(sink.columns(operator.columns()));
+        let mut batch = Vec::<u8>::new();
+        while let Some(row) = try!(operator.next()) {
+            if !batch.is_empty() && batch.len() + row.len() > BATCH_SIZE {
+                try!(sink.batch(&batch));
+                batch.clear();
+            }
+            batch.extend(row.into_iter());
+        }
+        if !batch.is_empty() {
+            try!(sink.batch(&batch));
+        }
+        Ok(())
+    }
+
+    fn execute_where<'b>(&self,
+          mut tableset:Rows<Cursor<Vec<u8>>>,
+          infos: NameInfos<'b>,
+          conditions: &Conditions, negate: bool,
+          wheretype: Where
+        )
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+
+        match conditions {
+
+            // not (a and b) = not a or not b
+            &Conditions::And(ref c1, ref c2) if negate => {
+                let cond = Conditions::Or(Box::new(Conditions::Not(c1.clone())),
+                    Box::new(Conditions::Not(c2.clone())));
+                self.execute_where(tableset, infos, &cond, false, wheretype)
+            },
+
+            // not (a or b) = not a and not b
+            &Conditions::Or(ref c1, ref c2) if negate => {
+                let cond = Conditions::And(Box::new(Conditions::Not(c1.clone())),
+                    Box::new(Conditions::Not(c2.clone())));
+                self.execute_where(tableset, infos, &cond, false, wheretype)
+            },
+
+            &Conditions::Not(ref c) => {
+                self.execute_where(tableset, infos, c, !negate, wheretype)
+            },
+
+            &Conditions::And(ref c1, ref c2) => {
+                if wheretype == Where::Select {
+                    let mut leftside = try!(
+                        self.execute_where(tableset, infos, c1, false, wheretype.clone()));
+                    self.execute_where(leftside, infos, c2, false, wheretype)
+                } else {
+
+
+                    // IMPLEMENT!!! Needs a custom merge function
+                    let mut rightresult = try!(
+                        self.execute_where(try!(tableset.full_scan()),
+                        infos, c1, true, Where::Select));
+                    try!(self.execute_where(
+                        tableset,
+                        infos, c2, false, wheretype.clone()));
+                    let mut engine = try!(self.get_engine(&wheretype.unwrap()));
+                    try!(rightresult.reset_pos());
+                    loop {
+                        let mut rightrow = Vec::<u8>::new();
+                        let outerres = rightresult.next_row(&mut rightrow);
+                         match outerres {
+                            Ok(_) => (),
+                            Err(_) => break
+                        }
+                        engine.insert_row(&rightrow);
+                    }
+
+
+                    Ok(rightresult)
+
+                }
+            },
+
+
+
+
+
+            &Conditions::Or(ref c1, ref c2) => {
+                // When changing to the EFFECTIVE PLAN:
+                // REMEMBER CHANGING HERE TOO! (TODO)
+                if wheretype == Where::Select {
+                    let tableset2 = try!(tableset.full_scan());
+                    let leftside = try!(self.execute_where(
+                        tableset, infos, c1, false, wheretype.clone()));
+                    let rightside = try!(self.execute_where(
+                        tableset2, infos, c2, false, wheretype));
+                    self.merge_rows(leftside, rightside)
+                }
+                else {
+                    try!(self.execute_where(try!(
+                        tableset.full_scan()), infos, c1, false, wheretype.clone()));
+                    self.execute_where(tableset, infos, c2, false, wheretype)
+                }
+
+            },
+
+
+
+            // TODO: SO MUCH REDUNDANT CODE!!!!!!!11111
+            //       remove whenever there is time.
+            &Conditions::Leaf(ref c) => {
+                let tablename = if c.aliascol.is_some() {
+                    match infos.0.get(&c.clone().aliascol.unwrap()) {
+                        Some(x) => x,
+                        None => return Err(ExecutionError::UnknownAlias)
+                    }
+                } else {
+                    match infos.1.get(&c.col) {
+                        Some(x) => x,
+                        None => return Err(ExecutionError::UnknownColumn)
+                    }
+                };
+                let columntoindex = infos.2.get(tablename).unwrap();
+                let column = columntoindex.get(&c.col);
+                if column.is_none() {
+                    return Err(ExecutionError::UnknownColumn)
+                }
+                let index = column.unwrap().clone();
+
+                match c.rhs {
+
+                    CondType::Word(ref column) => {
+                        let tablename2 = if c.aliasrhs.is_some() {
+                            match infos.0.get(&c.clone().aliasrhs.unwrap()) {
+                                Some(x) => x,
+                                None => return Err(ExecutionError::UnknownAlias)
+                            }
+                        } else {
+                            match infos.1.get(column) {
+                                Some(x) => x,
+                                None => return Err(ExecutionError::UnknownColumn)
+                            }
+                        };
+                        let columntoindex2 = infos.2.get(tablename2).unwrap();
+                        let column2 = columntoindex2.get(column);
+                        if column2.is_none() {
+                            return Err(ExecutionError::UnknownColumn)
+                        }
+                        let index2 = column2.unwrap().clone();
+                        let operator = if negate {
+                            c.op.negate()
+                        } else {
+                            c.op
+                        };
+                        if tableset.columns[index].sql_type != tableset.columns[index2].sql_type {
+                            return Err(ExecutionError::CompareDatatypeMissmatch)
+                        }
+                        if wheretype == Where::Select {
+                            Ok(try!(tableset.lookup(index,
+                                (&Vec::<u8>::new(), Some(index2)) , operator)))
+                        } else {
+                            let engine = try!(self.get_engine(&wheretype.unwrap()));
+                            try!(engine.delete(index,
+                                (&Vec::<u8>::new(), Some(index2)), operator));
+                            Ok(generate_rows_dummy())
+                        }
+
+                    },
+
+                    CondType::Literal(ref lit) => {
+                        // Error handling: if wrong compare type is giving => Missmatch error
+                        try!(check_compare_type(&tableset.columns[index].sql_type, lit));
+                        // TODO: use get_column methods!!
+                        let mut comparedata = Vec::<u8>::new();
+                        try!(tableset.columns[index].sql_type.encode_into(& mut comparedata,lit));
+                        let operator = if negate {
+                            c.op.negate()
+                        } else {
+                            c.op
+                        };
+                        if wheretype == Where::Select {
+                            Ok(try!(tableset.lookup(index, (&comparedata, None) , operator)))
+                        } else {
+                            let engine = try!(self.get_engine(&wheretype.unwrap()));
+                            engine.delete(index, (&comparedata, None), operator);
+                            Ok(generate_rows_dummy())
+                        }
+                    },
+
+                    CondType::Subquery(ref sub) => {
+                        if wheretype != Where::Select {
+                            return self.delete_where(&wheretype.unwrap(), infos,
+                                conditions, negate)
+                        }
+                        let operator = if negate {
+                            c.op.negate()
+                        } else {
+                            c.op
+                        };
+                        let mut cache = SubqueryCache::new();
+                        self.filter_rows(tableset, |columns, row| {
+                            let result = try!(self.execute_subquery(sub, &OuterRow {
+                                infos: infos,
+                                columns: columns,
+                                row: row,
+                            }, &mut cache));
+                            match try!(result.scalar()) {
+                                Some(value) =>
+                                    self.compare_value(columns, row, index, &value, operator),
+                                // comparing with no value is never true
+                                None => Ok(false),
+                            }
+                        })
+                    },
+                }
+
+
+            },
+
+            &Conditions::In(ref c) => {
+                if wheretype != Where::Select {
+                    return self.delete_where(&wheretype.unwrap(), infos, conditions, negate)
+                }
+                let index = try!(lookup_column(infos, &c.aliascol, &c.col));
+                match c.set {
+                    InSet::Select(ref sub) => {
+                        let mut cache = SubqueryCache::new();
+                        self.filter_rows(tableset, |columns, row| {
+                            let result = try!(self.execute_subquery(sub, &OuterRow {
+                                infos: infos,
+                                columns: columns,
+                                row: row,
+                            }, &mut cache));
+                            let found = try!(self.contains_value(columns, row, index,
+                                &result.values));
+                            Ok(found != negate)
+                        })
+                    },
+                    InSet::List(ref values) => {
+                        self.filter_rows(tableset, |columns, row| {
+                            let found = try!(self.contains_value(columns, row, index, values));
+                            Ok(found != negate)
+                        })
+                    },
+                }
+            },
+
+            &Conditions::Between(ref c) => {
+                if wheretype != Where::Select {
+                    return self.delete_where(&wheretype.unwrap(), infos, conditions, negate)
+                }
+                self.filter_rows(tableset, |columns, row| {
+                    let inside = try!(self.eval_between(c, &OuterRow {
+                        infos: infos,
+                        columns: columns,
+                        row: row,
+                    }));
+                    Ok(inside != negate)
+                })
+            },
+
+            &Conditions::Like(ref c) => {
+                if wheretype != Where::Select {
+                    return self.delete_where(&wheretype.unwrap(), infos, conditions, negate)
+                }
+                let index = try!(lookup_column(infos, &c.aliascol, &c.col));
+                try!(check_compare_type(&tableset.columns[index].sql_type,
+                    &Lit::String(c.pattern.clone())));
+                let pattern: Vec<char> = if c.ignore_case {
+                    c.pattern.to_lowercase().chars().collect()
+                } else {
+                    c.pattern.chars().collect()
+                };
+                self.filter_rows(tableset, |columns, row| {
+                    let value = match try!(decode_value(&columns[index].sql_type,
+                        &column_value(columns, row, index)))
+                    {
+                        Lit::String(s) => if c.ignore_case { s.to_lowercase() } else { s },
+                        _ => return Err(ExecutionError::CompareDatatypeMissmatch),
+                    };
+                    let value: Vec<char> = value.chars().collect();
+                    Ok(like_match(&pattern, &value) != negate)
+                })
+            },
+
+            &Conditions::Exists(ref sub) => {
+                if wheretype != Where::Select {
+                    return self.delete_where(&wheretype.unwrap(), infos, conditions, negate)
+                }
+                let mut cache = SubqueryCache::new();
+                self.filter_rows(tableset, |columns, row| {
+                    let result = try!(self.execute_subquery(sub, &OuterRow {
+                        infos: infos,
+                        columns: columns,
+                        row: row,
+                    }, &mut cache));
+                    Ok(result.values.is_empty() == negate)
+                })
+            },
+
+            &Conditions::Const(value) => {
+                if wheretype != Where::Select {
+                    return self.delete_where(&wheretype.unwrap(), infos, conditions, negate)
+                }
+                self.filter_rows(tableset, |_, _| Ok(value != negate))
+            },
+        }
+
+    }
+
+    /// Deletes the rows matching conditions the storage engine can not
+    /// evaluate itself: the matching rows are selected and the table is
+    /// written anew without them.
+    fn delete_where(&self, table: &str, infos: NameInfos, conditions: &Conditions,
+        negate: bool)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        let mut matching = try!(self.execute_where(try!(self.get_rows(table)),
+            infos, conditions, negate, Where::Select));
+        let deleted: HashSet<Vec<u8>> = try!(self.map_rows(&mut matching,
+            |_, row| Ok(row.to_vec()))).into_iter().collect();
+
+        let keep = try!(self.filter_rows(try!(self.get_rows(table)),
+            |_, row| Ok(!deleted.contains(row))));
+        try!(self.rewrite_table(table, keep));
+        Ok(generate_rows_dummy())
+    }
+
+    /// Replaces the content of a table by the given rows
+    fn rewrite_table(&self, table: &str, mut rows: Rows<Cursor<Vec<u8>>>)
+        -> Result<(), ExecutionError>
+    {
+        let mut engine = try!(self.get_engine(table));
+        try!(engine.reset());
+        try!(rows.reset_pos());
+        loop {
+            let mut row = Vec::<u8>::new();
+            match rows.next_row(&mut row) {
+                Ok(_) => { try!(engine.insert_row(&row)); },
+                Err(Error::EndOfFile) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Executes a subquery for the current row of the outer query. Columns
+    /// of the outer query are replaced by their values first, so correlated
+    /// subqueries see the current row. The last result is reused as long as
+    /// the bound subquery does not change, so uncorrelated ones run once.
+    fn execute_subquery(&self, stmt: &SelectStmt, outer: &OuterRow,
+        cache: &mut SubqueryCache)
+        -> Result<SubqueryResult, ExecutionError>
+    {
+        let bound = try!(self.bind_select(stmt.clone(), None, outer, &mut cache.names));
+        if cache.stmt.as_ref() == Some(&bound) {
+            if let Some(ref result) = cache.result {
+                return Ok(result.clone())
+            }
+        }
+
+        let mut rows = try!(self.execute_select_stmt(bound.clone()));
+        let result = try!(SubqueryResult::from_rows(&mut rows));
+        cache.stmt = Some(bound);
+        cache.result = Some(result.clone());
+        Ok(result)
+    }
+
+    /// Collects the column names of all tables a subquery selects from. They
+    /// are looked up once for every select of the cache.
+    fn inner_names(&self, stmt: &SelectStmt, cache: &mut Vec<(SelectStmt, InnerNames)>)
+        -> Result<InnerNames, ExecutionError>
+    {
+        if let Some(&(_, ref names)) = cache.iter().find(|&&(ref s, _)| s == stmt) {
+            return Ok(names.clone())
+        }
+        let mut columns = HashMap::new();
+        for name in &stmt.tid {
+            let names: Vec<String> = try!(self.source_columns(&stmt.derived, name))
+                .into_iter().map(|c| c.name).collect();
+            columns.insert(name.clone(), names);
+        }
+        let names = InnerNames { alias: stmt.alias.clone(), columns: columns };
+        cache.push((stmt.clone(), names.clone()));
+        Ok(names)
+    }
+
+    /// Replaces all columns of the outer query in a subquery by their values
+    /// in the current outer row. `enclosing` are the names of the selects
+    /// between the outer query and this one, their columns stay.
+    fn bind_select(&self, mut stmt: SelectStmt, enclosing: Option<&InnerNames>,
+        outer: &OuterRow, cache: &mut Vec<(SelectStmt, InnerNames)>)
+        -> Result<SelectStmt, ExecutionError>
+    {
+        let own = try!(self.inner_names(&stmt, cache));
+        let names = match enclosing {
+            Some(enclosing) => enclosing.nested(&own),
+            None => own,
+        };
+        for mut target in mem::replace(&mut stmt.target, Vec::new()) {
+            target.col = match target.col {
+                Col::Specified(name) => if names.resolves(&target.alias, &name) {
+                    Col::Specified(name)
+                } else {
+                    let value = try!(outer.value(&target.alias, &name));
+                    target.alias = None;
+                    Col::Expr(Expr::Literal(value))
+                },
+                Col::Expr(expr) => Col::Expr(try!(self.bind_expr(expr, &names, outer, cache))),
+                Col::Subquery(sub) => Col::Subquery(Box::new(
+                    try!(self.bind_select(*sub, Some(&names), outer, cache)))),
+                col => col,
+            };
+            stmt.target.push(target);
+        }
+        stmt.cond = match stmt.cond.take() {
+            Some(cond) => Some(try!(self.bind_conditions(cond, &names, outer, cache))),
+            None => None,
+        };
+        // the selects combined with this one are nested in the same ones
+        for mut compound in mem::replace(&mut stmt.compound, Vec::new()) {
+            compound.select = Box::new(
+                try!(self.bind_select(*compound.select, enclosing, outer, cache)));
+            stmt.compound.push(compound);
+        }
+        Ok(stmt)
+    }
+
+    fn bind_conditions(&self, cond: Conditions, names: &InnerNames, outer: &OuterRow,
+        cache: &mut Vec<(SelectStmt, InnerNames)>)
+        -> Result<Conditions, ExecutionError>
+    {
+        let cond = match cond {
+            Conditions::And(c1, c2) => return Ok(Conditions::And(
+                Box::new(try!(self.bind_conditions(*c1, names, outer, cache))),
+                Box::new(try!(self.bind_conditions(*c2, names, outer, cache))))),
+            Conditions::Or(c1, c2) => return Ok(Conditions::Or(
+                Box::new(try!(self.bind_conditions(*c1, names, outer, cache))),
+                Box::new(try!(self.bind_conditions(*c2, names, outer, cache))))),
+            Conditions::Not(c) => return Ok(Conditions::Not(
+                Box::new(try!(self.bind_conditions(*c, names, outer, cache))))),
+            Conditions::Exists(sub) => return Ok(Conditions::Exists(
+                Box::new(try!(self.bind_select(*sub, Some(names), outer, cache))))),
+            Conditions::Const(value) => return Ok(Conditions::Const(value)),
+            Conditions::Leaf(mut c) => {
+                c.rhs = match c.rhs {
+                    CondType::Word(name) => if names.resolves(&c.aliasrhs, &name) {
+                        CondType::Word(name)
+                    } else {
+                        let value = try!(outer.value(&c.aliasrhs, &name));
+                        c.aliasrhs = None;
+                        CondType::Literal(value)
+                    },
+                    CondType::Subquery(sub) => CondType::Subquery(Box::new(
+                        try!(self.bind_select(*sub, Some(names), outer, cache)))),
+                    rhs => rhs,
+                };
+                if names.resolves(&c.aliascol, &c.col) {
+                    return Ok(Conditions::Leaf(c))
+                }
+                // only the right side may be a value, so both sides swap
+                if let CondType::Word(name) = c.rhs.clone() {
+                    return Ok(Conditions::Leaf(Condition {
+                        aliascol: c.aliasrhs.clone(),
+                        col: name,
+                        op: c.op.mirror(),
+                        aliasrhs: None,
+                        rhs: CondType::Literal(try!(outer.value(&c.aliascol, &c.col))),
+                    }))
+                }
+                Conditions::Leaf(c)
+            },
+            Conditions::In(mut c) => {
+                c.set = match c.set {
+                    InSet::Select(sub) => InSet::Select(Box::new(
+                        try!(self.bind_select(*sub, Some(names), outer, cache)))),
+                    list => list,
+                };
+                if names.resolves(&c.aliascol, &c.col) {
+                    return Ok(Conditions::In(c))
+                }
+                Conditions::In(c)
+            },
+            Conditions::Between(mut c) => {
+                c.low = try!(self.bind_expr(c.low, names, outer, cache));
+                c.high = try!(self.bind_expr(c.high, names, outer, cache));
+                if names.resolves(&c.aliascol, &c.col) {
+                    return Ok(Conditions::Between(c))
+                }
+                // the column is a value, so the bounds are compared with it
+                let value = try!(outer.value(&c.aliascol, &c.col));
+                return Ok(Conditions::And(
+                    Box::new(try!(bound_condition(c.low, CompType::SEThan, &value))),
+                    Box::new(try!(bound_condition(c.high, CompType::GEThan, &value)))))
+            },
+            Conditions::Like(c) => {
+                if names.resolves(&c.aliascol, &c.col) {
+                    return Ok(Conditions::Like(c))
+                }
+                Conditions::Like(c)
+            },
+        };
+        // the condition only compares values of the outer row, so it is
+        // decided for the whole subquery
+        Ok(Conditions::Const(try!(self.eval_condition(&cond, outer, &mut EvalCache::default()))))
+    }
+
+    fn bind_expr(&self, expr: Expr, names: &InnerNames, outer: &OuterRow,
+        cache: &mut Vec<(SelectStmt, InnerNames)>)
+        -> Result<Expr, ExecutionError>
+    {
+        Ok(match expr {
+            Expr::Column(alias, name) => if names.resolves(&alias, &name) {
+                Expr::Column(alias, name)
+            } else {
+                Expr::Literal(try!(outer.value(&alias, &name)))
+            },
+            Expr::Function(name, args) => {
+                let mut bound = Vec::new();
+                for arg in args {
+                    bound.push(try!(self.bind_expr(arg, names, outer, cache)));
+                }
+                Expr::Function(name, bound)
+            },
+            Expr::Case(whens, default) => {
+                let mut bound = Vec::new();
+                for (cond, then) in whens {
+                    bound.push((try!(self.bind_conditions(cond, names, outer, cache)),
+                        try!(self.bind_expr(then, names, outer, cache))));
+                }
+                let default = match default {
+                    Some(default) => Some(Box::new(
+                        try!(self.bind_expr(*default, names, outer, cache)))),
+                    None => None,
+                };
+                Expr::Case(bound, default)
+            },
+            literal => literal,
+        })
+    }
+
+    /// Compares the value of a column in the row with a literal
+    fn compare_value(&self, columns: &[Column], row: &[u8], index: usize, lit: &Lit,
+        operator: CompType)
+        -> Result<bool, ExecutionError>
+    {
+        try!(check_compare_type(&columns[index].sql_type, lit));
+        let mut comparedata = Vec::<u8>::new();
+        try!(columns[index].sql_type.encode_into(&mut comparedata, lit));
+        let value = column_value(columns, row, index);
+        Ok(try!(columns[index].sql_type.cmp(&value, &comparedata, operator)))
+    }
+
+    /// Checks if the value of a column in the row equals one of the literals
+    fn contains_value(&self, columns: &[Column], row: &[u8], index: usize, values: &[Lit])
+        -> Result<bool, ExecutionError>
+    {
+        for value in values {
+            if try!(self.compare_value(columns, row, index, value, CompType::Equ)) {
+                return Ok(true)
+            }
+        }
+        Ok(false)
+    }
+
+    /// Returns a new Rows object with all rows the predicate is true for
+    fn filter_rows<F>(&self, mut rows: Rows<Cursor<Vec<u8>>>, mut pred: F)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+        where F: FnMut(&[Column], &[u8]) -> Result<bool, ExecutionError>
+    {
+        try!(rows.reset_pos());
+        let columns = rows.columns.clone();
+        let mut result = Rows::new(Cursor::new(Vec::<u8>::new()), &columns);
+        loop {
+            let mut row = Vec::<u8>::new();
+            match rows.next_row(&mut row) {
+                Ok(_) => (),
+                Err(Error::EndOfFile) => break,
+                Err(e) => return Err(e.into()),
+            }
+            if try!(pred(&columns, &row)) {
+                try!(result.add_row(&row));
+            }
+        }
+        Ok(result)
+    }
+
+    /// Calls f for every row and collects the results
+    fn map_rows<F, T>(&self, rows: &mut Rows<Cursor<Vec<u8>>>, mut f: F)
+        -> Result<Vec<T>, ExecutionError>
+        where F: FnMut(&[Column], &[u8]) -> Result<T, ExecutionError>
+    {
+        try!(rows.reset_pos());
+        let columns = rows.columns.clone();
+        let mut results = Vec::new();
+        loop {
+            let mut row = Vec::<u8>::new();
+            match rows.next_row(&mut row) {
+                Ok(_) => results.push(try!(f(&columns, &row))),
+                Err(Error::EndOfFile) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(results)
+    }
+
+    fn execute_delete_stmt(&mut self, mut query: DeleteStmt)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError> {
+
+        let mut table = try!(self.get_rows(&query.tid));
+        let mut name_column_map = HashMap::<String, HashMap<String, usize>>::new();
+        let mut column_index_map = HashMap::<String, usize>::new();
+        let mut column_tablename_map = HashMap::<String, String>::new();
+        let mut columnindex: usize = 0;
+        for column in table.columns.clone() {
+            column_tablename_map.insert(column.name.clone(), query.tid.clone());
+            column_index_map.insert(column.name.into(), columnindex);
+            columnindex += 1;
+        }
+
+        name_column_map.insert(query.tid.clone(), column_index_map);
+        query.alias.insert(query.tid.clone(), query.tid.clone());
+
+        if query.cond.is_some() {
+            try!(self.execute_where(table,
+                    (&query.alias, &column_tablename_map, &name_column_map),
+                    &query.cond.unwrap(), false, Where::Delete(query.tid)));
+        } else {
+            let mut engine = try!(self.get_engine(&query.tid));
+            // Todo: Storage: enable full table reset!!
+            try!(engine.reset());
+        }
+
+        Ok(generate_rows_dummy())
+    }
+
+
+    fn execute_describe_stmt(&mut self, query: String)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        if system::is_system_table(&query) {
+            return Ok(Rows::new(Cursor::new(Vec::<u8>::new()), &system::columns(&query)))
+        }
+        // views have no stored columns, they are taken from the select
+        if try!(self.get_own_database()).has_view(&query) {
+            let rows = try!(self.get_rows(&query));
+            return Ok(Rows::new(Cursor::new(Vec::<u8>::new()), &rows.columns))
+        }
+
+        let table = try!(self.get_table(&query));
+        let columns = table.columns();
+        let mut columnvec = Vec::new();
+
+        columnvec.extend(columns.iter().cloned());
+        Ok(Rows::new(Cursor::new(Vec::<u8>::new()), &columnvec))
+    }
+
+    fn execute_create_stmt(&mut self, query: CreateStmt)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        match query {
+            CreateStmt::Database(s) => {
+                self.user._currentDatabase = Some(try!(Database::create(&s)));
+                Ok(generate_rows_dummy())
+            },
+            CreateStmt::Table(stmt) => self.execute_create_table_stmt(stmt),
+            CreateStmt::View(stmt) => self.execute_create_view_stmt(stmt),
+            CreateStmt::User(stmt) => self.execute_create_user_stmt(stmt),
+            CreateStmt::Role(name) => self.execute_create_role_stmt(name),
+        }
+    }
+
+    fn execute_create_table_stmt(&mut self, query: CreateTableStmt)
+         -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError> {
+        // the table could never be read, the system table would be found
+        if system::is_system_table(&query.tid) {
+            return Err(ExecutionError::TableExists)
+        }
+        let base = try!(self.get_own_database());
+        // views are found before tables, the table would never be read
+        if base.has_view(&query.tid) {
+            return Err(ExecutionError::TableExists)
+        }
+        let tmp_vec : Vec<_> = query.cols.into_iter().map(|c| Column {
+            name: c.cid,
+            sql_type: c.datatype,
+            allow_null: false,
+            description: "this is a column".to_string(),
+             is_primary_key: c.primary,
+        }).collect();
+        // a single Int primary key gets an index
+        let engine_id = {
+            let keys: Vec<_> = tmp_vec.iter().filter(|c| c.is_primary_key).collect();
+            if keys.len() == 1 && keys[0].sql_type == SqlType::Int {
+                EngineID::BStar
+            } else {
+                EngineID::FlatFile
+            }
+        };
+        let table = try!(base.create_table(&query.tid, tmp_vec, engine_id));
+        let mut engine = table.create_engine();
+        engine.create_table();
+        Ok(generate_rows_dummy())
+    }
+
+    fn execute_create_view_stmt(&mut self, query: CreateViewStmt)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        if system::is_system_table(&query.name) {
+            return Err(ExecutionError::ViewExists)
+        }
+        {
+            let base = try!(self.get_own_database());
+            if base.load_table(&query.name).is_ok() {
+                return Err(ExecutionError::TableExists)
+            }
+            if !query.opt && base.has_view(&query.name) {
+                return Err(ExecutionError::ViewExists)
+            }
+        }
+        // a view selecting from itself could never be expanded
+        try!(self.check_view_cycle(&query.name, &query.sel));
+
+        // run the select once to reject unknown tables and columns now
+        // instead of every time the view is used
+        try!(self.execute_select_stmt(query.sel.clone()));
+
+        let base = try!(self.get_own_database());
+        try!(base.create_view(&query.name, query.sel));
+        Ok(generate_rows_dummy())
+    }
+
+    fn execute_drop_stmt(&mut self, query: DropStmt)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        match query {
+            DropStmt::Table(s) => {
+                let base = try!(self.get_own_database());
+                let table = try!(base.load_table(&s));
+                try!(table.delete());
+                Ok(generate_rows_dummy())
+            },
+            DropStmt::Database(s) => {
+                let base = try!(Database::load(&s));
+                try!(base.delete());
+                let mut baseinuse = false;
+                match self.user._currentDatabase {
+                    Some(ref base) => {
+                        if base.name == s {
+                            baseinuse = true;
+                        };
+                        ()
+                    },
+                    None => (),
+                };
+                if baseinuse {
+                    self.user._currentDatabase = None;
+                };
+                Ok(generate_rows_dummy())
+            },
+            DropStmt::View(s) => {
+                let base = try!(self.get_own_database());
+                let view = try!(base.load_view(&s));
+                try!(view.delete());
+                Ok(generate_rows_dummy())
+            },
+            DropStmt::User(s) => self.execute_drop_user_stmt(s),
+            DropStmt::Role(s) => self.execute_drop_role_stmt(s),
+        }
+    }
+
+
+    fn execute_alt_stmt(&mut self, query: AltStmt)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        match query {
+            AltStmt::Table(stmt) => self.execute_alt_table_stmt(stmt),
+            AltStmt::User(stmt) => self.execute_alter_user_stmt(stmt),
+        }
+
+    }
+
+
+    fn execute_alt_table_stmt(&mut self, stmt: AlterTableStmt)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        let table = try!(self.get_table(&stmt.tid));
+        match stmt.op {
+            AlterOp::Add(columninfo) => {
+                let mut table = try!(self.get_table(&stmt.tid));
+                // Todo: no fullscan necessary!
+                let mut rows = try!(self.get_rows(&stmt.tid));
+                if !try!(rows.is_empty()) {
+                    return Err(ExecutionError::TableNotEmpty)
+                }
+
+                let comment = if columninfo.comment.is_some() {
+                    columninfo.comment.unwrap()
+                } else {
+                    "".into()
+                };
+
+                table.add_column(&columninfo.cid,
+                                 columninfo.datatype,
+                                 !columninfo.not_null,
+                                 &comment,
+                                 columninfo.primary
+                                 );
+                try!(table.save());
+                Ok(generate_rows_dummy())
+            },
+            AlterOp::Drop(column) => {
+                let mut table = try!(self.get_table(&stmt.tid));
+                // Todo: no fullscan necessary!
+                let mut rows = try!(self.get_rows(&stmt.tid));
+                if !try!(rows.is_empty()) {
+                    return Err(ExecutionError::TableNotEmpty)
+                }
+                table.remove_column(&column);
+                try!(table.save());
+                Ok(generate_rows_dummy())
+            },
+            AlterOp::Modify(columninfo) => {
+                let mut table = try!(self.get_table(&stmt.tid));
+                {
+                let columns = &mut table.meta_data.columns;
+                let comment = if columninfo.comment.is_some() {
+                    columninfo.comment.unwrap()
+                } else {
+                    "".into()
+                };
+
+                for index in 0..columns.len() {
+                    if columns[index].name == columninfo.cid {
+                        columns[index] = Column {
+                            name: columninfo.cid.clone(),
+                            sql_type: columninfo.datatype,
+                            is_primary_key: columninfo.primary,
+                            allow_null: !columninfo.not_null,
+                            description: comment.clone()
+                        };
+                    }
+                }
+                }
+                //println!("{:?}",table);
+                try!(table.save());
+                Ok(generate_rows_dummy())
+            },
+        }
+
+
+    }
+
+    fn get_own_database(&self) -> Result<&Database, ExecutionError> {
+        match self.user._currentDatabase {
+            Some(ref base) => Ok(base),
+            None => Err(ExecutionError::NoDatabaseSelected),
+        }
+    }
+
+    fn get_table(&self, table: &str) -> Result<Table, ExecutionError>{
+        let dbase = try!(self.get_own_database());
+        Ok(try!(dbase.load_table(table)))
+    }
+
+    fn get_engine<'b>(&'b self, table: &str) -> Result<Box<Engine + 'b>, ExecutionError> {
+        let table = try!(self.get_table(table));
+        Ok(table.create_engine())
+    }
+
+    /// Returns the rows of a table, view or derived table in the from list
+    fn get_source_rows(&self, derived: &HashMap<String, SelectStmt>, name: &str)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        match derived.get(name) {
+            Some(query) => self.execute_view(query.clone()),
+            None => self.get_rows(name),
+        }
+    }
+
+    /// Returns the columns of a table, view or derived table in the from list
+    fn source_columns(&self, derived: &HashMap<String, SelectStmt>, name: &str)
+        -> Result<Vec<Column>, ExecutionError>
+    {
+        if system::is_system_table(name) && !derived.contains_key(name) {
+            return Ok(system::columns(name))
+        }
+        if derived.contains_key(name) || try!(self.get_own_database()).has_view(name) {
+            return Ok(try!(self.get_source_rows(derived, name)).columns)
+        }
+        Ok(try!(self.get_table(name)).columns().to_vec())
+    }
+
+    fn get_rows(&self, table: &str) -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError> {
+        if system::is_system_table(table) {
+            return self.system_rows(table)
+        }
+        {
+            let base = try!(self.get_own_database());
+            if base.has_view(table) {
+                let view = try!(base.load_view(table));
+                return self.execute_view(view.query.clone())
+            }
+        }
+        let engine = try!(self.get_engine(table));
+        let mut rows = try!(engine.full_scan());
+        try!(rows.reset_pos());
+        Ok(rows)
+    }
+
+    /// Returns the rows of a table whose column compares to the value, the
+    /// storage engine selects them
+    fn lookup_rows(&self, table: &str, column: &str, op: CompType, value: &Lit, index: bool)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        let engine = try!(self.get_engine(table));
+        let position = match engine.table().columns().iter().position(|c| c.name == column) {
+            Some(position) => position,
+            None => return Err(ExecutionError::UnknownColumn),
+        };
+        let mut data = Vec::<u8>::new();
+        try!(engine.table().columns()[position].sql_type.encode_into(&mut data, value));
+        let mut rows = if index {
+            try!(engine.index_lookup(position, &data, op))
+        } else {
+            try!(engine.lookup(position, (&data, None), op))
+        };
+        try!(rows.reset_pos());
+        Ok(rows)
+    }
+
+    /// Fails if the select reads from the view `name`, directly or through
+    /// other views. The stored definition of `name` is not looked at, so a
+    /// new definition can be checked before it replaces the old one.
+    fn check_view_cycle(&self, name: &str, query: &SelectStmt) -> Result<(), ExecutionError> {
+        let base = try!(self.get_own_database());
+        let mut pending = Vec::new();
+        select_sources(query, &mut pending);
+        let mut seen = HashSet::new();
+        while let Some(source) = pending.pop() {
+            if source == name {
+                return Err(ExecutionError::RecursiveView)
+            }
+            if !seen.insert(source.clone()) || !base.has_view(&source) {
+                continue
+            }
+            let view = try!(base.load_view(&source));
+            select_sources(&view.query, &mut pending);
+        }
+        Ok(())
+    }
+
+    /// Executes the select statement of a view. Within the outer query the
+    /// view is used like a table, so the table prefixes the select puts in
+    /// front of the column names are removed again.
+    fn execute_view(&self, query: SelectStmt)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        let mut rows = try!(self.execute_select_stmt(query));
+        for column in rows.columns.iter_mut() {
+            let name = column.name.rsplit('.').next().unwrap_or("").to_string();
+            column.name = name;
+        }
+        try!(rows.reset_pos());
+        Ok(rows)
+    }
+
+    fn merge_rows(&self, mut left: Rows<Cursor<Vec<u8>>>, mut right: Rows<Cursor<Vec<u8>>>)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        try!(right.reset_pos());
+        loop {
+            try!(left.reset_pos());
+            let mut valid = true;
+
+            let mut rightrow = Vec::<u8>::new();
+            let outerres = right.next_row(&mut rightrow);
+             match outerres {
+                Ok(_) => (),
+                Err(_) => break
+            }
+            loop {
+                let mut leftrow = Vec::<u8>::new();
+                let outerres = left.next_row(&mut leftrow);
+                 match outerres {
+                    Ok(_) => (),
+                    Err(_) => break
+                }
+                            let mut primarykeys = 0;
+            let mut equalprimarykeys = 0;
+                for index in 0..left.columns.len() {
+                    if left.columns[index].is_primary_key {
+                        primarykeys += 1;
+                        let leftval = try!(left.get_value(&leftrow,index));
+                        let rightval = try!(right.get_value(&rightrow,index));
+                        if leftval == rightval {
+                            equalprimarykeys +=1;
+                        }
+                    }
+                }
+                if primarykeys==equalprimarykeys {
+                    valid = false;
+                }
+            }
+            if valid {
+                left.add_row(&rightrow);
+            }
+        }
+
+        Ok(left)
+
+
+    }
+
+
+
+}
+
+
+fn generate_rows_dummy() -> Rows<Cursor<Vec<u8>>> {
+    let v = Vec::<u8>::new();
+    let c = Cursor::new(v);
+
+    Rows::new(c, &[])
+}
+
+/// Checks if a literal can be compared with values of the given type
+fn check_compare_type(sql_type: &SqlType, lit: &Lit) -> Result<(), ExecutionError> {
+    let compatible = match sql_type {
+        &SqlType::Char(_) => lit.sqltype() == SqlType::Char(0),
+        _ => *sql_type == lit.sqltype(),
+    };
+    if compatible {
+        Ok(())
+    } else {
+        Err(ExecutionError::CompareDatatypeMissmatch)
+    }
+}
+
+/// Returns the columns of a set operation result. Both sides need the same
+/// number of columns with matching types, char columns get the larger length.
+fn set_operation_columns(left: &[Column], right: &[Column])
+    -> Result<Vec<Column>, ExecutionError>
+{
+    if left.len() != right.len() {
+        return Err(ExecutionError::SetOperationColumnCount)
+    }
+    let mut columns = Vec::new();
+    for (l, r) in left.iter().zip(right.iter()) {
+        let sql_type = match (&l.sql_type, &r.sql_type) {
+            (&SqlType::Char(a), &SqlType::Char(b)) => SqlType::Char(max(a, b)),
+            (a, b) if a == b => a.clone(),
+            _ => return Err(ExecutionError::SetOperationTypeMissmatch),
+        };
+        let mut column = l.clone();
+        column.sql_type = sql_type;
+        columns.push(column);
+    }
+    Ok(columns)
+}
+
+/// Returns the bytes of the column with the given index in a row
+fn column_value(columns: &[Column], row: &[u8], index: usize) -> Vec<u8> {
+    let start = columns[..index].iter().fold(0, |acc, c| acc + c.get_size() as usize);
+    let end = start + columns[index].get_size() as usize;
+    row[start..end].to_vec()
+}
+
+/// Decodes a stored value, strings lose their NUL padding
+fn decode_value(sql_type: &SqlType, mut data: &[u8]) -> Result<Lit, ExecutionError> {
+    match try!(sql_type.decode_from(&mut data)) {
+        Lit::String(s) => Ok(Lit::String(s.trim_right_matches('\0').to_string())),
+        lit => Ok(lit),
+    }
+}
+
+/// Orders two values of the same type
+fn compare_lits(a: &Lit, b: &Lit) -> Ordering {
+    match (a, b) {
+        (&Lit::Int(a), &Lit::Int(b)) => a.cmp(&b),
+        (&Lit::String(ref a), &Lit::String(ref b)) => a.cmp(b),
+        (&Lit::Bool(a), &Lit::Bool(b)) => a.cmp(&b),
+        (&Lit::Float(a), &Lit::Float(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => Ordering::Equal,
+    }
+}
+
+/// Matches a value against a LIKE pattern: % matches any number of
+/// characters, _ exactly one. On a mismatch only the last % takes one more
+/// character, so the time grows with the product of both lengths at most.
+fn like_match(pattern: &[char], value: &[char]) -> bool {
+    let (mut p, mut v) = (0, 0);
+    // the pattern position after the last % and the value position its
+    // characters end at
+    let mut last = None;
+    while v < value.len() {
+        if p < pattern.len() && pattern[p] == '%' {
+            p += 1;
+            last = Some((p, v));
+        } else if p < pattern.len() && (pattern[p] == '_' || pattern[p] == value[v]) {
+            p += 1;
+            v += 1;
+        } else if let Some((after, end)) = last {
+            p = after;
+            v = end + 1;
+            last = Some((after, end + 1));
+        } else {
+            return false
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '%')
+}
+
+/// Returns the column name shown for an expression
+fn expr_name(expr: &Expr) -> String {
+    match expr {
+        &Expr::Column(Some(ref alias), ref name) => format!("{}.{}", alias, name),
+        &Expr::Column(None, ref name) => name.clone(),
+        &Expr::Literal(Lit::String(ref s)) => format!("'{}'", s),
+        &Expr::Literal(Lit::Int(i)) => i.to_string(),
+        &Expr::Literal(Lit::Float(f)) => f.to_string(),
+        &Expr::Literal(Lit::Bool(b)) => (b != 0).to_string(),
+        &Expr::Literal(Lit::Param(n)) => format!("${}", n),
+        &Expr::Function(ref name, ref args) => format!("{}({})", name,
+            args.iter().map(expr_name).collect::<Vec<_>>().join(", ")),
+        &Expr::Case(_, _) => "case".into(),
+    }
+}
+
+/// Collects the names of the tables and views the select reads from, also
+/// those of its derived tables, subqueries and compound selects
+fn select_sources(stmt: &SelectStmt, names: &mut Vec<String>) {
+    for name in &stmt.tid {
+        if !stmt.derived.contains_key(name) {
+            names.push(name.clone());
+        }
+    }
+    for query in stmt.derived.values() {
+        select_sources(query, names);
+    }
+    for compound in &stmt.compound {
+        select_sources(&compound.select, names);
+    }
+    for target in &stmt.target {
+        match target.col {
+            Col::Subquery(ref query) => select_sources(query, names),
+            Col::Expr(ref expr) => expr_sources(expr, names),
+            _ => (),
+        }
+    }
+    if let Some(ref cond) = stmt.cond {
+        condition_sources(cond, names);
+    }
+}
+
+fn condition_sources(cond: &Conditions, names: &mut Vec<String>) {
+    match cond {
+        &Conditions::And(ref c1, ref c2) | &Conditions::Or(ref c1, ref c2) => {
+            condition_sources(c1, names);
+            condition_sources(c2, names);
+        },
+        &Conditions::Not(ref c) => condition_sources(c, names),
+        &Conditions::Leaf(Condition { rhs: CondType::Subquery(ref query), .. })
+        | &Conditions::In(InCondition { set: InSet::Select(ref query), .. })
+        | &Conditions::Exists(ref query) => select_sources(query, names),
+        _ => (),
+    }
+}
+
+fn expr_sources(expr: &Expr, names: &mut Vec<String>) {
+    match expr {
+        &Expr::Function(_, ref args) => {
+            for arg in args {
+                expr_sources(arg, names);
+            }
+        },
+        &Expr::Case(ref cases, ref default) => {
+            for &(ref cond, ref expr) in cases {
+                condition_sources(cond, names);
+                expr_sources(expr, names);
+            }
+            if let Some(ref default) = *default {
+                expr_sources(default, names);
+            }
+        },
+        _ => (),
+    }
+}
+
+/// Finds the index of a column in a row set by its (optionally aliased) name
+fn lookup_column(infos: NameInfos, alias: &Option<String>, column: &str)
+    -> Result<usize, ExecutionError>
+{
+    let tablename = match alias {
+        &Some(ref alias) => match infos.0.get(alias) {
+            Some(x) => x,
+            None => return Err(ExecutionError::UnknownAlias)
+        },
+        &None => match infos.1.get(column) {
+            Some(x) => x,
+            None => return Err(ExecutionError::UnknownColumn)
+        },
+    };
+    match infos.2.get(tablename).and_then(|columns| columns.get(column)) {
+        Some(index) => Ok(*index),
+        None => Err(ExecutionError::UnknownColumn),
+    }
+}
+
+//---------------------------------------------------------------
+// Subqueries
+//---------------------------------------------------------------
+
+/// The current row of an outer query, seen by its subqueries
+pub struct OuterRow<'b> {
+    infos: NameInfos<'b>,
+    columns: &'b [Column],
+    row: &'b [u8],
+}
+
+impl<'b> OuterRow<'b> {
+    /// Returns the value of a column of the outer row
+    fn value(&self, alias: &Option<String>, column: &str) -> Result<Lit, ExecutionError> {
+        let index = try!(lookup_column(self.infos, alias, column));
+        decode_value(&self.columns[index].sql_type,
+            &column_value(self.columns, self.row, index))
+    }
+}
+
+/// The tables a subquery selects from with their column names. Names in
+/// the conditions that are not found here belong to the outer query.
+#[derive(Clone)]
+struct InnerNames {
+    alias: HashMap<String, String>,
+    columns: HashMap<String, Vec<String>>,
+}
+
+impl InnerNames {
+    fn resolves(&self, alias: &Option<String>, column: &str) -> bool {
+        match alias {
+            &Some(ref alias) => {
+                let table = self.alias.get(alias).unwrap_or(alias);
+                self.columns.get(table).map_or(false, |c| c.iter().any(|n| n == column))
+            },
+            &None => self.columns.values().any(|c| c.iter().any(|n| n == column)),
+        }
+    }
+
+    /// Returns the names seen in a select nested in this one. Its own
+    /// tables hide the ones around it.
+    fn nested(&self, own: &InnerNames) -> InnerNames {
+        let mut names = self.clone();
+        for table in own.columns.keys() {
+            names.alias.remove(table);
+        }
+        names.alias.extend(own.alias.clone().into_iter());
+        names.columns.extend(own.columns.clone().into_iter());
+        names
+    }
+}
+
+/// The first column of a subquery result with all its values
+#[derive(Clone)]
+struct SubqueryResult {
+    column: Column,
+    values: Vec<Lit>,
+}
+
+impl SubqueryResult {
+    fn from_rows(rows: &mut Rows<Cursor<Vec<u8>>>) -> Result<SubqueryResult, ExecutionError> {
+        let column = match rows.columns.first() {
+            Some(column) => column.clone(),
+            None => return Err(ExecutionError::UnknownColumn),
+        };
+        let mut values = Vec::new();
+        try!(rows.reset_pos());
+        loop {
+            let mut row = Vec::<u8>::new();
+            match rows.next_row(&mut row) {
+                Ok(_) => values.push(try!(decode_value(&column.sql_type,
+                    &try!(rows.get_value(&row, 0))))),
+                Err(Error::EndOfFile) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(SubqueryResult { column: column, values: values })
+    }
+
+    /// Returns the value of a scalar subquery, None if there was no row
+    fn scalar(&self) -> Result<Option<Lit>, ExecutionError> {
+        if self.values.len() > 1 {
+            return Err(ExecutionError::SubqueryTooManyRows)
+        }
+        Ok(self.values.first().cloned())
+    }
+}
+
+/// The last bound subquery and its result
+pub struct SubqueryCache {
+    // names of the subquery and of the selects nested in it, found once
+    names: Vec<(SelectStmt, InnerNames)>,
+    stmt: Option<SelectStmt>,
+    result: Option<SubqueryResult>,
+}
+
+impl SubqueryCache {
+    fn new() -> SubqueryCache {
+        SubqueryCache { names: Vec::new(), stmt: None, result: None }
+    }
+}
+
+/// Returns the condition `bound op value` for a BETWEEN whose column
+/// belongs to the outer query. The bounds are literals or columns.
+fn bound_condition(bound: Expr, op: CompType, value: &Lit)
+    -> Result<Conditions, ExecutionError>
+{
+    match bound {
+        Expr::Column(alias, column) => Ok(Conditions::Leaf(Condition {
+            aliascol: alias,
+            col: column,
+            op: op,
+            aliasrhs: None,
+            rhs: CondType::Literal(value.clone()),
+        })),
+        Expr::Literal(lit) => {
+            if lit.sqltype() != value.sqltype() {
+                return Err(ExecutionError::CompareDatatypeMissmatch)
+            }
+            let ordering = compare_lits(&lit, value);
+            Ok(Conditions::Const(match op {
+                CompType::Equ => ordering == Ordering::Equal,
+                CompType::NEqu => ordering != Ordering::Equal,
+                CompType::GThan => ordering == Ordering::Greater,
+                CompType::SThan => ordering == Ordering::Less,
+                CompType::GEThan => ordering != Ordering::Less,
+                CompType::SEThan => ordering != Ordering::Greater,
+            }))
+        },
+        _ => Err(ExecutionError::CompareDatatypeMissmatch),
+    }
+}
+
+#[derive(Debug)]
+pub enum ExecutionError {
+
+    ParseError(ParseError),
+    StorageError(storage::Error),
+    UnknownError,
+    NoDatabaseSelected,
+    InsertMissmatch,
+    DebugError(String),
+    UnknownAlias,
+    UnknownColumn,
+    CompareDatatypeMissmatch,
+    TableNotEmpty,
+    TableExists,
+    ViewExists,
+    RecursiveView,
+    SubqueryTooManyRows,
+    EmptySubquery,
+    SetOperationColumnCount,
+    SetOperationTypeMissmatch,
+    FunctionError(functions::FunctionError),
+    CaseTypeMissmatch,
+    // NULL values can not be stored in a result
+    NullValue,
+    // the result could not be passed on to the client
+    SendFailed,
+    // the statement was cancelled from another connection
+    Cancelled,
+    // the statement ran longer than the statement timeout
+    StatementTimeout,
+    UnknownSetting,
+    InvalidSettingValue,
+    // the values given for a prepared statement do not match its parameters
+    WrongParameterCount,
+    InvalidParameter,
+    // a placeholder outside of an executed prepared statement
+    UnboundParameter,
+    // a prepared statement skips the number of a parameter
+    MissingParameter(u32),
+    AuthError(auth::AuthError),
+    // the user is not allowed to run the statement
+    PermissionDenied,
+    // the user lacks the privilege for the table or database
+    MissingPrivilege(Privilege, String),
+}
+
+impl From<ParseError> for ExecutionError {
+    fn from(error: ParseError) -> ExecutionError {
+        ExecutionError::ParseError(error)
+    }
+}
+
+impl From<functions::FunctionError> for ExecutionError {
+    fn from(error: functions::FunctionError) -> ExecutionError {
+        ExecutionError::FunctionError(error)
+    }
+}
+
+impl From<auth::AuthError> for ExecutionError {
+    fn from(error: auth::AuthError) -> ExecutionError {
+        ExecutionError::AuthError(error)
+    }
+}
+
+impl From<storage::Error> for ExecutionError {
+    fn from(error: storage::Error) -> ExecutionError {
+        ExecutionError::StorageError(error)
+    }
+}
+
+impl From<io::Error> for ExecutionError {
+    fn from(error: io::Error) -> ExecutionError {
+        ExecutionError::StorageError(error.into())
+    }
+}
+
+#[derive(PartialEq, Clone)]
+pub enum Where {
+    Select,
+    Delete(String)
+}
+
+impl Where {
+    pub fn unwrap(&self ) -> String {
+        match self {
+            &Where::Select => "".into(),
+            &Where::Delete(ref s) => s.clone(),
+        }
+    }
+}
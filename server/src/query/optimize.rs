@@ -0,0 +1,401 @@
+//! Rewrites of logical plans
+//!
+//! Conditions are split at their ANDs and every part which only needs the
+//! columns of a single table is moved below the joins, right above the scan
+//! of that table. One comparison of a column with a literal is then handed
+//! to the storage engine, which can use the index of the column.
+//!
+//! The tables of a join are then put in a new order: starting with the
+//! smallest one, the table giving the fewest rows is joined next. A join
+//! comparing columns of both sides with `=` is run as a hash join.
+//!
+
+use super::{Executor, ExecutionError};
+use super::check_compare_type;
+use super::plan::{LogicalPlan, Access, JoinKind, split_and, and_all};
+use super::system;
+use super::super::parse::ast::*;
+
+impl<'a> Executor<'a> {
+    /// Optimizes the plan and all plans it contains
+    pub fn optimize(&self, plan: LogicalPlan) -> Result<LogicalPlan, ExecutionError> {
+        Ok(match plan {
+            LogicalPlan::Filter { input, cond } => {
+                let input = try!(self.optimize(*input));
+                let mut conds = Vec::new();
+                split_and(cond, &mut conds);
+                let (plan, rest) = try!(self.push_down(input, conds));
+                let (plan, rest) = try!(self.order_joins(plan, rest));
+                with_filter(plan, rest)
+            },
+            LogicalPlan::Subquery { name, aliases, input } => LogicalPlan::Subquery {
+                name: name,
+                aliases: aliases,
+                input: Box::new(try!(self.optimize(*input))),
+            },
+            LogicalPlan::Join { left, right, cond, kind } => LogicalPlan::Join {
+                left: Box::new(try!(self.optimize(*left))),
+                right: Box::new(try!(self.optimize(*right))),
+                cond: cond,
+                kind: kind,
+            },
+            LogicalPlan::Project { input, targets, tables } => LogicalPlan::Project {
+                input: Box::new(try!(self.optimize(*input))),
+                targets: targets,
+                tables: tables,
+            },
+            LogicalPlan::Aggregate { input, group } => LogicalPlan::Aggregate {
+                input: Box::new(try!(self.optimize(*input))),
+                group: group,
+            },
+            LogicalPlan::Sort { input, keys } => LogicalPlan::Sort {
+                input: Box::new(try!(self.optimize(*input))),
+                keys: keys,
+            },
+            LogicalPlan::Limit { input, offset, count } => LogicalPlan::Limit {
+                input: Box::new(try!(self.optimize(*input))),
+                offset: offset,
+                count: count,
+            },
+            LogicalPlan::SetOperation { left, right, op, all } => LogicalPlan::SetOperation {
+                left: Box::new(try!(self.optimize(*left))),
+                right: Box::new(try!(self.optimize(*right))),
+                op: op,
+                all: all,
+            },
+            plan => plan,
+        })
+    }
+
+    /// Moves the conditions as far down into the plan as possible. Returns
+    /// the new plan and the conditions which have to be checked above it.
+    fn push_down(&self, plan: LogicalPlan, conds: Vec<Conditions>)
+        -> Result<(LogicalPlan, Vec<Conditions>), ExecutionError>
+    {
+        match plan {
+            LogicalPlan::Join { left, right, cond, kind } => {
+                let leftsources = sources(&left);
+                let mut leftconds = Vec::new();
+                let mut rightconds = Vec::new();
+                let mut rest = Vec::new();
+                for c in conds {
+                    match try!(self.condition_source(&c, &left, &right)) {
+                        Some(source) if leftsources.contains(&source) => leftconds.push(c),
+                        Some(_) => rightconds.push(c),
+                        None => rest.push(c),
+                    }
+                }
+                let (left, leftrest) = try!(self.push_down(*left, leftconds));
+                let (right, rightrest) = try!(self.push_down(*right, rightconds));
+                Ok((LogicalPlan::Join {
+                    left: Box::new(with_filter(left, leftrest)),
+                    right: Box::new(with_filter(right, rightrest)),
+                    cond: cond,
+                    kind: kind,
+                }, rest))
+            },
+            LogicalPlan::Scan { table, aliases, access: Access::Full } => {
+                let (access, rest) = try!(self.choose_access(&table, &aliases, conds));
+                Ok((LogicalPlan::Scan { table: table, aliases: aliases, access: access }, rest))
+            },
+            plan => Ok((plan, conds)),
+        }
+    }
+
+    /// Joins the tables of a join without conditions in the order giving
+    /// the fewest rows and moves the conditions comparing them into the
+    /// joins. Returns the conditions which are left.
+    fn order_joins(&self, plan: LogicalPlan, mut conds: Vec<Conditions>)
+        -> Result<(LogicalPlan, Vec<Conditions>), ExecutionError>
+    {
+        let mut leaves = Vec::new();
+        match plan {
+            plan @ LogicalPlan::Join { cond: None, .. } => split_joins(plan, &mut leaves),
+            plan => return Ok((plan, conds)),
+        }
+
+        let mut estimates = Vec::new();
+        for leaf in &leaves {
+            estimates.push(try!(self.estimate_rows(leaf)));
+        }
+        let first = (0..leaves.len()).min_by_key(|&i| estimates[i]).unwrap();
+        estimates.remove(first);
+        let mut joined = leaves.remove(first);
+
+        while !leaves.is_empty() {
+            let rows = try!(self.estimate_rows(&joined));
+            let mut best: Option<(usize, u64)> = None;
+            for (i, leaf) in leaves.iter().enumerate() {
+                let (applicable, _) = try!(self.join_conditions(&conds, &joined, leaf));
+                let mut estimate = rows.saturating_mul(estimates[i]);
+                if let Some(cond) = and_all(applicable.into_iter().cloned().collect()) {
+                    let selectivity = try!(self.join_selectivity(&cond, &joined, leaf));
+                    estimate = (estimate as f64 * selectivity).ceil() as u64;
+                }
+                if best.map_or(true, |(_, b)| estimate < b) {
+                    best = Some((i, estimate));
+                }
+            }
+
+            let (i, _) = best.unwrap();
+            let leaf = leaves.remove(i);
+            let other = estimates.remove(i);
+            let (applicable, rest): (Vec<Conditions>, Vec<Conditions>) = {
+                let (applicable, rest) = try!(self.join_conditions(&conds, &joined, &leaf));
+                (applicable.into_iter().cloned().collect(), rest.into_iter().cloned().collect())
+            };
+            let mut kind = JoinKind::NestedLoop;
+            for cond in &applicable {
+                if try!(self.is_equi_join(cond, &joined, &leaf))
+                    && rows.saturating_mul(other) > rows.saturating_add(other)
+                {
+                    kind = JoinKind::Hash;
+                }
+            }
+            conds = rest;
+            joined = LogicalPlan::Join {
+                left: Box::new(joined),
+                right: Box::new(leaf),
+                cond: and_all(applicable),
+                kind: kind,
+            };
+        }
+        Ok((joined, conds))
+    }
+
+    /// Splits the conditions into the ones which only need the columns of
+    /// both plans and the others
+    fn join_conditions<'c>(&self, conds: &'c [Conditions], left: &LogicalPlan,
+        right: &LogicalPlan)
+        -> Result<(Vec<&'c Conditions>, Vec<&'c Conditions>), ExecutionError>
+    {
+        let mut applicable = Vec::new();
+        let mut rest = Vec::new();
+        for cond in conds {
+            let mut columns = Vec::new();
+            let mut found = condition_columns(cond, &mut columns);
+            for &(ref alias, ref column) in &columns {
+                if !found {
+                    break;
+                }
+                found = try!(self.column_source(left, alias, column)).is_some()
+                    || try!(self.column_source(right, alias, column)).is_some();
+            }
+            if found { applicable.push(cond) } else { rest.push(cond) }
+        }
+        Ok((applicable, rest))
+    }
+
+    /// Checks if the condition compares a column of the left plan with a
+    /// column of the right plan using `=`
+    fn is_equi_join(&self, cond: &Conditions, left: &LogicalPlan, right: &LogicalPlan)
+        -> Result<bool, ExecutionError>
+    {
+        if let &Conditions::Leaf(Condition {
+            ref aliascol, ref col, op: CompType::Equ, ref aliasrhs, rhs: CondType::Word(ref rhs)
+        }) = cond {
+            let l1 = try!(self.column_source(left, aliascol, col)).is_some();
+            let r1 = try!(self.column_source(right, aliascol, col)).is_some();
+            let l2 = try!(self.column_source(left, aliasrhs, rhs)).is_some();
+            let r2 = try!(self.column_source(right, aliasrhs, rhs)).is_some();
+            return Ok((l1 && r2) || (r1 && l2))
+        }
+        Ok(false)
+    }
+
+    /// Returns the name of the only source of the join the condition needs,
+    /// None if it needs more than one or contains a subquery
+    fn condition_source(&self, cond: &Conditions, left: &LogicalPlan, right: &LogicalPlan)
+        -> Result<Option<String>, ExecutionError>
+    {
+        let mut columns = Vec::new();
+        if !condition_columns(cond, &mut columns) {
+            return Ok(None)
+        }
+        let mut result = None;
+        for (alias, column) in columns {
+            let source = match try!(self.column_source(left, &alias, &column)) {
+                Some(source) => source,
+                None => match try!(self.column_source(right, &alias, &column)) {
+                    Some(source) => source,
+                    None => return Ok(None),
+                },
+            };
+            if result.as_ref().map_or(false, |r| *r != source) {
+                return Ok(None)
+            }
+            result = Some(source);
+        }
+        Ok(result)
+    }
+
+    /// Finds the source of the plan a column belongs to. An unqualified
+    /// column is only found in tables, the columns of views are unknown
+    /// before they are run.
+    pub fn column_source(&self, plan: &LogicalPlan, alias: &Option<String>, column: &str)
+        -> Result<Option<String>, ExecutionError>
+    {
+        match plan {
+            &LogicalPlan::Scan { ref table, ref aliases, .. } => Ok(match alias {
+                &Some(ref alias) if alias == table || aliases.contains(alias) =>
+                    Some(table.clone()),
+                &Some(_) => None,
+                &None => {
+                    let t = try!(self.get_table(table));
+                    if t.columns().iter().any(|c| c.name == column) {
+                        Some(table.clone())
+                    } else {
+                        None
+                    }
+                },
+            }),
+            &LogicalPlan::SystemScan { ref table, ref aliases } => Ok(match alias {
+                &Some(ref alias) if alias == table || aliases.contains(alias) =>
+                    Some(table.clone()),
+                &Some(_) => None,
+                &None if system::columns(table).iter().any(|c| c.name == column) =>
+                    Some(table.clone()),
+                &None => None,
+            }),
+            &LogicalPlan::Subquery { ref name, ref aliases, .. } => Ok(match alias {
+                &Some(ref alias) if alias == name || aliases.contains(alias) =>
+                    Some(name.clone()),
+                _ => None,
+            }),
+            &LogicalPlan::Join { ref left, ref right, .. } => {
+                match try!(self.column_source(left, alias, column)) {
+                    Some(source) => Ok(Some(source)),
+                    None => self.column_source(right, alias, column),
+                }
+            },
+            &LogicalPlan::Filter { ref input, .. } => self.column_source(input, alias, column),
+            _ => Ok(None),
+        }
+    }
+
+    /// Picks a comparison of a column with a literal the engine can check,
+    /// indexed columns first. The other conditions are returned.
+    fn choose_access(&self, table: &str, aliases: &[String], mut conds: Vec<Conditions>)
+        -> Result<(Access, Vec<Conditions>), ExecutionError>
+    {
+        let engine = try!(self.get_engine(table));
+        let mut chosen: Option<(usize, bool)> = None;
+        for (i, cond) in conds.iter().enumerate() {
+            if let &Conditions::Leaf(ref c) = cond {
+                let lit = match c.rhs {
+                    CondType::Literal(ref lit) => lit,
+                    _ => continue,
+                };
+                // unknown aliases are reported by the filter
+                if let Some(ref alias) = c.aliascol {
+                    if alias != table && !aliases.contains(alias) {
+                        continue;
+                    }
+                }
+                let columns = engine.table().columns();
+                let index = match columns.iter().position(|col| col.name == c.col) {
+                    Some(index) => index,
+                    None => continue,
+                };
+                // mismatches are reported by the filter
+                let mut data = Vec::<u8>::new();
+                if check_compare_type(&columns[index].sql_type, lit).is_err()
+                    || columns[index].sql_type.encode_into(&mut data, lit).is_err()
+                {
+                    continue;
+                }
+                let indexed = engine.has_index(index) && c.op != CompType::NEqu;
+                if chosen.is_none() || (indexed && !chosen.unwrap().1) {
+                    chosen = Some((i, indexed));
+                }
+            }
+        }
+
+        Ok(match chosen {
+            Some((i, indexed)) => match conds.remove(i) {
+                Conditions::Leaf(Condition { col, op, rhs: CondType::Literal(value), .. }) =>
+                    (Access::Lookup { column: col, op: op, value: value, index: indexed }, conds),
+                _ => unreachable!(),
+            },
+            None => (Access::Full, conds),
+        })
+    }
+}
+
+/// Adds the inputs of joins without conditions
+fn split_joins(plan: LogicalPlan, leaves: &mut Vec<LogicalPlan>) {
+    match plan {
+        LogicalPlan::Join { left, right, cond: None, .. } => {
+            split_joins(*left, leaves);
+            split_joins(*right, leaves);
+        },
+        plan => leaves.push(plan),
+    }
+}
+
+/// Returns the names of the tables and views a plan reads
+fn sources(plan: &LogicalPlan) -> Vec<String> {
+    match plan {
+        &LogicalPlan::Scan { ref table, .. }
+        | &LogicalPlan::SystemScan { ref table, .. } => vec![table.clone()],
+        &LogicalPlan::Subquery { ref name, .. } => vec![name.clone()],
+        &LogicalPlan::Join { ref left, ref right, .. } => {
+            let mut names = sources(left);
+            names.extend(sources(right).into_iter());
+            names
+        },
+        &LogicalPlan::Filter { ref input, .. } => sources(input),
+        _ => Vec::new(),
+    }
+}
+
+/// Adds the columns the condition compares, returns false if it contains
+/// a subquery
+fn condition_columns(cond: &Conditions, columns: &mut Vec<(Option<String>, String)>) -> bool {
+    match cond {
+        &Conditions::And(ref c1, ref c2) | &Conditions::Or(ref c1, ref c2) =>
+            condition_columns(c1, columns) && condition_columns(c2, columns),
+        &Conditions::Not(ref c) => condition_columns(c, columns),
+        &Conditions::Leaf(ref c) => {
+            columns.push((c.aliascol.clone(), c.col.clone()));
+            match c.rhs {
+                CondType::Literal(_) => true,
+                CondType::Word(ref column) => {
+                    columns.push((c.aliasrhs.clone(), column.clone()));
+                    true
+                },
+                CondType::Subquery(_) => false,
+            }
+        },
+        &Conditions::In(ref c) => {
+            columns.push((c.aliascol.clone(), c.col.clone()));
+            match c.set {
+                InSet::List(_) => true,
+                InSet::Select(_) => false,
+            }
+        },
+        &Conditions::Between(ref c) => {
+            columns.push((c.aliascol.clone(), c.col.clone()));
+            for bound in &[&c.low, &c.high] {
+                if let &&Expr::Column(ref alias, ref column) = bound {
+                    columns.push((alias.clone(), column.clone()));
+                }
+            }
+            true
+        },
+        &Conditions::Like(ref c) => {
+            columns.push((c.aliascol.clone(), c.col.clone()));
+            true
+        },
+        &Conditions::Exists(_) => false,
+        &Conditions::Const(_) => true,
+    }
+}
+
+/// Puts a filter checking all conditions on top of the plan
+fn with_filter(plan: LogicalPlan, conds: Vec<Conditions>) -> LogicalPlan {
+    match and_all(conds) {
+        Some(cond) => LogicalPlan::Filter { input: Box::new(plan), cond: cond },
+        None => plan,
+    }
+}
@@ -0,0 +1,1305 @@
+//! Physical query operators
+//!
+//! Every node of a logical plan is turned into an operator. Operators form
+//! a tree and produce their rows one at a time: calling `next` on the root
+//! pulls the rows it needs from its inputs.
+//!
+
+use super::{Executor, ExecutionError, OuterRow};
+use super::{column_value, decode_value, expr_name, set_operation_columns, compare_lits};
+use super::eval::{Names, EvalCache};
+use super::session::Interrupt;
+use super::spill::{MemoryBudget, SpillFile, SpillReader, MAX_LEVEL, row_cost, partition_of};
+use super::plan::{LogicalPlan, Access, JoinKind, split_and, and_all};
+use super::super::parse::ast::*;
+use super::super::parse::token::Lit;
+use super::super::storage::{Column, Rows, Error};
+use std::collections::{HashMap, HashSet};
+use std::cmp::{min, Ordering};
+use std::io::{Write, Read, Seek};
+use std::cell::RefCell;
+use std::mem;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// An operator producing rows
+pub trait Operator {
+    /// Returns the columns of the produced rows
+    fn columns(&self) -> &[Column];
+
+    /// Returns the next row or None if there are no more rows
+    fn next(&mut self) -> Result<Option<Vec<u8>>, ExecutionError>;
+
+    /// Starts again with the first row
+    fn rewind(&mut self) -> Result<(), ExecutionError>;
+}
+
+fn boxed<'b, O: Operator + 'b>(operator: O) -> Box<Operator + 'b> {
+    Box::new(operator)
+}
+
+impl<'a> Executor<'a> {
+    /// Turns a logical plan into a tree of operators. The names of the
+    /// produced columns are returned, too.
+    pub fn build_operator<'b>(&'b self, plan: LogicalPlan)
+        -> Result<(Box<Operator + 'b>, Names), ExecutionError>
+    {
+        self.build_node(plan, &mut None)
+    }
+
+    /// Like `build_operator`, but every operator is measured. The stats
+    /// are pushed in the order the plan nodes are visited: a node comes
+    /// before its inputs, left inputs before right ones.
+    pub fn build_measured<'b>(&'b self, plan: LogicalPlan,
+        stats: &mut Vec<Rc<RefCell<OperatorStats>>>)
+        -> Result<(Box<Operator + 'b>, Names), ExecutionError>
+    {
+        self.build_node(plan, &mut Some(stats))
+    }
+
+    fn build_node<'b>(&'b self, plan: LogicalPlan,
+        stats: &mut Option<&mut Vec<Rc<RefCell<OperatorStats>>>>)
+        -> Result<(Box<Operator + 'b>, Names), ExecutionError>
+    {
+        let measured = match stats {
+            &mut Some(ref mut stats) => {
+                let node = Rc::new(RefCell::new(OperatorStats::default()));
+                stats.push(node.clone());
+                Some(node)
+            },
+            &mut None => None,
+        };
+
+        let (operator, names) = match plan {
+            LogicalPlan::Scan { table, aliases, access } => match access {
+                Access::Full => {
+                    let mut rows = try!(try!(self.get_engine(&table)).reader());
+                    try!(rows.reset_pos());
+                    let names = Names::source(&table, &rows.columns, &aliases);
+                    (boxed(RowsScan { rows: rows }), names)
+                },
+                Access::Lookup { column, op, value, index } => {
+                    let rows = try!(self.lookup_rows(&table, &column, op, &value, index));
+                    let names = Names::source(&table, &rows.columns, &aliases);
+                    (boxed(RowsScan { rows: rows }), names)
+                },
+            },
+
+            LogicalPlan::SystemScan { table, aliases } => {
+                let rows = try!(self.system_rows(&table));
+                let names = Names::source(&table, &rows.columns, &aliases);
+                (boxed(RowsScan { rows: rows }), names)
+            },
+
+            LogicalPlan::Subquery { name, aliases, input } => {
+                let (input, _) = try!(self.build_node(*input, stats));
+                // the select puts table prefixes in front of the column
+                // names, but the result is used like a table
+                let columns: Vec<Column> = input.columns().iter().map(|c| {
+                    let mut column = c.clone();
+                    column.name = c.name.rsplit('.').next().unwrap_or("").to_string();
+                    column
+                }).collect();
+                let names = Names::source(&name, &columns, &aliases);
+                (boxed(Rename { input: input, columns: columns }), names)
+            },
+
+            LogicalPlan::Filter { input, cond } => {
+                let (input, names) = try!(self.build_node(*input, stats));
+                (boxed(Filter::new(self, input, cond, names.clone())), names)
+            },
+
+            LogicalPlan::Join { left, right, cond, kind } => {
+                let (left, leftnames) = try!(self.build_node(*left, stats));
+                let (right, rightnames) = try!(self.build_node(*right, stats));
+                let mut conds = Vec::new();
+                if let Some(cond) = cond {
+                    split_and(cond, &mut conds);
+                }
+                let mut keys = Vec::new();
+                if kind == JoinKind::Hash {
+                    let mut rest = Vec::new();
+                    for cond in conds {
+                        match join_key(&cond, &*left, &leftnames, &*right, &rightnames) {
+                            Some(key) => keys.push(key),
+                            None => rest.push(cond),
+                        }
+                    }
+                    conds = rest;
+                }
+                let names = Names::join(leftnames, rightnames, left.columns().len());
+                let join = if keys.is_empty() {
+                    boxed(NestedLoopJoin::new(left, right))
+                } else {
+                    boxed(HashJoin::new(left, right, keys, self.budget.clone(), 0))
+                };
+                match and_all(conds) {
+                    Some(cond) => (boxed(Filter::new(self, join, cond, names.clone())), names),
+                    None => (join, names),
+                }
+            },
+
+            LogicalPlan::Project { input, targets, tables } => {
+                let (input, names) = try!(self.build_node(*input, stats));
+                let (project, names) = try!(Project::new(self, input, names, targets, &tables));
+                (boxed(project), names)
+            },
+
+            LogicalPlan::Aggregate { input, group } => {
+                let (input, names) = try!(self.build_node(*input, stats));
+                (boxed(Aggregate::new(input, group, self.budget.clone(), 0)), names)
+            },
+
+            LogicalPlan::Sort { input, keys } => {
+                let (input, names) = try!(self.build_node(*input, stats));
+                let mut sortkeys = Vec::new();
+                for key in keys {
+                    sortkeys.push((try!(names.lookup(&key.alias, &key.col)),
+                        key.order == Some(Order::Desc)));
+                }
+                (boxed(Sort::new(input, sortkeys, self.budget.clone())), names)
+            },
+
+            LogicalPlan::Limit { input, offset, count } => {
+                let (input, names) = try!(self.build_node(*input, stats));
+                (boxed(Limit { input: input, offset: offset, count: count, position: 0 }), names)
+            },
+
+            LogicalPlan::SetOperation { left, right, op, all } => {
+                let (left, names) = try!(self.build_node(*left, stats));
+                let (right, _) = try!(self.build_node(*right, stats));
+                let columns = try!(set_operation_columns(left.columns(), right.columns()));
+                let left = boxed(Convert { input: left, columns: columns.clone() });
+                let right = boxed(Convert { input: right, columns: columns });
+                if op == SetOp::Union && all {
+                    (boxed(Append { left: left, right: right, left_done: false }), names)
+                } else {
+                    // equal rows follow each other once both sides are sorted
+                    // by all columns
+                    let keys: Vec<_> = (0..left.columns().len()).map(|i| (i, false)).collect();
+                    let left = boxed(Sort::new(left, keys.clone(), self.budget.clone()));
+                    let right = boxed(Sort::new(right, keys.clone(), self.budget.clone()));
+                    (boxed(SetOperation {
+                        left: SortedSide::new(left, keys.clone()),
+                        right: SortedSide::new(right, keys),
+                        op: op,
+                        all: all,
+                        row: None,
+                        repeat: 0,
+                    }), names)
+                }
+            },
+        };
+
+        let operator = boxed(Checked { input: operator, interrupt: &self.interrupt });
+        Ok(match measured {
+            Some(node) => (boxed(Measured { input: operator, stats: node }), names),
+            None => (operator, names),
+        })
+    }
+}
+
+/// Produces the rows of a row set. Rows of a data file are read one at a
+/// time.
+pub struct RowsScan<B: Write + Read + Seek> {
+    rows: Rows<B>,
+}
+
+impl<B: Write + Read + Seek> Operator for RowsScan<B> {
+    fn columns(&self) -> &[Column] {
+        &self.rows.columns
+    }
+
+    fn next(&mut self) -> Result<Option<Vec<u8>>, ExecutionError> {
+        let mut row = Vec::<u8>::new();
+        match self.rows.next_row(&mut row) {
+            Ok(_) => Ok(Some(row)),
+            Err(Error::EndOfFile) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn rewind(&mut self) -> Result<(), ExecutionError> {
+        try!(self.rows.reset_pos());
+        Ok(())
+    }
+}
+
+/// The rows an operator produced in total and the time it needed,
+/// including the time of its inputs
+#[derive(Debug, Default)]
+pub struct OperatorStats {
+    pub rows: u64,
+    pub time: Duration,
+}
+
+/// Passes the rows on and counts them for EXPLAIN ANALYZE
+pub struct Measured<'b> {
+    input: Box<Operator + 'b>,
+    stats: Rc<RefCell<OperatorStats>>,
+}
+
+impl<'b> Operator for Measured<'b> {
+    fn columns(&self) -> &[Column] {
+        self.input.columns()
+    }
+
+    fn next(&mut self) -> Result<Option<Vec<u8>>, ExecutionError> {
+        let start = Instant::now();
+        let row = try!(self.input.next());
+        let mut stats = self.stats.borrow_mut();
+        stats.time = stats.time + start.elapsed();
+        if row.is_some() {
+            stats.rows += 1;
+        }
+        Ok(row)
+    }
+
+    fn rewind(&mut self) -> Result<(), ExecutionError> {
+        self.input.rewind()
+    }
+}
+
+/// Passes the rows on and stops when the statement is cancelled or its
+/// timeout is reached
+pub struct Checked<'b> {
+    input: Box<Operator + 'b>,
+    interrupt: &'b Interrupt,
+}
+
+impl<'b> Operator for Checked<'b> {
+    fn columns(&self) -> &[Column] {
+        self.input.columns()
+    }
+
+    fn next(&mut self) -> Result<Option<Vec<u8>>, ExecutionError> {
+        try!(self.interrupt.check());
+        self.input.next()
+    }
+
+    fn rewind(&mut self) -> Result<(), ExecutionError> {
+        self.input.rewind()
+    }
+}
+
+/// Passes the rows on with other column names
+pub struct Rename<'b> {
+    input: Box<Operator + 'b>,
+    columns: Vec<Column>,
+}
+
+impl<'b> Operator for Rename<'b> {
+    fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    fn next(&mut self) -> Result<Option<Vec<u8>>, ExecutionError> {
+        self.input.next()
+    }
+
+    fn rewind(&mut self) -> Result<(), ExecutionError> {
+        self.input.rewind()
+    }
+}
+
+/// Passes the rows matching the conditions on
+pub struct Filter<'b, 'a: 'b> {
+    executor: &'b Executor<'a>,
+    input: Box<Operator + 'b>,
+    cond: Conditions,
+    names: Names,
+    cache: EvalCache,
+}
+
+impl<'b, 'a: 'b> Filter<'b, 'a> {
+    fn new(executor: &'b Executor<'a>, input: Box<Operator + 'b>, cond: Conditions,
+        names: Names)
+        -> Filter<'b, 'a>
+    {
+        Filter {
+            executor: executor,
+            input: input,
+            cond: cond,
+            names: names,
+            cache: EvalCache::default(),
+        }
+    }
+}
+
+impl<'b, 'a: 'b> Operator for Filter<'b, 'a> {
+    fn columns(&self) -> &[Column] {
+        self.input.columns()
+    }
+
+    fn next(&mut self) -> Result<Option<Vec<u8>>, ExecutionError> {
+        while let Some(row) = try!(self.input.next()) {
+            let matches = try!(self.executor.eval_condition(&self.cond, &OuterRow {
+                infos: self.names.infos(),
+                columns: self.input.columns(),
+                row: &row,
+            }, &mut self.cache));
+            if matches {
+                return Ok(Some(row))
+            }
+        }
+        Ok(None)
+    }
+
+    fn rewind(&mut self) -> Result<(), ExecutionError> {
+        self.input.rewind()
+    }
+}
+
+/// Combines every row of the left side with every row of the right side,
+/// the right side is read again for each left row
+pub struct NestedLoopJoin<'b> {
+    left: Box<Operator + 'b>,
+    right: Box<Operator + 'b>,
+    columns: Vec<Column>,
+    current: Option<Vec<u8>>,
+}
+
+impl<'b> NestedLoopJoin<'b> {
+    fn new(left: Box<Operator + 'b>, right: Box<Operator + 'b>) -> NestedLoopJoin<'b> {
+        let mut columns = left.columns().to_vec();
+        columns.extend(right.columns().iter().cloned());
+        NestedLoopJoin { left: left, right: right, columns: columns, current: None }
+    }
+}
+
+impl<'b> Operator for NestedLoopJoin<'b> {
+    fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    fn next(&mut self) -> Result<Option<Vec<u8>>, ExecutionError> {
+        loop {
+            if self.current.is_none() {
+                match try!(self.left.next()) {
+                    Some(row) => self.current = Some(row),
+                    None => return Ok(None),
+                }
+                try!(self.right.rewind());
+            }
+            match try!(self.right.next()) {
+                Some(right) => {
+                    let mut row = self.current.clone().unwrap();
+                    row.extend(right.into_iter());
+                    return Ok(Some(row))
+                },
+                None => self.current = None,
+            }
+        }
+    }
+
+    fn rewind(&mut self) -> Result<(), ExecutionError> {
+        self.current = None;
+        self.left.rewind()
+    }
+}
+
+/// Combines the rows of both sides whose key columns are equal. The rows
+/// of the right side are put into a hash table by their keys once, the
+/// rows of the left side look up their partners in it. If the right side
+/// does not fit into the memory budget, both sides are split into
+/// partitions by their keys and the partitions are joined one by one.
+pub struct HashJoin<'b> {
+    left: Box<Operator + 'b>,
+    right: Box<Operator + 'b>,
+    columns: Vec<Column>,
+    // pairs of the indices of a left and a right key column
+    keys: Vec<(usize, usize)>,
+    budget: MemoryBudget,
+    // level of partitioning, 0 for the join of the query
+    level: u64,
+    table: Option<HashMap<Vec<u8>, Vec<Vec<u8>>>>,
+    reserved: u64,
+    // the left row and its partners which were not returned yet
+    current: Option<(Vec<u8>, Vec<Vec<u8>>)>,
+    // left and right partitions which were not joined yet
+    partitions: Option<Vec<(SpillFile, SpillFile)>>,
+    // the join of the partitions read at the moment
+    joined: Option<Box<HashJoin<'b>>>,
+}
+
+impl<'b> HashJoin<'b> {
+    fn new(left: Box<Operator + 'b>, right: Box<Operator + 'b>, keys: Vec<(usize, usize)>,
+        budget: MemoryBudget, level: u64)
+        -> HashJoin<'b>
+    {
+        let mut columns = left.columns().to_vec();
+        columns.extend(right.columns().iter().cloned());
+        HashJoin {
+            left: left,
+            right: right,
+            columns: columns,
+            keys: keys,
+            budget: budget,
+            level: level,
+            table: None,
+            reserved: 0,
+            current: None,
+            partitions: None,
+            joined: None,
+        }
+    }
+
+    fn build_table(&mut self) -> Result<(), ExecutionError> {
+        let mut table: HashMap<Vec<u8>, Vec<Vec<u8>>> = HashMap::new();
+        let mut spilled: Option<Vec<SpillFile>> = None;
+        while let Some(row) = try!(self.right.next()) {
+            let key = row_key(self.right.columns(), &row, self.keys.iter().map(|k| k.1));
+            if let Some(ref mut files) = spilled {
+                try!(files[partition_of(&key, self.level)].write_row(&row));
+                continue;
+            }
+            if table.is_empty() || self.level >= MAX_LEVEL {
+                self.budget.force(&row);
+            } else if !self.budget.reserve(&row) {
+                // the right side does not fit, the rows kept so far are
+                // written to the partitions, too
+                let mut files = try!(self.budget.partitions(self.right.columns()));
+                for (key, rows) in table.drain() {
+                    for kept in rows {
+                        try!(files[partition_of(&key, self.level)].write_row(&kept));
+                    }
+                }
+                self.budget.release(self.reserved);
+                self.reserved = 0;
+                try!(files[partition_of(&key, self.level)].write_row(&row));
+                spilled = Some(files);
+                continue;
+            }
+            self.reserved += row_cost(&row);
+            table.entry(key).or_insert(Vec::new()).push(row);
+        }
+
+        match spilled {
+            None => self.table = Some(table),
+            Some(rightfiles) => {
+                let mut leftfiles = try!(self.budget.partitions(self.left.columns()));
+                while let Some(row) = try!(self.left.next()) {
+                    let key = row_key(self.left.columns(), &row, self.keys.iter().map(|k| k.0));
+                    try!(leftfiles[partition_of(&key, self.level)].write_row(&row));
+                }
+                self.partitions = Some(leftfiles.into_iter().zip(rightfiles).collect());
+            },
+        }
+        Ok(())
+    }
+
+    /// Joins the partitions one after another
+    fn next_partitioned(&mut self) -> Result<Option<Vec<u8>>, ExecutionError> {
+        loop {
+            if let Some(ref mut joined) = self.joined {
+                if let Some(row) = try!(joined.next()) {
+                    return Ok(Some(row))
+                }
+            }
+            self.joined = None;
+            match self.partitions.as_mut().and_then(|p| p.pop()) {
+                Some((left, right)) => {
+                    let left = boxed(try!(left.into_reader()));
+                    let right = boxed(try!(right.into_reader()));
+                    self.joined = Some(Box::new(HashJoin::new(left, right, self.keys.clone(),
+                        self.budget.clone(), self.level + 1)));
+                },
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+impl<'b> Operator for HashJoin<'b> {
+    fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    fn next(&mut self) -> Result<Option<Vec<u8>>, ExecutionError> {
+        if self.table.is_none() && self.partitions.is_none() {
+            try!(self.build_table());
+        }
+        if self.partitions.is_some() {
+            return self.next_partitioned()
+        }
+        loop {
+            if let Some((ref left, ref mut partners)) = self.current {
+                if let Some(right) = partners.pop() {
+                    let mut row = left.clone();
+                    row.extend(right.into_iter());
+                    return Ok(Some(row))
+                }
+            }
+            let left = match try!(self.left.next()) {
+                Some(row) => row,
+                None => return Ok(None),
+            };
+            let key = row_key(self.left.columns(), &left, self.keys.iter().map(|k| k.0));
+            // the partners are popped from the end, so they are reversed
+            let mut partners = self.table.as_ref().and_then(|t| t.get(&key))
+                .cloned().unwrap_or(Vec::new());
+            partners.reverse();
+            self.current = Some((left, partners));
+        }
+    }
+
+    /// the hash table is kept, partitions are read only once, so a
+    /// partitioned join starts again
+    fn rewind(&mut self) -> Result<(), ExecutionError> {
+        self.current = None;
+        if self.partitions.is_some() {
+            self.partitions = None;
+            self.joined = None;
+            try!(self.right.rewind());
+        }
+        self.left.rewind()
+    }
+}
+
+/// Returns the left and right column index if the condition compares a
+/// column of each side with `=`, and both columns have the same type
+fn join_key(cond: &Conditions, left: &Operator, leftnames: &Names, right: &Operator,
+    rightnames: &Names) -> Option<(usize, usize)>
+{
+    if let &Conditions::Leaf(Condition {
+        ref aliascol, ref col, op: CompType::Equ, ref aliasrhs, rhs: CondType::Word(ref rhs)
+    }) = cond {
+        let pair = match (leftnames.lookup(aliascol, col), rightnames.lookup(aliasrhs, rhs)) {
+            (Ok(l), Ok(r)) => Some((l, r)),
+            _ => match (leftnames.lookup(aliasrhs, rhs), rightnames.lookup(aliascol, col)) {
+                (Ok(l), Ok(r)) => Some((l, r)),
+                _ => None,
+            },
+        };
+        return pair.and_then(|(l, r)| {
+            if left.columns()[l].sql_type == right.columns()[r].sql_type {
+                Some((l, r))
+            } else {
+                None
+            }
+        })
+    }
+    None
+}
+
+/// Returns the values of the columns of the row, one after another
+fn row_key<I: Iterator<Item=usize>>(columns: &[Column], row: &[u8], indices: I) -> Vec<u8> {
+    let mut key = Vec::new();
+    for index in indices {
+        key.extend(column_value(columns, row, index).into_iter());
+    }
+    key
+}
+
+/// What a column of a projection is computed from
+enum Output {
+    // index of an input column
+    Column(usize),
+    Expr(Expr),
+    // scalar subquery
+    Subquery(Box<SelectStmt>),
+}
+
+/// The output columns of a projection while they are resolved
+#[derive(Default)]
+struct Projection {
+    outputs: Vec<Output>,
+    columns: Vec<Column>,
+    // table and column name of each output column
+    names: Vec<(Option<String>, String)>,
+}
+
+impl Projection {
+    /// Adds a computed column, it is known by its name only
+    fn push(&mut self, output: Output, column: Column) {
+        self.names.push((None, column.name.clone()));
+        self.outputs.push(output);
+        self.columns.push(column);
+    }
+
+    /// Adds an input column, which is shown under the given name unless it
+    /// is renamed
+    fn push_input(&mut self, column: &Column, index: usize, table: Option<String>,
+        name: String, rename: &Option<String>)
+    {
+        let mut output = column.clone();
+        match rename {
+            &Some(ref rename) => {
+                output.name = rename.clone();
+                self.names.push((None, rename.clone()));
+            },
+            &None => {
+                output.name = name;
+                self.names.push((table, column.name.clone()));
+            },
+        }
+        self.outputs.push(Output::Column(index));
+        self.columns.push(output);
+    }
+}
+
+/// Computes the output columns of a select
+pub struct Project<'b, 'a: 'b> {
+    executor: &'b Executor<'a>,
+    input: Box<Operator + 'b>,
+    names: Names,
+    outputs: Vec<Output>,
+    columns: Vec<Column>,
+    cache: EvalCache,
+}
+
+impl<'b, 'a: 'b> Project<'b, 'a> {
+    /// Resolves the targets, returns the projection and the names of its
+    /// columns
+    fn new(executor: &'b Executor<'a>, input: Box<Operator + 'b>, names: Names,
+        targets: Vec<Target>, tables: &[String])
+        -> Result<(Project<'b, 'a>, Names), ExecutionError>
+    {
+        let mut out = Projection::default();
+        {
+            let inputcolumns = input.columns();
+            for target in targets {
+                match target.col {
+                    Col::Every => match target.alias {
+                        Some(ref alias) => {
+                            let table = match names.alias.get(alias) {
+                                Some(table) => table.clone(),
+                                None => return Err(ExecutionError::UnknownAlias),
+                            };
+                            let mut indices: Vec<usize> = match names.table_columns.get(&table) {
+                                Some(columns) => columns.values().cloned().collect(),
+                                None => Vec::new(),
+                            };
+                            indices.sort();
+                            for index in indices {
+                                let column = &inputcolumns[index];
+                                let name = format!("{}.{}", alias, column.name);
+                                out.push_input(column, index, Some(table.clone()), name,
+                                    &target.rename);
+                            }
+                        },
+                        None => {
+                            // joins may be reordered, the columns are listed
+                            // in the order of the from list
+                            let mut indices: Vec<(usize, usize, Option<String>)> = inputcolumns
+                                .iter().enumerate().map(|(index, _)| {
+                                    let table = names.table_columns.iter()
+                                        .find(|&(_, c)| c.values().any(|i| *i == index))
+                                        .map(|(table, _)| table.clone());
+                                    let position = table.as_ref()
+                                        .and_then(|t| tables.iter().position(|n| n == t))
+                                        .unwrap_or(tables.len());
+                                    (position, index, table)
+                                }).collect();
+                            indices.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+                            for (_, index, table) in indices {
+                                let column = &inputcolumns[index];
+                                out.push_input(column, index, table, column.name.clone(),
+                                    &target.rename);
+                            }
+                        },
+                    },
+                    Col::Specified(ref column) => {
+                        let index = try!(names.lookup(&target.alias, column));
+                        let table = match target.alias {
+                            Some(ref alias) => names.alias.get(alias).cloned(),
+                            None => names.column_table.get(column).cloned(),
+                        }.unwrap_or(String::new());
+                        let name = format!("{}.{}", table, column);
+                        out.push_input(&inputcolumns[index], index, Some(table), name,
+                            &target.rename);
+                    },
+                    Col::Expr(expr) => {
+                        let sql_type = try!(executor.expr_type(&expr, inputcolumns, &names));
+                        let name = target.rename.clone().unwrap_or(expr_name(&expr));
+                        let column = Column::new(&name, sql_type, false, "", false);
+                        out.push(Output::Expr(expr), column);
+                    },
+                    Col::Subquery(sub) => {
+                        // the type is known without running the subquery. The
+                        // columns of this select are in its scope, they are
+                        // bound to the values of a row of zero bytes.
+                        let size = inputcolumns.iter()
+                            .fold(0, |acc, c| acc + c.get_size() as usize);
+                        let zeros = vec![0u8; size];
+                        let outer = OuterRow {
+                            infos: names.infos(),
+                            columns: inputcolumns,
+                            row: &zeros,
+                        };
+                        let bound = try!(executor.bind_select((*sub).clone(), None, &outer,
+                            &mut Vec::new()));
+                        let plan = try!(executor.plan_select(bound));
+                        let (operator, _) = try!(executor.build_operator(plan));
+                        let mut column = match operator.columns().first() {
+                            Some(column) => column.clone(),
+                            None => return Err(ExecutionError::UnknownColumn),
+                        };
+                        if let Some(rename) = target.rename.clone() {
+                            column.name = rename;
+                        }
+                        out.push(Output::Subquery(sub), column);
+                    },
+                }
+            }
+        }
+        let outnames = Names::new(&out.names, &names.alias);
+        Ok((Project {
+            executor: executor,
+            input: input,
+            names: names,
+            outputs: out.outputs,
+            columns: out.columns,
+            cache: EvalCache::default(),
+        }, outnames))
+    }
+}
+
+impl<'b, 'a: 'b> Operator for Project<'b, 'a> {
+    fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    fn next(&mut self) -> Result<Option<Vec<u8>>, ExecutionError> {
+        let row = match try!(self.input.next()) {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        let current = OuterRow {
+            infos: self.names.infos(),
+            columns: self.input.columns(),
+            row: &row,
+        };
+        let mut result = Vec::<u8>::new();
+        for (output, column) in self.outputs.iter().zip(self.columns.iter()) {
+            let value = match output {
+                &Output::Column(index) => {
+                    result.extend(column_value(current.columns, &row, index).into_iter());
+                    continue;
+                },
+                &Output::Expr(ref expr) => {
+                    match try!(self.executor.eval_expr(expr, &current, &mut self.cache)) {
+                        Some(value) => value,
+                        None => return Err(ExecutionError::NullValue),
+                    }
+                },
+                &Output::Subquery(ref sub) => {
+                    let subresult = try!(self.executor.execute_subquery(sub, &current,
+                        self.cache.get(sub)));
+                    match try!(subresult.scalar()) {
+                        Some(value) => value,
+                        None => return Err(ExecutionError::EmptySubquery),
+                    }
+                },
+            };
+            try!(column.sql_type.encode_into(&mut result, &value));
+        }
+        Ok(Some(result))
+    }
+
+    fn rewind(&mut self) -> Result<(), ExecutionError> {
+        self.input.rewind()
+    }
+}
+
+/// Passes only the first row of every group on. Once the keys of the
+/// groups seen do not fit into the memory budget, the rows of new groups
+/// are written to partitions by their keys, which are read one by one
+/// after the input.
+pub struct Aggregate<'b> {
+    input: Box<Operator + 'b>,
+    group: Option<Vec<usize>>,
+    budget: MemoryBudget,
+    // level of partitioning, 0 for the rows of the query
+    level: u64,
+    seen: HashSet<Vec<u8>>,
+    reserved: u64,
+    input_done: bool,
+    partitions: Vec<SpillFile>,
+    // the groups of the partition read at the moment
+    current: Option<Box<Aggregate<'b>>>,
+}
+
+impl<'b> Aggregate<'b> {
+    fn new(input: Box<Operator + 'b>, group: Option<Vec<usize>>, budget: MemoryBudget,
+        level: u64)
+        -> Aggregate<'b>
+    {
+        Aggregate {
+            input: input,
+            group: group,
+            budget: budget,
+            level: level,
+            seen: HashSet::new(),
+            reserved: 0,
+            input_done: false,
+            partitions: Vec::new(),
+            current: None,
+        }
+    }
+
+    fn key(&self, row: &[u8]) -> Vec<u8> {
+        match self.group {
+            Some(ref group) => row_key(self.input.columns(), row, group.iter().cloned()),
+            None => row.to_vec(),
+        }
+    }
+
+    /// Forgets the groups seen and gives their memory back
+    fn forget(&mut self) {
+        self.seen.clear();
+        self.budget.release(self.reserved);
+        self.reserved = 0;
+    }
+}
+
+impl<'b> Operator for Aggregate<'b> {
+    fn columns(&self) -> &[Column] {
+        self.input.columns()
+    }
+
+    fn next(&mut self) -> Result<Option<Vec<u8>>, ExecutionError> {
+        loop {
+            if let Some(ref mut current) = self.current {
+                if let Some(row) = try!(current.next()) {
+                    return Ok(Some(row))
+                }
+            }
+            self.current = None;
+
+            if !self.input_done {
+                let row = match try!(self.input.next()) {
+                    Some(row) => row,
+                    None => {
+                        // the partitions only hold rows of other groups
+                        self.input_done = true;
+                        self.forget();
+                        continue;
+                    },
+                };
+                let key = self.key(&row);
+                if self.seen.contains(&key) {
+                    continue;
+                }
+                if self.partitions.is_empty() {
+                    let kept = if self.seen.is_empty() || self.level >= MAX_LEVEL {
+                        self.budget.force(&key);
+                        true
+                    } else {
+                        self.budget.reserve(&key)
+                    };
+                    if kept {
+                        self.reserved += row_cost(&key);
+                        self.seen.insert(key);
+                        return Ok(Some(row))
+                    }
+                    self.partitions = try!(self.budget.partitions(self.input.columns()));
+                }
+                try!(self.partitions[partition_of(&key, self.level)].write_row(&row));
+                continue;
+            }
+
+            match self.partitions.pop() {
+                Some(file) => {
+                    let input = boxed(try!(file.into_reader()));
+                    self.current = Some(Box::new(Aggregate::new(input, self.group.clone(),
+                        self.budget.clone(), self.level + 1)));
+                },
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn rewind(&mut self) -> Result<(), ExecutionError> {
+        self.forget();
+        self.input_done = false;
+        self.partitions.clear();
+        self.current = None;
+        self.input.rewind()
+    }
+}
+
+/// A row and the values it is sorted by
+type SortRow = (Vec<Lit>, Vec<u8>);
+
+/// Reads all rows of the input and returns them in order. If the rows do
+/// not fit into the memory budget, sorted runs are written to temporary
+/// files and merged.
+pub struct Sort<'b> {
+    input: Box<Operator + 'b>,
+    // column index and descending
+    keys: Vec<(usize, bool)>,
+    budget: MemoryBudget,
+    sorted: bool,
+    rows: Vec<Vec<u8>>,
+    reserved: u64,
+    position: usize,
+    // the runs and their next rows while merging
+    runs: Vec<(SpillReader, Option<SortRow>)>,
+}
+
+impl<'b> Sort<'b> {
+    fn new(input: Box<Operator + 'b>, keys: Vec<(usize, bool)>, budget: MemoryBudget)
+        -> Sort<'b>
+    {
+        Sort {
+            input: input,
+            keys: keys,
+            budget: budget,
+            sorted: false,
+            rows: Vec::new(),
+            reserved: 0,
+            position: 0,
+            runs: Vec::new(),
+        }
+    }
+
+    fn sort_rows(&mut self) -> Result<(), ExecutionError> {
+        let mut rows = Vec::new();
+        let mut files = Vec::new();
+        while let Some(row) = try!(self.input.next()) {
+            if rows.is_empty() {
+                self.budget.force(&row);
+            } else if !self.budget.reserve(&row) {
+                files.push(try!(self.write_run(&mut rows)));
+                self.budget.force(&row);
+            }
+            self.reserved += row_cost(&row);
+            let values = try!(sort_values(self.input.columns(), &self.keys, &row));
+            rows.push((values, row));
+        }
+
+        if files.is_empty() {
+            let keys = &self.keys;
+            rows.sort_by(|a, b| compare_values(keys, &a.0, &b.0));
+            self.rows = rows.into_iter().map(|(_, row)| row).collect();
+            return Ok(())
+        }
+        if !rows.is_empty() {
+            files.push(try!(self.write_run(&mut rows)));
+        }
+        for file in files {
+            let mut reader = try!(file.into_reader());
+            let head = try!(next_sorted(&mut reader, &self.keys));
+            self.runs.push((reader, head));
+        }
+        Ok(())
+    }
+
+    /// Sorts the rows, writes them to a temporary file and gives their
+    /// memory back
+    fn write_run(&mut self, rows: &mut Vec<SortRow>) -> Result<SpillFile, ExecutionError> {
+        {
+            let keys = &self.keys;
+            rows.sort_by(|a, b| compare_values(keys, &a.0, &b.0));
+        }
+        let mut file = try!(self.budget.temp_file(self.input.columns()));
+        for &(_, ref row) in rows.iter() {
+            try!(file.write_row(row));
+        }
+        rows.clear();
+        self.budget.release(self.reserved);
+        self.reserved = 0;
+        Ok(file)
+    }
+
+    /// Returns the smallest next row of the runs
+    fn next_merged(&mut self) -> Result<Option<Vec<u8>>, ExecutionError> {
+        let mut smallest: Option<usize> = None;
+        for (i, run) in self.runs.iter().enumerate() {
+            if let Some((ref values, _)) = run.1 {
+                smallest = match smallest {
+                    // the earlier run wins, so equal rows keep their order
+                    Some(j) if compare_values(&self.keys, &self.runs[j].1.as_ref().unwrap().0,
+                        values) != Ordering::Greater => Some(j),
+                    _ => Some(i),
+                };
+            }
+        }
+        let i = match smallest {
+            Some(i) => i,
+            None => return Ok(None),
+        };
+        let next = try!(next_sorted(&mut self.runs[i].0, &self.keys));
+        Ok(mem::replace(&mut self.runs[i].1, next).map(|(_, row)| row))
+    }
+}
+
+impl<'b> Operator for Sort<'b> {
+    fn columns(&self) -> &[Column] {
+        self.input.columns()
+    }
+
+    fn next(&mut self) -> Result<Option<Vec<u8>>, ExecutionError> {
+        if !self.sorted {
+            try!(self.sort_rows());
+            self.sorted = true;
+        }
+        if !self.runs.is_empty() {
+            return self.next_merged()
+        }
+        let row = self.rows.get(self.position).cloned();
+        self.position += 1;
+        Ok(row)
+    }
+
+    fn rewind(&mut self) -> Result<(), ExecutionError> {
+        self.position = 0;
+        for run in self.runs.iter_mut() {
+            try!(run.0.rewind());
+            run.1 = try!(next_sorted(&mut run.0, &self.keys));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the values of the row the rows are sorted by
+fn sort_values(columns: &[Column], keys: &[(usize, bool)], row: &[u8])
+    -> Result<Vec<Lit>, ExecutionError>
+{
+    let mut values = Vec::new();
+    for &(index, _) in keys {
+        values.push(try!(decode_value(&columns[index].sql_type,
+            &column_value(columns, row, index))));
+    }
+    Ok(values)
+}
+
+fn compare_values(keys: &[(usize, bool)], a: &[Lit], b: &[Lit]) -> Ordering {
+    for (i, &(_, desc)) in keys.iter().enumerate() {
+        let ordering = compare_lits(&a[i], &b[i]);
+        if ordering != Ordering::Equal {
+            return if desc { ordering.reverse() } else { ordering }
+        }
+    }
+    Ordering::Equal
+}
+
+/// Reads the next row of a sorted run
+fn next_sorted(run: &mut SpillReader, keys: &[(usize, bool)])
+    -> Result<Option<SortRow>, ExecutionError>
+{
+    match try!(run.next()) {
+        Some(row) => Ok(Some((try!(sort_values(run.columns(), keys, &row)), row))),
+        None => Ok(None),
+    }
+}
+
+/// Skips offset rows and passes at most count rows on
+pub struct Limit<'b> {
+    input: Box<Operator + 'b>,
+    offset: u64,
+    count: Option<u64>,
+    position: u64,
+}
+
+impl<'b> Operator for Limit<'b> {
+    fn columns(&self) -> &[Column] {
+        self.input.columns()
+    }
+
+    fn next(&mut self) -> Result<Option<Vec<u8>>, ExecutionError> {
+        while self.position < self.offset {
+            if try!(self.input.next()).is_none() {
+                return Ok(None)
+            }
+            self.position += 1;
+        }
+        if self.count.map_or(false, |count| self.position - self.offset >= count) {
+            return Ok(None)
+        }
+        self.position += 1;
+        self.input.next()
+    }
+
+    fn rewind(&mut self) -> Result<(), ExecutionError> {
+        self.position = 0;
+        self.input.rewind()
+    }
+}
+
+/// Passes the rows on with the column types of a set operation
+pub struct Convert<'b> {
+    input: Box<Operator + 'b>,
+    columns: Vec<Column>,
+}
+
+impl<'b> Operator for Convert<'b> {
+    fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    fn next(&mut self) -> Result<Option<Vec<u8>>, ExecutionError> {
+        match try!(self.input.next()) {
+            Some(row) => Ok(Some(try!(convert_row(self.input.columns(), &self.columns, &row)))),
+            None => Ok(None),
+        }
+    }
+
+    fn rewind(&mut self) -> Result<(), ExecutionError> {
+        self.input.rewind()
+    }
+}
+
+/// UNION ALL: the rows of the left side followed by the ones of the right
+/// side
+pub struct Append<'b> {
+    left: Box<Operator + 'b>,
+    right: Box<Operator + 'b>,
+    left_done: bool,
+}
+
+impl<'b> Operator for Append<'b> {
+    fn columns(&self) -> &[Column] {
+        self.left.columns()
+    }
+
+    fn next(&mut self) -> Result<Option<Vec<u8>>, ExecutionError> {
+        if !self.left_done {
+            match try!(self.left.next()) {
+                Some(row) => return Ok(Some(row)),
+                None => self.left_done = true,
+            }
+        }
+        self.right.next()
+    }
+
+    fn rewind(&mut self) -> Result<(), ExecutionError> {
+        self.left_done = false;
+        try!(self.left.rewind());
+        self.right.rewind()
+    }
+}
+
+/// A side of a set operation, sorted by all columns, and its next row
+struct SortedSide<'b> {
+    input: Box<Operator + 'b>,
+    keys: Vec<(usize, bool)>,
+    head: Option<SortRow>,
+    started: bool,
+}
+
+impl<'b> SortedSide<'b> {
+    fn new(input: Box<Operator + 'b>, keys: Vec<(usize, bool)>) -> SortedSide<'b> {
+        SortedSide { input: input, keys: keys, head: None, started: false }
+    }
+
+    fn read(&mut self) -> Result<Option<SortRow>, ExecutionError> {
+        match try!(self.input.next()) {
+            Some(row) => Ok(Some((try!(sort_values(self.input.columns(), &self.keys, &row)),
+                row))),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the values of the next row
+    fn peek(&mut self) -> Result<Option<Vec<Lit>>, ExecutionError> {
+        if !self.started {
+            self.head = try!(self.read());
+            self.started = true;
+        }
+        Ok(self.head.as_ref().map(|&(ref values, _)| values.clone()))
+    }
+
+    /// Skips the rows with the given values. Returns how many there were
+    /// and the first of them.
+    fn skip(&mut self, values: &[Lit]) -> Result<(u64, Option<Vec<u8>>), ExecutionError> {
+        let mut count = 0;
+        let mut first = None;
+        loop {
+            let equal = match self.head {
+                Some((ref head, _)) => compare_values(&self.keys, head, values) == Ordering::Equal,
+                None => false,
+            };
+            if !equal {
+                return Ok((count, first))
+            }
+            let next = try!(self.read());
+            if let Some((_, row)) = mem::replace(&mut self.head, next) {
+                if first.is_none() {
+                    first = Some(row);
+                }
+            }
+            count += 1;
+        }
+    }
+
+    fn rewind(&mut self) -> Result<(), ExecutionError> {
+        self.head = None;
+        self.started = false;
+        self.input.rewind()
+    }
+}
+
+/// UNION returns the rows of both sides. INTERSECT [ALL] returns the rows
+/// of the left side found on the right side, EXCEPT [ALL] the ones which
+/// are not. Without ALL duplicates are removed, with ALL every row of the
+/// right side matches only once. Both sides are sorted within the memory
+/// budget, so equal rows follow each other and are counted while merging.
+pub struct SetOperation<'b> {
+    left: SortedSide<'b>,
+    right: SortedSide<'b>,
+    op: SetOp,
+    all: bool,
+    // the row returned `repeat` more times
+    row: Option<Vec<u8>>,
+    repeat: u64,
+}
+
+impl<'b> Operator for SetOperation<'b> {
+    fn columns(&self) -> &[Column] {
+        self.left.input.columns()
+    }
+
+    fn next(&mut self) -> Result<Option<Vec<u8>>, ExecutionError> {
+        loop {
+            if self.repeat > 0 {
+                self.repeat -= 1;
+                return Ok(self.row.clone())
+            }
+            let values = match (try!(self.left.peek()), try!(self.right.peek())) {
+                (Some(left), Some(right)) =>
+                    if compare_values(&self.left.keys, &left, &right) == Ordering::Greater {
+                        right
+                    } else {
+                        left
+                    },
+                (Some(values), None) | (None, Some(values)) => values,
+                (None, None) => return Ok(None),
+            };
+            let (left, row) = try!(self.left.skip(&values));
+            let (right, other) = try!(self.right.skip(&values));
+            self.repeat = match (self.op, self.all) {
+                // UNION ALL is an `Append`
+                (SetOp::Union, _) => 1,
+                (SetOp::Intersect, true) => min(left, right),
+                (SetOp::Intersect, false) => if left > 0 && right > 0 { 1 } else { 0 },
+                (SetOp::Except, true) => left.saturating_sub(right),
+                (SetOp::Except, false) => if left > 0 && right == 0 { 1 } else { 0 },
+            };
+            self.row = row.or(other);
+        }
+    }
+
+    fn rewind(&mut self) -> Result<(), ExecutionError> {
+        self.row = None;
+        self.repeat = 0;
+        try!(self.left.rewind());
+        self.right.rewind()
+    }
+}
+
+/// Encodes a row with other column types
+fn convert_row(from: &[Column], to: &[Column], row: &[u8])
+    -> Result<Vec<u8>, ExecutionError>
+{
+    if from.iter().zip(to.iter()).all(|(f, t)| f.sql_type == t.sql_type) {
+        return Ok(row.to_vec())
+    }
+    let mut converted = Vec::<u8>::new();
+    for i in 0..from.len() {
+        let value = try!(decode_value(&from[i].sql_type, &column_value(from, row, i)));
+        try!(to[i].sql_type.encode_into(&mut converted, &value));
+    }
+    Ok(converted)
+}
@@ -0,0 +1,440 @@
+//! Logical query plans
+//!
+//! A select statement is first turned into a tree of logical operators
+//! describing what has to be computed. The `physical` module turns this
+//! tree into operators which actually produce the rows.
+//!
+
+use super::{Executor, ExecutionError, compare_lits};
+use super::system;
+use super::super::parse::ast::*;
+use super::super::parse::token::Lit;
+use super::super::storage::{TableStats, ColumnStats};
+use std::cmp::{min, max, Ordering};
+
+/// A node of a logical plan
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogicalPlan {
+    // the rows of a table, known by its name and the aliases
+    Scan {
+        table: String,
+        aliases: Vec<String>,
+        access: Access,
+    },
+    // the rows of a system table, computed when it is scanned
+    SystemScan {
+        table: String,
+        aliases: Vec<String>,
+    },
+    // a view or derived table, used like a table with the given name
+    Subquery {
+        name: String,
+        aliases: Vec<String>,
+        input: Box<LogicalPlan>,
+    },
+    // the rows matching the conditions
+    Filter {
+        input: Box<LogicalPlan>,
+        cond: Conditions,
+    },
+    // every combination of rows of both sides matching the conditions
+    Join {
+        left: Box<LogicalPlan>,
+        right: Box<LogicalPlan>,
+        cond: Option<Conditions>,
+        kind: JoinKind,
+    },
+    // computes the output columns. `*` lists the columns of the sources in
+    // the order of `tables`, which is the FROM order.
+    Project {
+        input: Box<LogicalPlan>,
+        targets: Vec<Target>,
+        tables: Vec<String>,
+    },
+    // rows with equal values in the group columns (None: all columns) are
+    // collapsed into one. There are no aggregate functions yet, so this
+    // implements DISTINCT.
+    Aggregate {
+        input: Box<LogicalPlan>,
+        group: Option<Vec<usize>>,
+    },
+    Sort {
+        input: Box<LogicalPlan>,
+        keys: Vec<Sort>,
+    },
+    Limit {
+        input: Box<LogicalPlan>,
+        offset: u64,
+        count: Option<u64>,
+    },
+    // UNION, INTERSECT or EXCEPT of two selects
+    SetOperation {
+        left: Box<LogicalPlan>,
+        right: Box<LogicalPlan>,
+        op: SetOp,
+        all: bool,
+    },
+}
+
+/// How a scan reads the rows of a table
+#[derive(Debug, Clone, PartialEq)]
+pub enum Access {
+    // every row
+    Full,
+    // the rows whose column compares to the value, selected by the storage
+    // engine. With `index` the engine uses the index of the column.
+    Lookup {
+        column: String,
+        op: CompType,
+        value: Lit,
+        index: bool,
+    },
+}
+
+/// How the rows of both sides of a join are combined
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JoinKind {
+    // the right side is read again for every row of the left side
+    NestedLoop,
+    // the rows of the right side are put into a hash table by the columns
+    // compared with `=` to the left side
+    Hash,
+}
+
+impl LogicalPlan {
+    /// Returns the plans this plan reads its rows from
+    pub fn inputs(&self) -> Vec<&LogicalPlan> {
+        match self {
+            &LogicalPlan::Scan { .. } | &LogicalPlan::SystemScan { .. } => Vec::new(),
+            &LogicalPlan::Subquery { ref input, .. }
+            | &LogicalPlan::Filter { ref input, .. }
+            | &LogicalPlan::Project { ref input, .. }
+            | &LogicalPlan::Aggregate { ref input, .. }
+            | &LogicalPlan::Sort { ref input, .. }
+            | &LogicalPlan::Limit { ref input, .. } => vec![&**input],
+            &LogicalPlan::Join { ref left, ref right, .. }
+            | &LogicalPlan::SetOperation { ref left, ref right, .. } => vec![&**left, &**right],
+        }
+    }
+}
+
+impl<'a> Executor<'a> {
+    /// Turns a select statement into a logical plan
+    pub fn plan_select(&self, mut stmt: SelectStmt) -> Result<LogicalPlan, ExecutionError> {
+        // every source can be referenced by its own name
+        for name in &stmt.tid {
+            stmt.alias.insert(name.clone(), name.clone());
+        }
+
+        let mut plan = try!(self.plan_source(&mut stmt, 0));
+        for i in 1..stmt.tid.len() {
+            plan = LogicalPlan::Join {
+                left: Box::new(plan),
+                right: Box::new(try!(self.plan_source(&mut stmt, i))),
+                cond: None,
+                kind: JoinKind::NestedLoop,
+            };
+        }
+
+        if let Some(cond) = stmt.cond.take() {
+            plan = LogicalPlan::Filter { input: Box::new(plan), cond: cond };
+        }
+
+        plan = LogicalPlan::Project {
+            input: Box::new(plan),
+            targets: stmt.target.clone(),
+            tables: stmt.tid.clone(),
+        };
+
+        if stmt.distinct {
+            plan = LogicalPlan::Aggregate { input: Box::new(plan), group: None };
+        }
+
+        for compound in stmt.compound.drain(..) {
+            plan = LogicalPlan::SetOperation {
+                left: Box::new(plan),
+                right: Box::new(try!(self.plan_select(*compound.select))),
+                op: compound.op,
+                all: compound.all,
+            };
+        }
+
+        if !stmt.order.is_empty() {
+            // qualified sort keys refer to output columns by table name
+            let mut keys = stmt.order.clone();
+            for key in keys.iter_mut() {
+                if let Some(table) = key.alias.as_ref().and_then(|a| stmt.alias.get(a)) {
+                    key.alias = Some(table.clone());
+                }
+            }
+            plan = LogicalPlan::Sort { input: Box::new(plan), keys: keys };
+        }
+
+        if let Some(limit) = stmt.limit.take() {
+            plan = LogicalPlan::Limit {
+                input: Box::new(plan),
+                offset: limit.offset.unwrap_or(0) as u64,
+                count: limit.count.map(|c| c as u64),
+            };
+        }
+        Ok(plan)
+    }
+
+    /// Plans the i-th source of the from list
+    fn plan_source(&self, stmt: &mut SelectStmt, i: usize)
+        -> Result<LogicalPlan, ExecutionError>
+    {
+        let name = stmt.tid[i].clone();
+        let aliases = stmt.alias.iter()
+            .filter(|&(alias, table)| *table == name && *alias != name)
+            .map(|(alias, _)| alias.clone())
+            .collect();
+
+        let query = match stmt.derived.remove(&name) {
+            Some(query) => Some(query),
+            None if system::is_system_table(&name) =>
+                return Ok(LogicalPlan::SystemScan { table: name, aliases: aliases }),
+            None => {
+                let base = try!(self.get_own_database());
+                if base.has_view(&name) {
+                    let query = try!(base.load_view(&name)).query.clone();
+                    // views replaced by two connections at once may still
+                    // form a cycle, expanding it would never end
+                    try!(self.check_view_cycle(&name, &query));
+                    Some(query)
+                } else {
+                    None
+                }
+            },
+        };
+        Ok(match query {
+            Some(query) => LogicalPlan::Subquery {
+                name: name,
+                aliases: aliases,
+                input: Box::new(try!(self.plan_select(query))),
+            },
+            None => LogicalPlan::Scan { table: name, aliases: aliases, access: Access::Full },
+        })
+    }
+
+    /// Estimates the number of rows a plan produces. Tables without
+    /// statistics are guessed from the size of their data file.
+    pub fn estimate_rows(&self, plan: &LogicalPlan) -> Result<u64, ExecutionError> {
+        Ok(match plan {
+            &LogicalPlan::Scan { ref table, ref access, .. } => {
+                let stats = try!(self.table_stats(table));
+                let rows = match stats {
+                    Some(ref stats) => stats.rows,
+                    None => try!(try!(self.get_table(table)).estimated_rows()),
+                };
+                match access {
+                    &Access::Full => rows,
+                    // an index is kept on the primary key only
+                    &Access::Lookup { op: CompType::Equ, index: true, .. } => min(rows, 1),
+                    &Access::Lookup { ref column, op, ref value, .. } => {
+                        let column = stats.as_ref().and_then(|s| s.column(column));
+                        scale(rows, comparison_selectivity(column, op, value))
+                    },
+                }
+            },
+            // there are few, the rows are not computed for a guess
+            &LogicalPlan::SystemScan { .. } => 10,
+            &LogicalPlan::Subquery { ref input, .. }
+            | &LogicalPlan::Project { ref input, .. }
+            | &LogicalPlan::Aggregate { ref input, .. }
+            | &LogicalPlan::Sort { ref input, .. } => try!(self.estimate_rows(input)),
+            &LogicalPlan::Filter { ref input, ref cond } => {
+                let stats = try!(self.scanned_stats(input));
+                scale(try!(self.estimate_rows(input)), selectivity(cond, stats.as_ref()))
+            },
+            &LogicalPlan::Join { ref left, ref right, ref cond, .. } => {
+                let rows = try!(self.estimate_rows(left))
+                    .saturating_mul(try!(self.estimate_rows(right)));
+                match cond {
+                    &Some(ref cond) => scale(rows, try!(self.join_selectivity(cond, left, right))),
+                    &None => rows,
+                }
+            },
+            &LogicalPlan::Limit { ref input, offset, count } => {
+                let rows = try!(self.estimate_rows(input)).saturating_sub(offset);
+                count.map_or(rows, |count| min(count, rows))
+            },
+            &LogicalPlan::SetOperation { ref left, ref right, op, .. } => {
+                let left = try!(self.estimate_rows(left));
+                let right = try!(self.estimate_rows(right));
+                match op {
+                    SetOp::Union => left + right,
+                    SetOp::Intersect => min(left, right),
+                    SetOp::Except => left,
+                }
+            },
+        })
+    }
+
+    /// Guesses the share of the combined rows of both sides matching the
+    /// join condition. Comparing two columns with `=` matches one of the
+    /// distinct values of the column with more of them.
+    pub fn join_selectivity(&self, cond: &Conditions, left: &LogicalPlan, right: &LogicalPlan)
+        -> Result<f64, ExecutionError>
+    {
+        Ok(match cond {
+            &Conditions::And(ref c1, ref c2) => try!(self.join_selectivity(c1, left, right))
+                * try!(self.join_selectivity(c2, left, right)),
+            &Conditions::Leaf(Condition {
+                ref aliascol, ref col, op: CompType::Equ, ref aliasrhs, rhs: CondType::Word(ref rhs)
+            }) => {
+                let mut distinct = None;
+                for &(alias, column) in &[(aliascol, col), (aliasrhs, rhs)] {
+                    for plan in &[left, right] {
+                        if let Some(stats) = try!(self.column_stats(plan, alias, column)) {
+                            distinct = Some(max(distinct.unwrap_or(1), stats.distinct));
+                        }
+                    }
+                }
+                distinct.map_or(0.1, |distinct| 1.0 / distinct as f64)
+            },
+            cond => selectivity(cond, None),
+        })
+    }
+
+    /// Returns the statistics of the column if it belongs to a table of the
+    /// plan which was analyzed
+    pub fn column_stats(&self, plan: &LogicalPlan, alias: &Option<String>, column: &str)
+        -> Result<Option<ColumnStats>, ExecutionError>
+    {
+        let table = match try!(self.column_source(plan, alias, column)) {
+            Some(table) => table,
+            None => return Ok(None),
+        };
+        if !scans_table(plan, &table) {
+            return Ok(None)
+        }
+        Ok(try!(self.table_stats(&table)).and_then(|s| s.column(column).cloned()))
+    }
+
+    /// Returns the statistics of the table the plan reads, if it reads a
+    /// single one
+    fn scanned_stats(&self, plan: &LogicalPlan) -> Result<Option<TableStats>, ExecutionError> {
+        match plan {
+            &LogicalPlan::Scan { ref table, .. } => self.table_stats(table),
+            &LogicalPlan::Filter { ref input, .. } => self.scanned_stats(input),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Splits the condition at its ANDs
+pub fn split_and(cond: Conditions, conds: &mut Vec<Conditions>) {
+    match cond {
+        Conditions::And(c1, c2) => {
+            split_and(*c1, conds);
+            split_and(*c2, conds);
+        },
+        cond => conds.push(cond),
+    }
+}
+
+/// Combines the conditions with AND
+pub fn and_all(conds: Vec<Conditions>) -> Option<Conditions> {
+    let mut conds = conds.into_iter();
+    match conds.next() {
+        Some(first) =>
+            Some(conds.fold(first, |acc, c| Conditions::And(Box::new(acc), Box::new(c)))),
+        None => None,
+    }
+}
+
+/// Checks if the plan reads the table, views and derived tables are not
+/// read directly
+fn scans_table(plan: &LogicalPlan, name: &str) -> bool {
+    match plan {
+        &LogicalPlan::Scan { ref table, .. } => table == name,
+        &LogicalPlan::Subquery { .. } => false,
+        plan => plan.inputs().iter().any(|input| scans_table(input, name)),
+    }
+}
+
+/// Guesses the share of rows matching the conditions, using the statistics
+/// of the table if there are some
+fn selectivity(cond: &Conditions, stats: Option<&TableStats>) -> f64 {
+    let column = |name: &str| stats.and_then(|s| s.column(name));
+    match cond {
+        &Conditions::And(ref c1, ref c2) => selectivity(c1, stats) * selectivity(c2, stats),
+        &Conditions::Or(ref c1, ref c2) => {
+            let (s1, s2) = (selectivity(c1, stats), selectivity(c2, stats));
+            s1 + s2 - s1 * s2
+        },
+        &Conditions::Not(ref c) => 1.0 - selectivity(c, stats),
+        &Conditions::Leaf(ref c) => match c.rhs {
+            CondType::Literal(ref lit) => comparison_selectivity(column(&c.col), c.op, lit),
+            _ => default_selectivity(c.op),
+        },
+        &Conditions::In(ref c) => match c.set {
+            InSet::List(ref values) => {
+                let each = match column(&c.col) {
+                    Some(stats) => 1.0 / max(stats.distinct, 1) as f64,
+                    None => 0.1,
+                };
+                (values.len() as f64 * each).min(1.0)
+            },
+            InSet::Select(_) => 0.5,
+        },
+        &Conditions::Between(ref c) => match (column(&c.col), &c.low, &c.high) {
+            (Some(stats), &Expr::Literal(ref low), &Expr::Literal(ref high))
+                if !stats.histogram.is_empty() =>
+                (below(&stats.histogram, high) - below(&stats.histogram, low)).max(0.0),
+            _ => 0.25,
+        },
+        &Conditions::Like(_) => 0.25,
+        &Conditions::Exists(_) => 0.5,
+        &Conditions::Const(value) => if value { 1.0 } else { 0.0 },
+    }
+}
+
+/// Guesses the share of rows whose column compares to the value
+fn comparison_selectivity(stats: Option<&ColumnStats>, op: CompType, value: &Lit) -> f64 {
+    let stats = match stats {
+        Some(stats) => stats,
+        None => return default_selectivity(op),
+    };
+    let equal = 1.0 / max(stats.distinct, 1) as f64;
+    if stats.histogram.is_empty() {
+        return match op {
+            CompType::Equ => equal,
+            CompType::NEqu => 1.0 - equal,
+            _ => default_selectivity(op),
+        }
+    }
+    let below = below(&stats.histogram, value);
+    match op {
+        CompType::Equ => equal,
+        CompType::NEqu => 1.0 - equal,
+        CompType::SThan | CompType::SEThan => below,
+        CompType::GThan | CompType::GEThan => 1.0 - below,
+    }
+}
+
+fn default_selectivity(op: CompType) -> f64 {
+    match op {
+        CompType::Equ => 0.1,
+        CompType::NEqu => 0.9,
+        _ => 0.33,
+    }
+}
+
+/// Returns the share of values smaller than the value. Within a bucket
+/// half of the values are counted.
+fn below(histogram: &[Lit], value: &Lit) -> f64 {
+    let buckets = histogram.len() - 1;
+    if buckets == 0 || compare_lits(value, &histogram[0]) != Ordering::Greater {
+        return 0.0
+    }
+    match histogram.iter().position(|bound| compare_lits(value, bound) != Ordering::Greater) {
+        Some(i) => (i as f64 - 0.5) / buckets as f64,
+        None => 1.0,
+    }
+}
+
+fn scale(rows: u64, selectivity: f64) -> u64 {
+    (rows as f64 * selectivity).ceil() as u64
+}
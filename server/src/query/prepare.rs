@@ -0,0 +1,274 @@
+//! Parameters of prepared statements
+//!
+//! A prepared statement is parsed once and kept by the connection. Its
+//! parameters are `Lit::Param` placeholders in the AST, which are replaced
+//! by the values given for every execution.
+//!
+
+use super::{Executor, ExecutionError, Session};
+use super::super::auth;
+use super::super::parse::ast::*;
+use super::super::parse::token::Lit;
+use super::super::storage::Column;
+use super::super::storage::types::SqlType;
+use std::cmp::max;
+use std::collections::{BTreeMap, HashMap};
+
+/// The tables the column names of a statement refer to
+#[derive(Default)]
+struct Scope {
+    tables: Vec<String>,
+    // alias -> table name
+    alias: HashMap<String, String>,
+}
+
+/// Where the value of a literal ends up
+enum Place {
+    Unknown,
+    // compared with or assigned to the column
+    Column(Option<String>, String),
+    // the n-th value of an insert without a column list
+    Position(usize),
+}
+
+/// Returns the types of the parameters, None if the type does not follow
+/// from the statement. The parameters have to be numbered from 1 without
+/// gaps.
+pub fn parameter_types(query: &Query, user: &mut auth::User, session: &mut Session)
+    -> Result<Vec<Option<SqlType>>, ExecutionError>
+{
+    let executor = Executor::new(user, session);
+    let mut query = query.clone();
+    // parameter number -> type, only for the placeholders of the statement
+    let mut seen = BTreeMap::new();
+    try!(visit_query(&mut query, &mut |scope, place, lit| {
+        if let &mut Lit::Param(n) = lit {
+            let sql_type = executor.column_type(scope, &place);
+            let known = seen.entry(n).or_insert(None);
+            if known.is_none() {
+                *known = sql_type;
+            }
+        }
+        Ok(())
+    }));
+    let mut types = Vec::with_capacity(seen.len());
+    for (n, sql_type) in seen {
+        if n as usize != types.len() + 1 {
+            return Err(ExecutionError::MissingParameter(types.len() as u32 + 1))
+        }
+        types.push(sql_type);
+    }
+    Ok(types)
+}
+
+/// Fails if the query still has placeholders, they are only replaced for
+/// the execution of a prepared statement
+pub fn check_bound(query: &mut Query) -> Result<(), ExecutionError> {
+    visit_query(query, &mut |_, _, lit| match lit {
+        &mut Lit::Param(_) => Err(ExecutionError::UnboundParameter),
+        _ => Ok(()),
+    })
+}
+
+/// Replaces the parameters by the values, `$n` by the n-th value
+pub fn bind(query: &mut Query, values: &[Lit]) -> Result<(), ExecutionError> {
+    let mut count = 0;
+    try!(visit_query(query, &mut |_, _, lit| {
+        let n = match lit {
+            &mut Lit::Param(n) => n as usize,
+            _ => return Ok(()),
+        };
+        count = max(count, n);
+        match values.get(n - 1) {
+            None => Err(ExecutionError::WrongParameterCount),
+            Some(&Lit::Param(_)) => Err(ExecutionError::InvalidParameter),
+            Some(value) => {
+                *lit = value.clone();
+                Ok(())
+            },
+        }
+    }));
+    if count != values.len() {
+        return Err(ExecutionError::WrongParameterCount)
+    }
+    Ok(())
+}
+
+impl<'a> Executor<'a> {
+    /// Returns the type of the column a literal ends up in
+    fn column_type(&self, scope: &Scope, place: &Place) -> Option<SqlType> {
+        let (alias, name) = match place {
+            &Place::Column(ref alias, ref name) => (alias, name),
+            &Place::Position(i) => return scope.tables.get(0)
+                .and_then(|table| self.table_columns(table))
+                .and_then(|columns| columns.get(i).map(|c| c.sql_type)),
+            &Place::Unknown => return None,
+        };
+        let tables = match alias {
+            &Some(ref alias) => vec![scope.alias.get(alias).unwrap_or(alias).clone()],
+            &None => scope.tables.clone(),
+        };
+        for table in tables {
+            let columns = match self.table_columns(&table) {
+                Some(columns) => columns,
+                None => continue,
+            };
+            if let Some(column) = columns.iter().find(|c| &c.name == name) {
+                return Some(column.sql_type)
+            }
+        }
+        None
+    }
+
+    /// Returns the columns of a stored table, None for views and unknown
+    /// tables
+    fn table_columns(&self, table: &str) -> Option<Vec<Column>> {
+        match self.get_own_database() {
+            Ok(base) if !base.has_view(table) => {},
+            _ => return None,
+        }
+        self.get_table(table).ok().map(|t| t.columns().to_vec())
+    }
+}
+
+/// Calls `f` for every literal of the query
+fn visit_query<F>(query: &mut Query, f: &mut F) -> Result<(), ExecutionError>
+    where F: FnMut(&Scope, Place, &mut Lit) -> Result<(), ExecutionError>
+{
+    let stmt = match query {
+        &mut Query::ManipulationStmt(ref mut stmt) => stmt,
+        &mut Query::DefStmt(DefStmt::Create(CreateStmt::View(ref mut view))) =>
+            return visit_select(&mut view.sel, f),
+        _ => return Ok(()),
+    };
+    match stmt {
+        &mut ManipulationStmt::Select(ref mut select) => visit_select(select, f),
+        &mut ManipulationStmt::Explain(ref mut explain) => visit_select(&mut explain.select, f),
+        &mut ManipulationStmt::Insert(ref mut insert) => {
+            let scope = Scope { tables: vec![insert.tid.clone()], alias: HashMap::new() };
+            for (i, value) in insert.val.iter_mut().enumerate() {
+                let place = match insert.col.get(i) {
+                    Some(col) => Place::Column(None, col.clone()),
+                    None => Place::Position(i),
+                };
+                try!(f(&scope, place, value));
+            }
+            Ok(())
+        },
+        &mut ManipulationStmt::Update(ref mut update) => {
+            let scope = Scope { tables: vec![update.tid.clone()], alias: update.alias.clone() };
+            for cond in update.set.iter_mut() {
+                try!(visit_condition(&scope, cond, f));
+            }
+            match update.conds {
+                Some(ref mut conds) => visit_conditions(&scope, conds, f),
+                None => Ok(()),
+            }
+        },
+        &mut ManipulationStmt::Delete(ref mut delete) => {
+            let scope = Scope { tables: vec![delete.tid.clone()], alias: delete.alias.clone() };
+            match delete.cond {
+                Some(ref mut conds) => visit_conditions(&scope, conds, f),
+                None => Ok(()),
+            }
+        },
+        &mut ManipulationStmt::Set(ref mut set) =>
+            f(&Scope::default(), Place::Unknown, &mut set.value),
+        _ => Ok(()),
+    }
+}
+
+fn visit_select<F>(select: &mut SelectStmt, f: &mut F) -> Result<(), ExecutionError>
+    where F: FnMut(&Scope, Place, &mut Lit) -> Result<(), ExecutionError>
+{
+    let scope = Scope { tables: select.tid.clone(), alias: select.alias.clone() };
+    for target in select.target.iter_mut() {
+        match target.col {
+            Col::Subquery(ref mut subquery) => try!(visit_select(subquery, f)),
+            Col::Expr(ref mut expr) => try!(visit_expr(&scope, expr, f)),
+            _ => {},
+        }
+    }
+    for (_, derived) in select.derived.iter_mut() {
+        try!(visit_select(derived, f));
+    }
+    if let Some(ref mut conds) = select.cond {
+        try!(visit_conditions(&scope, conds, f));
+    }
+    for compound in select.compound.iter_mut() {
+        try!(visit_select(&mut compound.select, f));
+    }
+    Ok(())
+}
+
+fn visit_conditions<F>(scope: &Scope, conds: &mut Conditions, f: &mut F)
+    -> Result<(), ExecutionError>
+    where F: FnMut(&Scope, Place, &mut Lit) -> Result<(), ExecutionError>
+{
+    match conds {
+        &mut Conditions::Leaf(ref mut cond) => visit_condition(scope, cond, f),
+        &mut Conditions::And(ref mut left, ref mut right) |
+        &mut Conditions::Or(ref mut left, ref mut right) => {
+            try!(visit_conditions(scope, left, f));
+            visit_conditions(scope, right, f)
+        },
+        &mut Conditions::Not(ref mut cond) => visit_conditions(scope, cond, f),
+        &mut Conditions::In(ref mut cond) => match cond.set {
+            InSet::Select(ref mut select) => visit_select(select, f),
+            InSet::List(ref mut values) => {
+                for value in values.iter_mut() {
+                    let place = Place::Column(cond.aliascol.clone(), cond.col.clone());
+                    try!(f(scope, place, value));
+                }
+                Ok(())
+            },
+        },
+        &mut Conditions::Between(ref mut cond) => {
+            for bound in vec![&mut cond.low, &mut cond.high] {
+                if let &mut Expr::Literal(ref mut lit) = bound {
+                    try!(f(scope, Place::Column(cond.aliascol.clone(), cond.col.clone()), lit));
+                }
+            }
+            Ok(())
+        },
+        &mut Conditions::Like(_) | &mut Conditions::Const(_) => Ok(()),
+        &mut Conditions::Exists(ref mut select) => visit_select(select, f),
+    }
+}
+
+fn visit_condition<F>(scope: &Scope, cond: &mut Condition, f: &mut F)
+    -> Result<(), ExecutionError>
+    where F: FnMut(&Scope, Place, &mut Lit) -> Result<(), ExecutionError>
+{
+    match cond.rhs {
+        CondType::Literal(ref mut lit) =>
+            f(scope, Place::Column(cond.aliascol.clone(), cond.col.clone()), lit),
+        CondType::Subquery(ref mut select) => visit_select(select, f),
+        CondType::Word(_) => Ok(()),
+    }
+}
+
+fn visit_expr<F>(scope: &Scope, expr: &mut Expr, f: &mut F) -> Result<(), ExecutionError>
+    where F: FnMut(&Scope, Place, &mut Lit) -> Result<(), ExecutionError>
+{
+    match expr {
+        &mut Expr::Literal(ref mut lit) => f(scope, Place::Unknown, lit),
+        &mut Expr::Function(_, ref mut args) => {
+            for arg in args.iter_mut() {
+                try!(visit_expr(scope, arg, f));
+            }
+            Ok(())
+        },
+        &mut Expr::Case(ref mut whens, ref mut default) => {
+            for &mut (ref mut cond, ref mut result) in whens.iter_mut() {
+                try!(visit_conditions(scope, cond, f));
+                try!(visit_expr(scope, result, f));
+            }
+            match default {
+                &mut Some(ref mut default) => visit_expr(scope, default, f),
+                &mut None => Ok(()),
+            }
+        },
+        &mut Expr::Column(_, _) => Ok(()),
+    }
+}
@@ -0,0 +1,201 @@
+//! Privileges needed to run a statement
+//!
+//! Before a statement is dispatched, the executor collects the privileges
+//! it needs: SELECT on every table or view read, INSERT, UPDATE or DELETE on
+//! the changed table and CREATE, DROP or ALTER on the defined table. A
+//! privilege granted for the database holds for all of its tables. System
+//! tables can only be read by admins.
+//!
+
+use super::{Executor, ExecutionError};
+use super::system;
+use super::super::auth;
+use super::super::parse::ast::*;
+
+/// A privilege for a table of the database in use, without a table for the
+/// whole database
+type Needed = Vec<(Privilege, Option<String>)>;
+
+impl<'a> Executor<'a> {
+    /// Returns an error if the user lacks a privilege the statement needs
+    pub fn check_privileges(&self, query: &Query) -> Result<(), ExecutionError> {
+        let mut needed = Vec::new();
+        match query {
+            &Query::ManipulationStmt(ref stmt) => match stmt {
+                &ManipulationStmt::Select(ref select) => visit_select(select, &mut needed),
+                &ManipulationStmt::Explain(ref explain) =>
+                    visit_select(&explain.select, &mut needed),
+                &ManipulationStmt::Describe(ref table) =>
+                    needed.push((Privilege::Select, Some(table.clone()))),
+                &ManipulationStmt::Analyze(ref table) =>
+                    needed.push((Privilege::Select, table.clone())),
+                &ManipulationStmt::Insert(ref insert) =>
+                    needed.push((Privilege::Insert, Some(insert.tid.clone()))),
+                &ManipulationStmt::Update(ref update) => {
+                    needed.push((Privilege::Update, Some(update.tid.clone())));
+                    for cond in &update.set {
+                        visit_condition(cond, &mut needed);
+                    }
+                    if let Some(ref conds) = update.conds {
+                        visit_conditions(conds, &mut needed);
+                    }
+                },
+                &ManipulationStmt::Delete(ref delete) => {
+                    needed.push((Privilege::Delete, Some(delete.tid.clone())));
+                    if let Some(ref conds) = delete.cond {
+                        visit_conditions(conds, &mut needed);
+                    }
+                },
+                &ManipulationStmt::Use(_) | &ManipulationStmt::Set(_) |
+                &ManipulationStmt::SetRole(_) => {},
+            },
+            &Query::DefStmt(ref stmt) => match stmt {
+                &DefStmt::Create(CreateStmt::Table(ref create)) =>
+                    needed.push((Privilege::Create, Some(create.tid.clone()))),
+                &DefStmt::Create(CreateStmt::View(ref create)) => {
+                    needed.push((Privilege::Create, Some(create.name.clone())));
+                    visit_select(&create.sel, &mut needed);
+                },
+                // there is no database yet to have a privilege for
+                &DefStmt::Create(CreateStmt::Database(_)) => return self.require_admin(),
+                &DefStmt::Drop(DropStmt::Database(ref name)) => {
+                    let users = auth::lock(&self.user.users);
+                    if !users.has_privilege(self.user.principal(), name, None, Privilege::Drop) {
+                        return Err(ExecutionError::MissingPrivilege(Privilege::Drop,
+                            name.clone()))
+                    }
+                },
+                &DefStmt::Drop(DropStmt::Table(ref table)) |
+                &DefStmt::Drop(DropStmt::View(ref table)) =>
+                    needed.push((Privilege::Drop, Some(table.clone()))),
+                &DefStmt::Alter(AltStmt::Table(ref alter)) =>
+                    needed.push((Privilege::Alter, Some(alter.tid.clone()))),
+                // accounts and privileges are checked by the statements
+                &DefStmt::Create(CreateStmt::User(_)) |
+                &DefStmt::Alter(AltStmt::User(_)) |
+                &DefStmt::Drop(DropStmt::User(_)) |
+                &DefStmt::Create(CreateStmt::Role(_)) |
+                &DefStmt::Drop(DropStmt::Role(_)) |
+                &DefStmt::Grant(_) |
+                &DefStmt::Revoke(_) |
+                &DefStmt::GrantRole(_) |
+                &DefStmt::RevokeRole(_) => {},
+            },
+            &Query::Dummy => {},
+        }
+        self.check_needed(needed)
+    }
+
+    /// Returns an error if the user may not read all tables of the select
+    pub fn check_select(&self, select: &SelectStmt) -> Result<(), ExecutionError> {
+        let mut needed = Vec::new();
+        visit_select(select, &mut needed);
+        self.check_needed(needed)
+    }
+
+    fn check_needed(&self, mut needed: Needed) -> Result<(), ExecutionError> {
+        // system tables are not part of a database, only admins read them
+        if needed.iter().any(|&(_, ref table)| is_system(table)) {
+            try!(self.require_admin());
+            needed = needed.into_iter().filter(|&(_, ref table)| !is_system(table)).collect();
+        }
+
+        if needed.is_empty() {
+            return Ok(())
+        }
+        // the privileges can not be checked without a database
+        let database = match self.user._currentDatabase {
+            Some(ref base) => base.name.clone(),
+            None => return Err(ExecutionError::NoDatabaseSelected),
+        };
+        let users = auth::lock(&self.user.users);
+        for (privilege, table) in needed {
+            if !users.has_privilege(self.user.principal(), &database,
+                table.as_ref().map(|t| &t[..]), privilege)
+            {
+                return Err(ExecutionError::MissingPrivilege(privilege,
+                    table.unwrap_or_else(|| database.clone())))
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns an error if the user is not an admin
+    pub fn require_admin(&self) -> Result<(), ExecutionError> {
+        if self.user.is_admin() {
+            Ok(())
+        } else {
+            Err(ExecutionError::PermissionDenied)
+        }
+    }
+}
+
+fn is_system(table: &Option<String>) -> bool {
+    table.as_ref().map_or(false, |table| system::is_system_table(table))
+}
+
+/// Collects the tables and views a select reads
+fn visit_select(select: &SelectStmt, needed: &mut Needed) {
+    for table in &select.tid {
+        if !select.derived.contains_key(table) {
+            needed.push((Privilege::Select, Some(table.clone())));
+        }
+    }
+    for target in &select.target {
+        match target.col {
+            Col::Subquery(ref subquery) => visit_select(subquery, needed),
+            Col::Expr(ref expr) => visit_expr(expr, needed),
+            _ => {},
+        }
+    }
+    for derived in select.derived.values() {
+        visit_select(derived, needed);
+    }
+    if let Some(ref conds) = select.cond {
+        visit_conditions(conds, needed);
+    }
+    for compound in &select.compound {
+        visit_select(&compound.select, needed);
+    }
+}
+
+fn visit_conditions(conds: &Conditions, needed: &mut Needed) {
+    match conds {
+        &Conditions::Leaf(ref cond) => visit_condition(cond, needed),
+        &Conditions::And(ref left, ref right) |
+        &Conditions::Or(ref left, ref right) => {
+            visit_conditions(left, needed);
+            visit_conditions(right, needed);
+        },
+        &Conditions::Not(ref cond) => visit_conditions(cond, needed),
+        &Conditions::In(ref cond) => if let InSet::Select(ref select) = cond.set {
+            visit_select(select, needed);
+        },
+        &Conditions::Exists(ref select) => visit_select(select, needed),
+        &Conditions::Between(_) | &Conditions::Like(_) | &Conditions::Const(_) => {},
+    }
+}
+
+fn visit_condition(cond: &Condition, needed: &mut Needed) {
+    if let CondType::Subquery(ref select) = cond.rhs {
+        visit_select(select, needed);
+    }
+}
+
+fn visit_expr(expr: &Expr, needed: &mut Needed) {
+    match expr {
+        &Expr::Function(_, ref args) => for arg in args {
+            visit_expr(arg, needed);
+        },
+        &Expr::Case(ref whens, ref default) => {
+            for &(ref cond, ref result) in whens {
+                visit_conditions(cond, needed);
+                visit_expr(result, needed);
+            }
+            if let &Some(ref default) = default {
+                visit_expr(default, needed);
+            }
+        },
+        &Expr::Column(_, _) | &Expr::Literal(_) => {},
+    }
+}
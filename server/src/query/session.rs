@@ -0,0 +1,77 @@
+//! State of a connection the execution of its statements depends on
+//!
+//! A running statement can be stopped from another connection by setting
+//! the cancel flag of its session, or by its statement timeout. The
+//! operators check both between rows.
+//!
+
+use super::ExecutionError;
+use super::spill::Settings;
+use std::cell::Cell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Number of checks between two looks at the clock
+const CLOCK_INTERVAL: u32 = 256;
+
+/// The settings and the cancel flag of a connection
+#[derive(Debug)]
+pub struct Session {
+    pub settings: Settings,
+    // milliseconds a statement may run, set with `SET statement_timeout`
+    pub statement_timeout: Option<u64>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Session {
+    pub fn new(settings: Settings) -> Session {
+        Session {
+            settings: settings,
+            statement_timeout: None,
+            cancel: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Returns the flag which stops the running statement when it is set
+    pub fn cancel_flag(&self) -> Arc<AtomicBool> {
+        self.cancel.clone()
+    }
+
+    /// Starts a statement. A cancel request no statement has seen yet,
+    /// e.g. one which arrived just before, stops this one.
+    pub fn interrupt(&self) -> Interrupt {
+        Interrupt {
+            cancel: self.cancel.clone(),
+            deadline: self.statement_timeout
+                .map(|ms| Instant::now() + Duration::from_millis(ms)),
+            checks: Cell::new(0),
+        }
+    }
+}
+
+/// Tells a running statement whether it has to stop
+#[derive(Debug)]
+pub struct Interrupt {
+    cancel: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+    checks: Cell<u32>,
+}
+
+impl Interrupt {
+    /// Returns an error if the statement was cancelled or ran too long. The
+    /// cancel request is used up then.
+    pub fn check(&self) -> Result<(), ExecutionError> {
+        if self.cancel.swap(false, Ordering::SeqCst) {
+            return Err(ExecutionError::Cancelled)
+        }
+        if let Some(deadline) = self.deadline {
+            let checks = self.checks.get() + 1;
+            self.checks.set(checks % CLOCK_INTERVAL);
+            if checks == CLOCK_INTERVAL && Instant::now() > deadline {
+                return Err(ExecutionError::StatementTimeout)
+            }
+        }
+        Ok(())
+    }
+}
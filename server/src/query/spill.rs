@@ -0,0 +1,196 @@
+//! Working memory of queries and temporary files
+//!
+//! Every query gets a memory budget which is shared by its operators. An
+//! operator keeping rows reserves their size first. If the budget is used
+//! up, the rows are written to temporary files in the `tmp` folder of the
+//! data directory instead. The files are removed when they are dropped.
+//!
+
+use super::ExecutionError;
+use super::physical::Operator;
+use super::super::storage::Column;
+use std::cell::Cell;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{Hash, Hasher, SipHasher};
+use std::io::{self, BufReader, BufWriter, Read, Write, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
+
+/// Number of files the rows are split into when a hash table does not fit
+pub const PARTITIONS: usize = 8;
+
+/// Partitions of deeper levels are kept in memory. They only get that big
+/// if most rows have the same key, so splitting them again does not help.
+pub const MAX_LEVEL: u64 = 4;
+
+/// Bytes counted for every kept row in addition to its data
+const ROW_OVERHEAD: u64 = 32;
+
+/// Numbers the temporary files of all connections
+static NEXT_FILE: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Settings of the query execution, taken from the server configuration
+#[derive(Debug, Clone)]
+pub struct Settings {
+    // bytes a single query may keep in memory
+    pub memory_limit: u64,
+    // folder for the temporary files
+    pub temp_dir: PathBuf,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            memory_limit: 64 * 1024 * 1024,
+            temp_dir: PathBuf::from("data/tmp"),
+        }
+    }
+}
+
+/// The memory a query may use, shared by its operators
+#[derive(Debug, Clone)]
+pub struct MemoryBudget {
+    limit: u64,
+    used: Rc<Cell<u64>>,
+    dir: PathBuf,
+}
+
+impl MemoryBudget {
+    pub fn new(settings: &Settings) -> MemoryBudget {
+        MemoryBudget {
+            limit: settings.memory_limit,
+            used: Rc::new(Cell::new(0)),
+            dir: settings.temp_dir.clone(),
+        }
+    }
+
+    /// Reserves the memory for a row, returns false if it does not fit
+    pub fn reserve(&self, row: &[u8]) -> bool {
+        let bytes = row_cost(row);
+        if self.used.get() + bytes > self.limit {
+            return false
+        }
+        self.used.set(self.used.get() + bytes);
+        true
+    }
+
+    /// Reserves the memory for a row even if it does not fit. An operator
+    /// has to keep at least one row to make progress.
+    pub fn force(&self, row: &[u8]) {
+        self.used.set(self.used.get() + row_cost(row));
+    }
+
+    /// Gives back memory reserved before
+    pub fn release(&self, bytes: u64) {
+        let used = self.used.get();
+        self.used.set(if bytes > used { 0 } else { used - bytes });
+    }
+
+    /// Creates an empty temporary file for rows with the given columns
+    pub fn temp_file(&self, columns: &[Column]) -> Result<SpillFile, ExecutionError> {
+        try!(fs::create_dir_all(&self.dir));
+        let number = NEXT_FILE.fetch_add(1, Ordering::SeqCst);
+        let path = TempPath(self.dir.join(format!("spill-{}.tmp", number)));
+        let file = try!(OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path.0));
+        Ok(SpillFile {
+            path: path,
+            writer: BufWriter::new(file),
+            columns: columns.to_vec(),
+        })
+    }
+
+    /// Creates one temporary file per partition
+    pub fn partitions(&self, columns: &[Column]) -> Result<Vec<SpillFile>, ExecutionError> {
+        let mut files = Vec::new();
+        for _ in 0..PARTITIONS {
+            files.push(try!(self.temp_file(columns)));
+        }
+        Ok(files)
+    }
+}
+
+/// Returns the bytes counted for keeping a row
+pub fn row_cost(row: &[u8]) -> u64 {
+    row.len() as u64 + ROW_OVERHEAD
+}
+
+/// Returns the partition of a key. Every level of partitioning hashes
+/// differently, so the rows of a partition are split up again.
+pub fn partition_of(key: &[u8], level: u64) -> usize {
+    let mut hasher = SipHasher::new_with_keys(level, 0);
+    key.hash(&mut hasher);
+    (hasher.finish() % PARTITIONS as u64) as usize
+}
+
+/// Path of a temporary file, the file is removed when it is dropped
+struct TempPath(PathBuf);
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.0) {
+            warn!("Could not remove temporary file {:?}: {}", self.0, e);
+        }
+    }
+}
+
+/// Rows written to a temporary file
+pub struct SpillFile {
+    path: TempPath,
+    writer: BufWriter<File>,
+    columns: Vec<Column>,
+}
+
+impl SpillFile {
+    pub fn write_row(&mut self, row: &[u8]) -> Result<(), ExecutionError> {
+        try!(self.writer.write_all(row));
+        Ok(())
+    }
+
+    /// Finishes writing and returns an operator reading the rows again
+    pub fn into_reader(mut self) -> Result<SpillReader, ExecutionError> {
+        try!(self.writer.flush());
+        let file = try!(File::open(&self.path.0));
+        let row_size = self.columns.iter().fold(0, |acc, c| acc + c.get_size() as usize);
+        Ok(SpillReader {
+            path: self.path,
+            reader: BufReader::new(file),
+            columns: self.columns,
+            row_size: row_size,
+        })
+    }
+}
+
+/// Produces the rows of a temporary file in the order they were written
+pub struct SpillReader {
+    // removes the file once the rows are not needed anymore
+    #[allow(dead_code)]
+    path: TempPath,
+    reader: BufReader<File>,
+    columns: Vec<Column>,
+    row_size: usize,
+}
+
+impl Operator for SpillReader {
+    fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    fn next(&mut self) -> Result<Option<Vec<u8>>, ExecutionError> {
+        let mut row = vec![0; self.row_size];
+        match self.reader.read_exact(&mut row) {
+            Ok(_) => Ok(Some(row)),
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn rewind(&mut self) -> Result<(), ExecutionError> {
+        try!(self.reader.seek(SeekFrom::Start(0)));
+        Ok(())
+    }
+}
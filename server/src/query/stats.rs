@@ -0,0 +1,100 @@
+//! ANALYZE and the statistics the planner reads
+//!
+//! For every column the number of distinct values is counted. Int and Char
+//! columns get a histogram, too: the sorted values are split into buckets
+//! holding the same number of rows and the bounds of the buckets are kept.
+//!
+
+use super::{Executor, ExecutionError};
+use super::{generate_rows_dummy, column_value, decode_value, compare_lits};
+use super::super::parse::token::Lit;
+use super::super::storage::{Rows, TableStats, ColumnStats, Error};
+use super::super::storage::types::SqlType;
+use std::collections::HashSet;
+use std::io::Cursor;
+
+/// Number of buckets of a histogram
+const BUCKETS: usize = 10;
+
+impl<'a> Executor<'a> {
+    /// Collects the statistics of the table, or of every table of the
+    /// database if no table is named
+    pub fn execute_analyze_stmt(&self, table: Option<String>)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        let tables = match table {
+            Some(table) => vec![table],
+            None => try!(try!(self.get_own_database()).table_names()),
+        };
+        for table in tables {
+            try!(self.analyze_table(&table));
+        }
+        Ok(generate_rows_dummy())
+    }
+
+    /// Returns the statistics of the last ANALYZE of the table, None for
+    /// views and tables which were never analyzed
+    pub fn table_stats(&self, table: &str) -> Result<Option<TableStats>, ExecutionError> {
+        if try!(self.get_own_database()).has_view(table) {
+            return Ok(None)
+        }
+        Ok(try!(try!(self.get_table(table)).load_stats()))
+    }
+
+    fn analyze_table(&self, name: &str) -> Result<(), ExecutionError> {
+        let engine = try!(self.get_engine(name));
+        let columns = engine.table().columns().to_vec();
+        let mut rows = try!(engine.full_scan());
+        try!(rows.reset_pos());
+
+        let mut values: Vec<Vec<Lit>> = columns.iter().map(|_| Vec::new()).collect();
+        let mut encoded: Vec<HashSet<Vec<u8>>> = columns.iter().map(|_| HashSet::new()).collect();
+        let mut count = 0;
+        loop {
+            let mut row = Vec::<u8>::new();
+            match rows.next_row(&mut row) {
+                Ok(_) => {},
+                Err(Error::EndOfFile) => break,
+                Err(e) => return Err(e.into()),
+            }
+            count += 1;
+            for (i, column) in columns.iter().enumerate() {
+                let data = column_value(&columns, &row, i);
+                if has_histogram(&column.sql_type) {
+                    values[i].push(try!(decode_value(&column.sql_type, &data)));
+                }
+                encoded[i].insert(data);
+            }
+        }
+
+        let mut stats = TableStats { rows: count, columns: Vec::new() };
+        for ((column, mut values), encoded) in columns.iter().zip(values).zip(encoded) {
+            values.sort_by(compare_lits);
+            stats.columns.push(ColumnStats {
+                name: column.name.clone(),
+                distinct: encoded.len() as u64,
+                histogram: histogram(&values),
+            });
+        }
+        try!(try!(self.get_table(name)).save_stats(&stats));
+        Ok(())
+    }
+}
+
+fn has_histogram(sql_type: &SqlType) -> bool {
+    match sql_type {
+        &SqlType::Int | &SqlType::Char(_) => true,
+        _ => false,
+    }
+}
+
+/// Returns the bounds of the buckets of the sorted values
+fn histogram(values: &[Lit]) -> Vec<Lit> {
+    if values.is_empty() {
+        return Vec::new()
+    }
+    let buckets = if values.len() < BUCKETS { values.len() } else { BUCKETS };
+    (0..buckets + 1)
+        .map(|j| values[(j * values.len() / buckets).min(values.len() - 1)].clone())
+        .collect()
+}
@@ -0,0 +1,67 @@
+//! System tables
+//!
+//! System tables show the state of the server. They belong to no database
+//! and can be read from every one, but only by admins. Their rows are
+//! computed when they are scanned.
+//!
+//! `sys_lockouts` lists the user names and client addresses which have to
+//! wait before the next login, see `auth::throttle`.
+//!
+
+use super::{Executor, ExecutionError};
+use super::super::auth::{self, throttle};
+use super::super::parse::token::Lit;
+use super::super::storage::{Column, Rows};
+use super::super::storage::types::SqlType;
+use std::io::Cursor;
+
+pub const LOCKOUTS: &'static str = "sys_lockouts";
+
+/// Checks if the name is the name of a system table
+pub fn is_system_table(name: &str) -> bool {
+    name == LOCKOUTS
+}
+
+/// Returns the columns of the system table. There is only `sys_lockouts`
+/// yet.
+pub fn columns(_table: &str) -> Vec<Column> {
+    vec![
+        Column::new("kind", SqlType::Char(8), false, "user or address", false),
+        Column::new("name", SqlType::Char(64), false, "", false),
+        Column::new("failures", SqlType::Int, false, "failed logins in a row", false),
+        Column::new("locked", SqlType::Bool, false, "refused until the end", false),
+        Column::new("seconds", SqlType::Int, false, "seconds left to wait", false),
+    ]
+}
+
+impl<'a> Executor<'a> {
+    /// Computes the rows of the system table
+    pub fn system_rows(&self, table: &str) -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError> {
+        let columns = columns(table);
+        let lockouts = auth::lock(&self.user.users).throttle().lockouts();
+        let mut rows = Rows::new(Cursor::new(Vec::<u8>::new()), &columns);
+        for lockout in lockouts {
+            let (kind, name) = match lockout.key {
+                throttle::Key::User(name) => ("user", name),
+                throttle::Key::Address(address) => ("address", address.to_string()),
+            };
+            // rounded up like in the error of a refused login
+            let seconds = lockout.wait.as_secs()
+                + if lockout.wait.subsec_nanos() > 0 { 1 } else { 0 };
+            let values = vec![
+                Lit::String(kind.into()),
+                Lit::String(name),
+                Lit::Int(lockout.failures as i64),
+                Lit::Bool(lockout.locked as u8),
+                Lit::Int(seconds as i64),
+            ];
+            let mut row = Vec::<u8>::new();
+            for (column, value) in columns.iter().zip(values.iter()) {
+                try!(column.sql_type.encode_into(&mut row, value));
+            }
+            try!(rows.add_row(&row));
+        }
+        try!(rows.reset_pos());
+        Ok(rows)
+    }
+}
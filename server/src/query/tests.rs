@@ -0,0 +1,626 @@
+//! Tests running statements against a database in a temporary folder
+//!
+
+use super::{Session, Settings, ExecutionError, execute_from_ast, decode_value, like_match};
+use super::{bind, parameter_types};
+use super::super::auth::{self, throttle, Catalog};
+use super::super::parse;
+use super::super::parse::token::Lit;
+use super::super::storage::Database;
+use rand::{OsRng, Rng};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::Ordering;
+
+/// A database of its own for every test, removed again when dropped
+struct TestDb {
+    dir: PathBuf,
+    user: auth::User,
+    session: Session,
+}
+
+impl TestDb {
+    fn new(name: &str) -> TestDb {
+        let dir = env::temp_dir().join(format!("crate-query-{}-{}", name, rand_suffix()));
+        fs::create_dir_all(&dir).unwrap();
+        let catalog = Catalog::open(&dir, Some("secret"), throttle::Settings::default());
+        let database = Database::create(dir.join("db").to_str().unwrap()).unwrap();
+        let user = auth::User {
+            name: auth::ADMIN.into(),
+            _currentDatabase: Some(database),
+            users: Arc::new(Mutex::new(catalog.unwrap())),
+            role: None,
+        };
+        let settings = Settings { temp_dir: dir.join("tmp"), ..Settings::default() };
+        TestDb { dir: dir, user: user, session: Session::new(settings) }
+    }
+
+    fn run(&mut self, sql: &str) -> Result<Vec<Vec<Lit>>, ExecutionError> {
+        let query = parse::parse(sql).unwrap();
+        let result = try!(execute_from_ast(query, &mut self.user, &mut self.session));
+        let mut rows = Vec::new();
+        let mut data = &result.data[..];
+        while !data.is_empty() {
+            let mut row = Vec::new();
+            for column in &result.columns {
+                let (value, rest) = data.split_at(column.get_size() as usize);
+                row.push(try!(decode_value(&column.sql_type, value)));
+                data = rest;
+            }
+            rows.push(row);
+        }
+        Ok(rows)
+    }
+
+    fn ok(&mut self, sql: &str) -> Vec<Vec<Lit>> {
+        match self.run(sql) {
+            Ok(rows) => rows,
+            Err(e) => panic!("{}: {:?}", sql, e),
+        }
+    }
+}
+
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn rand_suffix() -> u32 {
+    OsRng::new().unwrap().next_u32()
+}
+
+fn int(i: i64) -> Lit {
+    Lit::Int(i)
+}
+
+/// Two tables, `a` with ids 1 to 3 and `b` with ids 2 to 4
+fn numbers(name: &str) -> TestDb {
+    let mut db = TestDb::new(name);
+    db.ok("create table a (id int, name char(16))");
+    db.ok("create table b (id int, low int, high int)");
+    db.ok("insert into a values (1, 'one')");
+    db.ok("insert into a values (2, 'two')");
+    db.ok("insert into a values (3, 'three')");
+    db.ok("insert into b values (2, 1, 2)");
+    db.ok("insert into b values (3, 5, 9)");
+    db.ok("insert into b values (4, 0, 9)");
+    db
+}
+
+// ============================================================================
+// Views
+// ============================================================================
+
+#[test]
+fn test_view_create_and_select() {
+    let mut db = numbers("view-create");
+    db.ok("create view v as select id from a where id > 1");
+    assert_eq!(db.ok("select * from v"), vec![vec![int(2)], vec![int(3)]]);
+}
+
+#[test]
+fn test_view_exists() {
+    let mut db = numbers("view-exists");
+    db.ok("create view v as select id from a");
+    match db.run("create view v as select id from b") {
+        Err(ExecutionError::ViewExists) => (),
+        other => panic!("{:?}", other),
+    }
+    // a view can not shadow a table
+    match db.run("create view a as select id from b") {
+        Err(ExecutionError::TableExists) => (),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn test_table_named_like_view() {
+    let mut db = numbers("table-like-view");
+    db.ok("create view v as select id from a");
+    // the table would take the inserts, the view would answer the selects
+    match db.run("create table v (id int)") {
+        Err(ExecutionError::TableExists) => (),
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(ids(db.ok("select id from v")), vec![1, 2, 3]);
+}
+
+#[test]
+fn test_view_replace() {
+    let mut db = numbers("view-replace");
+    db.ok("create view v as select id from a where id = 1");
+    db.ok("create or replace view v as select id from b where id = 4");
+    assert_eq!(db.ok("select * from v"), vec![vec![int(4)]]);
+}
+
+#[test]
+fn test_view_drop() {
+    let mut db = numbers("view-drop");
+    db.ok("create view v as select id from a");
+    db.ok("drop view v");
+    assert!(db.run("select * from v").is_err());
+    db.ok("create view v as select id from b where id = 2");
+    assert_eq!(db.ok("select * from v"), vec![vec![int(2)]]);
+}
+
+#[test]
+fn test_view_of_view() {
+    let mut db = numbers("view-of-view");
+    db.ok("create view v as select id from a where id > 1");
+    db.ok("create view w as select id from v where id < 3");
+    assert_eq!(db.ok("select * from w"), vec![vec![int(2)]]);
+}
+
+#[test]
+fn test_view_derived_table_shadows_view() {
+    let mut db = numbers("view-shadow");
+    db.ok("create view v as select id from a");
+    // the derived table named like the view is no reference to the view
+    db.ok("create or replace view v as select id from (select id from b) v");
+    assert_eq!(db.ok("select * from v").len(), 3);
+}
+
+#[test]
+fn test_view_direct_cycle() {
+    let mut db = numbers("view-direct-cycle");
+    match db.run("create view v as select * from v") {
+        Err(ExecutionError::RecursiveView) => (),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn test_view_indirect_cycle() {
+    let mut db = numbers("view-indirect-cycle");
+    db.ok("create view v as select id from a");
+    db.ok("create view w as select id from v");
+    match db.run("create or replace view v as select id from w") {
+        Err(ExecutionError::RecursiveView) => (),
+        other => panic!("{:?}", other),
+    }
+    // the old definition is kept
+    assert_eq!(db.ok("select * from w").len(), 3);
+}
+
+#[test]
+fn test_view_cycle_through_subqueries() {
+    let mut db = numbers("view-subquery-cycle");
+    db.ok("create view v as select id from a");
+    db.ok("create view w as select id from b where id in (select id from v)");
+    let sql = "create or replace view v as select id from a where exists (select id from w)";
+    match db.run(sql) {
+        Err(ExecutionError::RecursiveView) => (),
+        other => panic!("{:?}", other),
+    }
+    db.ok("create view x as select id from a union select id from w");
+    match db.run("create or replace view v as select id from (select id from x) d") {
+        Err(ExecutionError::RecursiveView) => (),
+        other => panic!("{:?}", other),
+    }
+}
+
+// ============================================================================
+// Correlated subqueries
+// ============================================================================
+
+fn ids(rows: Vec<Vec<Lit>>) -> Vec<i64> {
+    rows.into_iter().map(|row| match row[0] {
+        Lit::Int(i) => i,
+        ref other => panic!("{:?}", other),
+    }).collect()
+}
+
+#[test]
+fn test_correlated_leaf() {
+    let mut db = numbers("correlated-leaf");
+    let rows = db.ok("select id from a where exists (select id from b where b.id = a.id)");
+    assert_eq!(ids(rows), vec![2, 3]);
+}
+
+#[test]
+fn test_correlated_between_outer_bounds() {
+    let mut db = numbers("correlated-between-bounds");
+    let rows = db.ok("select id from b where exists \
+        (select id from a where a.id = b.id and a.id between b.low and b.high)");
+    assert_eq!(ids(rows), vec![2]);
+}
+
+#[test]
+fn test_correlated_between_outer_column() {
+    let mut db = numbers("correlated-between-column");
+    let rows = db.ok("select id from a where exists \
+        (select id from b where b.id = 2 and a.id between b.low and b.high)");
+    assert_eq!(ids(rows), vec![1, 2]);
+    let rows = db.ok("select id from a where exists (select id from b where a.id between 2 and 3)");
+    assert_eq!(ids(rows), vec![2, 3]);
+}
+
+#[test]
+fn test_correlated_in_list() {
+    let mut db = numbers("correlated-in");
+    let rows = db.ok("select id from a where exists \
+        (select id from b where b.id = a.id and a.id not in (3, 4))");
+    assert_eq!(ids(rows), vec![2]);
+}
+
+#[test]
+fn test_correlated_like() {
+    let mut db = numbers("correlated-like");
+    let rows = db.ok("select id from a where exists \
+        (select id from b where b.low < a.id and a.name like 't%')");
+    assert_eq!(ids(rows), vec![2, 3]);
+}
+
+#[test]
+fn test_correlated_nested() {
+    let mut db = numbers("correlated-nested");
+    db.ok("create table c (id int)");
+    db.ok("insert into c values (2)");
+    db.ok("insert into c values (3)");
+    let rows = db.ok("select id from a where exists \
+        (select id from b where b.id in (select id from c where c.id = a.id))");
+    assert_eq!(ids(rows), vec![2, 3]);
+    let rows = db.ok("select id from a where exists \
+        (select id from b where b.id = (select id from c where c.id = a.id))");
+    assert_eq!(ids(rows), vec![2, 3]);
+    let rows = db.ok("select id from a where exists \
+        (select id from b where exists (select id from c where c.id = a.id and c.id = b.id))");
+    assert_eq!(ids(rows), vec![2, 3]);
+}
+
+#[test]
+fn test_correlated_compound() {
+    let mut db = numbers("correlated-compound");
+    db.ok("create table c (id int)");
+    db.ok("insert into c values (1)");
+    let rows = db.ok("select id from a where exists \
+        (select id from b where b.id = a.id union select id from c where c.id = a.id)");
+    assert_eq!(ids(rows), vec![1, 2, 3]);
+}
+
+#[test]
+fn test_correlated_target() {
+    let mut db = numbers("correlated-target");
+    let rows = db.ok("select id from a where id in (select a.id from b where b.id = 4)");
+    assert_eq!(ids(rows), vec![1, 2, 3]);
+    // the type of a scalar subquery is found with the outer columns in scope
+    let rows = db.ok("select id, (select a.id from b where b.id = 4) from a");
+    assert_eq!(pairs(rows), vec![(1, 1), (2, 2), (3, 3)]);
+    let rows = db.ok("select id, (select low from b where b.id = a.id) from a where id > 1");
+    assert_eq!(pairs(rows), vec![(2, 1), (3, 5)]);
+}
+
+// ============================================================================
+// LIKE
+// ============================================================================
+
+fn like(pattern: &str, value: &str) -> bool {
+    like_match(&pattern.chars().collect::<Vec<_>>(), &value.chars().collect::<Vec<_>>())
+}
+
+#[test]
+fn test_like_match() {
+    assert!(like("", ""));
+    assert!(!like("", "a"));
+    assert!(like("%", ""));
+    assert!(like("abc", "abc"));
+    assert!(!like("abc", "abcd"));
+    assert!(like("a_c", "abc"));
+    assert!(!like("a_c", "ac"));
+    assert!(like("%c", "abc"));
+    assert!(like("a%", "abc"));
+    assert!(like("%b%", "abc"));
+    assert!(like("a%%c", "ac"));
+    assert!(like("%a%b", "aab"));
+    assert!(like("%ab%ab", "abxabab"));
+    assert!(!like("%ab%ab", "abxab_"));
+    assert!(like("_%_", "ab"));
+    assert!(!like("_%_", "a"));
+}
+
+#[test]
+fn test_like_many_percent_signs() {
+    // took exponential time when every % tried every split
+    let value: String = (0..2000).map(|_| 'a').collect();
+    assert!(!like("%a%a%a%a%a%a%a%a%a%a%b", &value));
+    assert!(like("%a%a%a%a%a%a%a%a%a%a%", &value));
+
+    let mut db = TestDb::new("like-percent");
+    db.ok("create table t (id int, name char(64))");
+    db.ok(&format!("insert into t values (1, '{}')", &value[..64]));
+    let rows = db.ok("select id from t where name like '%a%a%a%a%a%a%a%a%a%a%a%a%b'");
+    assert!(rows.is_empty());
+    let rows = db.ok("select id from t where name ilike '%A%A%A%A%A%A%A%A%A%A%A%A'");
+    assert_eq!(ids(rows), vec![1]);
+}
+
+// ============================================================================
+// Set operations
+// ============================================================================
+
+fn sorted(mut values: Vec<i64>) -> Vec<i64> {
+    values.sort();
+    values
+}
+
+#[test]
+fn test_set_operations_spill() {
+    let mut db = TestDb::new("set-spill");
+    db.ok("create table l (id int)");
+    db.ok("create table r (id int)");
+    // 0 to 9 four times each on the left, 3 to 7 four times each on the right
+    for i in 0..40 {
+        db.ok(&format!("insert into l values ({})", i % 10));
+    }
+    for i in 0..20 {
+        db.ok(&format!("insert into r values ({})", i % 5 + 3));
+    }
+    // only a few rows fit, the sorts of both sides write runs
+    db.session.settings.memory_limit = 256;
+
+    let rows = db.ok("select id from l union select id from r");
+    assert_eq!(ids(rows), (0..10).collect::<Vec<_>>());
+    let rows = db.ok("select id from l union all select id from r");
+    assert_eq!(rows.len(), 60);
+    let rows = db.ok("select id from l intersect select id from r");
+    assert_eq!(ids(rows), vec![3, 4, 5, 6, 7]);
+    let rows = db.ok("select id from l intersect all select id from r where id < 5");
+    assert_eq!(sorted(ids(rows)), vec![3, 3, 3, 3, 4, 4, 4, 4]);
+    let rows = db.ok("select id from l except select id from r");
+    assert_eq!(ids(rows), vec![0, 1, 2, 8, 9]);
+    let rows = db.ok("select id from l except all select id from r where id > 6");
+    assert_eq!(rows.len(), 36);
+}
+
+// ============================================================================
+// Cancellation
+// ============================================================================
+
+#[test]
+fn test_cancel_before_statement() {
+    let mut db = numbers("cancel-early");
+    // the request arrives before the statement starts
+    db.session.cancel_flag().store(true, Ordering::SeqCst);
+    match db.run("select id from a") {
+        Err(ExecutionError::Cancelled) => (),
+        other => panic!("{:?}", other),
+    }
+    // it is used up by the cancelled statement
+    assert_eq!(ids(db.ok("select id from a")), vec![1, 2, 3]);
+}
+
+// ============================================================================
+// Parameters
+// ============================================================================
+
+#[test]
+fn test_unbound_parameter() {
+    let mut db = numbers("unbound-param");
+    match db.run("select id from a where id = ?") {
+        Err(ExecutionError::UnboundParameter) => (),
+        other => panic!("{:?}", other),
+    }
+    match db.run("create view v as select id from a where id = $1") {
+        Err(ExecutionError::UnboundParameter) => (),
+        other => panic!("{:?}", other),
+    }
+    assert!(db.run("select id from v").is_err());
+}
+
+#[test]
+fn test_prepared_parameters() {
+    let mut db = numbers("prepared-param");
+    let query = parse::parse("select id from a where id > $2 and name = $3").unwrap();
+    match parameter_types(&query, &mut db.user, &mut db.session) {
+        Err(ExecutionError::MissingParameter(1)) => (),
+        other => panic!("{:?}", other),
+    }
+
+    let mut query = parse::parse("select id from a where id > ?").unwrap();
+    assert_eq!(parameter_types(&query, &mut db.user, &mut db.session).unwrap().len(), 1);
+    bind(&mut query, &[Lit::Int(1)]).unwrap();
+    assert!(execute_from_ast(query, &mut db.user, &mut db.session).is_ok());
+}
+
+// ============================================================================
+// Privileges
+// ============================================================================
+
+#[test]
+fn test_privileges_without_database() {
+    let mut db = numbers("no-database");
+    db.user._currentDatabase = None;
+    for sql in &["select id from a", "insert into a values (4, 'four')", "drop table a"] {
+        match db.run(sql) {
+            Err(ExecutionError::NoDatabaseSelected) => (),
+            other => panic!("{}: {:?}", sql, other),
+        }
+    }
+}
+
+// ============================================================================
+// Index access
+// ============================================================================
+
+/// The operators of the plan, one per line
+fn plan_text(db: &mut TestDb, sql: &str) -> String {
+    let rows = db.ok(&format!("explain {}", sql));
+    let lines: Vec<String> = rows.into_iter().map(|row| match row[0] {
+        Lit::String(ref s) => s.clone(),
+        ref other => panic!("{:?}", other),
+    }).collect();
+    lines.join("\n")
+}
+
+#[test]
+fn test_index_lookup_matches_scans() {
+    let mut db = TestDb::new("index-access");
+    // `id` has the index, `copy` holds the same values without one
+    db.ok("create table k (id int primary key, copy int)");
+    for i in 0..20 {
+        db.ok(&format!("insert into k values ({}, {})", i, i));
+    }
+    // the index keeps entries of deleted rows, inserting the key again
+    // replaces them
+    for i in &[0, 3, 7, 12, 19] {
+        db.ok(&format!("delete from k where id = {}", i));
+    }
+    for i in &[0, 7, 12] {
+        db.ok(&format!("insert into k values ({}, {})", i, i));
+    }
+    let present: Vec<i64> = (0..20).filter(|i| *i != 3 && *i != 19).collect();
+
+    let ops: [(&str, fn(i64, i64) -> bool); 6] = [
+        ("=", |a, b| a == b),
+        ("<", |a, b| a < b),
+        ("<=", |a, b| a <= b),
+        (">", |a, b| a > b),
+        (">=", |a, b| a >= b),
+        ("<>", |a, b| a != b),
+    ];
+    for &(op, compare) in &ops {
+        for value in &[-1, 0, 3, 7, 10, 12, 19, 20] {
+            let expected: Vec<i64> =
+                present.iter().cloned().filter(|&i| compare(i, *value)).collect();
+            let index = format!("select id from k where id {} {}", op, value);
+            let lookup = format!("select id from k where copy {} {}", op, value);
+            // a disjunction can not be handed to the engine
+            let full = format!("select id from k where id {} {} or id {} {}",
+                op, value, op, value);
+
+            let plan = plan_text(&mut db, &index);
+            assert!(plan.contains(if op == "<>" { "Engine Lookup" } else { "Index" }), "{}", plan);
+            assert!(plan_text(&mut db, &lookup).contains("Engine Lookup"));
+            assert!(plan_text(&mut db, &full).contains("Seq Scan"));
+
+            assert_eq!(sorted(ids(db.ok(&index))), expected, "{}", index);
+            assert_eq!(sorted(ids(db.ok(&lookup))), expected, "{}", lookup);
+            assert_eq!(sorted(ids(db.ok(&full))), expected, "{}", full);
+        }
+    }
+}
+
+// ============================================================================
+// Operators
+// ============================================================================
+
+/// The first two columns of every row, sorted
+fn pairs(rows: Vec<Vec<Lit>>) -> Vec<(i64, i64)> {
+    let mut pairs: Vec<(i64, i64)> = rows.into_iter().map(|row| match (&row[0], &row[1]) {
+        (&Lit::Int(a), &Lit::Int(b)) => (a, b),
+        other => panic!("{:?}", other),
+    }).collect();
+    pairs.sort();
+    pairs
+}
+
+#[test]
+fn test_join() {
+    let mut db = numbers("join");
+    assert_eq!(db.ok("select a.id from a, b").len(), 9);
+
+    let sql = "select a.id, b.low from a, b where a.id = b.id";
+    assert!(plan_text(&mut db, sql).contains("Hash Join"));
+    assert_eq!(pairs(db.ok(sql)), vec![(2, 1), (3, 5)]);
+    let rows = db.ok("select a.id, b.low from a, b where a.id = b.id and b.low > 1");
+    assert_eq!(pairs(rows), vec![(3, 5)]);
+
+    let sql = "select a.id, b.id from a, b where a.id > b.low and a.id <= b.high";
+    assert!(plan_text(&mut db, sql).contains("Nested Loop Join"));
+    assert_eq!(pairs(db.ok(sql)), vec![(1, 4), (2, 2), (2, 4), (3, 4)]);
+}
+
+#[test]
+fn test_order_by() {
+    let mut db = numbers("order-by");
+    assert_eq!(ids(db.ok("select id from a order by id desc")), vec![3, 2, 1]);
+    assert_eq!(ids(db.ok("select id from a order by name")), vec![1, 3, 2]);
+    // ties are broken by the following keys
+    let rows = db.ok("select id, high from b order by high desc, id desc");
+    assert_eq!(ids(rows), vec![4, 3, 2]);
+}
+
+#[test]
+fn test_limit_offset() {
+    let mut db = numbers("limit");
+    assert_eq!(ids(db.ok("select id from a order by id limit 2")), vec![1, 2]);
+    assert_eq!(ids(db.ok("select id from a order by id limit 1, 1")), vec![2]);
+    assert_eq!(ids(db.ok("select id from a order by id desc limit 1, 5")), vec![2, 1]);
+    assert!(db.ok("select id from a order by id limit 0").is_empty());
+    assert!(db.ok("select id from a order by id limit 5, 2").is_empty());
+    let rows = db.ok("select id, high from b order by high desc, id limit 1, 2");
+    assert_eq!(ids(rows), vec![4, 2]);
+}
+
+#[test]
+fn test_distinct() {
+    let mut db = numbers("distinct");
+    assert_eq!(ids(db.ok("select distinct high from b order by high")), vec![2, 9]);
+    assert_eq!(db.ok("select high from b").len(), 3);
+    let rows = db.ok("select distinct a.id from a, b where a.id < b.high order by id");
+    assert_eq!(ids(rows), vec![1, 2, 3]);
+    let rows = db.ok("select distinct high from b order by high desc limit 1");
+    assert_eq!(ids(rows), vec![9]);
+}
+
+// ============================================================================
+// Spilling
+// ============================================================================
+
+/// `l` with the ids 0 to 59 twice and `r` with the ids 0 to 29 twice
+fn spill_tables(name: &str) -> TestDb {
+    let mut db = TestDb::new(name);
+    db.ok("create table l (id int, grp int)");
+    db.ok("create table r (id int, val int)");
+    for i in 0..120 {
+        db.ok(&format!("insert into l values ({}, {})", i % 60, i % 60 % 7));
+    }
+    for i in 0..60 {
+        db.ok(&format!("insert into r values ({}, {})", i % 30, i));
+    }
+    db
+}
+
+/// Runs the select with the default budget and again with room for a few
+/// rows only. The temporary folder is only created by spilling operators.
+fn in_memory_and_spilled(db: &mut TestDb, sql: &str) -> (Vec<Vec<Lit>>, Vec<Vec<Lit>>) {
+    let tmp = db.dir.join("tmp");
+    let in_memory = db.ok(sql);
+    assert!(!tmp.exists(), "{}", sql);
+    db.session.settings.memory_limit = 256;
+    let spilled = db.ok(sql);
+    assert!(tmp.exists(), "{}", sql);
+    // the temporary files are removed with the operators
+    assert_eq!(fs::read_dir(&tmp).unwrap().count(), 0);
+    (in_memory, spilled)
+}
+
+#[test]
+fn test_hash_join_spill() {
+    let mut db = spill_tables("join-spill");
+    let sql = "select l.id, r.val from l, r where l.id = r.id";
+    assert!(plan_text(&mut db, sql).contains("Hash Join"));
+    let (in_memory, spilled) = in_memory_and_spilled(&mut db, sql);
+    assert_eq!(in_memory.len(), 120);
+    assert_eq!(pairs(spilled), pairs(in_memory));
+}
+
+#[test]
+fn test_distinct_spill() {
+    let mut db = spill_tables("distinct-spill");
+    let (in_memory, spilled) = in_memory_and_spilled(&mut db, "select distinct id, grp from l");
+    assert_eq!(in_memory.len(), 60);
+    assert_eq!(pairs(spilled), pairs(in_memory));
+}
+
+#[test]
+fn test_sort_spill() {
+    let mut db = spill_tables("sort-spill");
+    let sql = "select id, grp from l order by grp, id desc";
+    let (in_memory, spilled) = in_memory_and_spilled(&mut db, sql);
+    assert_eq!(in_memory.len(), 120);
+    assert_eq!(spilled, in_memory);
+}
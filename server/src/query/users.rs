@@ -0,0 +1,126 @@
+//! Statements for accounts, roles and privileges
+//!
+//! Only admins manage the accounts, roles and privileges, but every user may
+//! change the own password and use the roles it is a member of. The admin
+//! account created on the first start can not be dropped, so there is
+//! always someone to manage the server.
+//!
+
+use super::{Executor, ExecutionError, generate_rows_dummy};
+use super::super::auth::{self, ADMIN};
+use super::super::parse::ast::{UserStmt, GrantStmt, RoleGrantStmt, PrivilegeLevel};
+use super::super::storage::Rows;
+use std::io::Cursor;
+
+impl<'a> Executor<'a> {
+    pub fn execute_create_user_stmt(&mut self, stmt: UserStmt)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        try!(self.require_admin());
+        try!(auth::lock(&self.user.users).create_user(&stmt.name, &stmt.password));
+        Ok(generate_rows_dummy())
+    }
+
+    pub fn execute_alter_user_stmt(&mut self, stmt: UserStmt)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        if stmt.name != self.user.name {
+            try!(self.require_admin());
+        }
+        try!(auth::lock(&self.user.users).set_password(&stmt.name, &stmt.password));
+        Ok(generate_rows_dummy())
+    }
+
+    pub fn execute_drop_user_stmt(&mut self, name: String)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        try!(self.require_admin());
+        if name == ADMIN {
+            return Err(ExecutionError::PermissionDenied)
+        }
+        try!(auth::lock(&self.user.users).drop_user(&name));
+        Ok(generate_rows_dummy())
+    }
+
+    pub fn execute_grant_stmt(&mut self, stmt: GrantStmt)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        try!(self.require_admin());
+        let (database, table) = try!(self.privilege_level(stmt.on));
+        try!(auth::lock(&self.user.users).grant(&stmt.user, &database,
+            table.as_ref().map(|t| &t[..]), &stmt.privileges));
+        Ok(generate_rows_dummy())
+    }
+
+    pub fn execute_revoke_stmt(&mut self, stmt: GrantStmt)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        try!(self.require_admin());
+        let (database, table) = try!(self.privilege_level(stmt.on));
+        try!(auth::lock(&self.user.users).revoke(&stmt.user, &database,
+            table.as_ref().map(|t| &t[..]), &stmt.privileges));
+        Ok(generate_rows_dummy())
+    }
+
+    pub fn execute_create_role_stmt(&mut self, name: String)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        try!(self.require_admin());
+        try!(auth::lock(&self.user.users).create_role(&name));
+        Ok(generate_rows_dummy())
+    }
+
+    pub fn execute_drop_role_stmt(&mut self, name: String)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        try!(self.require_admin());
+        try!(auth::lock(&self.user.users).drop_role(&name));
+        Ok(generate_rows_dummy())
+    }
+
+    pub fn execute_grant_role_stmt(&mut self, stmt: RoleGrantStmt)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        try!(self.require_admin());
+        try!(auth::lock(&self.user.users).grant_role(&stmt.role, &stmt.member));
+        Ok(generate_rows_dummy())
+    }
+
+    pub fn execute_revoke_role_stmt(&mut self, stmt: RoleGrantStmt)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        try!(self.require_admin());
+        try!(auth::lock(&self.user.users).revoke_role(&stmt.role, &stmt.member));
+        Ok(generate_rows_dummy())
+    }
+
+    /// Uses the privileges of the role from now on. Admins may use any role,
+    /// other users only the roles they are members of.
+    pub fn execute_set_role_stmt(&mut self, role: Option<String>)
+        -> Result<Rows<Cursor<Vec<u8>>>, ExecutionError>
+    {
+        if let Some(ref role) = role {
+            let users = auth::lock(&self.user.users);
+            if !users.role_exists(role) {
+                return Err(auth::AuthError::RoleNotFound.into())
+            }
+            if !users.is_admin(&self.user.name) && !users.is_member(&self.user.name, role) {
+                return Err(auth::AuthError::NotAMember.into())
+            }
+        }
+        self.user.role = role;
+        Ok(generate_rows_dummy())
+    }
+
+    /// Returns the database and the table of a GRANT or REVOKE
+    fn privilege_level(&self, on: PrivilegeLevel)
+        -> Result<(String, Option<String>), ExecutionError>
+    {
+        match on {
+            PrivilegeLevel::Database(database) => Ok((database, None)),
+            PrivilegeLevel::Table(Some(database), table) => Ok((database, Some(table))),
+            PrivilegeLevel::Table(None, table) =>
+                Ok((try!(self.get_own_database()).name.clone(), Some(table))),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Stopping the server
+//!
+//! SIGTERM, SIGINT and the `Shutdown` command of an admin stop the server
+//! the same way: the listeners stop accepting connections and idle
+//! connections are closed, while running statements may finish. Statements
+//! still running after the grace period are cancelled. At last the written
+//! data is flushed to the disk and `listen` returns.
+//!
+
+use auth;
+use storage;
+use libc;
+use std::sync::atomic::{AtomicBool, ATOMIC_BOOL_INIT, Ordering};
+
+static REQUESTED: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// Asks the server to stop
+pub fn request() {
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Checks if the server was asked to stop
+pub fn is_requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Stops the server on SIGTERM and SIGINT instead of killing it
+pub fn catch_signals() {
+    unsafe {
+        libc::signal(libc::SIGTERM, on_signal as libc::sighandler_t);
+        libc::signal(libc::SIGINT, on_signal as libc::sighandler_t);
+    }
+}
+
+// storing to an atomic is all a signal handler may safely do here
+extern "C" fn on_signal(_: libc::c_int) {
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Writes the files the storage engine wrote and the user catalog to the
+/// disk
+pub fn sync(users: &auth::Users) {
+    if let Err(e) = storage::sync_written() {
+        error!("Could not write the tables to the disk: {}", e);
+    }
+    if let Err(e) = auth::lock(users).sync() {
+        error!("Could not write the user catalog to the disk: {}", e);
+    }
+}
@@ -9,6 +9,7 @@ use std::cmp::Ordering;
 use std::marker::PhantomData;
 use std::fmt::Debug;
 use std::iter::Iterator;
+use super::written::mark_written;
 
 
 pub trait KnownSize {
@@ -68,6 +69,8 @@ impl<T: KnownSize + PartialOrd + Clone + Debug> Bstar<T> {
 
     /// Loads a Bstar object from the specified name/path
     pub fn load(name: &str) -> Result<Bstar<T>>{
+        mark_written(format!("{}.{}", name, "bsdat"));
+        mark_written(format!("{}.{}", name, "bsmet"));
 
         let mut _file = OpenOptions::new()
         .read(true)
@@ -127,6 +130,8 @@ impl<T: KnownSize + PartialOrd + Clone + Debug> Bstar<T> {
     /// order*2 specifies the maximal amount of keys stored per node in the tree
     pub fn create(name: &str, target: &str, order: u64) -> Result<Bstar<T>> {
         let allowduplicates = false;
+        mark_written(format!("{}.{}", name, "bsdat"));
+        mark_written(format!("{}.{}", name, "bsmet"));
         let mut _file = OpenOptions::new()
         .read(true)
         .write(true)
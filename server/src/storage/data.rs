@@ -85,6 +85,23 @@ impl<B: Write + Read + Seek> Rows <B> {
         Ok(())
     }
 
+    /// reads the row starting at pos and writes the data into target_buf
+    /// returns false if the row is marked as deleted, nothing is written then.
+    /// Returns Error:EndOfFile if there is no row at pos.
+    pub fn row_at<W: Write>(&mut self, pos: u64, mut target_buf: &mut W)
+        -> Result<bool, Error>
+    {
+        try!(self.set_pos(SeekFrom::Start(pos)));
+        if try!(self.is_next_row_deleted(false)) {
+            return Ok(false)
+        }
+        let mut target_vec = Vec::<u8>::new();
+        let columns_size = self.columns_size;
+        try!(self.read_bytes(columns_size, &mut target_vec));
+        try!(target_buf.write_all(&target_vec));
+        Ok(true)
+    }
+
     /// reads the next row, which is not marked as deleted
     /// and writes the data into target_buf
     /// returns the bytes read or an Error otherwise.
@@ -127,7 +144,7 @@ impl<B: Write + Read + Seek> Rows <B> {
 
     /// Returns the size of a row including the size of the
     /// row header.
-    fn get_row_size(&self) -> u64 {
+    pub fn get_row_size(&self) -> u64 {
         self.columns_size + RowHeader::size()
     }
 
@@ -0,0 +1,196 @@
+use super::super::meta::{Table};
+use super::super::{Engine, Error};
+use super::super::bstar::{Bstar, KeyAddr, IterDirection, IterOption};
+use super::super::types::SqlType;
+use super::super::data::{Rows};
+use super::super::super::parse::ast::CompType;
+use super::flatfile::FlatFile;
+use std::fs::{self, File};
+use std::io::Cursor;
+use byteorder::{BigEndian, ReadBytesExt};
+
+/// half the number of keys stored per node of the index
+const INDEX_ORDER: u64 = 32;
+
+//---------------------------------------------------------------
+// BStar-Engine
+//---------------------------------------------------------------
+
+/// Stores the rows like the FlatFile engine and keeps a Bstar index on the
+/// primary key, if the table has a single Int primary key column.
+/// The index maps the key to the position of the row in the data file.
+pub struct BStar<'a> {
+    flatfile: FlatFile<'a>,
+}
+
+impl<'a> BStar<'a> {
+    ///
+    pub fn new<'b>(table: Table<'b>) -> BStar<'b> {
+        info!("new bstar with table: {:?}", table);
+        BStar { flatfile: FlatFile::new(table) }
+    }
+
+    /// Returns the index of the indexed column
+    fn key_column(&self) -> Option<usize> {
+        let columns = self.flatfile.table().columns();
+        let mut keys = columns.iter().enumerate().filter(|&(_, c)| c.is_primary_key);
+        match (keys.next(), keys.next()) {
+            (Some((index, column)), None) if column.sql_type == SqlType::Int => Some(index),
+            _ => None,
+        }
+    }
+
+    fn open_index(&self) -> Result<Bstar<u64>, Error> {
+        Ok(try!(Bstar::load(&self.flatfile.table().get_index_path())))
+    }
+
+    /// Creates an empty index and adds every row of the data file
+    fn rebuild_index(&self) -> Result<(), Error> {
+        let column = match self.key_column() {
+            Some(column) => column,
+            None => return Ok(()),
+        };
+        let table = self.flatfile.table();
+        let mut tree = try!(Bstar::<u64>::create(&table.get_index_path(),
+                                                 &table.get_table_data_path(),
+                                                 INDEX_ORDER));
+        let mut reader = try!(self.flatfile.get_reader());
+        let row_size = reader.get_row_size();
+        let mut pos = 0;
+        loop {
+            let mut row = Vec::<u8>::new();
+            match reader.row_at(pos, &mut row) {
+                Ok(true) => {
+                    let key = try!(index_key(&try!(reader.get_value(&row, column))));
+                    try!(tree.insert_keyaddr(KeyAddr::new(key, pos)));
+                },
+                Ok(false) => {},
+                Err(Error::EndOfFile) => break,
+                Err(e) => return Err(e),
+            }
+            pos += row_size;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Drop for BStar<'a> {
+    /// drops the BStar
+    fn drop(&mut self) {
+        info!("drop engine bstar");
+    }
+}
+
+impl<'a> Engine for BStar<'a> {
+    /// creates the table.dat file and the empty index
+    fn create_table(&mut self) -> Result<(), Error> {
+        try!(self.flatfile.create_table());
+        self.rebuild_index()
+    }
+
+    /// returns own table
+    fn table(&self) -> &Table {
+        self.flatfile.table()
+    }
+
+    fn full_scan(&self) -> Result<Rows<Cursor<Vec<u8>>>, Error> {
+        self.flatfile.full_scan()
+    }
+
+    fn reader(&self) -> Result<Rows<File>, Error> {
+        self.flatfile.get_reader()
+    }
+
+    fn lookup(&self, column_index: usize, value: (&[u8], Option<usize>), comp: CompType)
+    -> Result<Rows<Cursor<Vec<u8>>>, Error>
+    {
+        self.flatfile.lookup(column_index, value, comp)
+    }
+
+    /// Inserts the row at the end of the data file and adds it to the index.
+    /// An entry for a deleted row with the same key is replaced.
+    fn insert_row(&mut self, row_data: &[u8]) -> Result<u64, Error> {
+        let pos = try!(fs::metadata(self.flatfile.table().get_table_data_path())).len();
+        let inserted = try!(self.flatfile.insert_row(row_data));
+        if let Some(column) = self.key_column() {
+            let reader = try!(self.flatfile.get_reader());
+            let key = try!(index_key(&try!(reader.get_value(row_data, column))));
+            let mut tree = try!(self.open_index());
+            try!(tree.delete_keyaddr(key));
+            try!(tree.insert_keyaddr(KeyAddr::new(key, pos)));
+        }
+        Ok(inserted)
+    }
+
+    /// deleted rows keep their index entries, index lookups skip them
+    fn delete(&self, column_index: usize, value: (&[u8], Option<usize>), comp: CompType)
+    -> Result<u64, Error>
+    {
+        self.flatfile.delete(column_index, value, comp)
+    }
+
+    /// the primary key can not be modified, so the index stays valid
+    fn modify(&mut self, constraint_column_index: usize,
+    constraint_value: (&[u8], Option<usize>), comp: CompType,
+    values: &[(usize, &[u8])] )-> Result<u64, Error>
+    {
+        self.flatfile.modify(constraint_column_index, constraint_value, comp, values)
+    }
+
+    /// moves rows, so the index is built again
+    fn reorganize(&mut self) -> Result<(), Error> {
+        try!(self.flatfile.reorganize());
+        self.rebuild_index()
+    }
+
+    fn reset(&mut self) -> Result<(), Error> {
+        try!(self.flatfile.reset());
+        self.rebuild_index()
+    }
+
+    fn has_index(&self, column_index: usize) -> bool {
+        self.key_column() == Some(column_index)
+    }
+
+    /// Finds the positions of the matching rows in the index, comparisons
+    /// other than equality are range scans. `NEqu` reads the whole table.
+    fn index_lookup(&self, column_index: usize, value: &[u8], comp: CompType)
+    -> Result<Rows<Cursor<Vec<u8>>>, Error>
+    {
+        if !self.has_index(column_index) || comp == CompType::NEqu {
+            return self.lookup(column_index, (value, None), comp)
+        }
+        let key = try!(index_key(value));
+        let mut tree = try!(self.open_index());
+        let positions: Vec<u64> = match comp {
+            CompType::Equ => try!(tree.lookup_keyaddr(key)).into_iter()
+                .map(|k| k.addr).collect(),
+            CompType::GThan => tree.iter_options(IterDirection::Forward,
+                Some(IterOption::Excluding(key))).map(|k| k.addr).collect(),
+            CompType::GEThan => tree.iter_options(IterDirection::Forward,
+                Some(IterOption::Including(key))).map(|k| k.addr).collect(),
+            CompType::SThan => tree.iter_options(IterDirection::Backward,
+                Some(IterOption::Excluding(key))).map(|k| k.addr).collect(),
+            CompType::SEThan => tree.iter_options(IterDirection::Backward,
+                Some(IterOption::Including(key))).map(|k| k.addr).collect(),
+            CompType::NEqu => Vec::new(),
+        };
+
+        let mut reader = try!(self.flatfile.get_reader());
+        let mut rows = Rows::new(Cursor::new(Vec::<u8>::new()), self.table().columns());
+        for pos in positions {
+            let mut row = Vec::<u8>::new();
+            if try!(reader.row_at(pos, &mut row)) {
+                try!(rows.add_row(&row));
+            }
+        }
+        try!(rows.reset_pos());
+        Ok(rows)
+    }
+}
+
+/// Turns an encoded Int into a key with the same order
+fn index_key(mut value: &[u8]) -> Result<u64, Error> {
+    let value = try!(value.read_i32::<BigEndian>());
+    Ok((value as i64 - i32::min_value() as i64) as u64)
+}
@@ -1,5 +1,5 @@
 use super::super::meta::{Table};
-use super::super::{Engine, Error};
+use super::super::{Engine, Error, mark_written};
 use std::fs::{OpenOptions, File};
 use std::io::{Write, Read, Cursor};
 use super::super::super::parse::ast::CompType;
@@ -22,6 +22,7 @@ impl<'a> FlatFile<'a> {
     /// Opens table data file with read write access.
     fn open_file_rw(&self) -> Result<File, Error> {
         info!("Trying to open file: {}", &self.table.get_table_data_path());
+        mark_written(self.table.get_table_data_path());
         let file = try!(OpenOptions::new()
             .read(true)
             .write(true)
@@ -48,6 +49,7 @@ impl<'a> Engine for FlatFile<'a> {
     /// creates table for use later
     /// returns with error when it has either no permission or full disk
     fn create_table(&mut self) -> Result<(), Error> {
+        mark_written(self.table.get_table_data_path());
         let mut _file = try!(OpenOptions::new()
             .write(true)
             .create(true)
@@ -68,6 +70,10 @@ impl<'a> Engine for FlatFile<'a> {
         reader.full_scan()
     }
 
+    fn reader(&self) -> Result<Rows<File>, Error> {
+        self.get_reader()
+    }
+
     /// returns an new Rows object which fulfills a constraint
     fn lookup(&self, column_index: usize, value: (&[u8], Option<usize>), comp: CompType)
     -> Result<Rows<Cursor<Vec<u8>>>, Error>
@@ -1,3 +1,5 @@
 mod flatfile;
+mod bstar;
 
 pub use self::flatfile::FlatFile;
+pub use self::bstar::BStar;
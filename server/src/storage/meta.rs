@@ -12,9 +12,14 @@ use super::SqlType;
 
 use super::Engine;
 use super::Error;
-use super::engine::FlatFile;
+use super::engine::{FlatFile, BStar};
+use super::bstar::Bstar;
 use super::types::Column;
+use super::data::RowHeader;
+use super::written::mark_written;
 use super::EngineID;
+use super::super::parse::ast::SelectStmt;
+use super::super::parse::token::Lit;
 
 /// constants
 const MAGIC_NUMBER: u64 = 0x49616D4372616E43;
@@ -71,6 +76,7 @@ impl Database {
     fn save(&self) -> Result<(), Error> {
         info!("trying to create dir!");
         try!(create_dir(&self.name));
+        mark_written(&self.name);
         info!("created dir");
         Ok(())
     }
@@ -97,6 +103,44 @@ impl Database {
     pub fn load_table(&self, name: &str) -> Result<Table, Error> {
         Table::load(&self, name)
     }
+
+    /// Saves a new view in the DB folder, an existing view with the same
+    /// name is overwritten
+    /// Returns with Error on fail else View
+    pub fn create_view(&self, name: &str, query: SelectStmt)
+        -> Result<View, Error>
+    {
+        let v = View::new(&self, name, query);
+        try!(v.save());
+        info!("created new view {:?}", v);
+        Ok(v)
+    }
+
+    /// calls load for view with the database path
+    /// Returns with Error on fail else View
+    pub fn load_view(&self, name: &str) -> Result<View, Error> {
+        View::load(&self, name)
+    }
+
+    /// Returns the names of all tables in the DB folder
+    pub fn table_names(&self) -> Result<Vec<String>, Error> {
+        let mut names = Vec::new();
+        for entry in try!(fs::read_dir(&self.name)) {
+            let path = try!(entry).path();
+            if path.extension().map_or(false, |ext| ext == "tbl") {
+                if let Some(name) = path.file_stem().and_then(|n| n.to_str()) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Checks if a view with the given name exists in the DB folder
+    pub fn has_view(&self, name: &str) -> bool {
+        fs::metadata(Table::get_path(&self.name, name, "view")).is_ok()
+    }
 }
 
 
@@ -111,6 +155,34 @@ pub struct TableMetaData {
     pub columns: Vec<Column>,
 }
 
+//---------------------------------------------------------------
+// TableStats
+//---------------------------------------------------------------
+
+/// Statistics of a table collected by ANALYZE
+#[derive(Debug, Clone, RustcDecodable, RustcEncodable)]
+pub struct TableStats {
+    pub rows: u64,
+    pub columns: Vec<ColumnStats>,
+}
+
+/// Statistics of a single column
+#[derive(Debug, Clone, RustcDecodable, RustcEncodable)]
+pub struct ColumnStats {
+    pub name: String,
+    pub distinct: u64,
+    // bounds of buckets holding the same number of rows, starting with the
+    // smallest and ending with the largest value. Empty for Bool columns.
+    pub histogram: Vec<Lit>,
+}
+
+impl TableStats {
+    /// Returns the statistics of a column
+    pub fn column(&self, name: &str) -> Option<&ColumnStats> {
+        self.columns.iter().find(|c| c.name == name)
+    }
+}
+
 //---------------------------------------------------------------
 // Table
 //---------------------------------------------------------------
@@ -178,6 +250,7 @@ impl<'a> Table<'a> {
     pub fn save(&self) -> Result<(), Error> {
         // call for open file
         info!("opening file to write");
+        mark_written(self.get_table_metadata_path());
         let mut file = try!(OpenOptions::new()
             .write(true)
             .create(true)
@@ -204,6 +277,16 @@ impl<'a> Table<'a> {
         info!("remove data file: {:?}", self.get_table_data_path());
         try!(fs::remove_file(self.get_table_data_path()));
 
+        if fs::metadata(self.get_table_stats_path()).is_ok() {
+            info!("remove stats file: {:?}", self.get_table_stats_path());
+            try!(fs::remove_file(self.get_table_stats_path()));
+        }
+
+        if let EngineID::BStar = self.meta_data.engine_id {
+            info!("remove index files: {:?}", self.get_index_path());
+            try!(Bstar::<u64>::delete(&self.get_index_path()));
+        }
+
         Ok(())
     }
 
@@ -212,6 +295,43 @@ impl<'a> Table<'a> {
         &self.meta_data.columns
     }
 
+    /// Returns the statistics saved by the last ANALYZE, None if the table
+    /// was never analyzed
+    pub fn load_stats(&self) -> Result<Option<TableStats>, Error> {
+        let mut file = match OpenOptions::new().read(true).open(self.get_table_stats_path()) {
+            Ok(file) => file,
+            Err(_) => return Ok(None),
+        };
+        let ma_nmbr = try!(file.read_uint::<BigEndian>(mem::size_of_val(&MAGIC_NUMBER)));
+        if ma_nmbr != MAGIC_NUMBER {
+            info!("Magic Number not correct");
+            return Err(Error::WrongMagicNmbr)
+        }
+        Ok(Some(try!(decode_from(&mut file, SizeLimit::Infinite))))
+    }
+
+    /// Saves the statistics, replacing the old ones
+    pub fn save_stats(&self, stats: &TableStats) -> Result<(), Error> {
+        mark_written(self.get_table_stats_path());
+        let mut file = try!(OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(self.get_table_stats_path()));
+        try!(file.write_u64::<BigEndian>(MAGIC_NUMBER));
+        try!(encode_into(stats, &mut file, SizeLimit::Infinite));
+        Ok(())
+    }
+
+    /// Returns the number of rows the data file has room for, rows marked
+    /// as deleted are counted, too
+    pub fn estimated_rows(&self) -> Result<u64, Error> {
+        let row_size = self.meta_data.columns.iter()
+            .fold(RowHeader::size(), |acc, c| acc + c.sql_type.size() as u64);
+        let len = try!(fs::metadata(self.get_table_data_path())).len();
+        Ok(len / row_size)
+    }
+
     /// Adds a column to the tabel
     /// Returns name of Column or on fail Error
     pub fn add_column(
@@ -272,7 +392,7 @@ impl<'a> Table<'a> {
                 Box::new(FlatFile::new(self))
             },
             EngineID::BStar => {
-                Box::new(FlatFile::new(self))
+                Box::new(BStar::new(self))
             },
         }
     }
@@ -287,8 +407,105 @@ impl<'a> Table<'a> {
         Self::get_path(&self.database.name, &self.name, "dat")
     }
 
+    /// Returns the path for the statistics file
+    fn get_table_stats_path(&self) -> String {
+        Self::get_path(&self.database.name, &self.name, "sta")
+    }
+
+    /// Returns the path for the index files, without extension
+    pub fn get_index_path(&self) -> String {
+        format!("{}/{}", self.database.name, self.name)
+    }
+
     /// Returns the path of the table
     fn get_path(database: &str, name: &str, ext: &str) -> String {
          format!("{}/{}.{}", database, name, ext)
     }
 }
+
+
+//---------------------------------------------------------------
+// View
+//---------------------------------------------------------------
+
+#[derive(Debug, RustcDecodable, RustcEncodable)]
+pub struct ViewMetaData {
+    version_nmbr: u8,
+    query: SelectStmt,
+}
+
+/// View struct that contains the stored select statement of a view
+#[derive(Debug)]
+pub struct View<'a> {
+    database: &'a Database,
+    pub name: String,
+    pub query: SelectStmt,
+}
+
+impl<'a> View<'a> {
+    /// Creates new view object
+    /// Returns View
+    pub fn new<'b>(database: &'b Database, name: &str, query: SelectStmt)
+        -> View<'b>
+    {
+        View {
+            database: database,
+            name: name.to_string(),
+            query: query,
+        }
+    }
+
+    /// Loads the view from the DB
+    /// Returns with Error on fail else View
+    fn load<'b>(database: &'b Database, name: &str)
+        -> Result<View<'b>, Error>
+    {
+        let path_to_view = Table::get_path(&database.name, name, "view");
+        info!("getting path and opening file: {:?}", path_to_view);
+        let mut file = try!(OpenOptions::new()
+            .read(true)
+            .open(path_to_view));
+        let ma_nmbr = try!(file.read_uint::<BigEndian>(mem::size_of_val(&MAGIC_NUMBER)));
+
+        info!("checking magic number: {:?}", ma_nmbr);
+        if ma_nmbr != MAGIC_NUMBER {
+            info!("Magic Number not correct");
+            return Err(Error::WrongMagicNmbr)
+        }
+        let meta_data: ViewMetaData = try!(decode_from(&mut file, SizeLimit::Infinite));
+        info!("getting view definition {:?}", meta_data);
+
+        Ok(View::new(database, name, meta_data.query))
+    }
+
+    /// Saves the view definition with a identification number in view file
+    /// Returns Error on fail else Nothing
+    pub fn save(&self) -> Result<(), Error> {
+        mark_written(self.get_view_path());
+        let mut file = try!(OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(self.get_view_path()));
+        try!(file.write_u64::<BigEndian>(MAGIC_NUMBER));
+        let meta_data = ViewMetaData {
+            version_nmbr: VERSION_NO,
+            query: self.query.clone(),
+        };
+        try!(encode_into(&meta_data, &mut file, SizeLimit::Infinite));
+        Ok(())
+    }
+
+    /// Deletes the .view file
+    /// Returns Error on fail
+    pub fn delete(&self) -> Result<(), Error> {
+        info!("remove view file: {:?}", self.get_view_path());
+        try!(fs::remove_file(self.get_view_path()));
+        Ok(())
+    }
+
+    /// Returns the path for the view file
+    fn get_view_path(&self) -> String {
+        Table::get_path(&self.database.name, &self.name, "view")
+    }
+}
@@ -5,6 +5,7 @@ mod engine;
 mod meta;
 pub mod types;
 pub mod bstar;
+mod written;
 
 mod data;
 
@@ -12,11 +13,14 @@ use bincode::rustc_serialize::{EncodingError, DecodingError};
 
 pub use self::meta::Table;
 pub use self::meta::Database;
+pub use self::meta::View;
+pub use self::meta::{TableStats, ColumnStats};
 pub use self::data::Rows;
 pub use self::data::ResultSet;
 pub use self::types::Column;
 pub use self::types::SqlType;
-pub use self::engine::FlatFile;
+pub use self::engine::{FlatFile, BStar};
+pub use self::written::{mark_written, sync_written, sync_file};
 
 pub use parse::ast;
 pub use parse::ast::CompType;
@@ -25,6 +29,7 @@ pub use parse::ast::CompType;
 
 use std::io;
 use std::io::Cursor;
+use std::fs::File;
 use std::str::Utf8Error;
 use std::ffi::NulError;
 pub use std::string::FromUtf8Error;
@@ -128,6 +133,10 @@ pub trait Engine {
 
     fn full_scan(&self) -> Result<Rows<Cursor<Vec<u8>>>, Error>;
 
+    /// returns the rows of the data file, which are read when they are
+    /// needed
+    fn reader(&self) -> Result<Rows<File>, Error>;
+
     fn lookup(&self, column_index: usize, value: (&[u8], Option<usize>) , comp: CompType)
     -> Result<Rows<Cursor<Vec<u8>>>, Error>;
 
@@ -143,6 +152,19 @@ pub trait Engine {
     fn reorganize(&mut self) -> Result<(), Error>;
 
     fn reset(&mut self) -> Result<(), Error>;
+
+    /// returns true if the engine keeps an index on the column
+    fn has_index(&self, _column_index: usize) -> bool {
+        false
+    }
+
+    /// returns the rows which fulfill a constraint, using the index of the
+    /// column if there is one
+    fn index_lookup(&self, column_index: usize, value: &[u8], comp: CompType)
+    -> Result<Rows<Cursor<Vec<u8>>>, Error>
+    {
+        self.lookup(column_index, (value, None), comp)
+    }
 }
 
 #[repr(u8)]
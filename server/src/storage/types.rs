@@ -102,14 +102,20 @@ impl SqlType {
     }
 
     /// Convert s to a vector with l bytes.
-    /// If length of s is > l, the returning vector will only contain the first
-    /// l bytes.
+    /// If length of s is >= l, the returning vector will only contain the
+    /// whole characters of the first l - 1 bytes.
     /// Otherwise the returned vector will be filled with \0
     /// until it contains l bytes.
     fn to_nul_terminated_bytes(s : &str, l: u32) -> Vec<u8> {
+        let mut end = (l - 1) as usize;
+        if end < s.len() {
+            while !s.is_char_boundary(end) {
+                end -= 1;
+            }
+        }
         let mut v = s.to_string().into_bytes();
 
-        v.truncate((l - 1) as usize);
+        v.truncate(end);
 
         while v.len() < l as usize {
             v.push(0x00);
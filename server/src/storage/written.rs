@@ -0,0 +1,83 @@
+//! Files written by the storage engine
+//!
+//! Every file opened for writing is remembered, so the server can write
+//! exactly these files to the disk before it stops instead of flushing all
+//! file systems.
+//!
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+lazy_static! {
+    static ref WRITTEN: Mutex<HashSet<PathBuf>> = Mutex::new(HashSet::new());
+}
+
+fn written() -> MutexGuard<'static, HashSet<PathBuf>> {
+    // the set stays usable after a panic of another thread
+    WRITTEN.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Remembers that the file or directory is written
+pub fn mark_written<P: AsRef<Path>>(path: P) {
+    written().insert(path.as_ref().to_path_buf());
+}
+
+/// Writes the data of all files written so far to the disk, together with
+/// the directories holding them. Files removed in the meantime are skipped.
+/// All files are synced even if one fails, the first error is returned.
+pub fn sync_written() -> io::Result<()> {
+    let paths = mem::replace(&mut *written(), HashSet::new());
+    let mut result = Ok(());
+    for path in paths {
+        if let Err(e) = sync_file(&path) {
+            warn!("Could not sync {:?}: {}", path, e);
+            result = result.and(Err(e));
+        }
+    }
+    result
+}
+
+/// Writes the data of the file and the entry of its directory to the disk
+pub fn sync_file(path: &Path) -> io::Result<()> {
+    try!(sync_path(path));
+    match path.parent() {
+        Some(dir) if dir != Path::new("") => sync_path(dir),
+        _ => sync_path(Path::new(".")),
+    }
+}
+
+fn sync_path(path: &Path) -> io::Result<()> {
+    match File::open(path) {
+        Ok(file) => file.sync_all(),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+#[test]
+fn test_sync_written() {
+    use std::env;
+    use std::fs;
+
+    let dir = env::temp_dir().join("uosql-written-test");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let kept = dir.join("kept.dat");
+    let removed = dir.join("removed.dat");
+    File::create(&kept).unwrap();
+    File::create(&removed).unwrap();
+    mark_written(&kept);
+    mark_written(&removed);
+    fs::remove_file(&removed).unwrap();
+
+    // the removed file is skipped, both are forgotten afterwards
+    assert!(sync_written().is_ok());
+    let set = written();
+    assert!(!set.contains(&kept) && !set.contains(&removed));
+    drop(set);
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -17,14 +17,14 @@ extern {
 
 mod specialcrate;
 
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
 use std::cmp::{max, min};
 use std::fs::File;
 use std::error::Error;
 use std::io::{self, stdout, Write, Read};
 use std::str::FromStr;
 use uosql::logger;
-use uosql::Connection;
+use uosql::{Connection, TlsMode, Trust};
 use uosql::types::{DataSet};
 use server::storage::SqlType;
 use docopt::Docopt;
@@ -35,13 +35,20 @@ use bincode::SizeLimit;
 /// For console input, manages flags and arguments
 const USAGE: &'static str = "
 Usage: uosql-client [--bind=<address>] [--port=<port>] [--name=<username>]
-        [--pwd=<password>]
+        [--pwd=<password>] [--no-tls | --tls (--ca=<file> | --pin=<file>)]
 
 Options:
-    --bind=<address>    Change the bind address.
+    --bind=<address>    Change the bind address, IPv4, IPv6 or the path of
+                        a Unix socket.
     --port=<port>       Change the port.
     --name=<username>   Login with given username.
     --pwd=<password>    Login with given password.
+    --no-tls            Never encrypt the connection. Without this option
+                        TLS is used if the server offers it, with a
+                        certificate the system trusts.
+    --tls               Refuse to connect without TLS.
+    --ca=<file>         Check the certificate of the server with this CA.
+    --pin=<file>        Accept only this certificate of the server.
 ";
 
 #[derive(Debug, RustcDecodable)]
@@ -49,7 +56,11 @@ struct Args {
    flag_bind: Option<String>,
    flag_port: Option<u16>,
    flag_name: Option<String>,
-   flag_pwd:  Option<String>
+   flag_pwd:  Option<String>,
+   flag_no_tls: bool,
+   flag_tls: bool,
+   flag_ca: Option<String>,
+   flag_pin: Option<String>
 }
 
 
@@ -68,7 +79,7 @@ fn main() {
     let address = {
         match args.flag_bind {
             Some(a) => {
-                if Ipv4Addr::from_str(&a).is_ok() { a }
+                if is_valid_address(&a) { a }
                 else { read_address() }
             },
             None => {
@@ -77,8 +88,8 @@ fn main() {
         }
     };
 
-    // Change port if flag is set
-    let port = {
+    // Change port if flag is set, a Unix socket has none
+    let port = if uosql::is_socket_path(&address) { 0 } else {
         match args.flag_port {
             Some(p) => {
                 if p > 1024 {
@@ -107,8 +118,25 @@ fn main() {
         }
     };
 
+    // Encrypt the connection if the server offers it, unless told otherwise
+    let tls_mode = if args.flag_tls {
+        match (args.flag_ca, args.flag_pin) {
+            (Some(file), _) => TlsMode::Required(Trust::Ca(file)),
+            (None, Some(file)) => TlsMode::Required(Trust::Pinned(file)),
+            (None, None) => {
+                error!("--tls needs --ca or --pin to check the server");
+                return
+            }
+        }
+    } else if args.flag_no_tls {
+        TlsMode::Disabled
+    } else {
+        TlsMode::Preferred
+    };
+
     // Connect to uosql server with given parameters.
-    let mut conn = match Connection::connect(address, port, username, password)
+    let mut conn = match Connection::connect_with_tls(address, port, username, password,
+        tls_mode)
     {
         Ok(conn) => conn,
         Err(e) => {
@@ -140,13 +168,26 @@ fn main() {
                 uosql::Error::Server(_) => {
                     error!("{}", e.description());
                     return
+                },
+                uosql::Error::ProtocolVersion(v) => {
+                    error!("{} (version {})", e.description(), v);
+                    return
+                },
+                uosql::Error::Tls(ref err) => {
+                    error!("{}: {}", e.description(), err);
+                    return
+                },
+                uosql::Error::TlsUnavailable => {
+                    error!("{}", e.description());
+                    return
                 }
             }
         }
     };
 
-    println!("Connected (version: {}) to {}:{}\n{}",
-        conn.get_version(), conn.get_ip(), conn.get_port(), conn.get_message());
+    println!("Connected (version: {}{}) to {}:{}\n{}",
+        conn.get_version(), if conn.is_encrypted() { ", TLS" } else { "" },
+        conn.get_ip(), conn.get_port(), conn.get_message());
 
     // Load history from "uosql_client.history" if possible
     let mut history: Vec<String>;
@@ -347,6 +388,18 @@ fn process_input(input: &str, conn: &mut Connection, history: &Vec<String>) -> b
                 }
             }
         },
+        ":shutdown" => {
+            match conn.shutdown() {
+                Ok(_) => {
+                    println!("Server is shutting down.");
+                    return false
+                },
+                Err(e) => {
+                    error!("Shutdown: {}", e.description());
+                    return true
+                }
+            }
+        },
         ":exit" => {
             match conn.quit() {
                 Ok(_) => {
@@ -426,6 +479,7 @@ fn process_input(input: &str, conn: &mut Connection, history: &Vec<String>) -> b
                         },
                         uosql::Error::Server(_) => {
                             error!("{}", e.description());
+                            show_error_position(input, &e);
                             return true
                         }
                         _ => {
@@ -509,50 +563,47 @@ fn execute_sql(mut f: File, conn: &mut Connection) -> bool {
         }
     }
 
-    // split Strings and collect results in vec
-    let statem: Vec<&str> = sql.split(";").collect();
-
-    for i in statem {
+    // the server splits the script into statements and stops at the first
+    // one that fails
+    let results = match conn.execute_script(sql.clone(), false) {
+        Ok(results) => results,
+        Err(e) => {
+            error!("{}", e.description());
+            return true
+        }
+    };
 
-        println!("\n Query given was: {}", i);
-        match conn.execute(i.into()) {
+    for res in results {
+        match res {
             Ok(mut data) => {
             // show data belonging to executed query
                 display(&mut data);
             },
             Err(e) => {
-                match e {
-                    uosql::Error::Io(_) => {
-                        error!("{}", e.description());
-                        return true
-                    },
-                    uosql::Error::Decode(_) => {
-                        error!("{}", e.description());
-                        return true
-                    }
-                    uosql::Error::Encode(_) => {
-                        error!("{}", e.description());
-                        return true
-                    }
-                    uosql::Error::UnexpectedPkg => {
-                        error!("{}", e.description());
-                        return true
-                    },
-                    uosql::Error::Server(_) => {
-                        error!("{}", e.description());
-                        return true
-                    }
-                    _ => {
-                        error!("Unexpected behaviour during execute()");
-                        return true
-                    }
-                }
+                error!("{}", e.description());
+                show_error_position(&sql, &e);
             }
         }
     }
     true
 }
 
+/// Print the failing part of the query underlined, if the server told
+/// where it is.
+fn show_error_position(query: &str, e: &uosql::Error) {
+    let span = match e {
+        &uosql::Error::Server(ref msg) => match msg.span {
+            Some(ref span) => span,
+            None => return
+        },
+        _ => return
+    };
+    if let Some((line, marks)) = uosql::mark_span(query, span) {
+        println!("{}", line);
+        println!("{}", marks);
+    }
+}
+
 
 /// Read from command line and return trimmed string.
 /// If an error occurs reading from stdin loop until a valid String was read.
@@ -571,7 +622,12 @@ fn read_line() -> String {
     }
 }
 
-/// Read IP-address to connect to from command-line.
+/// Checks if the address is an IP address or the path of a Unix socket
+fn is_valid_address(address: &str) -> bool {
+    IpAddr::from_str(address).is_ok() || uosql::is_socket_path(address)
+}
+
+/// Read IP-address or socket path to connect to from command-line.
 /// In case no input was given ("\n") default address "127.0.0.1" is returned.
 pub fn read_address() -> String {
     loop {
@@ -585,7 +641,7 @@ pub fn read_address() -> String {
         match &*a {
             "\n" => return "127.0.0.1".into(),
             _ => {
-                if Ipv4Addr::from_str(&a).is_ok() {
+                if is_valid_address(&a) {
                     return a
                 }
             }
@@ -2,19 +2,26 @@
 extern crate server;
 extern crate bincode;
 
-use std::net::{Ipv4Addr, AddrParseError, TcpStream};
+use std::net::{IpAddr, AddrParseError, TcpStream};
+use std::os::unix::net::UnixStream;
 use std::str::FromStr;
-use std::io::{self, Write};
+use std::io::{self, Read, Write};
+use std::path::Path;
 use std::fmt;
 pub use server::net::types;
 pub use server::logger;
-use server::storage::ResultSet;
+pub use server::parse::token::Lit;
+pub use server::parse::Span;
+use server::auth::scram;
+use server::net::tls::{self, Stream};
+use server::storage::{ResultSet, Column};
 use bincode::SizeLimit;
 use bincode::rustc_serialize::{EncodingError, DecodingError,
     decode_from, encode_into};
 use types::*;
+use server::net::MAX_COMMAND_SIZE;
 
-const PROTOCOL_VERSION : u8 = 1;
+const PROTOCOL_VERSION : u8 = 4;
 
 /// Client specific Error definition.
 #[derive(Debug)]
@@ -26,6 +33,11 @@ pub enum Error {
     Decode(DecodingError),
     Auth,
     Server(ClientErrMsg),
+    // the server speaks another protocol version
+    ProtocolVersion(u8),
+    Tls(tls::Error),
+    // TLS is required, but the server does not offer it
+    TlsUnavailable,
 }
 
 /// Implement display for description of Error
@@ -39,13 +51,16 @@ impl fmt::Display for Error {
 impl std::error::Error for Error {
     fn description(&self) -> &str {
         match self {
-            &Error::AddrParse(_) => "wrong IPv4 address format",
+            &Error::AddrParse(_) => "wrong IP address format",
             &Error::Io(_) => "IO error occured",
             &Error::UnexpectedPkg => "received unexpected package",
             &Error::Encode(_) => "could not encode/ send package",
             &Error::Decode(_) => "could not decode/ receive package",
             &Error::Auth => "could not authenticate user",
             &Error::Server(ref e) => { &e.msg }
+            &Error::ProtocolVersion(_) => "server speaks another protocol version",
+            &Error::Tls(_) => "TLS handshake failed",
+            &Error::TlsUnavailable => "server does not offer TLS",
         }
     }
 }
@@ -71,6 +86,12 @@ impl  From<EncodingError> for Error {
     }
 }
 
+impl From<tls::Error> for Error {
+    fn from(err: tls::Error) -> Error {
+        Error::Tls(err)
+    }
+}
+
 /// Implement the conversion from DecodingError to NetworkError
 impl From<DecodingError> for Error {
     fn from(err: DecodingError) -> Error {
@@ -85,31 +106,86 @@ impl From<ClientErrMsg> for Error {
     }
 }
 
+/// Whether the connection to the server is encrypted with TLS
+#[derive(Clone, Debug)]
+pub enum TlsMode {
+    Disabled,
+    // use TLS if the server offers it, its certificate has to be signed by a
+    // CA the system trusts and issued for the address. A server which does
+    // not offer TLS is not authenticated.
+    Preferred,
+    // refuse to log in without TLS and a certificate passing the check
+    Required(Trust),
+}
+
+/// How the certificate of the server is checked, the files are PEM files
+#[derive(Clone, Debug)]
+pub enum Trust {
+    // signed by a CA of the file and issued for the address of the server
+    Ca(String),
+    // exactly the certificate of the file, e.g. a self-signed one
+    Pinned(String),
+}
+
+impl TlsMode {
+    fn trust(&self) -> tls::Trust {
+        match self {
+            &TlsMode::Required(Trust::Ca(ref file)) => tls::Trust::Ca(Path::new(file)),
+            &TlsMode::Required(Trust::Pinned(ref file)) => tls::Trust::Pinned(Path::new(file)),
+            _ => tls::Trust::System,
+        }
+    }
+}
+
+/// Checks if the address is the path of a Unix socket rather than an IP
+/// address
+pub fn is_socket_path(addr: &str) -> bool {
+    addr.contains('/')
+}
+
 /// Stores TCPConnection with a server. Contains IP, Port, Login data and
-/// greeting from server.
+/// greeting from server. The IP may be the path of a Unix socket instead,
+/// the port is not used then.
 pub struct Connection {
     ip: String,
     port: u16,
-    tcp: TcpStream,
+    tcp: Stream,
     greeting: Greeting,
     user_data: Login,
+    tls_mode: TlsMode,
+    // sent once the login succeeded, to cancel queries of this session
+    session_id: u64,
+    cancel_key: u64,
 }
 
 impl Connection {
-    /// Establish connection to specified address and port.
+    /// Establish connection to specified address and port, encrypted if the
+    /// server offers TLS with a certificate the system trusts. The address
+    /// may be IPv4, IPv6 or the path of a Unix socket.
     pub fn connect(addr: String, port: u16, usern: String, passwd: String)
         -> Result<Connection, Error>
     {
-        // Parse IPv4 address from String
-        let tmp_addr = match std::net::Ipv4Addr::from_str(&addr) {
-            Ok(tmp_addr) => tmp_addr,
-            Err(e) => return Err(e.into())
-        };
+        Connection::connect_with_tls(addr, port, usern, passwd, TlsMode::Preferred)
+    }
 
-        // Establish Tcp connection
-        let mut tmp_tcp = match TcpStream::connect((tmp_addr, port)) {
-            Ok(tmp_tcp) => tmp_tcp,
-            Err(e) => return Err(e.into())
+    /// Establish connection to specified address and port, `tls_mode` tells
+    /// whether it has to be encrypted.
+    pub fn connect_with_tls(addr: String, port: u16, usern: String, passwd: String,
+        tls_mode: TlsMode) -> Result<Connection, Error>
+    {
+        // Establish Tcp connection, or one over the Unix socket
+        let mut tmp_tcp = if is_socket_path(&addr) {
+            Stream::Unix(try!(UnixStream::connect(&addr)))
+        } else {
+            // Parse IP address from String
+            let tmp_addr = match IpAddr::from_str(&addr) {
+                Ok(tmp_addr) => tmp_addr,
+                Err(e) => return Err(e.into())
+            };
+            match TcpStream::connect((tmp_addr, port)) {
+                Ok(tmp_tcp) => Stream::Plain(tmp_tcp),
+                Err(e) => return Err(e.into())
+            }
         };
 
         // Greeting message
@@ -117,31 +193,53 @@ impl Connection {
             Ok(_) => {},
             Err(e) => return Err(e)
         };
-        let greet: Greeting =
-            try!(decode_from(&mut tmp_tcp, SizeLimit::Bounded(1024)));
+        let greet = try!(read_greeting(&mut tmp_tcp));
 
-        // Login package
-        let log = Login { username: usern, password: passwd };
-        match encode_into(&PkgType::Login, &mut tmp_tcp,
-            SizeLimit::Bounded(1024))
-        {
-            Ok(_) => {},
-            Err(e) => return Err(e.into())
-        }
+        // The login and everything after it is encrypted with TLS. It is
+        // never offered over a Unix socket.
+        let mut tmp_tcp = match (&tls_mode, greet.tls, tmp_tcp) {
+            (&TlsMode::Disabled, _, tmp_tcp) | (&TlsMode::Preferred, false, tmp_tcp) => tmp_tcp,
+            (_, true, Stream::Plain(mut tmp_tcp)) => {
+                try!(encode_into(&PkgType::StartTls, &mut tmp_tcp, SizeLimit::Bounded(1024)));
+                try!(tls::connect(tmp_tcp, &addr, tls_mode.trust()))
+            },
+            _ => return Err(Error::TlsUnavailable),
+        };
 
-        // Login data
-        match encode_into(&log, &mut tmp_tcp, SizeLimit::Bounded(1024)) {
-            Ok(_) => {},
-            Err(e) => return Err(e.into())
-        }
+        // Start of the login: the name and a nonce, never the password
+        let client_nonce = try!(scram::random_bytes(scram::NONCE_LEN));
+        let start = AuthStart { username: usern.clone(), nonce: client_nonce.clone() };
+        try!(encode_into(&PkgType::AuthStart, &mut tmp_tcp, SizeLimit::Bounded(1024)));
+        try!(encode_into(&start, &mut tmp_tcp, SizeLimit::Bounded(1024)));
+
+        // Salt and rounds of the password hash
+        try!(receive(&mut tmp_tcp, PkgType::AuthChallenge));
+        let challenge: AuthChallenge =
+            try!(decode_from(&mut tmp_tcp, SizeLimit::Bounded(1024)));
+
+        // Proof that we know the password
+        let message = scram::auth_message(&usern, &greet.nonce, &client_nonce);
+        let (proof, signature) = scram::client_final(&passwd, &challenge.salt,
+            challenge.iterations, &message);
+        try!(encode_into(&PkgType::AuthProof, &mut tmp_tcp, SizeLimit::Bounded(1024)));
+        try!(encode_into(&AuthProof { proof: proof }, &mut tmp_tcp, SizeLimit::Bounded(1024)));
 
         // Get Login response - either user is authorized or unauthorized
         let status: PkgType =
             try!(decode_from(&mut tmp_tcp, SizeLimit::Bounded(1024)));
         match status {
-            PkgType::AccGranted =>
+            PkgType::AccGranted => {
+                // the server proves that it knows the keys of the password
+                let granted: AccessGranted =
+                    try!(decode_from(&mut tmp_tcp, SizeLimit::Bounded(1024)));
+                if !scram::signatures_match(&granted.signature, &signature) {
+                    return Err(Error::Auth)
+                }
+                let log = Login { username: usern, password: passwd };
                 Ok(Connection { ip: addr, port: port, tcp: tmp_tcp,
-                    greeting: greet, user_data: log} ),
+                    greeting: greet, user_data: log, tls_mode: tls_mode,
+                    session_id: granted.session_id, cancel_key: granted.cancel_key } )
+            },
             PkgType::AccDenied =>
                 Err(Error::Auth),
             _ => Err(Error::UnexpectedPkg)
@@ -160,6 +258,19 @@ impl Connection {
         }
     }
 
+    /// Send shutdown-command to server and receive Ok-package. Only admins
+    /// may stop the server, running statements get some time to finish.
+    pub fn shutdown(&mut self) -> Result<(), Error> {
+        match send_cmd(&mut self.tcp, Command::Shutdown, 1024) {
+            Ok(_) => {},
+            Err(e) => return Err(e)
+        };
+        match receive(&mut self.tcp, PkgType::Ok) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err)
+        }
+    }
+
     /// Send quit-command to server and receive Ok-package
     pub fn quit(&mut self) -> Result<(), Error> {
         match send_cmd(&mut self.tcp, Command::Quit, 1024) {
@@ -172,20 +283,152 @@ impl Connection {
         }
     }
 
-    // TODO: Return results (response-package)
+    /// Send query to server and collect all rows of the response.
     pub fn execute(&mut self, query: String) -> Result<DataSet, Error> {
-        match send_cmd(&mut self.tcp, Command::Query(query), 1024) {
+        let mut data = Vec::new();
+        let columns = try!(self.execute_batches(query, |batch| {
+            data.extend(batch.data.into_iter());
+        }));
+        Ok(preprocess(&ResultSet { data: data, columns: columns }))
+    }
+
+    /// Send query to server and hand the rows of the response to `on_batch`
+    /// as they arrive, so a large result is never kept as a whole.
+    /// Returns the columns of the result.
+    pub fn execute_batches<F>(&mut self, query: String, on_batch: F)
+        -> Result<Vec<Column>, Error>
+        where F: FnMut(ResultSet)
+    {
+        self.run(Command::Query(query), on_batch)
+    }
+
+    /// Send query with ? or $n placeholders to server, which parses it once.
+    /// Returns the handle of the statement and the types of its parameters.
+    pub fn prepare(&mut self, query: String) -> Result<PreparedStmt, Error> {
+        match send_cmd(&mut self.tcp, Command::Prepare(query), MAX_COMMAND_SIZE) {
+            Ok(_) => {},
+            Err(e) => return Err(e)
+        };
+        match receive(&mut self.tcp, PkgType::Prepared) {
             Ok(_) => {},
             Err(e) => return Err(e)
         };
-        match receive(&mut self.tcp, PkgType::Response) {
-            Ok(_) => {
+        Ok(try!(decode_from(&mut self.tcp, SizeLimit::Infinite)))
+    }
+
+    /// Execute a prepared statement with the values for its parameters and
+    /// collect all rows of the response.
+    pub fn execute_prepared(&mut self, handle: u32, values: Vec<Lit>)
+        -> Result<DataSet, Error>
+    {
+        let mut data = Vec::new();
+        let columns = try!(self.run(Command::Execute(handle, values), |batch| {
+            data.extend(batch.data.into_iter());
+        }));
+        Ok(preprocess(&ResultSet { data: data, columns: columns }))
+    }
+
+    /// Send deallocate-command for a prepared statement and receive
+    /// Ok-package
+    pub fn deallocate(&mut self, handle: u32) -> Result<(), Error> {
+        match send_cmd(&mut self.tcp, Command::Deallocate(handle), 1024) {
+            Ok(_) => {},
+            Err(e) => return Err(e)
+        };
+        match receive(&mut self.tcp, PkgType::Ok) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Send several statements separated by semicolons to server and collect
+    /// the result of every statement. The server stops at the first failed
+    /// statement unless `keep_going` is set.
+    pub fn execute_script(&mut self, script: String, keep_going: bool)
+        -> Result<Vec<Result<DataSet, Error>>, Error>
+    {
+        match send_cmd(&mut self.tcp, Command::Script(script, keep_going),
+            MAX_COMMAND_SIZE)
+        {
+            Ok(_) => {},
+            Err(e) => return Err(e)
+        };
+        let mut results = Vec::new();
+        loop {
+            let status: PkgType =
+                try!(decode_from(&mut self.tcp, SizeLimit::Bounded(1024)));
+            if status == PkgType::ScriptEnd {
+                return Ok(results)
+            }
+            let mut data = Vec::new();
+            let res = self.read_result(status, |batch| {
+                data.extend(batch.data.into_iter());
+            });
+            match res {
+                Ok(columns) =>
+                    results.push(Ok(preprocess(&ResultSet { data: data, columns: columns }))),
+                // only the statement failed, the next result follows
+                Err(Error::Server(e)) => results.push(Err(Error::Server(e))),
+                Err(e) => return Err(e)
+            }
+        }
+    }
+
+    /// Send a command producing rows and hand them to `on_batch`
+    fn run<F>(&mut self, cmd: Command, on_batch: F) -> Result<Vec<Column>, Error>
+        where F: FnMut(ResultSet)
+    {
+        match send_cmd(&mut self.tcp, cmd, MAX_COMMAND_SIZE) {
+            Ok(_) => {},
+            Err(e) => return Err(e)
+        };
+        let status: PkgType =
+            try!(decode_from(&mut self.tcp, SizeLimit::Bounded(1024)));
+        self.read_result(status, on_batch)
+    }
+
+    /// Read the result of a statement, `status` is its first package type
+    fn read_result<F>(&mut self, status: PkgType, mut on_batch: F)
+        -> Result<Vec<Column>, Error>
+        where F: FnMut(ResultSet)
+    {
+        match status {
+            // the whole result in one package
+            PkgType::Response => {
                 let rows: ResultSet =
                     try!(decode_from(&mut self.tcp, SizeLimit::Infinite));
-                let dataset = preprocess (&rows);
-                Ok(dataset)
+                let columns = rows.columns.clone();
+                on_batch(rows);
+                Ok(columns)
             },
-            Err(err) => Err(err)
+            PkgType::ResponseColumns => {
+                let columns: Vec<Column> =
+                    try!(decode_from(&mut self.tcp, SizeLimit::Infinite));
+                loop {
+                    let status: PkgType =
+                        try!(decode_from(&mut self.tcp, SizeLimit::Bounded(1024)));
+                    match status {
+                        PkgType::ResponseBatch => {
+                            let data: Vec<u8> =
+                                try!(decode_from(&mut self.tcp, SizeLimit::Infinite));
+                            on_batch(ResultSet { data: data, columns: columns.clone() });
+                        },
+                        PkgType::ResponseEnd => return Ok(columns),
+                        PkgType::Error => {
+                            let err: ClientErrMsg =
+                                try!(decode_from(&mut self.tcp, SizeLimit::Infinite));
+                            return Err(Error::Server(err))
+                        },
+                        _ => return Err(Error::UnexpectedPkg)
+                    }
+                }
+            },
+            PkgType::Error => {
+                let err: ClientErrMsg =
+                    try!(decode_from(&mut self.tcp, SizeLimit::Infinite));
+                Err(Error::Server(err))
+            },
+            _ => Err(Error::UnexpectedPkg)
         }
     }
 
@@ -209,10 +452,99 @@ impl Connection {
         self.port
     }
 
+    /// Return whether the connection is encrypted with TLS.
+    pub fn is_encrypted(&self) -> bool {
+        self.tcp.is_tls()
+    }
+
     /// Return username used for current connection authentication.
     pub fn get_username(&self) -> &str {
         &self.user_data.username
     }
+
+    /// Return the id of the session on the server.
+    pub fn get_session_id(&self) -> u64 {
+        self.session_id
+    }
+
+    /// Return the key needed to cancel queries of this session.
+    pub fn get_cancel_key(&self) -> u64 {
+        self.cancel_key
+    }
+
+    /// Return a handle which cancels the running query of this connection.
+    /// It can be moved to another thread while this connection waits for
+    /// the result.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        CancelHandle {
+            ip: self.ip.clone(),
+            port: self.port,
+            username: self.user_data.username.clone(),
+            password: self.user_data.password.clone(),
+            session_id: self.get_session_id(),
+            cancel_key: self.get_cancel_key(),
+            tls_mode: self.tls_mode.clone(),
+        }
+    }
+
+    /// Send cancel-command for the session to server and receive Ok-package.
+    /// The query is cancelled, this connection stays open.
+    pub fn cancel(&mut self, session_id: u64, cancel_key: u64) -> Result<(), Error> {
+        match send_cmd(&mut self.tcp, Command::Cancel(session_id, cancel_key), 1024) {
+            Ok(_) => {},
+            Err(e) => return Err(e)
+        };
+        match receive(&mut self.tcp, PkgType::Ok) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err)
+        }
+    }
+}
+
+/// Everything needed to cancel the running query of a connection from
+/// another thread.
+#[derive(Clone, Debug)]
+pub struct CancelHandle {
+    ip: String,
+    port: u16,
+    username: String,
+    password: String,
+    session_id: u64,
+    cancel_key: u64,
+    tls_mode: TlsMode,
+}
+
+impl CancelHandle {
+    /// Open a second connection and cancel the running query with it. The
+    /// cancelled query returns an error.
+    pub fn cancel(&self) -> Result<(), Error> {
+        let mut conn = try!(Connection::connect_with_tls(self.ip.clone(), self.port,
+            self.username.clone(), self.password.clone(), self.tls_mode.clone()));
+        try!(conn.cancel(self.session_id, self.cancel_key));
+        conn.quit()
+    }
+}
+
+/// Return the line of the query containing the span and a second line
+/// marking the span with `^`, e.g. to show the failing part of a query.
+pub fn mark_span(query: &str, span: &Span) -> Option<(String, String)> {
+    if span.lo > span.hi || span.hi > query.len() || !query.is_char_boundary(span.lo)
+        || !query.is_char_boundary(span.hi)
+    {
+        return None
+    }
+    let start = query[..span.lo].rfind('\n').map_or(0, |i| i + 1);
+    let end = query[span.lo..].find('\n').map_or(query.len(), |i| span.lo + i);
+    let hi = if span.hi < end { span.hi } else { end };
+
+    let mut marks: String = query[start..span.lo].chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let width = query[span.lo..hi].chars().count();
+    for _ in 0..(if width == 0 { 1 } else { width }) {
+        marks.push('^');
+    }
+    Some((query[start..end].to_string(), marks))
 }
 
 /// Return current library version.
@@ -221,17 +553,34 @@ fn get_lib_version() -> u8 {
     PROTOCOL_VERSION
 }
 
-/// Send command package with actual command, e.g. quit, ping, query.
+/// Read the greeting field by field. The version and the message come first
+/// in every protocol version, so a server speaking another version is
+/// noticed before the rest is misread.
+fn read_greeting<R: Read>(s: &mut R) -> Result<Greeting, Error> {
+    let version: u8 = try!(decode_from(s, SizeLimit::Bounded(1024)));
+    let message: String = try!(decode_from(s, SizeLimit::Bounded(1024)));
+    if version != PROTOCOL_VERSION {
+        return Err(Error::ProtocolVersion(version))
+    }
+    let nonce: Vec<u8> = try!(decode_from(s, SizeLimit::Bounded(1024)));
+    let tls: bool = try!(decode_from(s, SizeLimit::Bounded(1024)));
+    Ok(Greeting::make_greeting(version, message, nonce, tls))
+}
+
+/// Send command package with actual command, e.g. quit, ping, query. A
+/// command larger than `size` is not sent at all.
 fn send_cmd<W: Write>(mut s: &mut W, cmd: Command, size: u64)
     -> Result<(), Error>
 {
+    let mut body = Vec::new();
+    try!(encode_into(&cmd, &mut body, SizeLimit::Bounded(size)));
     try!(encode_into(&PkgType::Command, s, SizeLimit::Bounded(1024)));
-    try!(encode_into(&cmd, &mut s, SizeLimit::Bounded(size)));
+    try!(s.write_all(&body));
     Ok(())
 }
 
 /// Match received packages to expected packages.
-fn receive(s: &mut TcpStream, cmd: PkgType) -> Result<(), Error> {
+fn receive<R: Read>(s: &mut R, cmd: PkgType) -> Result<(), Error> {
     let status: PkgType = try!(decode_from(s, SizeLimit::Bounded(1024)));
 
     if status == PkgType::Error {
@@ -245,9 +594,18 @@ fn receive(s: &mut TcpStream, cmd: PkgType) -> Result<(), Error> {
             PkgType::Response => {
                 let _ : ResultSet = try!(decode_from(s, SizeLimit::Infinite));
             },
+            PkgType::ResponseColumns => {
+                let _ : Vec<Column> = try!(decode_from(s, SizeLimit::Infinite));
+            },
+            PkgType::ResponseBatch => {
+                let _ : Vec<u8> = try!(decode_from(s, SizeLimit::Infinite));
+            },
             PkgType::Greet => {
                 let _ : Greeting = try!(decode_from(s, SizeLimit::Infinite));
             },
+            PkgType::Prepared => {
+                let _ : PreparedStmt = try!(decode_from(s, SizeLimit::Infinite));
+            },
             _ => {}
         }
         return Err(Error::UnexpectedPkg)
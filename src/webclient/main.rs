@@ -24,7 +24,7 @@ use nickel::{Nickel, HttpRouter};
 use cookie::Cookie as CookiePair;
 use hyper::method::Method;
 use url::form_urlencoded as urlencode;
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
 use std::str::FromStr;
 use nickel::QueryString;
 use uosql::types::DataSet;
@@ -181,6 +181,12 @@ fn main() {
                             Error::Server(_) => {
                                 "Network Error."
                             },
+                            Error::ProtocolVersion(_) => {
+                                "Server speaks another protocol version."
+                            },
+                            Error::Tls(_) | Error::TlsUnavailable => {
+                                "Could not encrypt the connection."
+                            },
                         };
                         let mut data = HashMap::new();
                         data.insert("err", errstr);
@@ -242,18 +248,27 @@ fn main() {
 
         let query = req.query().get("sql");
         if !query.is_none() {
-            let mut result = match con.execute(query.unwrap().trim().to_string()) {
+            let sql = query.unwrap().trim().to_string();
+            let mut result = match con.execute(sql.clone()) {
                 Ok(r) => r,
                 Err(e) => {
+                    let mut data = HashMap::new();
                     let errstr = match e {
-                        Error::Io(_) => "Connection failure. Try again later.",
-                        Error::Decode(_) => "Could not read data from server.",
-                        Error::Encode(_) => "Could not send data to server.",
-                        Error::UnexpectedPkg => "Received unexpected package.",
-                        Error::Server(_) => "Server error.",
-                        _ => "Unexpected behaviour during execute().",
+                        Error::Io(_) => "Connection failure. Try again later.".to_string(),
+                        Error::Decode(_) => "Could not read data from server.".to_string(),
+                        Error::Encode(_) => "Could not send data to server.".to_string(),
+                        Error::UnexpectedPkg => "Received unexpected package.".to_string(),
+                        Error::Server(ref err) => {
+                            // show the failing part of the query underlined
+                            let marked = err.span.as_ref()
+                                .and_then(|span| uosql::mark_span(&sql, span));
+                            if let Some((line, marks)) = marked {
+                                data.insert("position", format!("{}\n{}", line, marks));
+                            }
+                            err.msg.clone()
+                        },
+                        _ => "Unexpected behaviour during execute().".to_string(),
                     };
-                    let mut data = HashMap::new();
                     data.insert("err", errstr);
                     return res.render("src/webclient/templates/error.tpl", &data);
                 }
@@ -280,7 +295,10 @@ fn main() {
 
 /// Test if binding address is a valid address
 fn test_bind (bind : &str) -> bool {
-    let result = match Ipv4Addr::from_str(bind) {
+    if uosql::is_socket_path(bind) {
+        return true
+    }
+    let result = match IpAddr::from_str(bind) {
         Ok(_) => true,
         Err(_) => {
             false
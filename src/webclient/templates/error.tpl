@@ -11,6 +11,9 @@
         <h1>
             Error: {{ err }}
         </h1>
+        {{# position }}
+        <pre style="display:inline-block; text-align:left">{{ position }}</pre>
+        {{/ position }}
         <img src="http://cdn.meme.am/instances/500x/51018755.jpg">
     </div>
 </body>